@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use lambda_http::lambda_runtime::{self, service_fn, LambdaEvent};
+use ovaflus_backend::{jobs, AppState};
+use tracing_subscriber::EnvFilter;
+
+/// Entry point of the scheduled jobs function. EventBridge rules invoke it with the job to run,
+/// e.g. `{"job": "plaid-sync"}`.
+#[tokio::main]
+async fn main() -> Result<(), lambda_runtime::Error> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .json()
+        .init();
+
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let state = Arc::new(AppState::load(&config).await);
+
+    lambda_runtime::run(service_fn(|event: LambdaEvent<jobs::JobEvent>| {
+        let state = state.clone();
+        async move {
+            jobs::run(state, event.payload)
+                .await
+                .map_err(lambda_runtime::Error::from)
+        }
+    }))
+    .await
+}
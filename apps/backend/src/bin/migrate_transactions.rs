@@ -0,0 +1,73 @@
+use aws_sdk_dynamodb::Client as DynamoClient;
+use ovaflus_backend::db::transaction_keys::{backfill_transaction_keys, BackfillReport};
+use tokio::task::JoinSet;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_SEGMENTS: i32 = 4;
+
+/// Backfill the date-ordered index keys on existing `ovaflus-transactions` rows, and the
+/// split-index rows of split transactions.
+///
+/// The API writes the keys on every create and update, so this runs while it keeps serving.
+/// DynamoDB adds one index per table update, so the rollout is:
+///
+/// 1. deploy the API and `user-date-index` (the default `cdk deploy`);
+/// 2. deploy again with `-c budgetDateIndex=true` once that index is active;
+/// 3. run `migrate-transactions [--dry-run] [--segments N]`;
+/// 4. deploy with `-c transactionsDateIndex=true` so reads move to the indexes.
+///
+/// Re-running is harmless.
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let segments = match args.iter().position(|a| a == "--segments") {
+        Some(i) => match args.get(i + 1).and_then(|n| n.parse::<i32>().ok()) {
+            Some(n) if n > 0 => n,
+            _ => {
+                eprintln!("--segments needs a positive number");
+                std::process::exit(2);
+            }
+        },
+        None => DEFAULT_SEGMENTS,
+    };
+
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let dynamo = DynamoClient::new(&config);
+
+    // Scan segments in parallel; each one is an independent slice of the table
+    let mut tasks = JoinSet::new();
+    for segment in 0..segments {
+        let dynamo = dynamo.clone();
+        tasks.spawn(
+            async move { backfill_transaction_keys(&dynamo, segment, segments, dry_run).await },
+        );
+    }
+    let mut report = BackfillReport::default();
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(segment_report) => report.absorb(segment_report),
+            Err(e) => {
+                tracing::error!("Backfill task failed: {e}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "{}scanned {}, updated {}, skipped {} changed concurrently, split rows {}, failed {}",
+        if dry_run { "[dry run] " } else { "" },
+        report.scanned,
+        report.updated,
+        report.skipped,
+        report.split_rows,
+        report.failed
+    );
+    if report.failed > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,70 @@
+use aws_sdk_kms::{primitives::Blob, types::DataKeySpec, Client};
+
+use super::{CryptoError, DataKey, KeyProvider};
+
+/// Data keys from AWS KMS. The version is the KMS key id, so switching `TOKEN_KMS_KEY_ID` to a
+/// new key marks every existing token for re-encryption.
+pub struct KmsKeyProvider {
+    client: Client,
+    key_id: String,
+}
+
+impl KmsKeyProvider {
+    pub fn new(client: Client, key_id: String) -> Self {
+        Self { client, key_id }
+    }
+}
+
+#[axum::async_trait]
+impl KeyProvider for KmsKeyProvider {
+    fn current_version(&self) -> String {
+        format!("kms:{}", self.key_id)
+    }
+
+    async fn generate_data_key(&self) -> Result<DataKey, CryptoError> {
+        let output = self
+            .client
+            .generate_data_key()
+            .key_id(&self.key_id)
+            .key_spec(DataKeySpec::Aes256)
+            .send()
+            .await
+            .map_err(|e| CryptoError(format!("KMS GenerateDataKey failed: {}", e)))?;
+
+        let plaintext: [u8; 32] = output
+            .plaintext
+            .map(|b| b.into_inner())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| CryptoError("KMS returned no 256-bit data key".to_string()))?;
+        let wrapped = output
+            .ciphertext_blob
+            .map(|b| b.into_inner())
+            .ok_or_else(|| CryptoError("KMS returned no wrapped data key".to_string()))?;
+        Ok(DataKey { plaintext, wrapped })
+    }
+
+    async fn unwrap_data_key(
+        &self,
+        version: &str,
+        wrapped: &[u8],
+    ) -> Result<[u8; 32], CryptoError> {
+        let key_id = version
+            .strip_prefix("kms:")
+            .ok_or_else(|| CryptoError(format!("key version {} is not a KMS key", version)))?;
+
+        let output = self
+            .client
+            .decrypt()
+            .key_id(key_id)
+            .ciphertext_blob(Blob::new(wrapped))
+            .send()
+            .await
+            .map_err(|e| CryptoError(format!("KMS Decrypt failed: {}", e)))?;
+
+        output
+            .plaintext
+            .map(|b| b.into_inner())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| CryptoError("KMS returned no 256-bit data key".to_string()))
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use super::{open, seal, CryptoError, DataKey, KeyProvider};
+
+/// Wraps data keys with static AES-256 keys. For development and tests; production uses KMS.
+///
+/// Older versions stay configured after a rotation so existing tokens can still be read until
+/// the re-encryption tool has moved them to the current key.
+pub struct LocalKeyProvider {
+    current: String,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl LocalKeyProvider {
+    pub fn new(current: &str, keys: HashMap<String, [u8; 32]>) -> Self {
+        Self {
+            current: current.to_string(),
+            keys,
+        }
+    }
+
+    /// `TOKEN_LOCAL_KEYS` holds comma-separated `version:base64key` pairs; the current version
+    /// is `TOKEN_LOCAL_KEY_VERSION`, or the last pair when unset.
+    pub fn from_env() -> Result<Self, CryptoError> {
+        let spec = std::env::var("TOKEN_LOCAL_KEYS")
+            .map_err(|_| CryptoError("TOKEN_LOCAL_KEYS is not set".to_string()))?;
+        let keys = parse_keys(&spec)?;
+        let current = match std::env::var("TOKEN_LOCAL_KEY_VERSION") {
+            Ok(version) => version,
+            Err(_) => spec
+                .split(',')
+                .next_back()
+                .and_then(|pair| pair.split(':').next())
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        };
+        if !keys.contains_key(&current) {
+            return Err(CryptoError(format!(
+                "no key configured for version {}",
+                current
+            )));
+        }
+        Ok(Self::new(&current, keys))
+    }
+
+    fn key(&self, version: &str) -> Result<&[u8; 32], CryptoError> {
+        self.keys
+            .get(version)
+            .ok_or_else(|| CryptoError(format!("unknown key version {}", version)))
+    }
+}
+
+fn parse_keys(spec: &str) -> Result<HashMap<String, [u8; 32]>, CryptoError> {
+    spec.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (version, encoded) = pair
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| CryptoError("keys must be version:base64key".to_string()))?;
+            let key: [u8; 32] = BASE64
+                .decode(encoded)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| {
+                    CryptoError(format!("key {} must be 32 bytes of base64", version))
+                })?;
+            Ok((version.to_string(), key))
+        })
+        .collect()
+}
+
+#[axum::async_trait]
+impl KeyProvider for LocalKeyProvider {
+    fn current_version(&self) -> String {
+        self.current.clone()
+    }
+
+    async fn generate_data_key(&self) -> Result<DataKey, CryptoError> {
+        let mut plaintext = [0u8; 32];
+        OsRng.fill_bytes(&mut plaintext);
+        let wrapped = seal(
+            self.key(&self.current)?,
+            &plaintext,
+            self.current.as_bytes(),
+        )?;
+        Ok(DataKey { plaintext, wrapped })
+    }
+
+    async fn unwrap_data_key(
+        &self,
+        version: &str,
+        wrapped: &[u8],
+    ) -> Result<[u8; 32], CryptoError> {
+        open(self.key(version)?, wrapped, version.as_bytes())?
+            .try_into()
+            .map_err(|_| CryptoError("data key has the wrong length".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versioned_keys() {
+        let key = BASE64.encode([1u8; 32]);
+        let keys = parse_keys(&format!("v1:{key}, v2:{key}")).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(parse_keys("v1:c2hvcnQ=").is_err());
+    }
+
+    #[tokio::test]
+    async fn older_versions_still_unwrap_after_rotation() {
+        let keys = HashMap::from([("v1".to_string(), [1u8; 32]), ("v2".to_string(), [2u8; 32])]);
+        let old = LocalKeyProvider::new("v1", keys.clone());
+        let data_key = old.generate_data_key().await.unwrap();
+
+        let rotated = LocalKeyProvider::new("v2", keys);
+        assert_eq!(
+            rotated
+                .unwrap_data_key("v1", &data_key.wrapped)
+                .await
+                .unwrap(),
+            data_key.plaintext
+        );
+        assert!(rotated
+            .unwrap_data_key("v2", &data_key.wrapped)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,319 @@
+pub mod kms;
+pub mod local;
+pub mod rotation;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use aws_sdk_dynamodb::types::AttributeValue;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+/// AES-GCM nonce length in bytes.
+const NONCE_LEN: usize = 12;
+
+// ── Attribute names on ovaflus-plaid-items ──
+
+pub const ATTR_CIPHERTEXT: &str = "access_token_ciphertext";
+pub const ATTR_WRAPPED_KEY: &str = "access_token_key";
+pub const ATTR_KEY_VERSION: &str = "key_version";
+/// Plaintext token on rows written before encryption; removed by the re-encryption tool.
+pub const ATTR_LEGACY_PLAINTEXT: &str = "access_token";
+
+/// Errors never carry key material or plaintext, so they're safe to log and return.
+#[derive(Debug)]
+pub struct CryptoError(pub String);
+
+impl std::fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Token encryption error: {}", self.0)
+    }
+}
+
+/// A secret string, such as a Plaid access token. `Debug` and `Display` are redacted so it
+/// can't end up in logs or error messages; serde reads and writes the real value for API calls.
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl serde::Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Secret {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer).map(Secret)
+    }
+}
+
+/// A fresh 256-bit data key, and the same key wrapped by the key provider.
+pub struct DataKey {
+    pub plaintext: [u8; 32],
+    pub wrapped: Vec<u8>,
+}
+
+impl std::fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DataKey([redacted])")
+    }
+}
+
+/// Source of data keys for envelope encryption: KMS in production, a static key locally.
+#[axum::async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// Version tag stored next to every token encrypted with a key from this provider.
+    fn current_version(&self) -> String;
+
+    async fn generate_data_key(&self) -> Result<DataKey, CryptoError>;
+
+    /// Recover a data key wrapped under `version`, which may be an older key than the current one.
+    async fn unwrap_data_key(&self, version: &str, wrapped: &[u8])
+        -> Result<[u8; 32], CryptoError>;
+}
+
+/// An access token as stored: encrypted under its own data key, with the data key wrapped by
+/// the key provider. All fields are base64 except the version.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptedToken {
+    pub ciphertext: String,
+    pub wrapped_key: String,
+    pub key_version: String,
+}
+
+impl EncryptedToken {
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        Some(EncryptedToken {
+            ciphertext: s(ATTR_CIPHERTEXT)?,
+            wrapped_key: s(ATTR_WRAPPED_KEY)?,
+            key_version: s(ATTR_KEY_VERSION)?,
+        })
+    }
+
+    pub fn attributes(&self) -> [(&'static str, AttributeValue); 3] {
+        [
+            (ATTR_CIPHERTEXT, AttributeValue::S(self.ciphertext.clone())),
+            (
+                ATTR_WRAPPED_KEY,
+                AttributeValue::S(self.wrapped_key.clone()),
+            ),
+            (
+                ATTR_KEY_VERSION,
+                AttributeValue::S(self.key_version.clone()),
+            ),
+        ]
+    }
+}
+
+/// Binds a ciphertext to its row so it can't be copied onto another user's item.
+pub fn token_context(user_id: &str, item_id: &str) -> String {
+    format!("{}#{}", user_id, item_id)
+}
+
+/// AES-256-GCM with a random nonce; returns nonce || ciphertext.
+pub(crate) fn seal(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| CryptoError("encryption failed".to_string()))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+pub(crate) fn open(key: &[u8; 32], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(CryptoError("ciphertext is truncated".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| CryptoError("decryption failed".to_string()))
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, CryptoError> {
+    BASE64
+        .decode(value)
+        .map_err(|_| CryptoError(format!("{} is not valid base64", field)))
+}
+
+/// Envelope encryption of Plaid access tokens.
+#[derive(Clone)]
+pub struct TokenCipher {
+    provider: Arc<dyn KeyProvider>,
+}
+
+impl TokenCipher {
+    pub fn new(provider: Arc<dyn KeyProvider>) -> Self {
+        Self { provider }
+    }
+
+    /// KMS when `TOKEN_KMS_KEY_ID` is set, otherwise the static keys in `TOKEN_LOCAL_KEYS`.
+    pub fn from_env(config: &aws_config::SdkConfig) -> Self {
+        match std::env::var("TOKEN_KMS_KEY_ID") {
+            Ok(key_id) if !key_id.is_empty() => Self::new(Arc::new(kms::KmsKeyProvider::new(
+                aws_sdk_kms::Client::new(config),
+                key_id,
+            ))),
+            _ => Self::new(Arc::new(
+                local::LocalKeyProvider::from_env()
+                    .unwrap_or_else(|e| panic!("Failed to configure token encryption: {e}")),
+            )),
+        }
+    }
+
+    pub fn current_version(&self) -> String {
+        self.provider.current_version()
+    }
+
+    pub async fn encrypt(
+        &self,
+        token: &Secret,
+        context: &str,
+    ) -> Result<EncryptedToken, CryptoError> {
+        let data_key = self.provider.generate_data_key().await?;
+        let sealed = seal(
+            &data_key.plaintext,
+            token.expose().as_bytes(),
+            context.as_bytes(),
+        )?;
+        Ok(EncryptedToken {
+            ciphertext: BASE64.encode(sealed),
+            wrapped_key: BASE64.encode(&data_key.wrapped),
+            key_version: self.provider.current_version(),
+        })
+    }
+
+    pub async fn decrypt(
+        &self,
+        token: &EncryptedToken,
+        context: &str,
+    ) -> Result<Secret, CryptoError> {
+        let wrapped = decode(ATTR_WRAPPED_KEY, &token.wrapped_key)?;
+        let data_key = self
+            .provider
+            .unwrap_data_key(&token.key_version, &wrapped)
+            .await?;
+        let plaintext = open(
+            &data_key,
+            &decode(ATTR_CIPHERTEXT, &token.ciphertext)?,
+            context.as_bytes(),
+        )?;
+        String::from_utf8(plaintext)
+            .map(Secret)
+            .map_err(|_| CryptoError("token is not valid UTF-8".to_string()))
+    }
+
+    /// Read the access token off a plaid-items row, falling back to the legacy plaintext column.
+    pub async fn item_token(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+        context: &str,
+    ) -> Result<Secret, CryptoError> {
+        if let Some(encrypted) = EncryptedToken::from_item(item) {
+            return self.decrypt(&encrypted, context).await;
+        }
+        item.get(ATTR_LEGACY_PLAINTEXT)
+            .and_then(|v| v.as_s().ok())
+            .map(|t| Secret::new(t.clone()))
+            .ok_or_else(|| CryptoError("item has no access token".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> TokenCipher {
+        TokenCipher::new(Arc::new(local::LocalKeyProvider::new(
+            "v1",
+            HashMap::from([("v1".to_string(), [7u8; 32])]),
+        )))
+    }
+
+    #[tokio::test]
+    async fn round_trips_and_stores_no_plaintext() {
+        let cipher = cipher();
+        let token = Secret::new("access-sandbox-1234");
+        let encrypted = cipher.encrypt(&token, "user-1#item-1").await.unwrap();
+
+        assert_eq!(encrypted.key_version, "v1");
+        assert!(!encrypted.ciphertext.contains("access-sandbox"));
+        assert_eq!(
+            cipher.decrypt(&encrypted, "user-1#item-1").await.unwrap(),
+            token
+        );
+    }
+
+    #[tokio::test]
+    async fn ciphertext_is_bound_to_its_row() {
+        let cipher = cipher();
+        let encrypted = cipher
+            .encrypt(&Secret::new("access-sandbox-1234"), "user-1#item-1")
+            .await
+            .unwrap();
+        assert!(cipher.decrypt(&encrypted, "user-2#item-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn legacy_plaintext_rows_are_still_readable() {
+        let item = HashMap::from([(
+            ATTR_LEGACY_PLAINTEXT.to_string(),
+            AttributeValue::S("access-sandbox-legacy".to_string()),
+        )]);
+        let token = cipher().item_token(&item, "user-1#item-1").await.unwrap();
+        assert_eq!(token.expose(), "access-sandbox-legacy");
+    }
+
+    #[test]
+    fn secrets_are_redacted_when_formatted() {
+        let secret = Secret::new("access-sandbox-1234");
+        assert!(!format!("{secret:?}").contains("access"));
+        assert!(!format!("{secret}").contains("access"));
+        assert_eq!(
+            serde_json::to_string(&secret).unwrap(),
+            "\"access-sandbox-1234\""
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
+use serde::Serialize;
+
+use super::{token_context, TokenCipher, ATTR_KEY_VERSION, ATTR_LEGACY_PLAINTEXT};
+
+#[derive(Debug, Default, Serialize)]
+pub struct RotationReport {
+    pub scanned: usize,
+    pub reencrypted: usize,
+    pub failed: usize,
+}
+
+/// Rows that hold a plaintext token or a token under an older key version.
+fn needs_rotation(item: &HashMap<String, AttributeValue>, current_version: &str) -> bool {
+    if item.contains_key(ATTR_LEGACY_PLAINTEXT) {
+        return true;
+    }
+    match item.get(ATTR_KEY_VERSION).and_then(|v| v.as_s().ok()) {
+        Some(version) => version != current_version,
+        None => false,
+    }
+}
+
+/// Re-encrypt every Plaid access token under the current key, including legacy plaintext rows.
+///
+/// Run after rotating keys by invoking the jobs function with `{"job": "reencrypt-tokens"}`,
+/// or locally with `bootstrap reencrypt-tokens`. Each row is updated on the
+/// condition that it hasn't changed since it was read, so the tool is safe to run alongside
+/// the API and to re-run after a partial failure.
+pub async fn reencrypt_plaid_items(dynamo: &DynamoClient, cipher: &TokenCipher) -> RotationReport {
+    let current_version = cipher.current_version();
+    let mut report = RotationReport::default();
+    let mut start_key = None;
+
+    loop {
+        let output = match dynamo
+            .scan()
+            .table_name("ovaflus-plaid-items")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::error!("Scanning Plaid items failed: {e}");
+                report.failed += 1;
+                return report;
+            }
+        };
+
+        for item in output.items.unwrap_or_default() {
+            report.scanned += 1;
+            if !needs_rotation(&item, &current_version) {
+                continue;
+            }
+            let s = |name: &str| {
+                item.get(name)
+                    .and_then(|v| v.as_s().ok())
+                    .cloned()
+                    .unwrap_or_default()
+            };
+            let (user_id, item_id) = (s("user_id"), s("item_id"));
+            let context = token_context(&user_id, &item_id);
+
+            let encrypted = match cipher.item_token(&item, &context).await {
+                Ok(token) => cipher.encrypt(&token, &context).await,
+                Err(e) => Err(e),
+            };
+            let encrypted = match encrypted {
+                Ok(encrypted) => encrypted,
+                Err(e) => {
+                    tracing::error!("Re-encrypting Plaid item {item_id} failed: {e}");
+                    report.failed += 1;
+                    continue;
+                }
+            };
+
+            let update = dynamo
+                .update_item()
+                .table_name("ovaflus-plaid-items")
+                .key("user_id", AttributeValue::S(user_id))
+                .key("item_id", AttributeValue::S(item_id.clone()))
+                .update_expression(
+                    "SET access_token_ciphertext = :ciphertext, access_token_key = :wrapped, \
+                     key_version = :version REMOVE access_token",
+                )
+                .expression_attribute_values(":ciphertext", AttributeValue::S(encrypted.ciphertext))
+                .expression_attribute_values(":wrapped", AttributeValue::S(encrypted.wrapped_key))
+                .expression_attribute_values(":version", AttributeValue::S(encrypted.key_version));
+            let update = match item.get(ATTR_KEY_VERSION) {
+                Some(old) if !item.contains_key(ATTR_LEGACY_PLAINTEXT) => update
+                    .condition_expression("key_version = :old_version")
+                    .expression_attribute_values(":old_version", old.clone()),
+                _ => update.condition_expression("attribute_exists(access_token)"),
+            };
+
+            match update.send().await {
+                Ok(_) => report.reencrypted += 1,
+                Err(e) => {
+                    tracing::error!("Storing re-encrypted Plaid item {item_id} failed: {e}");
+                    report.failed += 1;
+                }
+            }
+        }
+
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plaintext_and_outdated_rows_are_rotated() {
+        let row = |attrs: &[(&str, &str)]| -> HashMap<String, AttributeValue> {
+            attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), AttributeValue::S(v.to_string())))
+                .collect()
+        };
+
+        assert!(needs_rotation(
+            &row(&[(ATTR_LEGACY_PLAINTEXT, "access-sandbox")]),
+            "v2"
+        ));
+        assert!(needs_rotation(&row(&[(ATTR_KEY_VERSION, "v1")]), "v2"));
+        assert!(!needs_rotation(&row(&[(ATTR_KEY_VERSION, "v2")]), "v2"));
+        assert!(!needs_rotation(&row(&[]), "v2"));
+    }
+}
@@ -2,7 +2,17 @@
 
 use std::collections::HashMap;
 
-use aws_sdk_dynamodb::{types::AttributeValue, Client};
+use aws_sdk_dynamodb::{
+    types::{AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, WriteRequest},
+    Client,
+};
+
+/// DynamoDB's limit on requests in a single BatchWriteItem call.
+const MAX_BATCH_WRITE_ITEMS: usize = 25;
+/// DynamoDB's limit on keys in a single BatchGetItem call.
+const MAX_BATCH_GET_KEYS: usize = 100;
+/// Rounds of retrying throttled batch requests before giving up on them.
+const MAX_BATCH_WRITE_ATTEMPTS: u32 = 5;
 
 // ── Table Name Constants ──
 
@@ -10,9 +20,20 @@ pub const TABLE_USERS: &str = "ovaflus-users";
 pub const TABLE_BUDGETS: &str = "ovaflus-budgets";
 pub const TABLE_TRANSACTIONS: &str = "ovaflus-transactions";
 pub const TABLE_PORTFOLIO: &str = "ovaflus-portfolio";
+pub const TABLE_PORTFOLIO_TRADES: &str = "ovaflus-portfolio-trades";
+pub const TABLE_ALLOCATION_TARGETS: &str = "ovaflus-allocation-targets";
 pub const TABLE_WATCHLIST: &str = "ovaflus-watchlist";
 pub const TABLE_GOALS: &str = "ovaflus-goals";
 pub const TABLE_PLAID_ITEMS: &str = "ovaflus-plaid-items";
+pub const TABLE_LIABILITIES: &str = "ovaflus-liabilities";
+pub const TABLE_BALANCE_SNAPSHOTS: &str = "ovaflus-balance-snapshots";
+pub const TABLE_CATEGORY_MAPPINGS: &str = "ovaflus-category-mappings";
+pub const TABLE_RECURRING: &str = "ovaflus-recurring";
+pub const TABLE_SUBSCRIPTIONS: &str = "ovaflus-subscriptions";
+pub const TABLE_RULES: &str = "ovaflus-rules";
+pub const TABLE_CATEGORY_MODELS: &str = "ovaflus-category-models";
+pub const TABLE_MERCHANTS: &str = "ovaflus-merchants";
+pub const TABLE_IMPORT_MAPPINGS: &str = "ovaflus-import-mappings";
 
 // ── Helper: extract String from AttributeValue ──
 
@@ -24,6 +45,18 @@ pub fn attr_n(av: &AttributeValue) -> Option<f64> {
     av.as_n().ok().and_then(|s| s.parse::<f64>().ok())
 }
 
+// ── Helper: a failed condition expression ──
+
+/// Whether a write was rejected by its `condition_expression`.
+pub fn is_conditional_check_failure<E, R>(err: &aws_sdk_dynamodb::error::SdkError<E, R>) -> bool
+where
+    E: aws_sdk_dynamodb::error::ProvideErrorMetadata,
+{
+    err.as_service_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == "ConditionalCheckFailedException")
+}
+
 // ── Put Item ──
 
 pub async fn put_item(
@@ -65,7 +98,7 @@ pub async fn get_item(
     Ok(result.item)
 }
 
-// ── Query by Partition Key ──
+// ── Query by Partition Key (all pages) ──
 
 pub async fn query_by_pk(
     client: &Client,
@@ -73,16 +106,25 @@ pub async fn query_by_pk(
     pk: &str,
     pk_val: &str,
 ) -> Result<Vec<HashMap<String, AttributeValue>>, aws_sdk_dynamodb::Error> {
-    let result = client
-        .query()
-        .table_name(table)
-        .key_condition_expression("#pk = :pk_val")
-        .expression_attribute_names("#pk", pk)
-        .expression_attribute_values(":pk_val", AttributeValue::S(pk_val.to_string()))
-        .send()
-        .await?;
-
-    Ok(result.items.unwrap_or_default())
+    let mut items = Vec::new();
+    let mut start_key = None;
+    loop {
+        let result = client
+            .query()
+            .table_name(table)
+            .key_condition_expression("#pk = :pk_val")
+            .expression_attribute_names("#pk", pk)
+            .expression_attribute_values(":pk_val", AttributeValue::S(pk_val.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+
+        items.extend(result.items.unwrap_or_default());
+        start_key = result.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(items);
+        }
+    }
 }
 
 // ── Delete Item ──
@@ -153,6 +195,121 @@ pub async fn update_item(
     Ok(())
 }
 
+// ── Batch Write ──
+
+pub fn put_request(item: HashMap<String, AttributeValue>) -> WriteRequest {
+    WriteRequest::builder()
+        .put_request(
+            PutRequest::builder()
+                .set_item(Some(item))
+                .build()
+                .expect("item is set"),
+        )
+        .build()
+}
+
+pub fn delete_request(key: HashMap<String, AttributeValue>) -> WriteRequest {
+    WriteRequest::builder()
+        .delete_request(
+            DeleteRequest::builder()
+                .set_key(Some(key))
+                .build()
+                .expect("key is set"),
+        )
+        .build()
+}
+
+/// Write requests to one table in batches of 25, retrying what DynamoDB leaves unprocessed.
+///
+/// Returns how many requests were still unprocessed after the last attempt.
+pub async fn batch_write(
+    client: &Client,
+    table: &str,
+    requests: Vec<WriteRequest>,
+) -> Result<usize, aws_sdk_dynamodb::Error> {
+    Ok(batch_write_unprocessed(client, table, requests)
+        .await?
+        .len())
+}
+
+/// `batch_write`, returning the requests still unprocessed after the last attempt.
+pub async fn batch_write_unprocessed(
+    client: &Client,
+    table: &str,
+    requests: Vec<WriteRequest>,
+) -> Result<Vec<WriteRequest>, aws_sdk_dynamodb::Error> {
+    let mut unprocessed = Vec::new();
+    for chunk in requests.chunks(MAX_BATCH_WRITE_ITEMS) {
+        let mut pending = chunk.to_vec();
+        for attempt in 0..MAX_BATCH_WRITE_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(50 * 2u64.pow(attempt))).await;
+            }
+            let output = client
+                .batch_write_item()
+                .request_items(table, pending)
+                .send()
+                .await?;
+            pending = output
+                .unprocessed_items
+                .and_then(|mut items| items.remove(table))
+                .unwrap_or_default();
+            if pending.is_empty() {
+                break;
+            }
+        }
+        unprocessed.extend(pending);
+    }
+    Ok(unprocessed)
+}
+
+// ── Batch Get ──
+
+/// Read items from one table by key in batches of 100, retrying what DynamoDB leaves
+/// unprocessed. Items that don't exist are left out; the order is not kept.
+pub async fn batch_get(
+    client: &Client,
+    table: &str,
+    keys: Vec<HashMap<String, AttributeValue>>,
+) -> Result<Vec<HashMap<String, AttributeValue>>, aws_sdk_dynamodb::Error> {
+    let mut items = Vec::new();
+    for chunk in keys.chunks(MAX_BATCH_GET_KEYS) {
+        let mut pending = chunk.to_vec();
+        for attempt in 0..MAX_BATCH_WRITE_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(50 * 2u64.pow(attempt))).await;
+            }
+            let request = KeysAndAttributes::builder()
+                .set_keys(Some(pending))
+                .build()
+                .expect("keys are set");
+            let output = client
+                .batch_get_item()
+                .request_items(table, request)
+                .send()
+                .await?;
+            items.extend(
+                output
+                    .responses
+                    .and_then(|mut responses| responses.remove(table))
+                    .unwrap_or_default(),
+            );
+            pending = output
+                .unprocessed_keys
+                .and_then(|mut unprocessed| unprocessed.remove(table))
+                .map(|k| k.keys)
+                .unwrap_or_default();
+            if pending.is_empty() {
+                break;
+            }
+        }
+        if !pending.is_empty() {
+            tracing::warn!("{} keys of {} were left unread", pending.len(), table);
+        }
+    }
+    Ok(items)
+}
+
 // ── Query with Index ──
 
 pub async fn query_by_index(
@@ -197,10 +354,10 @@ mod tests {
 
     #[test]
     fn attr_n_returns_f64_for_n_variant() {
-        let av = AttributeValue::N("3.14".to_string());
+        let av = AttributeValue::N("2.75".to_string());
         let result = attr_n(&av);
         assert!(result.is_some());
-        assert!((result.unwrap() - 3.14).abs() < f64::EPSILON);
+        assert!((result.unwrap() - 2.75).abs() < f64::EPSILON);
     }
 
     #[test]
@@ -221,8 +378,19 @@ mod tests {
         assert_eq!(TABLE_BUDGETS, "ovaflus-budgets");
         assert_eq!(TABLE_TRANSACTIONS, "ovaflus-transactions");
         assert_eq!(TABLE_PORTFOLIO, "ovaflus-portfolio");
+        assert_eq!(TABLE_PORTFOLIO_TRADES, "ovaflus-portfolio-trades");
+        assert_eq!(TABLE_ALLOCATION_TARGETS, "ovaflus-allocation-targets");
         assert_eq!(TABLE_WATCHLIST, "ovaflus-watchlist");
         assert_eq!(TABLE_GOALS, "ovaflus-goals");
         assert_eq!(TABLE_PLAID_ITEMS, "ovaflus-plaid-items");
+        assert_eq!(TABLE_LIABILITIES, "ovaflus-liabilities");
+        assert_eq!(TABLE_BALANCE_SNAPSHOTS, "ovaflus-balance-snapshots");
+        assert_eq!(TABLE_CATEGORY_MAPPINGS, "ovaflus-category-mappings");
+        assert_eq!(TABLE_RECURRING, "ovaflus-recurring");
+        assert_eq!(TABLE_SUBSCRIPTIONS, "ovaflus-subscriptions");
+        assert_eq!(TABLE_RULES, "ovaflus-rules");
+        assert_eq!(TABLE_CATEGORY_MODELS, "ovaflus-category-models");
+        assert_eq!(TABLE_MERCHANTS, "ovaflus-merchants");
+        assert_eq!(TABLE_IMPORT_MAPPINGS, "ovaflus-import-mappings");
     }
 }
@@ -1 +1,2 @@
 pub mod dynamo;
+pub mod transaction_keys;
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
+use chrono::NaiveDate;
+
+use crate::db::dynamo::{batch_write, is_conditional_check_failure, put_request};
+
+/// GSI over `user_id` + `date_key`: a user's transactions in date order.
+pub const USER_DATE_INDEX: &str = "user-date-index";
+/// GSI over `budget_key` + `date_key`: one budget's transactions in date order.
+pub const BUDGET_DATE_INDEX: &str = "budget-date-index";
+
+pub const ATTR_DATE_KEY: &str = "date_key";
+pub const ATTR_BUDGET_KEY: &str = "budget_key";
+/// Names the transaction a split-index row stands for.
+pub const ATTR_SPLIT_OF: &str = "split_of";
+
+/// `YYYY-MM-DD#transaction_id`. Only the day is kept, so dates stored with a time still sort
+/// by day, and the id makes keys unique and gives a stable order within a day.
+pub fn date_key(date: &str, transaction_id: &str) -> String {
+    let day = date.get(..10).unwrap_or(date);
+    format!("{}#{}", day, transaction_id)
+}
+
+/// The transaction id at the end of a date key.
+pub fn date_key_transaction_id(date_key: &str) -> Option<&str> {
+    date_key
+        .split_once('#')
+        .map(|(_, id)| id)
+        .filter(|id| !id.is_empty())
+}
+
+/// Partition key of the budget index; budgets ids are only unique per user.
+pub fn budget_key(user_id: &str, budget_id: &str) -> String {
+    format!("{}#{}", user_id, budget_id)
+}
+
+/// Partition of a user's split-index rows, apart from their transactions so listings and
+/// the user date index never see them.
+pub fn split_index_pk(user_id: &str) -> String {
+    format!("{}#splits", user_id)
+}
+
+/// Sort key of the split-index row for one budget a split transaction has a part in.
+pub fn split_index_id(transaction_id: &str, budget_id: &str) -> String {
+    format!("{}#{}", transaction_id, budget_id)
+}
+
+/// A row that puts a split transaction into the budget indexes of a budget one of its splits
+/// counts towards, whatever budget the transaction itself is assigned to. Its `date_key`
+/// ends in its own id, so a cursor taken at it resumes right after it.
+pub fn split_index_row(
+    user_id: &str,
+    transaction_id: &str,
+    budget_id: &str,
+    date: &str,
+) -> HashMap<String, AttributeValue> {
+    let id = split_index_id(transaction_id, budget_id);
+    HashMap::from([
+        (
+            "user_id".to_string(),
+            AttributeValue::S(split_index_pk(user_id)),
+        ),
+        ("transaction_id".to_string(), AttributeValue::S(id.clone())),
+        (
+            ATTR_SPLIT_OF.to_string(),
+            AttributeValue::S(transaction_id.to_string()),
+        ),
+        (
+            "budget_id".to_string(),
+            AttributeValue::S(budget_id.to_string()),
+        ),
+        (
+            ATTR_BUDGET_KEY.to_string(),
+            AttributeValue::S(budget_key(user_id, budget_id)),
+        ),
+        (
+            ATTR_DATE_KEY.to_string(),
+            AttributeValue::S(date_key(date, &id)),
+        ),
+    ])
+}
+
+/// Whether a date key was taken at a split-index row rather than a transaction.
+pub fn is_split_index_key(date_key: &str) -> bool {
+    date_key_transaction_id(date_key).is_some_and(|id| id.contains('#'))
+}
+
+/// Bounds on `date_key` covering every transaction from `from` through `to`, inclusive.
+/// `$` sorts right after `#`, so the upper bound sits just past the last key of `to`.
+pub fn date_key_bounds(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> (Option<String>, Option<String>) {
+    (
+        from.map(|d| d.format("%Y-%m-%d").to_string()),
+        to.map(|d| format!("{}$", d.format("%Y-%m-%d"))),
+    )
+}
+
+/// The key attributes a row should carry, given its other attributes. Rows without a budget
+/// have no `budget_key` and so stay out of the budget index.
+fn expected_keys(item: &HashMap<String, AttributeValue>) -> (String, Option<String>) {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok());
+    let user_id = s("user_id").map(String::as_str).unwrap_or_default();
+    let transaction_id = s("transaction_id").map(String::as_str).unwrap_or_default();
+    let date = s("date").map(String::as_str).unwrap_or_default();
+    let budget = s("budget_id")
+        .filter(|id| !id.is_empty())
+        .map(|id| budget_key(user_id, id));
+    (date_key(date, transaction_id), budget)
+}
+
+fn needs_backfill(item: &HashMap<String, AttributeValue>) -> bool {
+    if item.contains_key(ATTR_SPLIT_OF) {
+        return false;
+    }
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    let (date_key, budget_key) = expected_keys(item);
+    s(ATTR_DATE_KEY) != Some(date_key) || s(ATTR_BUDGET_KEY) != budget_key
+}
+
+/// Split-index rows for a transaction split before they were written, from the
+/// `split_budgets` set such rows carry.
+fn legacy_split_rows(
+    item: &HashMap<String, AttributeValue>,
+) -> Vec<HashMap<String, AttributeValue>> {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok());
+    let (Some(user_id), Some(transaction_id), Some(date)) =
+        (s("user_id"), s("transaction_id"), s("date"))
+    else {
+        return Vec::new();
+    };
+    item.get("split_budgets")
+        .and_then(|v| v.as_ss().ok())
+        .into_iter()
+        .flatten()
+        .map(|budget_id| split_index_row(user_id, transaction_id, budget_id, date))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct BackfillReport {
+    pub scanned: usize,
+    pub updated: usize,
+    /// Rows changed by the API between the scan and the update, which wrote the keys itself
+    pub skipped: usize,
+    pub failed: usize,
+    /// Split-index rows written for split transactions stored before the index existed
+    pub split_rows: usize,
+}
+
+impl BackfillReport {
+    pub fn absorb(&mut self, other: BackfillReport) {
+        self.scanned += other.scanned;
+        self.updated += other.updated;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.split_rows += other.split_rows;
+    }
+}
+
+/// Write `date_key` and `budget_key` on every row of one scan segment that lacks them or has
+/// stale ones.
+///
+/// Each update is conditional on the row's date and budget being unchanged since the scan,
+/// so the backfill can run while the API keeps writing and can be re-run after a failure.
+pub async fn backfill_transaction_keys(
+    dynamo: &DynamoClient,
+    segment: i32,
+    total_segments: i32,
+    dry_run: bool,
+) -> BackfillReport {
+    let mut report = BackfillReport::default();
+    let mut start_key = None;
+
+    loop {
+        let output = match dynamo
+            .scan()
+            .table_name("ovaflus-transactions")
+            .segment(segment)
+            .total_segments(total_segments)
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::error!("Scanning transactions segment {segment} failed: {e}");
+                report.failed += 1;
+                return report;
+            }
+        };
+
+        for item in output.items.unwrap_or_default() {
+            report.scanned += 1;
+            let split_rows = legacy_split_rows(&item);
+            report.split_rows += split_rows.len();
+            if !dry_run && !split_rows.is_empty() {
+                let requests = split_rows.into_iter().map(put_request).collect();
+                match batch_write(dynamo, "ovaflus-transactions", requests).await {
+                    Ok(0) => {}
+                    Ok(_) | Err(_) => report.failed += 1,
+                }
+            }
+            if !needs_backfill(&item) {
+                continue;
+            }
+            if dry_run {
+                report.updated += 1;
+                continue;
+            }
+
+            let (date_key, budget_key) = expected_keys(&item);
+            let key = |name: &str| {
+                item.get(name)
+                    .cloned()
+                    .unwrap_or(AttributeValue::S(String::new()))
+            };
+            let update = dynamo
+                .update_item()
+                .table_name("ovaflus-transactions")
+                .key("user_id", key("user_id"))
+                .key("transaction_id", key("transaction_id"))
+                .expression_attribute_names("#d", "date")
+                .expression_attribute_values(":date_key", AttributeValue::S(date_key))
+                .expression_attribute_values(":date", key("date"));
+            let update = match budget_key {
+                Some(budget_key) => update
+                    .update_expression("SET date_key = :date_key, budget_key = :budget_key")
+                    .condition_expression("#d = :date AND budget_id = :budget_id")
+                    .expression_attribute_values(":budget_key", AttributeValue::S(budget_key))
+                    .expression_attribute_values(":budget_id", key("budget_id")),
+                None => update
+                    .update_expression("SET date_key = :date_key REMOVE budget_key")
+                    .condition_expression(
+                        "#d = :date AND (attribute_not_exists(budget_id) OR budget_id = :empty)",
+                    )
+                    .expression_attribute_values(":empty", AttributeValue::S(String::new())),
+            };
+
+            match update.send().await {
+                Ok(_) => report.updated += 1,
+                Err(e) if is_conditional_check_failure(&e) => report.skipped += 1,
+                Err(e) => {
+                    tracing::error!("Backfilling transaction keys failed: {e}");
+                    report.failed += 1;
+                }
+            }
+        }
+
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(attrs: &[(&str, &str)]) -> HashMap<String, AttributeValue> {
+        attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), AttributeValue::S(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn date_keys_order_by_day_and_bound_inclusive_ranges() {
+        assert_eq!(date_key("2024-05-31", "t1"), "2024-05-31#t1");
+        assert_eq!(date_key("2024-05-31T18:30:00Z", "t1"), "2024-05-31#t1");
+        assert_eq!(
+            date_key_transaction_id("2024-05-31#plaid-abc"),
+            Some("plaid-abc")
+        );
+        assert_eq!(date_key_transaction_id("2024-05-31"), None);
+
+        let day = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok();
+        let (from, to) = date_key_bounds(day("2024-05-01"), day("2024-05-31"));
+        let (from, to) = (from.unwrap(), to.unwrap());
+        for inside in ["2024-05-01#a", "2024-05-15#b", "2024-05-31#zzz"] {
+            assert!(from.as_str() <= inside && inside <= to.as_str(), "{inside}");
+        }
+        for outside in ["2024-04-30#z", "2024-06-01#a"] {
+            assert!(
+                !(from.as_str() <= outside && outside <= to.as_str()),
+                "{outside}"
+            );
+        }
+    }
+
+    #[test]
+    fn rows_missing_or_with_stale_keys_are_backfilled() {
+        let base = [
+            ("user_id", "u1"),
+            ("transaction_id", "t1"),
+            ("date", "2024-05-02"),
+            ("budget_id", "b1"),
+        ];
+        assert!(needs_backfill(&row(&base)));
+
+        let mut current = base.to_vec();
+        current.push((ATTR_DATE_KEY, "2024-05-02#t1"));
+        current.push((ATTR_BUDGET_KEY, "u1#b1"));
+        assert!(!needs_backfill(&row(&current)));
+
+        // The date was edited before the API wrote date keys
+        let mut stale = current.clone();
+        stale[2] = ("date", "2024-05-03");
+        assert!(needs_backfill(&row(&stale)));
+
+        let unbudgeted = [
+            ("user_id", "u1"),
+            ("transaction_id", "t2"),
+            ("date", "2024-05-02"),
+            (ATTR_DATE_KEY, "2024-05-02#t2"),
+        ];
+        assert!(!needs_backfill(&row(&unbudgeted)));
+        let mut moved_out = unbudgeted.to_vec();
+        moved_out.push((ATTR_BUDGET_KEY, "u1#b1"));
+        assert!(needs_backfill(&row(&moved_out)));
+    }
+
+    #[test]
+    fn split_transactions_get_split_index_rows_and_those_are_left_alone() {
+        let mut split = row(&[
+            ("user_id", "u1"),
+            ("transaction_id", "t1"),
+            ("date", "2024-05-02"),
+        ]);
+        split.insert(
+            "split_budgets".to_string(),
+            AttributeValue::Ss(vec!["food".to_string(), "home".to_string()]),
+        );
+        let rows = legacy_split_rows(&split);
+        assert_eq!(rows.len(), 2);
+        let index_row = &rows[0];
+        assert_eq!(
+            index_row["user_id"],
+            AttributeValue::S("u1#splits".to_string())
+        );
+        assert_eq!(
+            index_row[ATTR_BUDGET_KEY],
+            AttributeValue::S("u1#food".to_string())
+        );
+        assert!(!needs_backfill(index_row));
+        assert!(legacy_split_rows(index_row).is_empty());
+
+        assert!(is_split_index_key("2024-05-02#t1#food"));
+        assert!(!is_split_index_key("2024-05-02#t1"));
+    }
+}
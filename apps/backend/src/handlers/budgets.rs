@@ -98,27 +98,33 @@ fn item_to_budget(item: &std::collections::HashMap<String, AttributeValue>) -> B
     }
 }
 
-pub async fn list_budgets(
-    State(state): State<Arc<AppState>>,
-    AuthUser(claims): AuthUser,
-) -> impl IntoResponse {
-    let result = state
+pub async fn load_budgets(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<Budget>, aws_sdk_dynamodb::Error> {
+    let output = state
         .dynamo
         .query()
         .table_name("ovaflus-budgets")
         .key_condition_expression("user_id = :uid")
-        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
         .send()
-        .await;
+        .await?;
 
-    match result {
-        Ok(output) => {
-            let budgets: Vec<Budget> = output
-                .items
-                .unwrap_or_default()
-                .iter()
-                .map(item_to_budget)
-                .collect();
+    Ok(output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .map(item_to_budget)
+        .collect())
+}
+
+pub async fn list_budgets(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match load_budgets(&state, &claims.sub).await {
+        Ok(budgets) => {
             (StatusCode::OK, Json(serde_json::to_value(budgets).unwrap())).into_response()
         }
         Err(e) => (
@@ -311,3 +317,21 @@ pub async fn delete_budget(
             .into_response(),
     }
 }
+
+/// Whether the user has a budget with this id, for ids that other records point at.
+pub(crate) async fn budget_exists(
+    state: &AppState,
+    user_id: &str,
+    budget_id: &str,
+) -> Result<bool, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-budgets")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("budget_id", AttributeValue::S(budget_id.to_string()))
+        .projection_expression("budget_id")
+        .send()
+        .await?;
+    Ok(output.item.is_some())
+}
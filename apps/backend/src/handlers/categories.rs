@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::handlers::budgets::Budget;
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::plaid::types::Transaction;
+use crate::AppState;
+
+/// The app's transaction categories. Budgets are matched against these case-insensitively.
+pub const APP_CATEGORIES: &[&str] = &[
+    "food",
+    "dining",
+    "transport",
+    "shopping",
+    "entertainment",
+    "health",
+    "housing",
+    "bills",
+    "education",
+    "travel",
+    "income",
+    "transfer",
+    "other",
+];
+
+pub(crate) const FALLBACK_CATEGORY: &str = "other";
+
+/// Plaid `personal_finance_category.primary` values and their default app category.
+const PRIMARY_DEFAULTS: &[(&str, &str)] = &[
+    ("INCOME", "income"),
+    ("TRANSFER_IN", "transfer"),
+    ("TRANSFER_OUT", "transfer"),
+    ("LOAN_PAYMENTS", "bills"),
+    ("BANK_FEES", "bills"),
+    ("ENTERTAINMENT", "entertainment"),
+    ("FOOD_AND_DRINK", "dining"),
+    ("GENERAL_MERCHANDISE", "shopping"),
+    ("HOME_IMPROVEMENT", "housing"),
+    ("MEDICAL", "health"),
+    ("PERSONAL_CARE", "health"),
+    ("GENERAL_SERVICES", "other"),
+    ("GOVERNMENT_AND_NON_PROFIT", "other"),
+    ("TRANSPORTATION", "transport"),
+    ("TRAVEL", "travel"),
+    ("RENT_AND_UTILITIES", "bills"),
+];
+
+/// Detailed categories that don't belong with the rest of their primary category.
+const DETAILED_DEFAULTS: &[(&str, &str)] = &[
+    ("FOOD_AND_DRINK_GROCERIES", "food"),
+    ("RENT_AND_UTILITIES_RENT", "housing"),
+    ("LOAN_PAYMENTS_MORTGAGE_PAYMENT", "housing"),
+    ("GENERAL_SERVICES_EDUCATION", "education"),
+    ("GENERAL_SERVICES_INSURANCE", "bills"),
+    ("GENERAL_SERVICES_AUTOMOTIVE", "transport"),
+    ("GENERAL_SERVICES_TELECOMMUNICATION_SERVICES", "bills"),
+];
+
+/// Levels of the deprecated `category` hierarchy, for transactions Plaid hasn't given a
+/// personal finance category. The most specific level that appears here wins.
+const LEGACY_DEFAULTS: &[(&str, &str)] = &[
+    ("Supermarkets and Groceries", "food"),
+    ("Taxi", "transport"),
+    ("Public Transportation Services", "transport"),
+    ("Gas Stations", "transport"),
+    ("Rent", "housing"),
+    ("Utilities", "bills"),
+    ("Food and Drink", "dining"),
+    ("Shops", "shopping"),
+    ("Travel", "travel"),
+    ("Recreation", "entertainment"),
+    ("Arts and Entertainment", "entertainment"),
+    ("Healthcare", "health"),
+    ("Personal Care", "health"),
+    ("Education", "education"),
+    ("Service", "bills"),
+    ("Payment", "bills"),
+    ("Bank Fees", "bills"),
+    ("Tax", "bills"),
+    ("Transfer", "transfer"),
+    ("Community", "other"),
+];
+
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// The app category for a synced transaction. The user's overrides win over the defaults, and
+/// a detailed category wins over its primary one. Transactions without a personal finance
+/// category fall back to the legacy hierarchy.
+pub fn app_category(txn: &Transaction, overrides: &HashMap<String, String>) -> String {
+    if let Some(pfc) = &txn.personal_finance_category {
+        let found = overrides
+            .get(&pfc.detailed)
+            .or_else(|| overrides.get(&pfc.primary))
+            .cloned()
+            .or_else(|| lookup(DETAILED_DEFAULTS, &pfc.detailed).map(str::to_string))
+            .or_else(|| lookup(PRIMARY_DEFAULTS, &pfc.primary).map(str::to_string));
+        if let Some(category) = found {
+            return category;
+        }
+    }
+    txn.category
+        .iter()
+        .rev()
+        .find_map(|level| lookup(LEGACY_DEFAULTS, level))
+        .unwrap_or(FALLBACK_CATEGORY)
+        .to_string()
+}
+
+/// The budget a transaction in `category` counts towards: the first whose category matches,
+/// or failing that, the first with the category as a word of its name. The catch-all category
+/// only ever matches on the budget's category.
+pub fn match_budget<'a>(category: &str, budgets: &'a [Budget]) -> Option<&'a Budget> {
+    let category = category.trim().to_lowercase();
+    let by_category = budgets
+        .iter()
+        .find(|b| b.category.trim().to_lowercase() == category);
+    if by_category.is_some() || category.is_empty() || category == FALLBACK_CATEGORY {
+        return by_category;
+    }
+    budgets.iter().find(|b| {
+        b.name
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word == category)
+    })
+}
+
+// --- Category Mappings ---
+
+#[derive(Serialize, Debug)]
+pub struct CategoryMapping {
+    /// A Plaid primary or detailed personal finance category
+    pub plaid_category: String,
+    pub category: String,
+    /// Whether the user has overridden the default for this Plaid category
+    pub overridden: bool,
+}
+
+#[derive(Serialize)]
+pub struct CategoryMappings {
+    pub categories: &'static [&'static str],
+    pub mappings: Vec<CategoryMapping>,
+}
+
+#[derive(Deserialize)]
+pub struct SetMappingRequest {
+    pub category: String,
+}
+
+fn normalize_plaid_category(value: &str) -> Result<String, String> {
+    let code = value.trim().to_uppercase();
+    let known = PRIMARY_DEFAULTS
+        .iter()
+        .any(|(primary, _)| code == *primary || code.starts_with(&format!("{}_", primary)));
+    if known {
+        Ok(code)
+    } else {
+        Err(format!("Unknown Plaid category: {}", value))
+    }
+}
+
+fn normalize_app_category(value: &str) -> Result<String, String> {
+    let category = value.trim().to_lowercase();
+    if APP_CATEGORIES.contains(&category.as_str()) {
+        Ok(category)
+    } else {
+        Err(format!("Unknown category: {}", value))
+    }
+}
+
+/// Defaults merged with the user's overrides, sorted by Plaid category.
+fn merge_mappings(overrides: &HashMap<String, String>) -> Vec<CategoryMapping> {
+    let mut merged: HashMap<String, CategoryMapping> = PRIMARY_DEFAULTS
+        .iter()
+        .chain(DETAILED_DEFAULTS)
+        .map(|(code, category)| {
+            let mapping = CategoryMapping {
+                plaid_category: code.to_string(),
+                category: category.to_string(),
+                overridden: false,
+            };
+            (code.to_string(), mapping)
+        })
+        .collect();
+    for (code, category) in overrides {
+        let mapping = CategoryMapping {
+            plaid_category: code.clone(),
+            category: category.clone(),
+            overridden: true,
+        };
+        merged.insert(code.clone(), mapping);
+    }
+
+    let mut mappings: Vec<CategoryMapping> = merged.into_values().collect();
+    mappings.sort_by(|a, b| a.plaid_category.cmp(&b.plaid_category));
+    mappings
+}
+
+/// The user's overrides, keyed by Plaid category.
+pub async fn load_category_overrides(
+    state: &AppState,
+    user_id: &str,
+) -> Result<HashMap<String, String>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-category-mappings")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+
+    Ok(output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            let code = item.get("plaid_category").and_then(|v| v.as_s().ok())?;
+            let category = item.get("category").and_then(|v| v.as_s().ok())?;
+            Some((code.clone(), category.clone()))
+        })
+        .collect())
+}
+
+pub async fn get_category_mappings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match load_category_overrides(&state, &claims.sub).await {
+        Ok(overrides) => {
+            let body = CategoryMappings {
+                categories: APP_CATEGORIES,
+                mappings: merge_mappings(&overrides),
+            };
+            (StatusCode::OK, Json(serde_json::to_value(body).unwrap())).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Map a Plaid category onto an app category for this user's future syncs.
+pub async fn set_category_mapping(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(plaid_category): Path<String>,
+    Json(body): Json<SetMappingRequest>,
+) -> impl IntoResponse {
+    let mapping = normalize_plaid_category(&plaid_category).and_then(|code| {
+        normalize_app_category(&body.category).map(|category| CategoryMapping {
+            plaid_category: code,
+            category,
+            overridden: true,
+        })
+    });
+    let mapping = match mapping {
+        Ok(m) => m,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-category-mappings")
+        .item("user_id", AttributeValue::S(claims.sub.clone()))
+        .item(
+            "plaid_category",
+            AttributeValue::S(mapping.plaid_category.clone()),
+        )
+        .item("category", AttributeValue::S(mapping.category.clone()))
+        .item("updated_at", AttributeValue::S(Utc::now().to_rfc3339()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(serde_json::to_value(mapping).unwrap())).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to store mapping: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Drop the user's override so the Plaid category goes back to its default.
+pub async fn delete_category_mapping(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(plaid_category): Path<String>,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-category-mappings")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key(
+            "plaid_category",
+            AttributeValue::S(plaid_category.trim().to_uppercase()),
+        )
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Delete failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txn(pfc: Option<(&str, &str)>, legacy: &[&str]) -> Transaction {
+        serde_json::from_value(serde_json::json!({
+            "transaction_id": "txn-1",
+            "category": legacy,
+            "personal_finance_category": pfc.map(|(primary, detailed)| serde_json::json!({
+                "primary": primary,
+                "detailed": detailed,
+            })),
+        }))
+        .unwrap()
+    }
+
+    fn budget(name: &str, category: &str) -> Budget {
+        Budget {
+            budget_id: name.to_lowercase(),
+            user_id: "user-1".to_string(),
+            name: name.to_string(),
+            category: category.to_string(),
+            amount: 100.0,
+            spent: 0.0,
+            period: "monthly".to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn personal_finance_categories_map_to_app_categories() {
+        let none = HashMap::new();
+        let groceries = txn(
+            Some(("FOOD_AND_DRINK", "FOOD_AND_DRINK_GROCERIES")),
+            &["Shops"],
+        );
+        let coffee = txn(Some(("FOOD_AND_DRINK", "FOOD_AND_DRINK_COFFEE")), &[]);
+        let unknown = txn(Some(("SOMETHING_NEW", "SOMETHING_NEW_ELSE")), &["Travel"]);
+
+        assert_eq!(app_category(&groceries, &none), "food");
+        assert_eq!(app_category(&coffee, &none), "dining");
+        // Unknown codes fall through to the legacy hierarchy
+        assert_eq!(app_category(&unknown, &none), "travel");
+        assert_eq!(
+            app_category(&txn(None, &["Travel", "Taxi"]), &none),
+            "transport"
+        );
+        assert_eq!(app_category(&txn(None, &[]), &none), "other");
+    }
+
+    #[test]
+    fn user_overrides_win_over_defaults() {
+        let groceries = txn(Some(("FOOD_AND_DRINK", "FOOD_AND_DRINK_GROCERIES")), &[]);
+
+        let primary = HashMap::from([("FOOD_AND_DRINK".to_string(), "shopping".to_string())]);
+        assert_eq!(app_category(&groceries, &primary), "shopping");
+
+        let mut detailed = primary.clone();
+        detailed.insert(
+            "FOOD_AND_DRINK_GROCERIES".to_string(),
+            "housing".to_string(),
+        );
+        assert_eq!(app_category(&groceries, &detailed), "housing");
+    }
+
+    #[test]
+    fn budgets_match_on_category_before_name() {
+        let budgets = vec![
+            budget("Weekly food shop", "shopping"),
+            budget("Groceries", "Food"),
+        ];
+        assert_eq!(
+            match_budget("food", &budgets).map(|b| b.name.as_str()),
+            Some("Groceries")
+        );
+        assert_eq!(
+            match_budget("shop", &budgets).map(|b| b.name.as_str()),
+            Some("Weekly food shop")
+        );
+        assert!(match_budget("travel", &budgets).is_none());
+
+        let gifts = vec![
+            budget("Mother's Day", "gifts"),
+            budget("Brother's gift", "gifts"),
+            budget("Transfers to savings", "savings"),
+            budget("Bank transfer fees", "bills"),
+        ];
+        assert!(match_budget(FALLBACK_CATEGORY, &gifts).is_none());
+        assert_eq!(
+            match_budget("transfer", &gifts).map(|b| b.name.as_str()),
+            Some("Bank transfer fees")
+        );
+        // The catch-all still matches a budget set up for it
+        let catch_all = vec![budget("Everything else", "Other")];
+        assert_eq!(
+            match_budget(FALLBACK_CATEGORY, &catch_all).map(|b| b.name.as_str()),
+            Some("Everything else")
+        );
+    }
+
+    #[test]
+    fn mapping_input_is_validated() {
+        assert_eq!(
+            normalize_plaid_category(" food_and_drink_coffee ").unwrap(),
+            "FOOD_AND_DRINK_COFFEE"
+        );
+        assert!(normalize_plaid_category("NOT_A_CATEGORY").is_err());
+        assert_eq!(normalize_app_category("Bills").unwrap(), "bills");
+        assert!(normalize_app_category("snacks").is_err());
+
+        let overrides = HashMap::from([("TRAVEL".to_string(), "transport".to_string())]);
+        let merged = merge_mappings(&overrides);
+        let travel = merged
+            .iter()
+            .find(|m| m.plaid_category == "TRAVEL")
+            .unwrap();
+        assert_eq!(travel.category, "transport");
+        assert!(travel.overridden);
+        assert_eq!(
+            merged.len(),
+            PRIMARY_DEFAULTS.len() + DETAILED_DEFAULTS.len()
+        );
+    }
+}
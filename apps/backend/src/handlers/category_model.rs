@@ -0,0 +1,579 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::db::dynamo::is_conditional_check_failure;
+use crate::handlers::categories::{APP_CATEGORIES, FALLBACK_CATEGORY};
+use crate::handlers::transactions::{transactions_since, Transaction};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+/// Below this many categorized transactions the model doesn't suggest anything.
+const MIN_EXAMPLES: u32 = 5;
+/// Rarer features are dropped past this. With the app's fixed categories and words no longer
+/// than `MAX_WORD_LEN`, this keeps a user's model well inside DynamoDB's 400KB item limit.
+const MAX_FEATURES_PER_CATEGORY: usize = 400;
+/// Longer words are mostly references and never repeat, so they aren't features.
+const MAX_WORD_LEN: usize = 24;
+const MAX_SUGGESTIONS: usize = 3;
+/// A suggestion this confident fills in the category when neither the request nor a rule did.
+pub(crate) const AUTO_CATEGORY_CONFIDENCE: f64 = 0.8;
+/// Learning retries this often when another request saved the model in between.
+const SAVE_ATTEMPTS: usize = 3;
+/// Set on a user's model row while it waits for the jobs function to train it from history.
+const ATTR_PENDING_TRAINING: &str = "pending_training";
+/// Upper bounds of the amount buckets; larger amounts share the last one.
+const AMOUNT_BUCKETS: &[f64] = &[10.0, 25.0, 50.0, 100.0, 250.0, 1000.0];
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CategorySuggestion {
+    pub category: String,
+    /// Posterior probability, rounded to two decimals
+    pub confidence: f64,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+struct CategoryCounts {
+    examples: u32,
+    features: BTreeMap<String, u32>,
+}
+
+/// Multinomial naive Bayes over merchant words and an amount bucket, with one set of counts
+/// per category the user has used.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct CategoryModel {
+    categories: BTreeMap<String, CategoryCounts>,
+}
+
+/// The features of a transaction: the distinct words of its merchant and description, minus
+/// store numbers and references, and which amount bucket it falls in.
+pub fn features(description: &str, merchant: Option<&str>, amount: f64) -> Vec<String> {
+    let mut words = BTreeSet::new();
+    for text in merchant.into_iter().chain([description]) {
+        let lower = text.to_lowercase();
+        words.extend(
+            lower
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| (2..=MAX_WORD_LEN).contains(&word.chars().count()))
+                .filter(|word| !word.chars().any(|c| c.is_ascii_digit()))
+                .map(|word| format!("w:{}", word)),
+        );
+    }
+    let mut features: Vec<String> = words.into_iter().collect();
+    let bucket = if amount < 0.0 {
+        "in".to_string()
+    } else {
+        let index = AMOUNT_BUCKETS.iter().take_while(|&&b| amount >= b).count();
+        index.to_string()
+    };
+    features.push(format!("amt:{}", bucket));
+    features
+}
+
+fn transaction_features(transaction: &Transaction) -> Vec<String> {
+    features(
+        &transaction.description,
+        transaction.merchant.as_deref(),
+        transaction.amount,
+    )
+}
+
+/// Whether a transaction says something about how the user categorizes. Split transactions,
+/// the catch-all category and categories outside the app's list don't.
+fn is_example(transaction: &Transaction) -> bool {
+    transaction.splits.is_empty()
+        && transaction.category != FALLBACK_CATEGORY
+        && APP_CATEGORIES.contains(&transaction.category.as_str())
+}
+
+impl CategoryModel {
+    pub fn train<'a>(transactions: impl IntoIterator<Item = &'a Transaction>) -> Self {
+        let mut model = CategoryModel::default();
+        for transaction in transactions.into_iter().filter(|t| is_example(t)) {
+            model.learn(&transaction_features(transaction), &transaction.category);
+        }
+        model
+    }
+
+    fn examples(&self) -> u32 {
+        self.categories.values().map(|c| c.examples).sum()
+    }
+
+    pub fn learn(&mut self, features: &[String], category: &str) {
+        let counts = self.categories.entry(category.to_string()).or_default();
+        counts.examples += 1;
+        for feature in features {
+            *counts.features.entry(feature.clone()).or_default() += 1;
+        }
+        if counts.features.len() > MAX_FEATURES_PER_CATEGORY {
+            let mut by_count: Vec<(String, u32)> =
+                std::mem::take(&mut counts.features).into_iter().collect();
+            by_count.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            by_count.truncate(MAX_FEATURES_PER_CATEGORY);
+            counts.features = by_count.into_iter().collect();
+        }
+    }
+
+    /// Undo `learn` for a transaction whose category the user changed.
+    pub fn forget(&mut self, features: &[String], category: &str) {
+        let Some(counts) = self.categories.get_mut(category) else {
+            return;
+        };
+        counts.examples = counts.examples.saturating_sub(1);
+        for feature in features {
+            if let Some(count) = counts.features.get_mut(feature) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.features.remove(feature);
+                }
+            }
+        }
+        if counts.examples == 0 {
+            self.categories.remove(category);
+        }
+    }
+
+    /// The most likely categories, best first. Empty until the model has seen enough of the
+    /// user's transactions.
+    pub fn suggest(&self, features: &[String]) -> Vec<CategorySuggestion> {
+        let total = self.examples();
+        if total < MIN_EXAMPLES {
+            return Vec::new();
+        }
+        let vocabulary = self
+            .categories
+            .values()
+            .flat_map(|c| c.features.keys())
+            .collect::<BTreeSet<_>>()
+            .len() as f64;
+
+        // Log posteriors with add-one smoothing, then normalized so they sum to one
+        let scores: Vec<(&String, f64)> = self
+            .categories
+            .iter()
+            .filter(|(_, counts)| counts.examples > 0)
+            .map(|(category, counts)| {
+                let seen: u32 = counts.features.values().sum();
+                let denominator = seen as f64 + vocabulary;
+                let likelihood: f64 = features
+                    .iter()
+                    .map(|f| {
+                        let count = counts.features.get(f).copied().unwrap_or(0);
+                        ((count as f64 + 1.0) / denominator).ln()
+                    })
+                    .sum();
+                let prior = (counts.examples as f64 / total as f64).ln();
+                (category, prior + likelihood)
+            })
+            .collect();
+        let best = scores
+            .iter()
+            .map(|(_, s)| *s)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let norm: f64 = scores.iter().map(|(_, s)| (s - best).exp()).sum();
+
+        let mut suggestions: Vec<CategorySuggestion> = scores
+            .into_iter()
+            .map(|(category, score)| CategorySuggestion {
+                category: category.clone(),
+                confidence: ((score - best).exp() / norm * 100.0).round() / 100.0,
+            })
+            .filter(|s| s.confidence > 0.0)
+            .collect();
+        suggestions.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| a.category.cmp(&b.category))
+        });
+        suggestions.truncate(MAX_SUGGESTIONS);
+        suggestions
+    }
+
+    pub fn suggest_for(
+        &self,
+        description: &str,
+        merchant: Option<&str>,
+        amount: f64,
+    ) -> Vec<CategorySuggestion> {
+        self.suggest(&features(description, merchant, amount))
+    }
+}
+
+fn model_to_attribute(model: &CategoryModel) -> AttributeValue {
+    let n = |value: u32| AttributeValue::N(value.to_string());
+    AttributeValue::M(
+        model
+            .categories
+            .iter()
+            .map(|(category, counts)| {
+                let features = counts
+                    .features
+                    .iter()
+                    .map(|(feature, count)| (feature.clone(), n(*count)))
+                    .collect();
+                let counts = HashMap::from([
+                    ("examples".to_string(), n(counts.examples)),
+                    ("features".to_string(), AttributeValue::M(features)),
+                ]);
+                (category.clone(), AttributeValue::M(counts))
+            })
+            .collect(),
+    )
+}
+
+fn item_to_model(item: &HashMap<String, AttributeValue>) -> CategoryModel {
+    let n = |value: &AttributeValue| value.as_n().ok().and_then(|n| n.parse::<u32>().ok());
+    let categories = item
+        .get("categories")
+        .and_then(|v| v.as_m().ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|(category, counts)| {
+            let counts = counts.as_m().ok()?;
+            let features = counts
+                .get("features")
+                .and_then(|v| v.as_m().ok())
+                .into_iter()
+                .flatten()
+                .filter_map(|(feature, count)| Some((feature.clone(), n(count)?)))
+                .collect();
+            let examples = counts.get("examples").and_then(n).unwrap_or(0);
+            Some((category.clone(), CategoryCounts { examples, features }))
+        })
+        .collect();
+    CategoryModel { categories }
+}
+
+/// A user's model row as read back.
+struct StoredModel {
+    model: CategoryModel,
+    /// The `updated_at` it was saved with
+    seen: String,
+    /// Training from history has been asked for and the jobs function hasn't done it yet
+    pending: bool,
+}
+
+fn item_to_stored_model(item: &HashMap<String, AttributeValue>) -> StoredModel {
+    StoredModel {
+        model: item_to_model(item),
+        seen: item
+            .get("updated_at")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        pending: item
+            .get(ATTR_PENDING_TRAINING)
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false),
+    }
+}
+
+/// The stored model, if the user has one.
+async fn load_model(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Option<StoredModel>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-category-models")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+    Ok(output.item.as_ref().map(item_to_stored_model))
+}
+
+/// Save the model, clearing any pending training. The write only succeeds if nobody saved
+/// the row since it was read with `updated_at` equal to `seen`; `Ok(false)` means it failed.
+async fn save_model(
+    state: &AppState,
+    user_id: &str,
+    model: &CategoryModel,
+    seen: &str,
+) -> Result<bool, aws_sdk_dynamodb::Error> {
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-category-models")
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("categories", model_to_attribute(model))
+        .item("examples", AttributeValue::N(model.examples().to_string()))
+        .item("updated_at", AttributeValue::S(Utc::now().to_rfc3339()))
+        .condition_expression("updated_at = :seen")
+        .expression_attribute_values(":seen", AttributeValue::S(seen.to_string()))
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) if is_conditional_check_failure(&e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Users whose models wait for training, with the `updated_at` each was read with.
+pub(crate) async fn pending_models(
+    state: &AppState,
+) -> Result<Vec<(String, String)>, aws_sdk_dynamodb::Error> {
+    let mut pending = Vec::new();
+    let mut start_key = None;
+    loop {
+        let output = state
+            .dynamo
+            .scan()
+            .table_name("ovaflus-category-models")
+            .filter_expression("#pending = :pending")
+            .projection_expression("user_id, updated_at")
+            .expression_attribute_names("#pending", ATTR_PENDING_TRAINING)
+            .expression_attribute_values(":pending", AttributeValue::Bool(true))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        for item in output.items.unwrap_or_default() {
+            let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+            if let (Some(user_id), Some(seen)) = (s("user_id"), s("updated_at")) {
+                pending.push((user_id, seen));
+            }
+        }
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(pending);
+        }
+    }
+}
+
+/// Train a user's model from their whole history and replace the stored one. `Ok(false)`
+/// when the row changed after it was read with `seen`, in which case it stays pending.
+pub(crate) async fn train_model(
+    state: &AppState,
+    user_id: &str,
+    seen: &str,
+) -> Result<bool, aws_sdk_dynamodb::Error> {
+    let transactions = transactions_since(state, user_id, None).await?;
+    let model = CategoryModel::train(&transactions);
+    save_model(state, user_id, &model, seen).await
+}
+
+/// Mark the user's model for training by the jobs function. A user without a model gets an
+/// empty one in the meantime; an existing model keeps answering until it is replaced.
+async fn request_training(state: &AppState, user_id: &str) -> Result<(), aws_sdk_dynamodb::Error> {
+    state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-category-models")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .update_expression("SET #pending = :pending, updated_at = :now")
+        .expression_attribute_names("#pending", ATTR_PENDING_TRAINING)
+        .expression_attribute_values(":pending", AttributeValue::Bool(true))
+        .expression_attribute_values(":now", AttributeValue::S(Utc::now().to_rfc3339()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// The user's model as stored. A user without one gets an empty model, which suggests
+/// nothing, and is queued for training from their history by the next `category-models` job.
+pub(crate) async fn user_model(
+    state: &AppState,
+    user_id: &str,
+) -> Result<CategoryModel, aws_sdk_dynamodb::Error> {
+    if let Some(stored) = load_model(state, user_id).await? {
+        return Ok(stored.model);
+    }
+    request_training(state, user_id).await?;
+    Ok(CategoryModel::default())
+}
+
+/// Teach the model a category the user chose, forgetting the one `before` had if this was an
+/// edit. A model waiting to be trained is left alone, as training from history will see the
+/// change.
+pub(crate) async fn learn_category(
+    state: &AppState,
+    user_id: &str,
+    before: Option<&Transaction>,
+    after: &Transaction,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    learn_categories(state, user_id, &[(before, after)]).await
+}
+
+/// `learn_category` for several edits at once, saving the model a single time.
+pub(crate) async fn learn_categories(
+    state: &AppState,
+    user_id: &str,
+    edits: &[(Option<&Transaction>, &Transaction)],
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let edits: Vec<(Option<&Transaction>, &Transaction)> = edits
+        .iter()
+        .map(|&(before, after)| (before.filter(|t| is_example(t)), after))
+        .filter(|(before, after)| before.is_some() || is_example(after))
+        .collect();
+    if edits.is_empty() {
+        return Ok(());
+    }
+    for _ in 0..SAVE_ATTEMPTS {
+        let (mut model, seen) = match load_model(state, user_id).await? {
+            Some(StoredModel {
+                model,
+                seen,
+                pending: false,
+            }) => (model, seen),
+            Some(_) => return Ok(()),
+            None => return request_training(state, user_id).await,
+        };
+        for &(before, after) in &edits {
+            if let Some(before) = before {
+                model.forget(&transaction_features(before), &before.category);
+            }
+            if is_example(after) {
+                model.learn(&transaction_features(after), &after.category);
+            }
+        }
+        if save_model(state, user_id, &model, &seen).await? {
+            return Ok(());
+        }
+    }
+    tracing::warn!("Gave up updating the category model for {user_id} after repeated conflicts");
+    Ok(())
+}
+
+// --- Endpoints ---
+
+#[derive(Deserialize)]
+pub struct SuggestionsQuery {
+    pub description: String,
+    pub merchant: Option<String>,
+    pub amount: f64,
+}
+
+pub async fn get_suggestions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<SuggestionsQuery>,
+) -> impl IntoResponse {
+    match user_model(&state, &claims.sub).await {
+        Ok(model) => {
+            let suggestions =
+                model.suggest_for(&query.description, query.merchant.as_deref(), query.amount);
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "suggestions": suggestions })),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Retrain the model from scratch, for when it has drifted from the user's history, say after
+/// many transactions were deleted. The next `category-models` job does the training.
+pub async fn rebuild_model(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match request_training(&state, &claims.sub).await {
+        Ok(()) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "status": "pending" })),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(description: &str, amount: f64, category: &str) -> Transaction {
+        Transaction {
+            transaction_id: description.to_string(),
+            user_id: "u1".to_string(),
+            amount,
+            description: description.to_string(),
+            category: category.to_string(),
+            transaction_type: "expense".to_string(),
+            date: "2024-05-01".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn history() -> Vec<Transaction> {
+        vec![
+            transaction("BLUE BOTTLE COFFEE 0423", 6.5, "dining"),
+            transaction("Blue Bottle Coffee", 5.75, "dining"),
+            transaction("SWEETGREEN #112", 14.2, "dining"),
+            transaction("WHOLE FOODS MKT 10233", 84.1, "food"),
+            transaction("Whole Foods Market", 62.3, "food"),
+            transaction("TRADER JOE'S #552", 45.0, "food"),
+            transaction("SHELL OIL 5744", 48.0, "transport"),
+            transaction("AMAZON MKTPLACE", 23.0, "other"),
+        ]
+    }
+
+    #[test]
+    fn features_are_merchant_words_and_an_amount_bucket() {
+        assert_eq!(
+            features("SQ *BLUE BOTTLE 0423 SF", Some("Blue Bottle"), 6.5),
+            ["w:blue", "w:bottle", "w:sf", "w:sq", "amt:0"]
+        );
+        assert_eq!(features("Payroll", None, -2500.0), ["w:payroll", "amt:in"]);
+        assert_eq!(features("Rent", None, 1800.0).last().unwrap(), "amt:6");
+    }
+
+    #[test]
+    fn suggestions_follow_the_users_history_and_learn_from_edits() {
+        let mut model = CategoryModel::train(&history());
+        assert_eq!(model.examples(), 7, "the catch-all category isn't learned");
+
+        let coffee = model.suggest_for("BLUE BOTTLE COFFEE 1187", None, 7.0);
+        assert_eq!(coffee[0].category, "dining");
+        assert!(coffee[0].confidence >= AUTO_CATEGORY_CONFIDENCE);
+        let total: f64 = coffee.iter().map(|s| s.confidence).sum();
+        assert!((total - 1.0).abs() < 0.05);
+        assert_eq!(
+            model.suggest_for("WHOLE FOODS #99", None, 70.0)[0].category,
+            "food"
+        );
+
+        // The user files their coffee shop visits under food from now on
+        let edits = [
+            transaction("BLUE BOTTLE COFFEE 0423", 6.5, "dining"),
+            transaction("Blue Bottle Coffee", 5.75, "dining"),
+        ];
+        for before in &edits {
+            let features = transaction_features(before);
+            model.forget(&features, "dining");
+            model.learn(&features, "food");
+        }
+        let coffee = model.suggest_for("BLUE BOTTLE COFFEE 1187", None, 7.0);
+        assert_eq!(coffee[0].category, "food");
+
+        let stored = HashMap::from([("categories".to_string(), model_to_attribute(&model))]);
+        assert_eq!(item_to_model(&stored), model);
+
+        // Only the app's categories are learned, which bounds the model's size
+        assert!(!is_example(&transaction("Blue Bottle", 6.0, "coffee")));
+        assert!(!features(&"x".repeat(40), None, 1.0)[0].starts_with("w:"));
+
+        let sparse = CategoryModel::train(&history()[..3]);
+        assert!(sparse.suggest_for("Blue Bottle", None, 6.0).is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const DEFAULT_UPCOMING_DAYS: i64 = 30;
+const MAX_UPCOMING_DAYS: i64 = 90;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Liability {
+    pub account_id: String,
+    pub user_id: String,
+    pub item_id: String,
+    /// "credit", "student" or "mortgage"
+    pub kind: String,
+    pub name: String,
+    pub balance: f64,
+    /// Annual rate in percent: the purchase APR for cards, the interest rate for loans
+    pub apr: Option<f64>,
+    pub minimum_payment: Option<f64>,
+    /// YYYY-MM-DD
+    pub next_payment_due_date: Option<String>,
+    pub updated_at: String,
+}
+
+pub(crate) fn item_to_liability(item: &HashMap<String, AttributeValue>) -> Liability {
+    let s = |name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let n = |name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+    };
+
+    Liability {
+        account_id: s("account_id"),
+        user_id: s("user_id"),
+        item_id: s("item_id"),
+        kind: s("kind"),
+        name: s("name"),
+        balance: n("balance").unwrap_or(0.0),
+        apr: n("apr"),
+        minimum_payment: n("minimum_payment"),
+        next_payment_due_date: item
+            .get("next_payment_due_date")
+            .and_then(|v| v.as_s().ok())
+            .cloned(),
+        updated_at: s("updated_at"),
+    }
+}
+
+pub(crate) fn liability_to_item(l: &Liability) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::from([
+        ("user_id".to_string(), AttributeValue::S(l.user_id.clone())),
+        (
+            "account_id".to_string(),
+            AttributeValue::S(l.account_id.clone()),
+        ),
+        ("item_id".to_string(), AttributeValue::S(l.item_id.clone())),
+        ("kind".to_string(), AttributeValue::S(l.kind.clone())),
+        ("name".to_string(), AttributeValue::S(l.name.clone())),
+        (
+            "balance".to_string(),
+            AttributeValue::N(l.balance.to_string()),
+        ),
+        (
+            "updated_at".to_string(),
+            AttributeValue::S(l.updated_at.clone()),
+        ),
+    ]);
+    if let Some(apr) = l.apr {
+        item.insert("apr".to_string(), AttributeValue::N(apr.to_string()));
+    }
+    if let Some(payment) = l.minimum_payment {
+        item.insert(
+            "minimum_payment".to_string(),
+            AttributeValue::N(payment.to_string()),
+        );
+    }
+    if let Some(date) = &l.next_payment_due_date {
+        item.insert(
+            "next_payment_due_date".to_string(),
+            AttributeValue::S(date.clone()),
+        );
+    }
+    item
+}
+
+pub(crate) async fn load_liabilities(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<Liability>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-liabilities")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+
+    Ok(output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .map(item_to_liability)
+        .collect())
+}
+
+pub async fn list_liabilities(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match load_liabilities(&state, &claims.sub).await {
+        Ok(liabilities) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(liabilities).unwrap()),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+// --- Upcoming Payments ---
+
+#[derive(Deserialize)]
+pub struct UpcomingQuery {
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UpcomingPayment {
+    pub account_id: String,
+    pub name: String,
+    pub kind: String,
+    pub due_date: String,
+    pub days_until_due: i64,
+    pub minimum_payment: Option<f64>,
+    pub balance: f64,
+}
+
+/// Payments due between `today` and `today + days`, soonest first.
+pub(crate) fn upcoming_payments(
+    liabilities: &[Liability],
+    today: NaiveDate,
+    days: i64,
+) -> Vec<UpcomingPayment> {
+    let mut upcoming: Vec<UpcomingPayment> = liabilities
+        .iter()
+        .filter_map(|l| {
+            let due_date = l.next_payment_due_date.as_deref()?;
+            let due = NaiveDate::parse_from_str(due_date, "%Y-%m-%d").ok()?;
+            let days_until_due = (due - today).num_days();
+            if !(0..=days).contains(&days_until_due) {
+                return None;
+            }
+            Some(UpcomingPayment {
+                account_id: l.account_id.clone(),
+                name: l.name.clone(),
+                kind: l.kind.clone(),
+                due_date: due_date.to_string(),
+                days_until_due,
+                minimum_payment: l.minimum_payment,
+                balance: l.balance,
+            })
+        })
+        .collect();
+
+    upcoming.sort_by(|a, b| a.due_date.cmp(&b.due_date));
+    upcoming
+}
+
+pub async fn get_upcoming_payments(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<UpcomingQuery>,
+) -> impl IntoResponse {
+    let days = params
+        .days
+        .unwrap_or(DEFAULT_UPCOMING_DAYS)
+        .clamp(0, MAX_UPCOMING_DAYS);
+
+    match load_liabilities(&state, &claims.sub).await {
+        Ok(liabilities) => {
+            let upcoming = upcoming_payments(&liabilities, Utc::now().date_naive(), days);
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(upcoming).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn liability(account_id: &str, due: Option<&str>) -> Liability {
+        Liability {
+            account_id: account_id.to_string(),
+            user_id: "user-1".to_string(),
+            item_id: "item-1".to_string(),
+            kind: "credit".to_string(),
+            name: format!("Card {account_id}"),
+            balance: 500.0,
+            apr: Some(24.99),
+            minimum_payment: Some(35.0),
+            next_payment_due_date: due.map(String::from),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn upcoming_payments_are_windowed_and_sorted() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let liabilities = vec![
+            liability("late", Some("2024-04-28")),
+            liability("far", Some("2024-07-01")),
+            liability("second", Some("2024-05-20")),
+            liability("first", Some("2024-05-01")),
+            liability("none", None),
+        ];
+
+        let upcoming = upcoming_payments(&liabilities, today, 30);
+        let ids: Vec<&str> = upcoming.iter().map(|p| p.account_id.as_str()).collect();
+        assert_eq!(ids, vec!["first", "second"]);
+        assert_eq!(upcoming[0].days_until_due, 0);
+        assert_eq!(upcoming[1].days_until_due, 19);
+    }
+
+    #[test]
+    fn liability_round_trips_through_dynamo_item() {
+        let original = liability("acct", Some("2024-05-20"));
+        assert_eq!(item_to_liability(&liability_to_item(&original)), original);
+
+        let mut without_optionals = liability("acct", None);
+        without_optionals.apr = None;
+        without_optionals.minimum_payment = None;
+        assert_eq!(
+            item_to_liability(&liability_to_item(&without_optionals)),
+            without_optionals
+        );
+    }
+}
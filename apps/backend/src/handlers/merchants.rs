@@ -0,0 +1,621 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::db::dynamo::is_conditional_check_failure;
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+/// Partition of the merchant directory shared by every user.
+const GLOBAL_OWNER: &str = "global";
+/// DynamoDB reads at most this many keys per batch.
+const BATCH_GET_LIMIT: usize = 100;
+const BATCH_GET_ATTEMPTS: usize = 3;
+/// Merchant ids keep this many words of the cleaned-up name.
+const ID_WORDS: usize = 3;
+
+/// What card processors and payment apps put before the merchant, up to a `*`.
+const PROCESSOR_PREFIXES: &[&str] = &[
+    "SQ", "TST", "SP", "PP", "PAYPAL", "PY", "CKE", "GOOGLE", "FS", "IC", "BT", "DD", "LS", "EB",
+    "WPY", "ZTL",
+];
+
+/// Words that card processors add to descriptions without naming the merchant.
+const NOISE_WORDS: &[&str] = &[
+    "pos",
+    "debit",
+    "credit",
+    "purchase",
+    "card",
+    "checkcard",
+    "recurring",
+    "payment",
+    "ach",
+    "online",
+    "autopay",
+    "sq",
+    "tst",
+    "www",
+    "com",
+    "net",
+    "inc",
+    "llc",
+    "store",
+    "str",
+];
+
+/// Trailing location words: state and country codes, and cities banks abbreviate.
+const LOCATION_WORDS: &[&str] = &[
+    "al", "ak", "az", "ar", "ca", "co", "ct", "de", "dc", "fl", "ga", "hi", "id", "il", "in", "ia",
+    "ks", "ky", "la", "me", "md", "ma", "mi", "mn", "ms", "mo", "mt", "ne", "nv", "nh", "nj", "nm",
+    "ny", "nc", "nd", "oh", "ok", "or", "pa", "ri", "sc", "sd", "tn", "tx", "ut", "vt", "va", "wa",
+    "wv", "wi", "wy", "us", "usa", "sf", "nyc",
+];
+
+/// Cities banks most often append in full, also stripped when trailing.
+const CITIES: &[&str] = &[
+    "new york",
+    "brooklyn",
+    "los angeles",
+    "san francisco",
+    "oakland",
+    "san jose",
+    "san diego",
+    "seattle",
+    "portland",
+    "chicago",
+    "boston",
+    "philadelphia",
+    "washington",
+    "atlanta",
+    "miami",
+    "orlando",
+    "houston",
+    "dallas",
+    "austin",
+    "san antonio",
+    "denver",
+    "phoenix",
+    "las vegas",
+    "minneapolis",
+    "detroit",
+    "nashville",
+    "charlotte",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedMerchant {
+    /// Lowercase words joined with `-`, the same for every charge from the merchant
+    pub merchant_id: String,
+    pub display_name: String,
+}
+
+/// The words of a description token. Words with digits are store numbers and references and
+/// are dropped, except for a brand that joins a number and a name with `-`, like 7-Eleven.
+fn token_words(token: &str) -> Vec<String> {
+    let parts: Vec<&str> = token.split('-').filter(|p| !p.is_empty()).collect();
+    let number = |p: &&str| p.chars().all(|c| c.is_ascii_digit());
+    let name = |p: &&str| !p.chars().any(|c| c.is_ascii_digit());
+    let is_brand = parts.iter().any(number)
+        && parts.iter().any(|p| name(p) && p.chars().count() >= 2)
+        && parts.iter().all(|p| number(p) || name(p));
+    if is_brand {
+        return vec![parts.join("-").to_lowercase()];
+    }
+    parts
+        .into_iter()
+        .filter(name)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// How many trailing words name a location, keeping at least one word for the merchant.
+fn trailing_location(words: &[String]) -> Option<usize> {
+    LOCATION_WORDS
+        .iter()
+        .chain(CITIES)
+        .map(|location| location.split(' ').collect::<Vec<_>>())
+        .filter(|location| location.len() < words.len())
+        .find(|location| {
+            words[words.len() - location.len()..]
+                .iter()
+                .zip(location)
+                .all(|(word, part)| word == part)
+        })
+        .map(|location| location.len())
+}
+
+/// Clean up a bank description or merchant name: drop the processor prefix, store numbers,
+/// references and trailing location, and keep the words that name the merchant. `None` when
+/// nothing is left, say for a bare reference number.
+pub fn normalize_merchant(raw: &str) -> Option<NormalizedMerchant> {
+    let mut name = raw.trim();
+    if let Some((prefix, rest)) = name.split_once('*') {
+        if PROCESSOR_PREFIXES.contains(&prefix.trim().to_uppercase().as_str()) {
+            name = rest;
+        }
+    }
+
+    let mut words: Vec<String> = name
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '&' | '\'' | '-')))
+        .flat_map(token_words)
+        .map(|word| word.trim_matches(|c| c == '&' || c == '\'').to_string())
+        .filter(|word| !word.is_empty())
+        .filter(|word| !NOISE_WORDS.contains(&word.as_str()))
+        .collect();
+    while let Some(len) = trailing_location(&words) {
+        words.truncate(words.len() - len);
+    }
+    words.truncate(ID_WORDS);
+    if words.is_empty() {
+        return None;
+    }
+
+    let display_name = words
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    Some(NormalizedMerchant {
+        merchant_id: words.join("-"),
+        display_name,
+    })
+}
+
+/// The merchant of a transaction, preferring the name Plaid recognised over the raw
+/// description.
+pub fn transaction_merchant(
+    description: &str,
+    merchant_name: Option<&str>,
+) -> Option<NormalizedMerchant> {
+    let recognised = merchant_name
+        .and_then(normalize_merchant)
+        .map(|m| NormalizedMerchant {
+            display_name: merchant_name.unwrap_or_default().trim().to_string(),
+            ..m
+        });
+    recognised.or_else(|| normalize_merchant(description))
+}
+
+pub(crate) fn is_merchant_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 100
+        && id
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '&' | '\''))
+        && id.chars().all(|c| !c.is_uppercase())
+}
+
+// --- Directory ---
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Merchant {
+    pub merchant_id: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_url: Option<String>,
+    /// `user` for the user's own entry, `global` for the shared one
+    pub scope: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct PutMerchantRequest {
+    pub display_name: String,
+    pub default_category: Option<String>,
+    pub logo_url: Option<String>,
+}
+
+fn item_to_merchant(item: &HashMap<String, AttributeValue>) -> Merchant {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    let owner = s("owner").unwrap_or_default();
+    Merchant {
+        merchant_id: s("merchant_id").unwrap_or_default(),
+        display_name: s("display_name").unwrap_or_default(),
+        default_category: s("default_category"),
+        logo_url: s("logo_url"),
+        scope: if owner == GLOBAL_OWNER {
+            "global"
+        } else {
+            "user"
+        }
+        .to_string(),
+        updated_at: s("updated_at").unwrap_or_default(),
+    }
+}
+
+fn merchant_to_item(owner: &str, merchant: &Merchant) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::from([
+        ("owner".to_string(), AttributeValue::S(owner.to_string())),
+        (
+            "merchant_id".to_string(),
+            AttributeValue::S(merchant.merchant_id.clone()),
+        ),
+        (
+            "display_name".to_string(),
+            AttributeValue::S(merchant.display_name.clone()),
+        ),
+        (
+            "updated_at".to_string(),
+            AttributeValue::S(merchant.updated_at.clone()),
+        ),
+    ]);
+    if let Some(ref category) = merchant.default_category {
+        item.insert(
+            "default_category".to_string(),
+            AttributeValue::S(category.clone()),
+        );
+    }
+    if let Some(ref logo_url) = merchant.logo_url {
+        item.insert("logo_url".to_string(), AttributeValue::S(logo_url.clone()));
+    }
+    item
+}
+
+/// Every entry the user made for themselves, by merchant id.
+pub(crate) async fn user_merchants(
+    state: &AppState,
+    user_id: &str,
+) -> Result<HashMap<String, Merchant>, aws_sdk_dynamodb::Error> {
+    let mut merchants = HashMap::new();
+    let mut start_key = None;
+    loop {
+        let output = state
+            .dynamo
+            .query()
+            .table_name("ovaflus-merchants")
+            .key_condition_expression("#owner = :owner")
+            .expression_attribute_names("#owner", "owner")
+            .expression_attribute_values(":owner", AttributeValue::S(user_id.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        for item in output.items.unwrap_or_default() {
+            let merchant = item_to_merchant(&item);
+            merchants.insert(merchant.merchant_id.clone(), merchant);
+        }
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(merchants);
+        }
+    }
+}
+
+/// The shared entries for the given merchant ids, by merchant id. Ids without one are left out.
+pub(crate) async fn global_merchants(
+    state: &AppState,
+    merchant_ids: &BTreeSet<String>,
+) -> Result<HashMap<String, Merchant>, aws_sdk_dynamodb::Error> {
+    let ids: Vec<&String> = merchant_ids.iter().collect();
+    let mut merchants = HashMap::new();
+    for chunk in ids.chunks(BATCH_GET_LIMIT) {
+        let keys = chunk
+            .iter()
+            .map(|id| {
+                HashMap::from([
+                    (
+                        "owner".to_string(),
+                        AttributeValue::S(GLOBAL_OWNER.to_string()),
+                    ),
+                    ("merchant_id".to_string(), AttributeValue::S(id.to_string())),
+                ])
+            })
+            .collect();
+        let mut request = HashMap::from([(
+            "ovaflus-merchants".to_string(),
+            KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .build()
+                .expect("keys are set"),
+        )]);
+        // Throttled batches come back as unprocessed keys, which are asked for again
+        for _ in 0..BATCH_GET_ATTEMPTS {
+            let output = state
+                .dynamo
+                .batch_get_item()
+                .set_request_items(Some(request))
+                .send()
+                .await?;
+            let items = output
+                .responses
+                .and_then(|mut r| r.remove("ovaflus-merchants"))
+                .unwrap_or_default();
+            for item in items {
+                let merchant = item_to_merchant(&item);
+                merchants.insert(merchant.merchant_id.clone(), merchant);
+            }
+            request = output.unprocessed_keys.unwrap_or_default();
+            if request.is_empty() {
+                break;
+            }
+        }
+    }
+    Ok(merchants)
+}
+
+async fn get_entry(
+    state: &AppState,
+    owner: &str,
+    merchant_id: &str,
+) -> Result<Option<Merchant>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-merchants")
+        .key("owner", AttributeValue::S(owner.to_string()))
+        .key("merchant_id", AttributeValue::S(merchant_id.to_string()))
+        .send()
+        .await?;
+    Ok(output.item.as_ref().map(item_to_merchant))
+}
+
+/// The user's own entry for a merchant, or else the shared one.
+pub(crate) async fn resolve_merchant(
+    state: &AppState,
+    user_id: &str,
+    merchant_id: &str,
+) -> Result<Option<Merchant>, aws_sdk_dynamodb::Error> {
+    match get_entry(state, user_id, merchant_id).await? {
+        Some(merchant) => Ok(Some(merchant)),
+        None => get_entry(state, GLOBAL_OWNER, merchant_id).await,
+    }
+}
+
+/// Add a merchant to the shared directory unless it is already there. Entries come from what
+/// Plaid recognised, so the first user to sync a merchant seeds it for everyone.
+pub(crate) async fn register_global_merchant(
+    state: &AppState,
+    merchant: &Merchant,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-merchants")
+        .set_item(Some(merchant_to_item(GLOBAL_OWNER, merchant)))
+        .condition_expression("attribute_not_exists(merchant_id)")
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if is_conditional_check_failure(&e) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// --- Endpoints ---
+
+pub async fn list_merchants(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match user_merchants(&state, &claims.sub).await {
+        Ok(merchants) => {
+            let mut merchants: Vec<Merchant> = merchants.into_values().collect();
+            merchants.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(merchants).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn get_merchant(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(merchant_id): Path<String>,
+) -> impl IntoResponse {
+    if !is_merchant_id(&merchant_id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("Invalid merchant id")),
+        )
+            .into_response();
+    }
+    match resolve_merchant(&state, &claims.sub, &merchant_id).await {
+        Ok(Some(merchant)) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(merchant).unwrap()),
+        )
+            .into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiError::new("Merchant not found")),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Set the user's own display name, default category and logo for a merchant. They win over
+/// the shared entry for this user only.
+pub async fn put_merchant(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(merchant_id): Path<String>,
+    Json(body): Json<PutMerchantRequest>,
+) -> impl IntoResponse {
+    if !is_merchant_id(&merchant_id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("Invalid merchant id")),
+        )
+            .into_response();
+    }
+    let display_name = body.display_name.trim().to_string();
+    if display_name.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("display_name is required")),
+        )
+            .into_response();
+    }
+    let logo_url = body
+        .logo_url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    if logo_url
+        .as_ref()
+        .is_some_and(|url| !url.starts_with("https://"))
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("logo_url must be an https URL")),
+        )
+            .into_response();
+    }
+
+    let merchant = Merchant {
+        merchant_id,
+        display_name,
+        default_category: body
+            .default_category
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty()),
+        logo_url,
+        scope: "user".to_string(),
+        updated_at: Utc::now().to_rfc3339(),
+    };
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-merchants")
+        .set_item(Some(merchant_to_item(&claims.sub, &merchant)))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(merchant).unwrap()),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to save merchant: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Remove the user's own entry, going back to the shared one.
+pub async fn delete_merchant(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(merchant_id): Path<String>,
+) -> impl IntoResponse {
+    if !is_merchant_id(&merchant_id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("Invalid merchant id")),
+        )
+            .into_response();
+    }
+    let result = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-merchants")
+        .key("owner", AttributeValue::S(claims.sub.clone()))
+        .key("merchant_id", AttributeValue::S(merchant_id))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Delete failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(raw: &str) -> Option<String> {
+        normalize_merchant(raw).map(|m| m.merchant_id)
+    }
+
+    #[test]
+    fn bank_descriptions_normalize_to_the_merchant() {
+        let blue_bottle = normalize_merchant("SQ *BLUE BOTTLE 0423 SF").unwrap();
+        assert_eq!(blue_bottle.merchant_id, "blue-bottle");
+        assert_eq!(blue_bottle.display_name, "Blue Bottle");
+        assert_eq!(id("SQ *BLUE BOTTLE #0042").as_deref(), Some("blue-bottle"));
+        assert_eq!(id("Blue Bottle").as_deref(), Some("blue-bottle"));
+
+        assert_eq!(id("NETFLIX.COM 866-579-7172").as_deref(), Some("netflix"));
+        assert_eq!(
+            id("POS DEBIT SPOTIFY USA P1A2B3").as_deref(),
+            Some("spotify")
+        );
+        assert_eq!(id("TST* SWEETGREEN 112 NY").as_deref(), Some("sweetgreen"));
+        assert_eq!(id("Trader Joe's #552").as_deref(), Some("trader-joe's"));
+        assert_eq!(id("AT&T *PAYMENT").as_deref(), Some("at&t"));
+        // A lone word that looks like a state code is still the merchant
+        assert_eq!(id("OK").as_deref(), Some("ok"));
+        assert_eq!(id("#1234"), None);
+        assert_eq!(id("7-ELEVEN 38211").as_deref(), Some("7-eleven"));
+        assert_eq!(id("1-800-FLOWERS.COM").as_deref(), Some("1-800-flowers"));
+        assert_eq!(id("CALL 866-579-7172").as_deref(), Some("call"));
+        assert_eq!(
+            id("WALGREENS STORE 4417 SAN FRANCISCO CA").as_deref(),
+            Some("walgreens")
+        );
+        assert_eq!(id("CHIPOTLE 1234 LOS ANGELES").as_deref(), Some("chipotle"));
+        assert_eq!(id("Boston Market").as_deref(), Some("boston-market"));
+
+        // Plaid's merchant name wins, and keeps its spelling for display
+        let uber = transaction_merchant("Uber 072515 SF**POOL**", Some("Uber")).unwrap();
+        assert_eq!(uber.merchant_id, "uber");
+        assert_eq!(uber.display_name, "Uber");
+        assert_eq!(
+            transaction_merchant("Whole Foods Market", None)
+                .unwrap()
+                .merchant_id,
+            "whole-foods-market"
+        );
+
+        for raw in [
+            "SQ *BLUE BOTTLE 0423 SF",
+            "AT&T",
+            "Trader Joe's",
+            "7-Eleven",
+        ] {
+            let merchant_id = id(raw).unwrap();
+            assert!(is_merchant_id(&merchant_id), "{merchant_id}");
+            assert_eq!(id(&merchant_id), Some(merchant_id));
+        }
+        assert!(!is_merchant_id("Blue Bottle"));
+    }
+}
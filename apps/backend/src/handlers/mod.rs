@@ -1,9 +1,24 @@
 pub mod auth;
 pub mod budgets;
+pub mod categories;
+pub mod category_model;
 pub mod goals;
+pub mod liabilities;
+pub mod merchants;
+pub mod net_worth;
+pub mod news;
 pub mod plaid;
+pub mod plaid_sandbox;
 pub mod portfolio;
+pub mod portfolio_import;
 pub mod profile;
+pub mod rebalance;
+pub mod recurring;
+pub mod returns;
+pub mod risk;
+pub mod rules;
 pub mod stocks;
+pub mod subscriptions;
+pub mod transaction_import;
 pub mod transactions;
 pub mod watchlist;
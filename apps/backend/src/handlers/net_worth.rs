@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::handlers::liabilities::Liability;
+use crate::handlers::portfolio::{item_to_holding, value_holdings, Holding};
+use crate::handlers::returns::range_start;
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const DEFAULT_RANGE: &str = "1y";
+/// Pseudo account ids for the snapshots of the portfolio itself.
+pub(crate) const PORTFOLIO_ACCOUNT_ID: &str = "portfolio";
+pub(crate) const MANUAL_ASSETS_ACCOUNT_ID: &str = "manual-assets";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BalanceSnapshot {
+    pub user_id: String,
+    /// "{date}#{account_id}", so a user's snapshots sort by day
+    pub snapshot_id: String,
+    /// YYYY-MM-DD
+    pub date: String,
+    pub account_id: String,
+    /// Empty for the portfolio pseudo accounts
+    pub item_id: String,
+    pub name: String,
+    /// "cash", "investments", "credit", "loan", "other", "portfolio" or "manual".
+    /// "investments_synced" marks brokerage accounts whose holdings are already in the
+    /// portfolio; they're kept for display but left out of net worth.
+    pub category: String,
+    pub current: f64,
+    pub available: Option<f64>,
+    pub iso_currency_code: Option<String>,
+    pub updated_at: String,
+}
+
+impl BalanceSnapshot {
+    pub(crate) fn snapshot_id(date: &str, account_id: &str) -> String {
+        format!("{}#{}", date, account_id)
+    }
+}
+
+/// Net worth category of a Plaid account type.
+pub(crate) fn account_category(account_type: &str, investments_synced: bool) -> &'static str {
+    match account_type {
+        "depository" => "cash",
+        "credit" => "credit",
+        "loan" => "loan",
+        "investment" | "brokerage" if investments_synced => "investments_synced",
+        "investment" | "brokerage" => "investments",
+        _ => "other",
+    }
+}
+
+pub(crate) fn item_to_snapshot(item: &HashMap<String, AttributeValue>) -> BalanceSnapshot {
+    let s = |name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let n = |name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+    };
+
+    BalanceSnapshot {
+        user_id: s("user_id"),
+        snapshot_id: s("snapshot_id"),
+        date: s("date"),
+        account_id: s("account_id"),
+        item_id: s("item_id"),
+        name: s("name"),
+        category: s("category"),
+        current: n("current").unwrap_or(0.0),
+        available: n("available"),
+        iso_currency_code: item
+            .get("iso_currency_code")
+            .and_then(|v| v.as_s().ok())
+            .cloned(),
+        updated_at: s("updated_at"),
+    }
+}
+
+pub(crate) fn snapshot_to_item(snapshot: &BalanceSnapshot) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::from([
+        (
+            "user_id".to_string(),
+            AttributeValue::S(snapshot.user_id.clone()),
+        ),
+        (
+            "snapshot_id".to_string(),
+            AttributeValue::S(snapshot.snapshot_id.clone()),
+        ),
+        ("date".to_string(), AttributeValue::S(snapshot.date.clone())),
+        (
+            "account_id".to_string(),
+            AttributeValue::S(snapshot.account_id.clone()),
+        ),
+        (
+            "item_id".to_string(),
+            AttributeValue::S(snapshot.item_id.clone()),
+        ),
+        ("name".to_string(), AttributeValue::S(snapshot.name.clone())),
+        (
+            "category".to_string(),
+            AttributeValue::S(snapshot.category.clone()),
+        ),
+        (
+            "current".to_string(),
+            AttributeValue::N(snapshot.current.to_string()),
+        ),
+        (
+            "updated_at".to_string(),
+            AttributeValue::S(snapshot.updated_at.clone()),
+        ),
+    ]);
+    if let Some(available) = snapshot.available {
+        item.insert(
+            "available".to_string(),
+            AttributeValue::N(available.to_string()),
+        );
+    }
+    if let Some(code) = &snapshot.iso_currency_code {
+        item.insert(
+            "iso_currency_code".to_string(),
+            AttributeValue::S(code.clone()),
+        );
+    }
+    item
+}
+
+/// Snapshot the portfolio's market value, with manual assets kept apart, for `date`.
+///
+/// Holdings synced from Plaid are included here, which is why their brokerage account
+/// balances are marked "investments_synced".
+pub(crate) async fn snapshot_portfolio(
+    state: &AppState,
+    user_id: &str,
+    date: &str,
+) -> Result<Vec<BalanceSnapshot>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+    let holdings: Vec<Holding> = output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .map(item_to_holding)
+        .collect();
+
+    let valued = value_holdings(state, holdings).await;
+    let (manual, market): (Vec<_>, Vec<_>) = valued
+        .iter()
+        .partition(|v| v.holding.asset_class == "manual");
+
+    let now = Utc::now().to_rfc3339();
+    let snapshot = |account_id: &str, name: &str, category: &str, current: f64| BalanceSnapshot {
+        user_id: user_id.to_string(),
+        snapshot_id: BalanceSnapshot::snapshot_id(date, account_id),
+        date: date.to_string(),
+        account_id: account_id.to_string(),
+        item_id: String::new(),
+        name: name.to_string(),
+        category: category.to_string(),
+        current,
+        available: None,
+        iso_currency_code: None,
+        updated_at: now.clone(),
+    };
+
+    Ok(vec![
+        snapshot(
+            PORTFOLIO_ACCOUNT_ID,
+            "Portfolio",
+            "portfolio",
+            market.iter().map(|v| v.market_value).sum(),
+        ),
+        snapshot(
+            MANUAL_ASSETS_ACCOUNT_ID,
+            "Manual assets",
+            "manual",
+            manual.iter().map(|v| v.market_value).sum(),
+        ),
+    ])
+}
+
+/// Count every stored liability against net worth. Its balance replaces the account's own
+/// snapshot, which may be missing or filed under another category, so it's never counted twice.
+pub(crate) fn apply_liabilities(
+    snapshots: &mut Vec<BalanceSnapshot>,
+    liabilities: &[Liability],
+    date: &str,
+    now: &str,
+) {
+    for liability in liabilities {
+        let category = if liability.kind == "credit" {
+            "credit"
+        } else {
+            "loan"
+        };
+        match snapshots
+            .iter_mut()
+            .find(|s| s.account_id == liability.account_id)
+        {
+            Some(snapshot) => {
+                snapshot.category = category.to_string();
+                snapshot.current = liability.balance;
+            }
+            None => snapshots.push(BalanceSnapshot {
+                user_id: liability.user_id.clone(),
+                snapshot_id: BalanceSnapshot::snapshot_id(date, &liability.account_id),
+                date: date.to_string(),
+                account_id: liability.account_id.clone(),
+                item_id: liability.item_id.clone(),
+                name: liability.name.clone(),
+                category: category.to_string(),
+                current: liability.balance,
+                available: None,
+                iso_currency_code: None,
+                updated_at: now.to_string(),
+            }),
+        }
+    }
+}
+
+// --- Net Worth ---
+
+#[derive(Deserialize)]
+pub struct NetWorthQuery {
+    pub range: Option<String>,
+}
+
+#[derive(Serialize, Default, Debug, Clone, PartialEq)]
+pub struct NetWorthBreakdown {
+    pub cash: f64,
+    pub investments: f64,
+    pub portfolio: f64,
+    pub manual: f64,
+    pub other: f64,
+    pub credit: f64,
+    pub loans: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NetWorthPoint {
+    pub date: String,
+    pub assets: f64,
+    pub liabilities: f64,
+    pub net_worth: f64,
+    pub breakdown: NetWorthBreakdown,
+}
+
+#[derive(Serialize)]
+pub struct NetWorthResponse {
+    pub range: String,
+    pub points: Vec<NetWorthPoint>,
+}
+
+fn snapshot_date(snapshot: &BalanceSnapshot) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&snapshot.date, "%Y-%m-%d").ok()
+}
+
+fn breakdown_point(date: NaiveDate, breakdown: NetWorthBreakdown) -> NetWorthPoint {
+    let assets = breakdown.cash
+        + breakdown.investments
+        + breakdown.portfolio
+        + breakdown.manual
+        + breakdown.other;
+    let liabilities = breakdown.credit + breakdown.loans;
+    NetWorthPoint {
+        date: date.format("%Y-%m-%d").to_string(),
+        assets,
+        liabilities,
+        net_worth: assets - liabilities,
+        breakdown,
+    }
+}
+
+/// Build a daily series from `start` (or the first snapshot) through `today`, carrying each
+/// account's latest balance forward over days it has no snapshot.
+///
+/// Unlinking an item deletes its snapshots, so a balance is carried until its item writes
+/// a later day's snapshots without the account, however long the job has been missing days.
+pub(crate) fn net_worth_series(
+    snapshots: &[BalanceSnapshot],
+    start: Option<NaiveDate>,
+    today: NaiveDate,
+) -> Vec<NetWorthPoint> {
+    let mut dated: Vec<(NaiveDate, &BalanceSnapshot)> = snapshots
+        .iter()
+        .filter_map(|s| Some((snapshot_date(s)?, s)))
+        .filter(|(d, _)| *d <= today)
+        .collect();
+    dated.sort_by_key(|(d, _)| *d);
+
+    let Some(first) = dated.first().map(|(d, _)| *d) else {
+        return Vec::new();
+    };
+    let start = start.map_or(first, |s| s.max(first));
+
+    let mut latest: HashMap<&str, (NaiveDate, &BalanceSnapshot)> = HashMap::new();
+    // The last day each item was snapshotted
+    let mut item_dates: HashMap<&str, NaiveDate> = HashMap::new();
+    let mut next = 0;
+    let mut points = Vec::new();
+    let mut day = first;
+    while day <= today {
+        while next < dated.len() && dated[next].0 == day {
+            let (date, snapshot) = dated[next];
+            latest.insert(snapshot.account_id.as_str(), (date, snapshot));
+            if !snapshot.item_id.is_empty() {
+                item_dates.insert(snapshot.item_id.as_str(), date);
+            }
+            next += 1;
+        }
+
+        if day >= start {
+            let mut breakdown = NetWorthBreakdown::default();
+            for (date, snapshot) in latest.values() {
+                let dropped = item_dates
+                    .get(snapshot.item_id.as_str())
+                    .is_some_and(|item_date| item_date > date);
+                if dropped {
+                    continue;
+                }
+                let bucket = match snapshot.category.as_str() {
+                    "cash" => &mut breakdown.cash,
+                    "investments" => &mut breakdown.investments,
+                    "portfolio" => &mut breakdown.portfolio,
+                    "manual" => &mut breakdown.manual,
+                    "credit" => &mut breakdown.credit,
+                    "loan" => &mut breakdown.loans,
+                    "other" => &mut breakdown.other,
+                    _ => continue,
+                };
+                *bucket += snapshot.current;
+            }
+            points.push(breakdown_point(day, breakdown));
+        }
+
+        day = match day.succ_opt() {
+            Some(d) => d,
+            None => break,
+        };
+    }
+    points
+}
+
+pub(crate) async fn load_snapshots(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<BalanceSnapshot>, aws_sdk_dynamodb::Error> {
+    let mut snapshots = Vec::new();
+    let mut start_key = None;
+    loop {
+        let output = state
+            .dynamo
+            .query()
+            .table_name("ovaflus-balance-snapshots")
+            .key_condition_expression("user_id = :uid")
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        snapshots.extend(
+            output
+                .items
+                .unwrap_or_default()
+                .iter()
+                .map(item_to_snapshot),
+        );
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+    Ok(snapshots)
+}
+
+pub async fn get_net_worth(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<NetWorthQuery>,
+) -> impl IntoResponse {
+    let range = params.range.unwrap_or_else(|| DEFAULT_RANGE.to_string());
+    if !matches!(
+        range.as_str(),
+        "1m" | "3m" | "6m" | "ytd" | "1y" | "3y" | "5y" | "all"
+    ) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(
+                "range must be one of 1m, 3m, 6m, ytd, 1y, 3y, 5y, all",
+            )),
+        )
+            .into_response();
+    }
+
+    let today = Utc::now().date_naive();
+    let start = range_start(&range, today);
+
+    // Load from the first snapshot so balances can be carried into the range's first day
+    match load_snapshots(&state, &claims.sub).await {
+        Ok(snapshots) => {
+            let points = net_worth_series(&snapshots, start, today);
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(NetWorthResponse { range, points }).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn snapshot(date: &str, account_id: &str, category: &str, current: f64) -> BalanceSnapshot {
+        BalanceSnapshot {
+            user_id: "user-1".to_string(),
+            snapshot_id: BalanceSnapshot::snapshot_id(date, account_id),
+            date: date.to_string(),
+            account_id: account_id.to_string(),
+            item_id: match account_id {
+                PORTFOLIO_ACCOUNT_ID | MANUAL_ASSETS_ACCOUNT_ID => String::new(),
+                _ => "item-1".to_string(),
+            },
+            name: account_id.to_string(),
+            category: category.to_string(),
+            current,
+            available: None,
+            iso_currency_code: Some("USD".to_string()),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn balances_carry_forward_and_split_assets_from_liabilities() {
+        let snapshots = vec![
+            snapshot("2024-05-01", "checking", "cash", 1000.0),
+            snapshot("2024-05-01", "card", "credit", 200.0),
+            snapshot("2024-05-01", PORTFOLIO_ACCOUNT_ID, "portfolio", 5000.0),
+            snapshot("2024-05-03", "checking", "cash", 1500.0),
+            snapshot("2024-05-03", "card", "credit", 200.0),
+        ];
+
+        let points = net_worth_series(&snapshots, None, date("2024-05-03"));
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].assets, 6000.0);
+        assert_eq!(points[0].liabilities, 200.0);
+        assert_eq!(points[1].net_worth, 5800.0);
+        assert_eq!(points[2].breakdown.cash, 1500.0);
+        assert_eq!(points[2].net_worth, 6300.0);
+    }
+
+    #[test]
+    fn synced_brokerage_balances_are_not_double_counted() {
+        let snapshots = vec![
+            snapshot("2024-05-01", "brokerage", "investments_synced", 5000.0),
+            snapshot("2024-05-01", PORTFOLIO_ACCOUNT_ID, "portfolio", 5000.0),
+        ];
+
+        let points = net_worth_series(&snapshots, None, date("2024-05-01"));
+        assert_eq!(points[0].assets, 5000.0);
+        assert_eq!(points[0].breakdown.investments, 0.0);
+    }
+
+    #[test]
+    fn balances_carry_over_missed_days_until_the_item_drops_the_account() {
+        let snapshots = vec![
+            snapshot("2024-04-01", "checking", "cash", 100.0),
+            snapshot("2024-04-01", "closed", "cash", 300.0),
+            snapshot("2024-05-05", "checking", "cash", 100.0),
+        ];
+
+        let points = net_worth_series(&snapshots, Some(date("2024-05-04")), date("2024-05-05"));
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].date, "2024-05-04");
+        // A month without snapshots still carries both balances
+        assert_eq!(points[0].assets, 400.0);
+        // The item's next snapshot no longer has the closed account
+        assert_eq!(points[1].assets, 100.0);
+    }
+
+    #[test]
+    fn liabilities_replace_account_snapshots() {
+        let liability = |account_id: &str, kind: &str, balance: f64| Liability {
+            account_id: account_id.to_string(),
+            user_id: "user-1".to_string(),
+            item_id: "item-1".to_string(),
+            kind: kind.to_string(),
+            name: account_id.to_string(),
+            balance,
+            apr: None,
+            minimum_payment: None,
+            next_payment_due_date: None,
+            updated_at: String::new(),
+        };
+        let mut snapshots = vec![
+            snapshot("2024-05-01", "checking", "cash", 1000.0),
+            snapshot("2024-05-01", "card", "other", 150.0),
+        ];
+
+        apply_liabilities(
+            &mut snapshots,
+            &[
+                liability("card", "credit", 200.0),
+                liability("mortgage", "mortgage", 50_000.0),
+            ],
+            "2024-05-01",
+            "now",
+        );
+
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[1].category, "credit");
+        assert_eq!(snapshots[1].current, 200.0);
+        let points = net_worth_series(&snapshots, None, date("2024-05-01"));
+        assert_eq!(points[0].liabilities, 50_200.0);
+        assert_eq!(points[0].net_worth, -49_200.0);
+    }
+
+    #[test]
+    fn plaid_account_types_map_to_categories() {
+        assert_eq!(account_category("depository", false), "cash");
+        assert_eq!(account_category("investment", false), "investments");
+        assert_eq!(account_category("investment", true), "investments_synced");
+        assert_eq!(account_category("loan", true), "loan");
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::handlers::portfolio::{item_to_holding, value_holdings, Holding, ValuedHolding};
+use crate::handlers::stocks::fetch_company_news;
+use crate::handlers::watchlist::item_to_watchlist_item;
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+const DEFAULT_LOOKBACK_DAYS: i64 = 7;
+/// A story loses half of its recency score every this many hours.
+const RECENCY_HALF_LIFE_HOURS: f64 = 24.0;
+
+#[derive(Deserialize)]
+pub struct NewsFeedQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct NewsStory {
+    pub id: String,
+    pub headline: String,
+    pub summary: String,
+    pub source: String,
+    pub url: String,
+    pub image: String,
+    pub datetime: i64,
+    /// Symbols from the user's holdings and watchlist this story was returned for.
+    pub symbols: Vec<String>,
+    /// Holdings affected by this story.
+    pub holding_ids: Vec<String>,
+    /// Combined portfolio weight (0.0..=1.0) of the affected symbols.
+    pub portfolio_weight: f64,
+    pub score: f64,
+}
+
+#[derive(Serialize)]
+pub struct NewsFeedResponse {
+    pub stories: Vec<NewsStory>,
+    pub page: usize,
+    pub page_size: usize,
+    pub total: usize,
+    /// Symbols whose news could not be fetched; their stories are missing from the feed.
+    pub failed_symbols: Vec<String>,
+}
+
+/// Portfolio weight of each symbol, by market value.
+fn symbol_weights(holdings: &[ValuedHolding]) -> HashMap<String, f64> {
+    let mut weights: HashMap<String, f64> = HashMap::new();
+    for h in holdings {
+        *weights
+            .entry(h.holding.symbol.to_uppercase())
+            .or_insert(0.0) += h.market_value;
+    }
+    let total: f64 = weights.values().sum();
+    if total > 0.0 {
+        for w in weights.values_mut() {
+            *w /= total;
+        }
+    }
+    weights
+}
+
+/// Dedupe key for a Finnhub story: the same article is returned once per related ticker.
+fn story_key(story: &serde_json::Value) -> String {
+    match story.get("id").and_then(|v| v.as_i64()) {
+        Some(id) => id.to_string(),
+        None => story
+            .get("url")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Whether Finnhub lists more than one ticker in the story's `related` field.
+fn mentions_several_tickers(story: &serde_json::Value) -> bool {
+    story
+        .get("related")
+        .and_then(|v| v.as_str())
+        .map(|related| related.split(',').filter(|t| !t.trim().is_empty()).count() > 1)
+        .unwrap_or(false)
+}
+
+fn recency_score(datetime: i64, now: i64) -> f64 {
+    let age_hours = ((now - datetime).max(0) as f64) / 3600.0;
+    0.5_f64.powf(age_hours / RECENCY_HALF_LIFE_HOURS)
+}
+
+/// Merge per-symbol story lists into one deduplicated, ranked feed.
+///
+/// Stories about several tickers, whether Finnhub relates them to several or they were
+/// returned for more than one of the user's symbols, are left out. The rest are ranked by
+/// recency, boosted by the portfolio weight of the affected symbol.
+fn merge_stories(
+    per_symbol: Vec<(String, Vec<serde_json::Value>)>,
+    holdings: &[ValuedHolding],
+    now: i64,
+) -> Vec<NewsStory> {
+    let weights = symbol_weights(holdings);
+    let mut merged: HashMap<String, (serde_json::Value, BTreeSet<String>)> = HashMap::new();
+
+    for (symbol, stories) in per_symbol {
+        for story in stories {
+            let key = story_key(&story);
+            if key.is_empty() || mentions_several_tickers(&story) {
+                continue;
+            }
+            merged
+                .entry(key)
+                .or_insert_with(|| (story, BTreeSet::new()))
+                .1
+                .insert(symbol.clone());
+        }
+    }
+
+    let mut feed: Vec<NewsStory> = merged
+        .into_iter()
+        .filter(|(_, (_, symbols))| symbols.len() == 1)
+        .map(|(key, (story, symbols))| {
+            let str_field = |name: &str| {
+                story
+                    .get(name)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let datetime = story.get("datetime").and_then(|v| v.as_i64()).unwrap_or(0);
+            let portfolio_weight: f64 = symbols
+                .iter()
+                .map(|s| weights.get(s).copied().unwrap_or(0.0))
+                .sum();
+            let holding_ids = holdings
+                .iter()
+                .filter(|h| symbols.contains(&h.holding.symbol.to_uppercase()))
+                .map(|h| h.holding.holding_id.clone())
+                .collect();
+
+            NewsStory {
+                id: key,
+                headline: str_field("headline"),
+                summary: str_field("summary"),
+                source: str_field("source"),
+                url: str_field("url"),
+                image: str_field("image"),
+                datetime,
+                symbols: symbols.into_iter().collect(),
+                holding_ids,
+                portfolio_weight,
+                score: recency_score(datetime, now) * (1.0 + portfolio_weight),
+            }
+        })
+        .collect();
+
+    feed.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.datetime.cmp(&a.datetime))
+    });
+    feed
+}
+
+pub async fn get_news_feed(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<NewsFeedQuery>,
+) -> impl IntoResponse {
+    let today = Utc::now().date_naive();
+    let from = match params.from.as_deref() {
+        Some(s) => match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            Ok(d) => d,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiError::new("from must be YYYY-MM-DD")),
+                )
+                    .into_response();
+            }
+        },
+        None => today - chrono::Duration::days(DEFAULT_LOOKBACK_DAYS),
+    };
+    let to = match params.to.as_deref() {
+        Some(s) => match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            Ok(d) => d,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiError::new("to must be YYYY-MM-DD")),
+                )
+                    .into_response();
+            }
+        },
+        None => today,
+    };
+    if from > to {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("from must not be after to")),
+        )
+            .into_response();
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let page_size = params
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let holdings_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let holdings: Vec<Holding> = match holdings_result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(item_to_holding)
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let watchlist_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-watchlist")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let watchlist_symbols: Vec<String> = match watchlist_result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(|item| item_to_watchlist_item(item).symbol)
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    // Company news only exists for exchange-traded holdings
+    let symbols: BTreeSet<String> = holdings
+        .iter()
+        .filter(|h| matches!(h.asset_class.as_str(), "equity" | "etf"))
+        .map(|h| h.symbol.clone())
+        .chain(watchlist_symbols)
+        .map(|s| s.to_uppercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    // Fetch company news for every symbol in parallel
+    let client = reqwest::Client::new();
+    let from_str = from.format("%Y-%m-%d").to_string();
+    let to_str = to.format("%Y-%m-%d").to_string();
+    let mut tasks = JoinSet::new();
+    for symbol in symbols {
+        let client = client.clone();
+        let api_key = state.finnhub_api_key.clone();
+        let (from_str, to_str) = (from_str.clone(), to_str.clone());
+        tasks.spawn(async move {
+            let result = fetch_company_news(&client, &api_key, &symbol, &from_str, &to_str).await;
+            (symbol, result)
+        });
+    }
+
+    let requested = tasks.len();
+    let mut per_symbol = Vec::new();
+    let mut failed_symbols = Vec::new();
+    let mut last_error = None;
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((symbol, Ok(stories))) => per_symbol.push((symbol, stories)),
+            Ok((symbol, Err(e))) => {
+                tracing::warn!("News fetch for {symbol} failed: {e}");
+                failed_symbols.push(symbol);
+                last_error = Some(e);
+            }
+            Err(e) => {
+                tracing::warn!("News fetch task failed: {e}");
+                last_error = Some(e.to_string());
+            }
+        }
+    }
+    if requested > 0 && per_symbol.is_empty() {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiError::new(format!(
+                "Failed to fetch news: {}",
+                last_error.unwrap_or_default()
+            ))),
+        )
+            .into_response();
+    }
+    failed_symbols.sort();
+
+    let valued = value_holdings(&state, holdings).await;
+    let feed = merge_stories(per_symbol, &valued, Utc::now().timestamp());
+    let total = feed.len();
+    let stories: Vec<NewsStory> = feed
+        .into_iter()
+        .skip((page - 1) * page_size)
+        .take(page_size)
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(
+            serde_json::to_value(NewsFeedResponse {
+                stories,
+                page,
+                page_size,
+                total,
+                failed_symbols,
+            })
+            .unwrap(),
+        ),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holding(id: &str, symbol: &str, market_value: f64) -> ValuedHolding {
+        ValuedHolding {
+            holding: Holding {
+                holding_id: id.to_string(),
+                user_id: "user-1".to_string(),
+                symbol: symbol.to_string(),
+                shares: 1.0,
+                avg_cost: 1.0,
+                asset_class: "equity".to_string(),
+                manual_price: None,
+                source: "manual".to_string(),
+                created_at: String::new(),
+                updated_at: String::new(),
+            },
+            price: Some(market_value),
+            market_value,
+            cost_basis: 1.0,
+        }
+    }
+
+    fn story(id: i64, datetime: i64) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "headline": format!("Story {id}"),
+            "datetime": datetime,
+            "url": format!("https://example.com/{id}"),
+        })
+    }
+
+    #[test]
+    fn stories_about_several_tickers_are_dropped() {
+        let holdings = vec![holding("h1", "AAPL", 1000.0), holding("h2", "MSFT", 1000.0)];
+        let mut related = story(2, 1000);
+        related["related"] = serde_json::json!("AAPL,GOOGL");
+        let per_symbol = vec![
+            (
+                "AAPL".to_string(),
+                vec![story(1, 1000), related, story(3, 1000)],
+            ),
+            ("MSFT".to_string(), vec![story(1, 1000)]),
+        ];
+
+        let feed = merge_stories(per_symbol, &holdings, 1000);
+        assert_eq!(feed.len(), 1);
+        assert_eq!(feed[0].id, "3");
+        assert_eq!(feed[0].symbols, vec!["AAPL"]);
+        assert_eq!(feed[0].holding_ids, vec!["h1"]);
+        assert!((feed[0].portfolio_weight - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn larger_market_value_ranks_first_at_equal_recency() {
+        let holdings = vec![holding("h1", "AAPL", 900.0), holding("h2", "TSLA", 100.0)];
+        let per_symbol = vec![
+            ("TSLA".to_string(), vec![story(2, 1000)]),
+            ("AAPL".to_string(), vec![story(1, 1000)]),
+        ];
+
+        let feed = merge_stories(per_symbol, &holdings, 1000);
+        assert_eq!(feed[0].symbols, vec!["AAPL"]);
+        assert_eq!(feed[1].symbols, vec!["TSLA"]);
+    }
+
+    #[test]
+    fn newer_story_outranks_older_for_watchlist_symbol() {
+        let per_symbol = vec![("NVDA".to_string(), vec![story(1, 0), story(2, 48 * 3600)])];
+
+        let feed = merge_stories(per_symbol, &[], 48 * 3600);
+        assert_eq!(feed[0].id, "2");
+        assert!(feed[0].holding_ids.is_empty());
+        assert!((feed[1].score - 0.25).abs() < 1e-9);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 
 use aws_sdk_dynamodb::types::AttributeValue;
@@ -7,40 +8,74 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::crypto::{token_context, CryptoError, Secret};
+use crate::db::dynamo::{batch_write, delete_request, is_conditional_check_failure, query_by_pk};
+use crate::db::transaction_keys::{budget_key, date_key};
+use crate::handlers::budgets::{load_budgets, Budget};
+use crate::handlers::categories::{
+    app_category, load_category_overrides, match_budget, FALLBACK_CATEGORY,
+};
+use crate::handlers::category_model::{user_model, CategoryModel, CategorySuggestion};
+use crate::handlers::liabilities::{liability_to_item, load_liabilities, Liability};
+use crate::handlers::merchants::{
+    global_merchants, register_global_merchant, transaction_merchant, user_merchants, Merchant,
+    NormalizedMerchant,
+};
+use crate::handlers::net_worth::{
+    account_category, apply_liabilities, snapshot_portfolio, snapshot_to_item, BalanceSnapshot,
+};
+use crate::handlers::portfolio::{
+    holding_to_item, item_to_holding, trade_to_item, Holding, Trade, PLAID_SOURCE,
+};
+use crate::handlers::profile::load_profile_country;
+use crate::handlers::rules::{load_rules, RuleInput, RuleOutcome, RuleSet};
+use crate::handlers::transactions::{
+    apply_spent_changes, budget_allocations, clear_splits, counted_allocations, item_split_index,
+    item_splits, split_index_entries, splits_add_up, update_split_index, ATTR_USER_EDITED,
+};
 use crate::middleware::auth::AuthUser;
 use crate::models::ApiError;
+use crate::plaid::link;
+use crate::plaid::types::{
+    Account, InvestmentTransaction, InvestmentsHoldingsGetResponse,
+    InvestmentsTransactionsGetRequest, InvestmentsTransactionsOptions, Item,
+    LiabilitiesGetResponse, LinkTokenCreateRequest, LinkUser, Security, Transaction,
+};
+use crate::plaid::PlaidError;
 use crate::AppState;
 
-fn plaid_base_url(env: &str) -> String {
-    format!("https://{}.plaid.com", env)
-}
-
-#[derive(Serialize)]
-#[allow(dead_code)]
-struct PlaidAuth {
-    client_id: String,
-    secret: String,
-}
-
 // --- Create Link Token ---
 
-#[derive(Serialize)]
-struct CreateLinkTokenBody {
-    client_id: String,
-    secret: String,
-    user: PlaidUser,
-    client_name: String,
-    products: Vec<String>,
-    country_codes: Vec<String>,
-    language: String,
+/// Client options accepted by both new and update-mode Link tokens.
+#[derive(Deserialize, Default)]
+pub struct LinkClientOptions {
+    /// Device locale such as `fr-CA`; Link falls back to the default language
+    pub locale: Option<String>,
+    /// OAuth redirect URI registered for the iOS or web app
+    pub redirect_uri: Option<String>,
+    /// Set by the Android app in place of a redirect URI
+    pub android_package_name: Option<String>,
 }
 
-#[derive(Serialize)]
-struct PlaidUser {
-    client_user_id: String,
+#[derive(Deserialize, Default)]
+pub struct CreateLinkTokenRequest {
+    /// Link a brokerage account: request the `investments` product, with transactions optional
+    #[serde(default)]
+    pub investments: bool,
+    /// Also request `liabilities` for credit cards, student loans and mortgages
+    #[serde(default)]
+    pub liabilities: bool,
+    /// Further optional products, from the configured allowed list
+    #[serde(default)]
+    pub products: Vec<String>,
+    /// Account subtypes to offer, by account type, e.g. `{"depository": ["checking"]}`
+    #[serde(default)]
+    pub account_filters: BTreeMap<String, Vec<String>>,
+    #[serde(flatten)]
+    pub options: LinkClientOptions,
 }
 
 #[derive(Serialize)]
@@ -48,67 +83,103 @@ pub struct LinkTokenResponse {
     pub link_token: String,
 }
 
+/// A Link token request for `user_id` with the configured client name and the user's country,
+/// language and platform settings; products are left for the caller.
+async fn link_token_request(
+    state: &AppState,
+    user_id: &str,
+    options: &LinkClientOptions,
+) -> Result<LinkTokenCreateRequest, axum::response::Response> {
+    let config = &state.plaid_link;
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response();
+
+    let platform = config
+        .platform(
+            options.redirect_uri.as_deref(),
+            options.android_package_name.as_deref(),
+        )
+        .map_err(bad_request)?;
+    let country = load_profile_country(state, user_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response()
+    })?;
+    let country_codes = config
+        .country_codes(country.as_deref())
+        .map_err(bad_request)?;
+
+    Ok(LinkTokenCreateRequest {
+        user: LinkUser {
+            client_user_id: user_id.to_string(),
+        },
+        client_name: config.client_name.clone(),
+        products: Vec::new(),
+        optional_products: Vec::new(),
+        access_token: None,
+        country_codes,
+        language: config.language(options.locale.as_deref()),
+        redirect_uri: platform.redirect_uri,
+        android_package_name: platform.android_package_name,
+        account_filters: None,
+    })
+}
+
 pub async fn create_link_token(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
+    request: Option<Json<CreateLinkTokenRequest>>,
 ) -> impl IntoResponse {
-    let client = reqwest::Client::new();
-    let base = plaid_base_url(&state.plaid_env);
-
-    let body = CreateLinkTokenBody {
-        client_id: state.plaid_client_id.clone(),
-        secret: state.plaid_secret.clone(),
-        user: PlaidUser {
-            client_user_id: claims.sub.clone(),
-        },
-        client_name: "Flus".to_string(),
-        products: vec!["transactions".to_string()],
-        country_codes: vec!["US".to_string()],
-        language: "en".to_string(),
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let config = &state.plaid_link;
+
+    let extra_products = match config.extra_products(&request.products) {
+        Ok(products) => products,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+    let account_filters = match link::account_filters(&request.account_filters) {
+        Ok(filters) => filters,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
     };
 
-    let result = client
-        .post(format!("{}/link/token/create", base))
-        .json(&body)
-        .send()
-        .await;
+    // Brokerages often don't support transactions, so it can't be required alongside investments
+    let (products, mut optional_products) = if request.investments {
+        let optional = config
+            .products
+            .iter()
+            .filter(|p| *p != "investments")
+            .cloned()
+            .collect();
+        (vec!["investments".to_string()], optional)
+    } else {
+        (config.products.clone(), Vec::new())
+    };
+    // Optional so accounts without debts can still be linked
+    let liabilities = request.liabilities.then(|| "liabilities".to_string());
+    for product in liabilities.into_iter().chain(extra_products) {
+        if !products.contains(&product) && !optional_products.contains(&product) {
+            optional_products.push(product);
+        }
+    }
 
-    match result {
-        Ok(resp) => match resp.json::<serde_json::Value>().await {
-            Ok(data) => {
-                if let Some(link_token) = data.get("link_token").and_then(|v| v.as_str()) {
-                    (
-                        StatusCode::OK,
-                        Json(LinkTokenResponse {
-                            link_token: link_token.to_string(),
-                        }),
-                    )
-                        .into_response()
-                } else {
-                    (
-                        StatusCode::BAD_GATEWAY,
-                        Json(ApiError::new(format!(
-                            "Plaid error: {}",
-                            serde_json::to_string(&data).unwrap_or_default()
-                        ))),
-                    )
-                        .into_response()
-                }
-            }
-            Err(e) => (
-                StatusCode::BAD_GATEWAY,
-                Json(ApiError::new(format!(
-                    "Failed to parse Plaid response: {}",
-                    e
-                ))),
-            )
-                .into_response(),
-        },
-        Err(e) => (
-            StatusCode::BAD_GATEWAY,
-            Json(ApiError::new(format!("Plaid request failed: {}", e))),
+    let mut body = match link_token_request(&state, &claims.sub, &request.options).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    body.products = products;
+    body.optional_products = optional_products;
+    body.account_filters = account_filters;
+
+    match state.plaid.link_token_create(&body).await {
+        Ok(resp) => (
+            StatusCode::OK,
+            Json(LinkTokenResponse {
+                link_token: resp.link_token,
+            }),
         )
             .into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response(),
     }
 }
 
@@ -131,86 +202,81 @@ pub struct ExchangeTokenRequest {
     pub accounts: Vec<PlaidAccountInfo>,
 }
 
-#[derive(Serialize)]
-struct ExchangeTokenBody {
-    client_id: String,
-    secret: String,
-    public_token: String,
-}
-
 pub async fn exchange_token(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
     Json(body): Json<ExchangeTokenRequest>,
 ) -> impl IntoResponse {
-    let client = reqwest::Client::new();
-    let base = plaid_base_url(&state.plaid_env);
-
-    let exchange_body = ExchangeTokenBody {
-        client_id: state.plaid_client_id.clone(),
-        secret: state.plaid_secret.clone(),
-        public_token: body.public_token.clone(),
+    let exchanged = match state
+        .plaid
+        .item_public_token_exchange(&body.public_token)
+        .await
+    {
+        Ok(exchanged) => exchanged,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response();
+        }
     };
+    store_linked_item(
+        &state,
+        &claims.sub,
+        &exchanged.access_token,
+        &exchanged.item_id,
+        &body,
+    )
+    .await
+}
 
-    let result = client
-        .post(format!("{}/item/public_token/exchange", base))
-        .json(&exchange_body)
-        .send()
-        .await;
+/// Store a newly linked item and the accounts picked in Link, answering with what was linked.
+///
+/// Which products to sync is read from the item itself, so it also covers products added
+/// through the configurable product list rather than the `investments`/`liabilities` options.
+pub(crate) async fn store_linked_item(
+    state: &AppState,
+    user_id: &str,
+    access_token: &Secret,
+    item_id: &str,
+    body: &ExchangeTokenRequest,
+) -> axum::response::Response {
+    let now = Utc::now().to_rfc3339();
 
-    let plaid_resp = match result {
-        Ok(resp) => match resp.json::<serde_json::Value>().await {
-            Ok(data) => data,
-            Err(e) => {
-                return (
-                    StatusCode::BAD_GATEWAY,
-                    Json(ApiError::new(format!(
-                        "Failed to parse Plaid response: {}",
-                        e
-                    ))),
-                )
-                    .into_response();
-            }
-        },
+    let item = match state.plaid.item_get(access_token).await {
+        Ok(resp) => resp.item,
         Err(e) => {
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ApiError::new(format!("Plaid request failed: {}", e))),
-            )
-                .into_response();
+            // Don't leave an item at Plaid that nothing here knows about
+            if let Err(remove_error) = state.plaid.item_remove(access_token).await {
+                tracing::warn!("Removing Plaid item {item_id} failed: {remove_error}");
+            }
+            return (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response();
         }
     };
 
-    let access_token = match plaid_resp.get("access_token").and_then(|v| v.as_str()) {
-        Some(t) => t.to_string(),
-        None => {
+    let encrypted = match state
+        .token_cipher
+        .encrypt(access_token, &token_context(user_id, item_id))
+        .await
+    {
+        Ok(encrypted) => encrypted,
+        Err(e) => {
             return (
-                StatusCode::BAD_GATEWAY,
-                Json(ApiError::new(format!(
-                    "Plaid error: {}",
-                    serde_json::to_string(&plaid_resp).unwrap_or_default()
-                ))),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(e.to_string())),
             )
                 .into_response();
         }
     };
 
-    let item_id = plaid_resp
-        .get("item_id")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
-
-    let now = Utc::now().to_rfc3339();
-
-    // Store Plaid item in DynamoDB (for access_token lookup)
-    let put_result = state
+    // Store Plaid item in DynamoDB; the access token is only ever stored encrypted
+    let mut put = state
         .dynamo
         .put_item()
         .table_name("ovaflus-plaid-items")
-        .item("user_id", AttributeValue::S(claims.sub.clone()))
-        .item("item_id", AttributeValue::S(item_id.clone()))
-        .item("access_token", AttributeValue::S(access_token))
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("item_id", AttributeValue::S(item_id.to_string()));
+    for (name, value) in encrypted.attributes() {
+        put = put.item(name, value);
+    }
+    let put_result = put
         .item(
             "institution_id",
             AttributeValue::S(body.institution_id.clone()),
@@ -219,6 +285,15 @@ pub async fn exchange_token(
             "institution_name",
             AttributeValue::S(body.institution_name.clone()),
         )
+        .item(
+            "investments",
+            AttributeValue::Bool(item.has_product("investments")),
+        )
+        .item(
+            "liabilities",
+            AttributeValue::Bool(item.has_product("liabilities")),
+        )
+        .item("status", AttributeValue::S(ITEM_HEALTHY.to_string()))
         .item("created_at", AttributeValue::S(now.clone()))
         .send()
         .await;
@@ -238,9 +313,9 @@ pub async fn exchange_token(
             .dynamo
             .put_item()
             .table_name("ovaflus-plaid-accounts")
-            .item("user_id", AttributeValue::S(claims.sub.clone()))
+            .item("user_id", AttributeValue::S(user_id.to_string()))
             .item("account_id", AttributeValue::S(account.id.clone()))
-            .item("item_id", AttributeValue::S(item_id.clone()))
+            .item("item_id", AttributeValue::S(item_id.to_string()))
             .item(
                 "institution_id",
                 AttributeValue::S(body.institution_id.clone()),
@@ -295,12 +370,18 @@ pub async fn exchange_token(
 #[derive(Serialize)]
 pub struct LinkedAccount {
     pub id: String,
+    pub item_id: String,
     pub institution_id: String,
     pub institution_name: String,
     pub account_name: String,
     pub account_type: String,
     pub mask: String,
     pub linked_at: String,
+    /// Balances from the last refresh, absent until the first one
+    pub current_balance: Option<f64>,
+    pub available_balance: Option<f64>,
+    pub balance_updated_at: Option<String>,
+    pub health: ItemHealth,
 }
 
 pub async fn get_accounts(
@@ -316,6 +397,38 @@ pub async fn get_accounts(
         .send()
         .await;
 
+    let items_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-plaid-items")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let health: HashMap<String, ItemHealth> = match items_result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(|item| {
+                let item_id = item
+                    .get("item_id")
+                    .and_then(|v| v.as_s().ok())
+                    .cloned()
+                    .unwrap_or_default();
+                (item_id, item_health(item))
+            })
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
     match result {
         Ok(output) => {
             let accounts: Vec<LinkedAccount> = output
@@ -328,6 +441,11 @@ pub async fn get_accounts(
                         .and_then(|v| v.as_s().ok())
                         .cloned()
                         .unwrap_or_default(),
+                    item_id: item
+                        .get("item_id")
+                        .and_then(|v| v.as_s().ok())
+                        .cloned()
+                        .unwrap_or_default(),
                     institution_id: item
                         .get("institution_id")
                         .and_then(|v| v.as_s().ok())
@@ -358,6 +476,24 @@ pub async fn get_accounts(
                         .and_then(|v| v.as_s().ok())
                         .cloned()
                         .unwrap_or_default(),
+                    current_balance: item
+                        .get("current_balance")
+                        .and_then(|v| v.as_n().ok())
+                        .and_then(|n| n.parse::<f64>().ok()),
+                    available_balance: item
+                        .get("available_balance")
+                        .and_then(|v| v.as_n().ok())
+                        .and_then(|n| n.parse::<f64>().ok()),
+                    balance_updated_at: item
+                        .get("balance_updated_at")
+                        .and_then(|v| v.as_s().ok())
+                        .cloned(),
+                    health: item
+                        .get("item_id")
+                        .and_then(|v| v.as_s().ok())
+                        .and_then(|id| health.get(id))
+                        .cloned()
+                        .unwrap_or_default(),
                 })
                 .collect();
             (
@@ -374,151 +510,186 @@ pub async fn get_accounts(
     }
 }
 
-// --- Sync Transactions ---
-
-#[derive(Serialize)]
-struct TransactionsSyncBody {
-    client_id: String,
-    secret: String,
-    access_token: String,
-}
+// --- Item Health ---
 
-pub async fn sync_transactions(
-    State(state): State<Arc<AppState>>,
-    AuthUser(claims): AuthUser,
-) -> impl IntoResponse {
-    let client = reqwest::Client::new();
-    let base = plaid_base_url(&state.plaid_env);
+pub(crate) const ITEM_HEALTHY: &str = "healthy";
+pub(crate) const ITEM_LOGIN_REQUIRED: &str = "login_required";
+pub(crate) const ITEM_PENDING_EXPIRATION: &str = "pending_expiration";
+pub(crate) const ITEM_REVOKED: &str = "revoked";
+/// An item whose consent expires within this many days is flagged for re-linking.
+const PENDING_EXPIRATION_DAYS: i64 = 7;
 
-    // Get all plaid items for this user
-    let items_result = state
-        .dynamo
-        .query()
-        .table_name("ovaflus-plaid-items")
-        .key_condition_expression("user_id = :uid")
-        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
-        .send()
-        .await;
+#[derive(Serialize, Clone)]
+pub struct ItemHealth {
+    /// "healthy", "login_required", "pending_expiration" or "revoked"
+    pub status: String,
+    pub last_error_code: Option<String>,
+    pub last_error_at: Option<String>,
+    pub last_synced_at: Option<String>,
+}
 
-    let items = match items_result {
-        Ok(output) => output.items.unwrap_or_default(),
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiError::new(format!("Database error: {}", e))),
-            )
-                .into_response();
+impl Default for ItemHealth {
+    fn default() -> Self {
+        ItemHealth {
+            status: ITEM_HEALTHY.to_string(),
+            last_error_code: None,
+            last_error_at: None,
+            last_synced_at: None,
         }
-    };
+    }
+}
 
-    let mut all_transactions: Vec<serde_json::Value> = Vec::new();
-    let mut total_added: usize = 0;
-    let mut total_modified: usize = 0;
+fn item_health(item: &HashMap<String, AttributeValue>) -> ItemHealth {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    ItemHealth {
+        // Items linked before health tracking have no status
+        status: s("status").unwrap_or_else(|| ITEM_HEALTHY.to_string()),
+        last_error_code: s("last_error_code"),
+        last_error_at: s("last_error_at"),
+        last_synced_at: s("last_synced_at"),
+    }
+}
 
-    for item in &items {
-        let access_token = match item.get("access_token").and_then(|v| v.as_s().ok()) {
-            Some(t) => t.clone(),
-            None => continue,
-        };
+/// Status after a failed call. Errors that don't say anything about the login, such as an
+/// institution outage, leave the status as it was.
+fn status_after_error(previous: &str, code: Option<&str>) -> String {
+    match code {
+        Some("ITEM_LOGIN_REQUIRED" | "INVALID_CREDENTIALS" | "INVALID_MFA") => {
+            ITEM_LOGIN_REQUIRED.to_string()
+        }
+        Some("USER_PERMISSION_REVOKED" | "ACCESS_NOT_GRANTED" | "ITEM_NOT_FOUND") => {
+            ITEM_REVOKED.to_string()
+        }
+        _ if previous.is_empty() => ITEM_HEALTHY.to_string(),
+        _ => previous.to_string(),
+    }
+}
 
-        let sync_body = TransactionsSyncBody {
-            client_id: state.plaid_client_id.clone(),
-            secret: state.plaid_secret.clone(),
-            access_token,
+/// Status after a successful call. Responses that include the Plaid `item` reveal when its
+/// consent expires; without one, a pending expiration is kept until a response clears it.
+fn status_after_success(
+    previous: &str,
+    plaid_item: Option<&Item>,
+    now: DateTime<Utc>,
+) -> &'static str {
+    let Some(plaid_item) = plaid_item else {
+        return if previous == ITEM_PENDING_EXPIRATION {
+            ITEM_PENDING_EXPIRATION
+        } else {
+            ITEM_HEALTHY
         };
-
-        let result = client
-            .post(format!("{}/transactions/sync", base))
-            .json(&sync_body)
-            .send()
-            .await;
-
-        if let Ok(resp) = result {
-            if let Ok(data) = resp.json::<serde_json::Value>().await {
-                if let Some(added) = data.get("added").and_then(|v| v.as_array()) {
-                    total_added += added.len();
-                    for txn in added {
-                        all_transactions.push(map_plaid_transaction(txn));
-                    }
-                }
-                if let Some(modified) = data.get("modified").and_then(|v| v.as_array()) {
-                    total_modified += modified.len();
-                    for txn in modified {
-                        all_transactions.push(map_plaid_transaction(txn));
-                    }
-                }
-            }
+    };
+    let expires = plaid_item
+        .consent_expiration_time
+        .as_deref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+    match expires {
+        Some(at) if at.with_timezone(&Utc) - now <= Duration::days(PENDING_EXPIRATION_DAYS) => {
+            ITEM_PENDING_EXPIRATION
         }
+        _ => ITEM_HEALTHY,
     }
-
-    (
-        StatusCode::OK,
-        Json(serde_json::json!({
-            "transactions": all_transactions,
-            "added": total_added,
-            "modified": total_modified,
-        })),
-    )
-        .into_response()
 }
 
-fn map_plaid_transaction(txn: &serde_json::Value) -> serde_json::Value {
-    let category = txn
-        .get("category")
-        .and_then(|v| v.as_array())
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.as_str().map(String::from))
-                .collect::<Vec<String>>()
-        })
+/// Record the outcome of a Plaid call on the item's row.
+async fn record_item_health(
+    state: &AppState,
+    user_id: &str,
+    item: &HashMap<String, AttributeValue>,
+    outcome: Result<Option<&Item>, &PlaidError>,
+) {
+    let item_id = item
+        .get("item_id")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
         .unwrap_or_default();
+    let previous = item_health(item).status;
+    let now = Utc::now();
 
-    serde_json::json!({
-        "id": txn.get("transaction_id").and_then(|v| v.as_str()).unwrap_or(""),
-        "account_id": txn.get("account_id").and_then(|v| v.as_str()).unwrap_or(""),
-        "name": txn.get("name").and_then(|v| v.as_str()).unwrap_or(""),
-        "amount": txn.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0),
-        "date": txn.get("date").and_then(|v| v.as_str()).unwrap_or(""),
-        "category": category,
-        "pending": txn.get("pending").and_then(|v| v.as_bool()).unwrap_or(false),
-    })
-}
+    let update = state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-plaid-items")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("item_id", AttributeValue::S(item_id.clone()))
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":now", AttributeValue::S(now.to_rfc3339()));
 
-// --- Unlink Account ---
+    let update = match outcome {
+        Ok(plaid_item) => update
+            .update_expression(
+                "SET #status = :status, last_synced_at = :now \
+                 REMOVE last_error_code, last_error_at",
+            )
+            .expression_attribute_values(
+                ":status",
+                AttributeValue::S(status_after_success(&previous, plaid_item, now).to_string()),
+            ),
+        Err(e) => update
+            .update_expression(
+                "SET #status = :status, last_error_code = :code, last_error_at = :now",
+            )
+            .expression_attribute_values(
+                ":status",
+                AttributeValue::S(status_after_error(&previous, e.code.as_deref())),
+            )
+            .expression_attribute_values(
+                ":code",
+                AttributeValue::S(
+                    e.code
+                        .clone()
+                        .unwrap_or_else(|| "REQUEST_FAILED".to_string()),
+                ),
+            ),
+    };
 
-#[derive(Serialize)]
-struct ItemRemoveBody {
-    client_id: String,
-    secret: String,
-    access_token: String,
+    if let Err(e) = update.send().await {
+        tracing::warn!("Recording health for Plaid item {item_id} failed: {e}");
+    }
 }
 
-pub async fn unlink_account(
+/// Decrypt the access token stored on a plaid-items row.
+pub(crate) async fn item_access_token(
+    state: &AppState,
+    user_id: &str,
+    item: &HashMap<String, AttributeValue>,
+) -> Result<Secret, CryptoError> {
+    let item_id = item
+        .get("item_id")
+        .and_then(|v| v.as_s().ok())
+        .map(String::as_str)
+        .unwrap_or_default();
+    state
+        .token_cipher
+        .item_token(item, &token_context(user_id, item_id))
+        .await
+}
+
+/// Create a Link token in update mode so the user can repair a broken login.
+pub async fn create_update_link_token(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
     Path(item_id): Path<String>,
+    options: Option<Json<LinkClientOptions>>,
 ) -> impl IntoResponse {
-    let client = reqwest::Client::new();
-    let base = plaid_base_url(&state.plaid_env);
-
-    // Get the access token from DynamoDB
+    let options = options.map(|Json(o)| o).unwrap_or_default();
     let get_result = state
         .dynamo
         .get_item()
         .table_name("ovaflus-plaid-items")
         .key("user_id", AttributeValue::S(claims.sub.clone()))
-        .key("item_id", AttributeValue::S(item_id.clone()))
+        .key("item_id", AttributeValue::S(item_id))
         .send()
         .await;
 
     let access_token = match get_result {
         Ok(output) => match output.item {
-            Some(item) => match item.get("access_token").and_then(|v| v.as_s().ok()) {
-                Some(t) => t.clone(),
-                None => {
+            Some(item) => match item_access_token(&state, &claims.sub, &item).await {
+                Ok(t) => t,
+                Err(e) => {
                     return (
                         StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(ApiError::new("Missing access token")),
+                        Json(ApiError::new(e.to_string())),
                     )
                         .into_response();
                 }
@@ -540,35 +711,1865 @@ pub async fn unlink_account(
         }
     };
 
-    // Remove from Plaid
-    let remove_body = ItemRemoveBody {
-        client_id: state.plaid_client_id.clone(),
-        secret: state.plaid_secret.clone(),
-        access_token,
+    let mut body = match link_token_request(&state, &claims.sub, &options).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
+    body.access_token = Some(access_token);
 
-    let _ = client
-        .post(format!("{}/item/remove", base))
-        .json(&remove_body)
-        .send()
-        .await;
+    match state.plaid.link_token_create(&body).await {
+        Ok(resp) => (
+            StatusCode::OK,
+            Json(LinkTokenResponse {
+                link_token: resp.link_token,
+            }),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response(),
+    }
+}
 
-    // Delete from DynamoDB
-    let delete_result = state
+// --- Sync Transactions ---
+
+/// The cursor `/transactions/sync` returned last, so the next sync resumes where it left off.
+const ATTR_TRANSACTIONS_CURSOR: &str = "transactions_cursor";
+/// Consecutive failed syncs after which the scheduled job leaves an item alone. A successful
+/// sync from the app resets the count. With the backoff doubling up to a day, the job keeps
+/// retrying for a little over four days.
+pub(crate) const MAX_SYNC_FAILURES: u32 = 10;
+const SYNC_BACKOFF_BASE_MINUTES: i64 = 30;
+const SYNC_BACKOFF_MAX_HOURS: i64 = 24;
+
+/// A user's category overrides and budgets, loaded once for all of their items.
+pub struct SyncContext {
+    pub overrides: HashMap<String, String>,
+    pub budgets: Vec<Budget>,
+    pub rules: RuleSet,
+    pub model: CategoryModel,
+    /// The user's own merchant directory entries, by merchant id
+    pub merchants: HashMap<String, Merchant>,
+}
+
+impl SyncContext {
+    pub async fn load(state: &AppState, user_id: &str) -> Result<Self, aws_sdk_dynamodb::Error> {
+        Ok(SyncContext {
+            overrides: load_category_overrides(state, user_id).await?,
+            budgets: load_budgets(state, user_id).await?,
+            rules: load_rules(state, user_id).await?,
+            model: user_model(state, user_id).await?,
+            merchants: user_merchants(state, user_id).await?,
+        })
+    }
+
+    /// Category, budget, merchant and tags for a synced transaction. A rule's category wins,
+    /// then the user's own merchant entry, then the mapped category; the shared directory only
+    /// fills in for the catch-all. The budget follows the category unless a rule sets it.
+    fn assign(
+        &self,
+        txn: &Transaction,
+        merchant: Option<&NormalizedMerchant>,
+        globals: &HashMap<String, Merchant>,
+    ) -> RuleOutcome {
+        let own = merchant.and_then(|m| self.merchants.get(&m.merchant_id));
+        let shared = merchant.and_then(|m| globals.get(&m.merchant_id));
+        let name = own
+            .or(shared)
+            .map(|m| m.display_name.clone())
+            .or_else(|| merchant.map(|m| m.display_name.clone()));
+
+        let mut outcome = self.rules.apply(&RuleInput {
+            description: &txn.name,
+            merchant: name.as_deref(),
+            amount: txn.amount,
+            account_id: Some(&txn.account_id),
+        });
+        let category = outcome.category.get_or_insert_with(|| {
+            let mapped = app_category(txn, &self.overrides);
+            let default = |m: Option<&Merchant>| m.and_then(|m| m.default_category.clone());
+            default(own)
+                .or_else(|| default(shared).filter(|_| mapped == FALLBACK_CATEGORY))
+                .unwrap_or(mapped)
+        });
+        if outcome.budget_id.is_none() {
+            outcome.budget_id = match_budget(category, &self.budgets).map(|b| b.budget_id.clone());
+        }
+        if outcome.merchant.is_none() {
+            outcome.merchant = name;
+        }
+        outcome
+    }
+}
+
+#[derive(Default)]
+pub struct ItemTransactionsSync {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+    /// Added or modified transactions that were assigned to a budget
+    pub assigned: usize,
+    pub transactions: Vec<serde_json::Value>,
+    /// Problems hit along the way, as "{item_id}: {error}"
+    pub errors: Vec<String>,
+    /// Set when the sync stopped before reaching the end of the item's changes
+    pub incomplete: bool,
+}
+
+pub(crate) fn sync_failures(item: &HashMap<String, AttributeValue>) -> u32 {
+    item.get("sync_failures")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0)
+}
+
+/// How long the scheduled job waits before retrying an item that has failed `failures` times
+/// in a row: doubling from 30 minutes, capped at a day.
+fn sync_backoff(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    Duration::minutes(SYNC_BACKOFF_BASE_MINUTES * 2i64.pow(doublings))
+        .min(Duration::hours(SYNC_BACKOFF_MAX_HOURS))
+}
+
+/// Count consecutive failures on the item's row, and when the scheduled job may retry it.
+async fn record_sync_attempt(
+    state: &AppState,
+    user_id: &str,
+    item: &HashMap<String, AttributeValue>,
+    failed: bool,
+) {
+    let previous = sync_failures(item);
+    if !failed && previous == 0 {
+        return;
+    }
+    let item_id = item
+        .get("item_id")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+
+    let update = state
         .dynamo
-        .delete_item()
+        .update_item()
         .table_name("ovaflus-plaid-items")
-        .key("user_id", AttributeValue::S(claims.sub.clone()))
-        .key("item_id", AttributeValue::S(item_id))
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("item_id", AttributeValue::S(item_id.clone()));
+    let update = if failed {
+        let failures = previous + 1;
+        let retry_after = Utc::now() + sync_backoff(failures);
+        update
+            .update_expression("SET sync_failures = :failures, sync_retry_after = :retry_after")
+            .expression_attribute_values(":failures", AttributeValue::N(failures.to_string()))
+            .expression_attribute_values(
+                ":retry_after",
+                AttributeValue::S(retry_after.to_rfc3339()),
+            )
+    } else {
+        update.update_expression("REMOVE sync_failures, sync_retry_after")
+    };
+
+    if let Err(e) = update.send().await {
+        tracing::warn!("Recording sync attempt for Plaid item {item_id} failed: {e}");
+    }
+}
+
+async fn save_transactions_cursor(
+    state: &AppState,
+    user_id: &str,
+    item_id: &str,
+    cursor: &str,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-plaid-items")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("item_id", AttributeValue::S(item_id.to_string()))
+        .update_expression("SET #cursor = :cursor")
+        .expression_attribute_names("#cursor", ATTR_TRANSACTIONS_CURSOR)
+        .expression_attribute_values(":cursor", AttributeValue::S(cursor.to_string()))
         .send()
-        .await;
+        .await?;
+    Ok(())
+}
 
-    match delete_result {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiError::new(format!("Failed to delete item: {}", e))),
-        )
-            .into_response(),
+/// Pull an item's transaction changes since its stored cursor, store them against the user's
+/// budgets and advance the cursor. Shared by `POST /plaid/sync` and the scheduled sync job.
+pub async fn sync_item_transactions(
+    state: &AppState,
+    user_id: &str,
+    item: &HashMap<String, AttributeValue>,
+    ctx: &SyncContext,
+) -> ItemTransactionsSync {
+    let item_id = item
+        .get("item_id")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+    let mut result = ItemTransactionsSync::default();
+
+    let access_token = match item_access_token(state, user_id, item).await {
+        Ok(t) => t,
+        Err(e) => {
+            result.errors.push(format!("{}: {}", item_id, e));
+            result.incomplete = true;
+            record_sync_attempt(state, user_id, item, true).await;
+            return result;
+        }
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let mut cursor = item
+        .get(ATTR_TRANSACTIONS_CURSOR)
+        .and_then(|v| v.as_s().ok())
+        .cloned();
+
+    // Page through the item's changes until Plaid reports nothing more
+    loop {
+        let page = match state.plaid.transactions_sync(&access_token, cursor).await {
+            Ok(page) => page,
+            Err(e) => {
+                record_item_health(state, user_id, item, Err(&e)).await;
+                result.errors.push(format!("{}: {}", item_id, e));
+                result.incomplete = true;
+                break;
+            }
+        };
+
+        let errors_before = result.errors.len();
+        result.added += page.added.len();
+        result.modified += page.modified.len();
+
+        // Shared directory entries for the merchants the user has no entry of their own for
+        let changed: Vec<(&Transaction, Option<NormalizedMerchant>)> = page
+            .added
+            .iter()
+            .chain(&page.modified)
+            .map(|txn| {
+                (
+                    txn,
+                    transaction_merchant(&txn.name, txn.merchant_name.as_deref()),
+                )
+            })
+            .collect();
+        let unknown: BTreeSet<String> = changed
+            .iter()
+            .filter_map(|(_, merchant)| merchant.as_ref())
+            .map(|m| m.merchant_id.clone())
+            .filter(|id| !ctx.merchants.contains_key(id))
+            .collect();
+        let mut globals = global_merchants(state, &unknown).await.unwrap_or_else(|e| {
+            tracing::error!("Looking up merchants failed: {e}");
+            HashMap::new()
+        });
+
+        for (txn, merchant) in changed {
+            if let Some(ref merchant) = merchant {
+                register_plaid_merchant(state, txn, merchant, &mut globals, &now).await;
+            }
+            let assigned = ctx.assign(txn, merchant.as_ref(), &globals);
+            let suggestions =
+                ctx.model
+                    .suggest_for(&txn.name, assigned.merchant.as_deref(), txn.amount);
+            let merchant_id = merchant.as_ref().map(|m| m.merchant_id.as_str());
+            let stored =
+                store_synced_transaction(state, user_id, txn, &assigned, merchant_id, &now).await;
+            if let Err(e) = stored {
+                let error = format!("{}: failed to store transaction: {}", item_id, e);
+                result.errors.push(error);
+            }
+            if assigned.budget_id.is_some() {
+                result.assigned += 1;
+            }
+            result
+                .transactions
+                .push(map_plaid_transaction(txn, &assigned, &suggestions));
+        }
+        for removed in &page.removed {
+            let id = &removed.transaction_id;
+            match remove_synced_transaction(state, user_id, id).await {
+                Ok(_) => result.removed += 1,
+                Err(e) => {
+                    let error = format!("{}: failed to remove transaction: {}", item_id, e);
+                    result.errors.push(error);
+                }
+            }
+        }
+
+        // Keep the old cursor if anything failed to store, so the next sync replays the page
+        if result.errors.len() > errors_before {
+            result.incomplete = true;
+            break;
+        }
+        let saved = save_transactions_cursor(state, user_id, &item_id, &page.next_cursor).await;
+        if let Err(e) = saved {
+            result
+                .errors
+                .push(format!("{}: failed to save sync cursor: {}", item_id, e));
+            result.incomplete = true;
+            break;
+        }
+
+        if !page.has_more {
+            record_item_health(state, user_id, item, Ok(None)).await;
+            break;
+        }
+        cursor = Some(page.next_cursor);
+    }
+
+    record_sync_attempt(state, user_id, item, result.incomplete).await;
+    result
+}
+
+pub async fn sync_transactions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    // Get all plaid items for this user
+    let items_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-plaid-items")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let items = match items_result {
+        Ok(output) => output.items.unwrap_or_default(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let ctx = match SyncContext::load(&state, &claims.sub).await {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let mut all_transactions: Vec<serde_json::Value> = Vec::new();
+    let mut total_added: usize = 0;
+    let mut total_modified: usize = 0;
+    let mut total_removed: usize = 0;
+    let mut total_assigned: usize = 0;
+    let mut errors: Vec<String> = Vec::new();
+
+    for item in &items {
+        let synced = sync_item_transactions(&state, &claims.sub, item, &ctx).await;
+        total_added += synced.added;
+        total_modified += synced.modified;
+        total_removed += synced.removed;
+        total_assigned += synced.assigned;
+        all_transactions.extend(synced.transactions);
+        errors.extend(synced.errors);
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "transactions": all_transactions,
+            "added": total_added,
+            "modified": total_modified,
+            "removed": total_removed,
+            "assigned": total_assigned,
+            "errors": errors,
+        })),
+    )
+        .into_response()
+}
+
+/// `category` keeps Plaid's legacy hierarchy for older clients; `app_category` is the mapped one.
+fn map_plaid_transaction(
+    txn: &Transaction,
+    assigned: &RuleOutcome,
+    suggestions: &[CategorySuggestion],
+) -> serde_json::Value {
+    serde_json::json!({
+        "id": txn.transaction_id,
+        "account_id": txn.account_id,
+        "name": txn.name,
+        "merchant": assigned.merchant,
+        "amount": txn.amount,
+        "date": txn.date,
+        "category": txn.category,
+        "app_category": assigned.category,
+        "budget_id": assigned.budget_id,
+        "tags": assigned.tags,
+        "category_suggestions": suggestions,
+        "pending": txn.pending,
+    })
+}
+
+/// Seed the shared merchant directory with a merchant Plaid recognised, unless it is already
+/// there. A failure only costs the directory entry, so it is logged rather than failing the sync.
+async fn register_plaid_merchant(
+    state: &AppState,
+    txn: &Transaction,
+    merchant: &NormalizedMerchant,
+    globals: &mut HashMap<String, Merchant>,
+    now: &str,
+) {
+    if txn.merchant_name.is_none() || globals.contains_key(&merchant.merchant_id) {
+        return;
+    }
+    let category = app_category(txn, &HashMap::new());
+    let entry = Merchant {
+        merchant_id: merchant.merchant_id.clone(),
+        display_name: merchant.display_name.clone(),
+        default_category: (category != FALLBACK_CATEGORY).then_some(category),
+        logo_url: txn.logo_url.clone(),
+        scope: "global".to_string(),
+        updated_at: now.to_string(),
+    };
+    if let Err(e) = register_global_merchant(state, &entry).await {
+        tracing::error!(
+            "Adding {} to the merchant directory failed: {e}",
+            entry.merchant_id
+        );
+    }
+    globals.insert(entry.merchant_id.clone(), entry);
+}
+
+/// Synced transactions are keyed by Plaid's id so a modified transaction overwrites itself.
+fn plaid_transaction_key(plaid_transaction_id: &str) -> String {
+    format!("plaid-{}", plaid_transaction_id)
+}
+
+/// Upsert a synced transaction, moving its amount between budgets if the assignment, amount
+/// or pending state changed since the last sync. Tags are added to any the row already has,
+/// and fields the user edited are kept.
+async fn store_synced_transaction(
+    state: &AppState,
+    user_id: &str,
+    txn: &Transaction,
+    assigned: &RuleOutcome,
+    merchant_id: Option<&str>,
+    now: &str,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let transaction_id = plaid_transaction_key(&txn.transaction_id);
+    // Most rows were never edited, so the whole row is written unless it turns out to have
+    // edits, and only then read to see which
+    let first = synced_update(state, user_id, txn, assigned, merchant_id, now, &[])
+        .condition_expression(format!("attribute_not_exists({})", ATTR_USER_EDITED))
+        .send()
+        .await;
+    let (old, edited) = match first {
+        Ok(output) => (output.attributes.unwrap_or_default(), Vec::new()),
+        Err(e) if is_conditional_check_failure(&e) => {
+            let edited = user_edited_fields(state, user_id, &transaction_id).await?;
+            let old = synced_update(state, user_id, txn, assigned, merchant_id, now, &edited)
+                .send()
+                .await?
+                .attributes
+                .unwrap_or_default();
+            (old, edited)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let kept_budget = old
+        .get("budget_id")
+        .and_then(|v| v.as_s().ok())
+        .map(String::as_str);
+    let budget_id = if edited.iter().any(|f| f == "category") {
+        kept_budget
+    } else {
+        assigned.budget_id.as_deref()
+    };
+
+    // Splits the user made survive a sync unless the bank changed the amount, say when a tip
+    // posts, and they no longer add up
+    let mut splits = item_splits(&old);
+    if !splits.is_empty() && !splits_add_up(txn.amount, &splits) {
+        clear_splits(state, user_id, &transaction_id).await?;
+        splits.clear();
+    }
+    let (was, now) = (
+        item_split_index(&old),
+        split_index_entries(&txn.date, &splits),
+    );
+    update_split_index(state, user_id, &transaction_id, &was, &now).await?;
+    let current = budget_allocations(budget_id, txn.amount, &splits, txn.pending);
+    apply_spent_changes(state, user_id, &counted_allocations(&old), &current).await
+}
+
+/// The fields of a stored transaction the user edited by hand.
+async fn user_edited_fields(
+    state: &AppState,
+    user_id: &str,
+    transaction_id: &str,
+) -> Result<Vec<String>, aws_sdk_dynamodb::Error> {
+    let item = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-transactions")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key(
+            "transaction_id",
+            AttributeValue::S(transaction_id.to_string()),
+        )
+        .projection_expression(ATTR_USER_EDITED)
+        .consistent_read(true)
+        .send()
+        .await?
+        .item
+        .unwrap_or_default();
+    Ok(item
+        .get(ATTR_USER_EDITED)
+        .and_then(|v| v.as_ss().ok())
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// The update writing a synced transaction, leaving out the `edited` fields.
+fn synced_update(
+    state: &AppState,
+    user_id: &str,
+    txn: &Transaction,
+    assigned: &RuleOutcome,
+    merchant_id: Option<&str>,
+    now: &str,
+    edited: &[String],
+) -> aws_sdk_dynamodb::operation::update_item::builders::UpdateItemFluentBuilder {
+    let is_edited = |field: &str| edited.iter().any(|f| f == field);
+    let mut fields = "amount = :amount, #d = :date, date_key = :date_key, account_id = :aid, \
+                      plaid_transaction_id = :pid, pending = :pending, \
+                      created_at = if_not_exists(created_at, :now), updated_at = :now"
+        .to_string();
+    let mut removed = Vec::new();
+    let mut added = String::new();
+    let transaction_id = plaid_transaction_key(&txn.transaction_id);
+    let mut update = state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-transactions")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("transaction_id", AttributeValue::S(transaction_id.clone()))
+        .expression_attribute_names("#d", "date")
+        .expression_attribute_values(":amount", AttributeValue::N(txn.amount.to_string()))
+        .expression_attribute_values(":date", AttributeValue::S(txn.date.clone()))
+        .expression_attribute_values(
+            ":date_key",
+            AttributeValue::S(date_key(&txn.date, &transaction_id)),
+        )
+        .expression_attribute_values(":aid", AttributeValue::S(txn.account_id.clone()))
+        .expression_attribute_values(":pid", AttributeValue::S(txn.transaction_id.clone()))
+        .expression_attribute_values(":pending", AttributeValue::Bool(txn.pending))
+        .expression_attribute_values(":now", AttributeValue::S(now.to_string()))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld);
+
+    if !is_edited("category") {
+        let category = assigned.category.as_deref().unwrap_or_default();
+        fields.push_str(", category = :category");
+        update = update
+            .expression_attribute_values(":category", AttributeValue::S(category.to_string()));
+        match assigned.budget_id.as_deref() {
+            Some(id) => {
+                fields.push_str(", budget_id = :bid, budget_key = :budget_key");
+                update = update
+                    .expression_attribute_values(":bid", AttributeValue::S(id.to_string()))
+                    .expression_attribute_values(
+                        ":budget_key",
+                        AttributeValue::S(budget_key(user_id, id)),
+                    );
+            }
+            None => removed.push("budget_id, budget_key"),
+        }
+    }
+    if !is_edited("description") {
+        fields.push_str(", description = :desc");
+        update = update.expression_attribute_values(":desc", AttributeValue::S(txn.name.clone()));
+        match assigned.merchant {
+            Some(ref merchant) => {
+                fields.push_str(", merchant = :merchant");
+                update = update
+                    .expression_attribute_values(":merchant", AttributeValue::S(merchant.clone()));
+            }
+            None => removed.push("merchant"),
+        }
+        match merchant_id {
+            Some(id) => {
+                fields.push_str(", merchant_id = :merchant_id");
+                update = update
+                    .expression_attribute_values(":merchant_id", AttributeValue::S(id.to_string()));
+            }
+            None => removed.push("merchant_id"),
+        }
+    }
+    if !assigned.tags.is_empty() {
+        added = " ADD tags :tags".to_string();
+        update =
+            update.expression_attribute_values(":tags", AttributeValue::Ss(assigned.tags.clone()));
+    }
+    let mut expression = format!("SET {}{}", fields, added);
+    if !removed.is_empty() {
+        expression = format!("{} REMOVE {}", expression, removed.join(", "));
+    }
+    update.update_expression(expression)
+}
+
+/// Delete a transaction Plaid dropped, typically a pending one that has since posted.
+async fn remove_synced_transaction(
+    state: &AppState,
+    user_id: &str,
+    plaid_transaction_id: &str,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let old = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-transactions")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key(
+            "transaction_id",
+            AttributeValue::S(plaid_transaction_key(plaid_transaction_id)),
+        )
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
+        .send()
+        .await?
+        .attributes
+        .unwrap_or_default();
+
+    let transaction_id = plaid_transaction_key(plaid_transaction_id);
+    update_split_index(
+        state,
+        user_id,
+        &transaction_id,
+        &item_split_index(&old),
+        &BTreeSet::new(),
+    )
+    .await?;
+    apply_spent_changes(state, user_id, &counted_allocations(&old), &BTreeMap::new()).await
+}
+
+// --- Sync Investments ---
+
+/// Plaid returns at most 24 months of investment transactions.
+const INVESTMENT_HISTORY_DAYS: i64 = 730;
+const INVESTMENT_TRANSACTIONS_PAGE_SIZE: usize = 500;
+
+#[derive(Serialize, Default)]
+pub struct InvestmentsSyncResult {
+    pub items_synced: usize,
+    pub holdings_upserted: usize,
+    pub holdings_removed: usize,
+    pub trades_upserted: usize,
+    /// Items that failed to sync, as "{item_id}: {error}"
+    pub errors: Vec<String>,
+}
+
+/// Plaid holdings are keyed by account and security so each sync overwrites the last one.
+fn plaid_holding_id(account_id: &str, security_id: &str) -> String {
+    format!("plaid-{}-{}", account_id, security_id)
+}
+
+/// Map a Plaid security onto a symbol and one of the portfolio asset classes.
+///
+/// Mutual funds are quoted by ticker, with the institution's price as a fallback. Securities
+/// the quote providers can't price (bonds, options, funds without a ticker) become manual
+/// assets valued at the institution's price.
+fn map_security(security: &Security) -> Option<(String, &'static str)> {
+    let trimmed = |v: &Option<String>| v.as_deref().unwrap_or_default().trim().to_string();
+    let is_cash = security.is_cash_equivalent.unwrap_or(false);
+
+    let asset_class = match trimmed(&security.security_type).as_str() {
+        _ if is_cash => "cash",
+        "cash" => "cash",
+        "equity" => "equity",
+        "etf" => "etf",
+        "cryptocurrency" => "crypto",
+        "mutual fund" if !trimmed(&security.ticker_symbol).is_empty() => "mutual_fund",
+        _ => "manual",
+    };
+
+    // Currencies and crypto come back as "CUR:USD", "CUR:BTC"
+    let ticker = trimmed(&security.ticker_symbol);
+    let ticker = ticker
+        .strip_prefix("CUR:")
+        .unwrap_or(&ticker)
+        .to_uppercase();
+    let symbol = match (ticker.is_empty(), asset_class) {
+        (false, _) => ticker,
+        (true, "manual") => trimmed(&security.name),
+        (true, "cash") => "USD".to_string(),
+        (true, _) => return None,
+    };
+    if symbol.is_empty() {
+        return None;
+    }
+    Some((symbol, asset_class))
+}
+
+fn securities_by_id(securities: &[Security]) -> HashMap<&str, &Security> {
+    securities
+        .iter()
+        .map(|s| (s.security_id.as_str(), s))
+        .collect()
+}
+
+/// Build holdings from an `/investments/holdings/get` response.
+fn map_investment_holdings(
+    user_id: &str,
+    data: &InvestmentsHoldingsGetResponse,
+    now: &str,
+) -> Vec<Holding> {
+    let securities = securities_by_id(&data.securities);
+
+    data.holdings
+        .iter()
+        .filter_map(|h| {
+            let (symbol, asset_class) = map_security(securities.get(h.security_id.as_str())?)?;
+            if h.quantity == 0.0 {
+                return None;
+            }
+            // Plaid reports the total cost basis of the position, when the institution knows it
+            let avg_cost = h
+                .cost_basis
+                .map(|total| total / h.quantity)
+                .unwrap_or(h.institution_price);
+
+            Some(Holding {
+                holding_id: plaid_holding_id(&h.account_id, &h.security_id),
+                user_id: user_id.to_string(),
+                symbol,
+                shares: h.quantity,
+                avg_cost,
+                asset_class: asset_class.to_string(),
+                manual_price: matches!(asset_class, "manual" | "mutual_fund")
+                    .then_some(h.institution_price),
+                source: PLAID_SOURCE.to_string(),
+                created_at: now.to_string(),
+                updated_at: now.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Map an investment transaction onto a trade; transfers, fees and cash movements are skipped.
+fn map_investment_transaction(
+    user_id: &str,
+    txn: &InvestmentTransaction,
+    securities: &HashMap<&str, &Security>,
+    now: &str,
+) -> Option<Trade> {
+    let subtype = txn.subtype.to_lowercase();
+    let trade_type = match (
+        txn.transaction_type.to_lowercase().as_str(),
+        subtype.as_str(),
+    ) {
+        ("buy", _) => "buy",
+        ("sell", _) => "sell",
+        ("cash", subtype) if subtype.contains("dividend") => "dividend",
+        _ => return None,
+    };
+    let security_id = txn.security_id.as_deref()?;
+    let (symbol, _) = map_security(securities.get(security_id)?)?;
+
+    Some(Trade {
+        trade_id: format!("plaid-{}", txn.investment_transaction_id),
+        user_id: user_id.to_string(),
+        symbol,
+        trade_type: trade_type.to_string(),
+        quantity: txn.quantity.abs(),
+        price: txn.price.abs(),
+        amount: txn.amount.abs(),
+        date: txn.date.clone(),
+        source: PLAID_SOURCE.to_string(),
+        created_at: now.to_string(),
+    })
+}
+
+pub async fn sync_investments(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    let items_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-plaid-items")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    // Only items linked with the investments product can be synced
+    let items: Vec<HashMap<String, AttributeValue>> = match items_result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| {
+                item.get("investments")
+                    .and_then(|v| v.as_bool().ok())
+                    .copied()
+                    .unwrap_or(false)
+            })
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let holdings_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let existing: HashMap<String, Holding> = match holdings_result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(item_to_holding)
+            .filter(|h| h.source == PLAID_SOURCE)
+            .map(|h| (h.holding_id.clone(), h))
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let now = Utc::now();
+    let now_str = now.to_rfc3339();
+    let start_date = (now - chrono::Duration::days(INVESTMENT_HISTORY_DAYS))
+        .format("%Y-%m-%d")
+        .to_string();
+    let end_date = now.format("%Y-%m-%d").to_string();
+    let mut summary = InvestmentsSyncResult::default();
+
+    for item in &items {
+        let item_id = item
+            .get("item_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default();
+        let access_token = match item_access_token(&state, &claims.sub, item).await {
+            Ok(t) => t,
+            Err(e) => {
+                summary.errors.push(format!("{}: {}", item_id, e));
+                continue;
+            }
+        };
+
+        // Holdings
+        let data = match state.plaid.investments_holdings_get(&access_token).await {
+            Ok(data) => data,
+            Err(e) => {
+                record_item_health(&state, &claims.sub, item, Err(&e)).await;
+                summary.errors.push(format!("{}: {}", item_id, e));
+                continue;
+            }
+        };
+        record_item_health(&state, &claims.sub, item, Ok(data.item.as_ref())).await;
+
+        let mut synced_ids = HashSet::new();
+        for mut holding in map_investment_holdings(&claims.sub, &data, &now_str) {
+            if let Some(previous) = existing.get(&holding.holding_id) {
+                holding.created_at = previous.created_at.clone();
+            }
+            let mut row = holding_to_item(&holding);
+            row.insert("item_id".to_string(), AttributeValue::S(item_id.clone()));
+            let put = state
+                .dynamo
+                .put_item()
+                .table_name("ovaflus-portfolio")
+                .set_item(Some(row))
+                .send()
+                .await;
+            if let Err(e) = put {
+                summary
+                    .errors
+                    .push(format!("{}: failed to store holding: {}", item_id, e));
+                continue;
+            }
+            synced_ids.insert(holding.holding_id);
+            summary.holdings_upserted += 1;
+        }
+
+        // Positions closed at the brokerage no longer appear in the response
+        for holding_id in existing.keys() {
+            let in_item = data
+                .accounts
+                .iter()
+                .any(|a| holding_id.starts_with(&plaid_holding_id(&a.account_id, "")));
+            if !in_item || synced_ids.contains(holding_id) {
+                continue;
+            }
+            let delete = state
+                .dynamo
+                .delete_item()
+                .table_name("ovaflus-portfolio")
+                .key("user_id", AttributeValue::S(claims.sub.clone()))
+                .key("holding_id", AttributeValue::S(holding_id.clone()))
+                .send()
+                .await;
+            match delete {
+                Ok(_) => summary.holdings_removed += 1,
+                Err(e) => summary
+                    .errors
+                    .push(format!("{}: failed to remove holding: {}", item_id, e)),
+            }
+        }
+
+        // Transactions, paged
+        let mut offset = 0;
+        loop {
+            let request = InvestmentsTransactionsGetRequest {
+                access_token: access_token.clone(),
+                start_date: start_date.clone(),
+                end_date: end_date.clone(),
+                options: InvestmentsTransactionsOptions {
+                    count: INVESTMENT_TRANSACTIONS_PAGE_SIZE,
+                    offset,
+                },
+            };
+            let data = match state.plaid.investments_transactions_get(&request).await {
+                Ok(data) => data,
+                Err(e) => {
+                    record_item_health(&state, &claims.sub, item, Err(&e)).await;
+                    summary.errors.push(format!("{}: {}", item_id, e));
+                    break;
+                }
+            };
+
+            let securities = securities_by_id(&data.securities);
+            let page = &data.investment_transactions;
+            for txn in page {
+                let Some(trade) =
+                    map_investment_transaction(&claims.sub, txn, &securities, &now_str)
+                else {
+                    continue;
+                };
+                let mut row = trade_to_item(&trade);
+                row.insert("item_id".to_string(), AttributeValue::S(item_id.clone()));
+                let put = state
+                    .dynamo
+                    .put_item()
+                    .table_name("ovaflus-portfolio-trades")
+                    .set_item(Some(row))
+                    .send()
+                    .await;
+                match put {
+                    Ok(_) => summary.trades_upserted += 1,
+                    Err(e) => summary
+                        .errors
+                        .push(format!("{}: failed to store trade: {}", item_id, e)),
+                }
+            }
+
+            offset += page.len();
+            if page.is_empty() || offset >= data.total_investment_transactions {
+                break;
+            }
+        }
+
+        summary.items_synced += 1;
+    }
+
+    (StatusCode::OK, Json(serde_json::to_value(summary).unwrap())).into_response()
+}
+
+// --- Sync Liabilities ---
+
+#[derive(Serialize, Default)]
+pub struct LiabilitiesSyncResult {
+    pub items_synced: usize,
+    pub liabilities_upserted: usize,
+    pub liabilities_removed: usize,
+    /// Items that failed to sync, as "{item_id}: {error}"
+    pub errors: Vec<String>,
+}
+
+/// Build liabilities from a `/liabilities/get` response.
+fn map_liabilities(
+    user_id: &str,
+    item_id: &str,
+    data: &LiabilitiesGetResponse,
+    now: &str,
+) -> Vec<Liability> {
+    let accounts: HashMap<&str, &Account> = data
+        .accounts
+        .iter()
+        .map(|a| (a.account_id.as_str(), a))
+        .collect();
+    let liabilities = &data.liabilities;
+
+    // Cards carry several APRs; the purchase APR is the one that applies to a balance
+    let credit = liabilities.credit.iter().map(|card| {
+        let apr = card
+            .aprs
+            .iter()
+            .find(|a| a.apr_type == "purchase_apr")
+            .or_else(|| card.aprs.first())
+            .and_then(|a| a.apr_percentage);
+        let terms = (apr, card.minimum_payment_amount);
+        (
+            "credit",
+            &card.account_id,
+            terms,
+            &card.next_payment_due_date,
+        )
+    });
+    let student = liabilities.student.iter().map(|loan| {
+        let terms = (loan.interest_rate_percentage, loan.minimum_payment_amount);
+        (
+            "student",
+            &loan.account_id,
+            terms,
+            &loan.next_payment_due_date,
+        )
+    });
+    let mortgage = liabilities.mortgage.iter().map(|loan| {
+        let terms = (loan.interest_rate.percentage, loan.next_monthly_payment);
+        (
+            "mortgage",
+            &loan.account_id,
+            terms,
+            &loan.next_payment_due_date,
+        )
+    });
+
+    credit
+        .chain(student)
+        .chain(mortgage)
+        .filter_map(|(kind, account_id, (apr, minimum_payment), due)| {
+            let account_id = account_id.as_deref()?;
+            let account = accounts.get(account_id);
+            Some(Liability {
+                account_id: account_id.to_string(),
+                user_id: user_id.to_string(),
+                item_id: item_id.to_string(),
+                kind: kind.to_string(),
+                name: account.map(|a| a.name.clone()).unwrap_or_default(),
+                balance: account.and_then(|a| a.balances.current).unwrap_or(0.0),
+                apr,
+                minimum_payment,
+                next_payment_due_date: due.clone(),
+                updated_at: now.to_string(),
+            })
+        })
+        .collect()
+}
+
+pub async fn sync_liabilities(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    let items_result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-plaid-items")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    // Only items linked with the liabilities product can be synced
+    let items: Vec<HashMap<String, AttributeValue>> = match items_result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| {
+                item.get("liabilities")
+                    .and_then(|v| v.as_bool().ok())
+                    .copied()
+                    .unwrap_or(false)
+            })
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let existing = match load_liabilities(&state, &claims.sub).await {
+        Ok(l) => l,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let mut summary = LiabilitiesSyncResult::default();
+
+    for item in &items {
+        let item_id = item
+            .get("item_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default();
+        let access_token = match item_access_token(&state, &claims.sub, item).await {
+            Ok(t) => t,
+            Err(e) => {
+                summary.errors.push(format!("{}: {}", item_id, e));
+                continue;
+            }
+        };
+
+        let data = match state.plaid.liabilities_get(&access_token).await {
+            Ok(data) => data,
+            Err(e) => {
+                record_item_health(&state, &claims.sub, item, Err(&e)).await;
+                summary.errors.push(format!("{}: {}", item_id, e));
+                continue;
+            }
+        };
+        record_item_health(&state, &claims.sub, item, Ok(data.item.as_ref())).await;
+
+        let liabilities = map_liabilities(&claims.sub, &item_id, &data, &now);
+        for liability in &liabilities {
+            let put = state
+                .dynamo
+                .put_item()
+                .table_name("ovaflus-liabilities")
+                .set_item(Some(liability_to_item(liability)))
+                .send()
+                .await;
+            match put {
+                Ok(_) => summary.liabilities_upserted += 1,
+                Err(e) => summary
+                    .errors
+                    .push(format!("{}: failed to store liability: {}", item_id, e)),
+            }
+        }
+
+        // Paid-off or closed accounts drop out of the response
+        let stale = existing.iter().filter(|l| {
+            l.item_id == item_id && !liabilities.iter().any(|n| n.account_id == l.account_id)
+        });
+        for liability in stale {
+            let delete = state
+                .dynamo
+                .delete_item()
+                .table_name("ovaflus-liabilities")
+                .key("user_id", AttributeValue::S(claims.sub.clone()))
+                .key(
+                    "account_id",
+                    AttributeValue::S(liability.account_id.clone()),
+                )
+                .send()
+                .await;
+            match delete {
+                Ok(_) => summary.liabilities_removed += 1,
+                Err(e) => summary
+                    .errors
+                    .push(format!("{}: failed to remove liability: {}", item_id, e)),
+            }
+        }
+
+        summary.items_synced += 1;
+    }
+
+    (StatusCode::OK, Json(serde_json::to_value(summary).unwrap())).into_response()
+}
+
+// --- Refresh Balances ---
+
+#[derive(Serialize, Default)]
+pub struct BalancesRefreshResult {
+    pub items_refreshed: usize,
+    pub snapshots_written: usize,
+    /// Items that failed to refresh, as "{item_id}: {error}"
+    pub errors: Vec<String>,
+}
+
+/// Build today's snapshots from the accounts of an `/accounts/get` or
+/// `/accounts/balance/get` response.
+fn map_balance_snapshots(
+    user_id: &str,
+    item_id: &str,
+    investments_synced: bool,
+    accounts: &[Account],
+    date: &str,
+    now: &str,
+) -> Vec<BalanceSnapshot> {
+    accounts
+        .iter()
+        .map(|account| BalanceSnapshot {
+            user_id: user_id.to_string(),
+            snapshot_id: BalanceSnapshot::snapshot_id(date, &account.account_id),
+            date: date.to_string(),
+            account_id: account.account_id.clone(),
+            item_id: item_id.to_string(),
+            name: account.name.clone(),
+            category: account_category(&account.account_type, investments_synced).to_string(),
+            current: account.balances.current.unwrap_or(0.0),
+            available: account.balances.available,
+            iso_currency_code: account.balances.iso_currency_code.clone(),
+            updated_at: now.to_string(),
+        })
+        .collect()
+}
+
+pub async fn refresh_balances(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match refresh_user_balances(&state, &claims.sub, true).await {
+        Ok(summary) => {
+            (StatusCode::OK, Json(serde_json::to_value(summary).unwrap())).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Fetch balances for every item the user linked and write today's snapshots, including one
+/// for the portfolio and one for each stored liability. Shared by the refresh endpoint and
+/// the daily job.
+///
+/// `live` asks the institution for real-time balances through the billed
+/// `/accounts/balance/get`; otherwise the balances Plaid cached at its last update are read
+/// from `/accounts/get`, which the daily job uses.
+pub(crate) async fn refresh_user_balances(
+    state: &AppState,
+    user_id: &str,
+    live: bool,
+) -> Result<BalancesRefreshResult, aws_sdk_dynamodb::Error> {
+    let items = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-plaid-items")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?
+        .items
+        .unwrap_or_default();
+
+    let now = Utc::now();
+    let now_str = now.to_rfc3339();
+    let date = now.format("%Y-%m-%d").to_string();
+    let mut summary = BalancesRefreshResult::default();
+    let mut snapshots = Vec::new();
+
+    for item in &items {
+        let item_id = item
+            .get("item_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default();
+        let access_token = match item_access_token(state, user_id, item).await {
+            Ok(t) => t,
+            Err(e) => {
+                summary.errors.push(format!("{}: {}", item_id, e));
+                continue;
+            }
+        };
+        let investments_synced = item
+            .get("investments")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false);
+
+        let fetched = if live {
+            state
+                .plaid
+                .accounts_balance_get(&access_token)
+                .await
+                .map(|data| (data.item, data.accounts))
+        } else {
+            state
+                .plaid
+                .accounts_get(&access_token)
+                .await
+                .map(|data| (data.item, data.accounts))
+        };
+        match fetched {
+            Ok((plaid_item, accounts)) => {
+                record_item_health(state, user_id, item, Ok(plaid_item.as_ref())).await;
+                snapshots.extend(map_balance_snapshots(
+                    user_id,
+                    &item_id,
+                    investments_synced,
+                    &accounts,
+                    &date,
+                    &now_str,
+                ));
+                summary.items_refreshed += 1;
+            }
+            Err(e) => {
+                record_item_health(state, user_id, item, Err(&e)).await;
+                summary.errors.push(format!("{}: {}", item_id, e));
+            }
+        }
+    }
+
+    match snapshot_portfolio(state, user_id, &date).await {
+        Ok(portfolio) => snapshots.extend(portfolio),
+        Err(e) => summary
+            .errors
+            .push(format!("portfolio: failed to value holdings: {}", e)),
+    }
+
+    match load_liabilities(state, user_id).await {
+        Ok(liabilities) => apply_liabilities(&mut snapshots, &liabilities, &date, &now_str),
+        Err(e) => summary
+            .errors
+            .push(format!("liabilities: failed to load: {}", e)),
+    }
+
+    for snapshot in &snapshots {
+        let put = state
+            .dynamo
+            .put_item()
+            .table_name("ovaflus-balance-snapshots")
+            .set_item(Some(snapshot_to_item(snapshot)))
+            .send()
+            .await;
+        if let Err(e) = put {
+            summary.errors.push(format!(
+                "{}: failed to store snapshot: {}",
+                snapshot.account_id, e
+            ));
+            continue;
+        }
+        summary.snapshots_written += 1;
+
+        if snapshot.item_id.is_empty() {
+            continue;
+        }
+        // Keep the latest balance on the linked account for get_accounts
+        let mut update = state
+            .dynamo
+            .update_item()
+            .table_name("ovaflus-plaid-accounts")
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .key("account_id", AttributeValue::S(snapshot.account_id.clone()))
+            .condition_expression("attribute_exists(account_id)")
+            .expression_attribute_values(
+                ":current",
+                AttributeValue::N(snapshot.current.to_string()),
+            )
+            .expression_attribute_values(":updated_at", AttributeValue::S(now_str.clone()));
+        update = match snapshot.available {
+            Some(available) => update
+                .update_expression(
+                    "SET current_balance = :current, available_balance = :available, \
+                     balance_updated_at = :updated_at",
+                )
+                .expression_attribute_values(
+                    ":available",
+                    AttributeValue::N(available.to_string()),
+                ),
+            None => update.update_expression(
+                "SET current_balance = :current, balance_updated_at = :updated_at \
+                 REMOVE available_balance",
+            ),
+        };
+        if let Err(e) = update.send().await {
+            tracing::warn!(
+                "Updating balance for account {} failed: {e}",
+                snapshot.account_id
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+// --- Unlink Account ---
+
+/// Delete a user's rows in `table` (sort key `sort_key`) that `belongs` to the unlinked item.
+async fn delete_user_rows(
+    state: &AppState,
+    table: &str,
+    user_id: &str,
+    sort_key: &str,
+    belongs: impl Fn(&HashMap<String, AttributeValue>) -> bool,
+) -> Result<(), String> {
+    let rows = query_by_pk(&state.dynamo, table, "user_id", user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let deletes = rows
+        .iter()
+        .filter(|row| belongs(row))
+        .filter_map(|row| {
+            let sort_value = row.get(sort_key)?.clone();
+            Some(delete_request(HashMap::from([
+                (
+                    "user_id".to_string(),
+                    AttributeValue::S(user_id.to_string()),
+                ),
+                (sort_key.to_string(), sort_value),
+            ])))
+        })
+        .collect();
+    match batch_write(&state.dynamo, table, deletes).await {
+        Ok(0) => Ok(()),
+        Ok(left) => Err(format!("{} rows in {} were not deleted", left, table)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Remove everything synced from an item: its accounts, Plaid holdings and trades,
+/// liabilities and balance snapshots.
+async fn remove_item_rows(state: &AppState, user_id: &str, item_id: &str) -> Result<(), String> {
+    let of_item = |row: &HashMap<String, AttributeValue>| {
+        row.get("item_id")
+            .and_then(|v| v.as_s().ok())
+            .map(String::as_str)
+            == Some(item_id)
+    };
+
+    // Holdings synced before they carried an item id are matched by their accounts
+    let accounts = query_by_pk(&state.dynamo, "ovaflus-plaid-accounts", "user_id", user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let account_prefixes: Vec<String> = accounts
+        .iter()
+        .filter(|row| of_item(row))
+        .filter_map(|row| row.get("account_id").and_then(|v| v.as_s().ok()))
+        .map(|account_id| plaid_holding_id(account_id, ""))
+        .collect();
+
+    delete_user_rows(state, "ovaflus-portfolio", user_id, "holding_id", |row| {
+        let holding = item_to_holding(row);
+        holding.source == PLAID_SOURCE
+            && (of_item(row)
+                || account_prefixes
+                    .iter()
+                    .any(|prefix| holding.holding_id.starts_with(prefix)))
+    })
+    .await?;
+    delete_user_rows(
+        state,
+        "ovaflus-portfolio-trades",
+        user_id,
+        "trade_id",
+        of_item,
+    )
+    .await?;
+    delete_user_rows(state, "ovaflus-liabilities", user_id, "account_id", of_item).await?;
+    delete_user_rows(
+        state,
+        "ovaflus-balance-snapshots",
+        user_id,
+        "snapshot_id",
+        of_item,
+    )
+    .await?;
+    delete_user_rows(
+        state,
+        "ovaflus-plaid-accounts",
+        user_id,
+        "account_id",
+        of_item,
+    )
+    .await
+}
+
+pub async fn unlink_account(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(item_id): Path<String>,
+) -> impl IntoResponse {
+    // Get the access token from DynamoDB
+    let get_result = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-plaid-items")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("item_id", AttributeValue::S(item_id.clone()))
+        .send()
+        .await;
+
+    let access_token = match get_result {
+        Ok(output) => match output.item {
+            Some(item) => match item_access_token(&state, &claims.sub, &item).await {
+                Ok(t) => t,
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ApiError::new(e.to_string())),
+                    )
+                        .into_response();
+                }
+            },
+            None => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(ApiError::new("Plaid item not found")),
+                )
+                    .into_response();
+            }
+        },
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    // Remove from Plaid; the item is deleted here even if Plaid has already forgotten it
+    if let Err(e) = state.plaid.item_remove(&access_token).await {
+        tracing::warn!("Removing Plaid item {item_id} failed: {e}");
+    }
+
+    // Delete what was synced from the item, then the item itself, so a failure can be retried
+    if let Err(e) = remove_item_rows(&state, &claims.sub, &item_id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to delete item data: {}", e))),
+        )
+            .into_response();
+    }
+
+    let delete_result = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-plaid-items")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("item_id", AttributeValue::S(item_id))
+        .send()
+        .await;
+
+    match delete_result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to delete item: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plaid::types::AccountsBalanceGetResponse;
+
+    fn holdings_response() -> InvestmentsHoldingsGetResponse {
+        serde_json::from_value(serde_json::json!({
+            "accounts": [{ "account_id": "acct1" }],
+            "holdings": [
+                { "account_id": "acct1", "security_id": "sec-aapl", "quantity": 10.0,
+                  "cost_basis": 1500.0, "institution_price": 190.0 },
+                { "account_id": "acct1", "security_id": "sec-fund", "quantity": 4.0,
+                  "cost_basis": null, "institution_price": 52.5 },
+                { "account_id": "acct1", "security_id": "sec-cash", "quantity": 250.0,
+                  "institution_price": 1.0 },
+                { "account_id": "acct1", "security_id": "sec-unknown", "quantity": 1.0 },
+            ],
+            "securities": [
+                { "security_id": "sec-aapl", "ticker_symbol": "AAPL", "type": "equity" },
+                { "security_id": "sec-fund", "ticker_symbol": null, "name": "Target 2050 Fund",
+                  "type": "mutual fund" },
+                { "security_id": "sec-cash", "ticker_symbol": "CUR:USD", "type": "cash",
+                  "is_cash_equivalent": true },
+                { "security_id": "sec-btc", "ticker_symbol": "CUR:BTC", "type": "cryptocurrency" },
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn holdings_map_to_plaid_sourced_portfolio_holdings() {
+        let holdings = map_investment_holdings("user-1", &holdings_response(), "now");
+        assert_eq!(holdings.len(), 3);
+
+        assert_eq!(holdings[0].holding_id, "plaid-acct1-sec-aapl");
+        assert_eq!(holdings[0].avg_cost, 150.0);
+        assert_eq!(holdings[0].asset_class, "equity");
+        assert_eq!(holdings[0].source, PLAID_SOURCE);
+
+        // Unquoted securities are valued at the institution's price
+        assert_eq!(holdings[1].symbol, "Target 2050 Fund");
+        assert_eq!(holdings[1].asset_class, "manual");
+        assert_eq!(holdings[1].manual_price, Some(52.5));
+        assert_eq!(holdings[1].avg_cost, 52.5);
+
+        assert_eq!(holdings[2].symbol, "USD");
+        assert_eq!(holdings[2].asset_class, "cash");
+    }
+
+    #[test]
+    fn mutual_funds_with_a_ticker_are_quoted() {
+        let security: Security = serde_json::from_value(
+            serde_json::json!({ "ticker_symbol": "VFIAX", "type": "mutual fund" }),
+        )
+        .unwrap();
+        assert_eq!(
+            map_security(&security),
+            Some(("VFIAX".to_string(), "mutual_fund"))
+        );
+    }
+
+    #[test]
+    fn crypto_tickers_drop_the_currency_prefix() {
+        let security: Security = serde_json::from_value(
+            serde_json::json!({ "ticker_symbol": "CUR:BTC", "type": "cryptocurrency" }),
+        )
+        .unwrap();
+        assert_eq!(map_security(&security), Some(("BTC".to_string(), "crypto")));
+    }
+
+    #[test]
+    fn investment_transactions_map_to_trades() {
+        let response = holdings_response();
+        let securities = securities_by_id(&response.securities);
+        let txn = |id: &str, kind: &str, subtype: &str| -> InvestmentTransaction {
+            serde_json::from_value(serde_json::json!({
+                "investment_transaction_id": id,
+                "security_id": "sec-aapl",
+                "type": kind,
+                "subtype": subtype,
+                "quantity": -2.0,
+                "price": 180.0,
+                "amount": -360.0,
+                "date": "2024-03-01",
+            }))
+            .unwrap()
+        };
+
+        let sell =
+            map_investment_transaction("user-1", &txn("t1", "sell", "sell"), &securities, "now")
+                .unwrap();
+        assert_eq!(sell.trade_id, "plaid-t1");
+        assert_eq!(sell.trade_type, "sell");
+        assert_eq!(sell.quantity, 2.0);
+        assert_eq!(sell.amount, 360.0);
+
+        let dividend = map_investment_transaction(
+            "user-1",
+            &txn("t2", "cash", "qualified dividend"),
+            &securities,
+            "now",
+        )
+        .unwrap();
+        assert_eq!(dividend.trade_type, "dividend");
+
+        assert!(map_investment_transaction(
+            "user-1",
+            &txn("t3", "fee", "account fee"),
+            &securities,
+            "now"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn liabilities_map_per_kind() {
+        let response: LiabilitiesGetResponse = serde_json::from_value(serde_json::json!({
+            "accounts": [
+                {
+                    "account_id": "card",
+                    "name": "Plaid Credit Card",
+                    "balances": { "current": 410.0 },
+                },
+                {
+                    "account_id": "loan",
+                    "name": "Plaid Student Loan",
+                    "balances": { "current": 65262.0 },
+                },
+                {
+                    "account_id": "home",
+                    "name": "Plaid Mortgage",
+                    "balances": { "current": 56302.06 },
+                },
+            ],
+            "liabilities": {
+                "credit": [{
+                    "account_id": "card",
+                    "aprs": [
+                        { "apr_type": "balance_transfer_apr", "apr_percentage": 15.24 },
+                        { "apr_type": "purchase_apr", "apr_percentage": 22.2 },
+                    ],
+                    "minimum_payment_amount": 20.0,
+                    "next_payment_due_date": "2024-05-28",
+                }],
+                "student": [{
+                    "account_id": "loan",
+                    "interest_rate_percentage": 5.25,
+                    "minimum_payment_amount": 25.0,
+                    "next_payment_due_date": "2024-05-15",
+                }],
+                "mortgage": [{
+                    "account_id": "home",
+                    "interest_rate": { "percentage": 3.99, "type": "fixed" },
+                    "next_monthly_payment": 3141.54,
+                    "next_payment_due_date": "2024-06-01",
+                }],
+            },
+        }))
+        .unwrap();
+
+        let liabilities = map_liabilities("user-1", "item-1", &response, "now");
+        assert_eq!(liabilities.len(), 3);
+
+        assert_eq!(liabilities[0].kind, "credit");
+        assert_eq!(liabilities[0].apr, Some(22.2));
+        assert_eq!(liabilities[0].balance, 410.0);
+        assert_eq!(
+            liabilities[0].next_payment_due_date.as_deref(),
+            Some("2024-05-28")
+        );
+
+        assert_eq!(liabilities[1].kind, "student");
+        assert_eq!(liabilities[1].apr, Some(5.25));
+
+        assert_eq!(liabilities[2].kind, "mortgage");
+        assert_eq!(liabilities[2].apr, Some(3.99));
+        assert_eq!(liabilities[2].minimum_payment, Some(3141.54));
+        assert_eq!(liabilities[2].item_id, "item-1");
+    }
+
+    #[test]
+    fn balance_snapshots_are_categorized_by_account_type() {
+        let response: AccountsBalanceGetResponse = serde_json::from_value(serde_json::json!({
+            "accounts": [
+                {
+                    "account_id": "checking",
+                    "name": "Plaid Checking",
+                    "type": "depository",
+                    "balances": {
+                        "current": 110.0,
+                        "available": 100.0,
+                        "iso_currency_code": "USD",
+                    },
+                },
+                {
+                    "account_id": "ira",
+                    "name": "Plaid IRA",
+                    "type": "investment",
+                    "balances": { "current": 320.76, "available": null },
+                },
+            ],
+        }))
+        .unwrap();
+
+        let snapshots = map_balance_snapshots(
+            "user-1",
+            "item-1",
+            true,
+            &response.accounts,
+            "2024-05-01",
+            "now",
+        );
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].snapshot_id, "2024-05-01#checking");
+        assert_eq!(snapshots[0].category, "cash");
+        assert_eq!(snapshots[0].available, Some(100.0));
+        assert_eq!(snapshots[1].category, "investments_synced");
+        assert_eq!(snapshots[1].available, None);
+    }
+
+    #[test]
+    fn login_errors_change_status_and_outages_do_not() {
+        assert_eq!(
+            status_after_error(ITEM_HEALTHY, Some("ITEM_LOGIN_REQUIRED")),
+            ITEM_LOGIN_REQUIRED
+        );
+        assert_eq!(
+            status_after_error(ITEM_HEALTHY, Some("USER_PERMISSION_REVOKED")),
+            ITEM_REVOKED
+        );
+        assert_eq!(
+            status_after_error(ITEM_PENDING_EXPIRATION, Some("INSTITUTION_DOWN")),
+            ITEM_PENDING_EXPIRATION
+        );
+        assert_eq!(status_after_error("", None), ITEM_HEALTHY);
+    }
+
+    #[test]
+    fn success_heals_and_detects_pending_expiration() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let expiring_at = |at: Option<&str>| Item {
+            consent_expiration_time: at.map(String::from),
+            ..Default::default()
+        };
+        let expiring = expiring_at(Some("2024-05-05T00:00:00Z"));
+        let distant = expiring_at(Some("2024-12-01T00:00:00Z"));
+        let no_expiry = expiring_at(None);
+
+        assert_eq!(
+            status_after_success(ITEM_LOGIN_REQUIRED, None, now),
+            ITEM_HEALTHY
+        );
+        assert_eq!(
+            status_after_success(ITEM_HEALTHY, Some(&expiring), now),
+            ITEM_PENDING_EXPIRATION
+        );
+        assert_eq!(
+            status_after_success(ITEM_PENDING_EXPIRATION, Some(&distant), now),
+            ITEM_HEALTHY
+        );
+        assert_eq!(
+            status_after_success(ITEM_PENDING_EXPIRATION, None, now),
+            ITEM_PENDING_EXPIRATION
+        );
+        assert_eq!(
+            status_after_success(ITEM_HEALTHY, Some(&no_expiry), now),
+            ITEM_HEALTHY
+        );
+    }
+
+    #[test]
+    fn only_posted_budgeted_transactions_count_towards_spent() {
+        let stored = |budget: Option<&str>, pending: Option<bool>| {
+            let mut item =
+                HashMap::from([("amount".to_string(), AttributeValue::N("42.5".to_string()))]);
+            if let Some(b) = budget {
+                item.insert("budget_id".to_string(), AttributeValue::S(b.to_string()));
+            }
+            if let Some(p) = pending {
+                item.insert("pending".to_string(), AttributeValue::Bool(p));
+            }
+            item
+        };
+
+        let food = BTreeMap::from([("food".to_string(), 42.5)]);
+        assert_eq!(
+            counted_allocations(&stored(Some("food"), Some(false))),
+            food
+        );
+        // Rows created by hand have no pending flag
+        assert_eq!(counted_allocations(&stored(Some("food"), None)), food);
+        assert!(counted_allocations(&stored(Some("food"), Some(true))).is_empty());
+        assert!(counted_allocations(&stored(None, Some(false))).is_empty());
+        assert!(counted_allocations(&HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn sync_backoff_doubles_up_to_a_day() {
+        assert_eq!(sync_backoff(1), Duration::minutes(30));
+        assert_eq!(sync_backoff(2), Duration::hours(1));
+        assert_eq!(sync_backoff(4), Duration::hours(4));
+        assert_eq!(sync_backoff(6), Duration::hours(16));
+        assert_eq!(sync_backoff(7), Duration::hours(SYNC_BACKOFF_MAX_HOURS));
+        assert_eq!(sync_backoff(40), Duration::hours(SYNC_BACKOFF_MAX_HOURS));
     }
 }
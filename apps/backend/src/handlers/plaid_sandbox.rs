@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::crypto::Secret;
+use crate::handlers::plaid::{
+    item_access_token, store_linked_item, ExchangeTokenRequest, PlaidAccountInfo,
+};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::plaid::types::{SandboxItemFireWebhookRequest, SandboxPublicTokenCreateRequest};
+use crate::AppState;
+
+const SANDBOX_ENV: &str = "sandbox";
+
+/// First Platypus Bank, Plaid's default sandbox institution.
+const DEFAULT_INSTITUTION_ID: &str = "ins_109508";
+const DEFAULT_INSTITUTION_NAME: &str = "First Platypus Bank";
+
+/// Webhooks the sandbox can fire, as `(webhook_type, webhook_code)`.
+const SANDBOX_WEBHOOKS: &[(&str, &str)] = &[
+    ("TRANSACTIONS", "SYNC_UPDATES_AVAILABLE"),
+    ("TRANSACTIONS", "DEFAULT_UPDATE"),
+    ("TRANSACTIONS", "RECURRING_TRANSACTIONS_UPDATE"),
+    ("ITEM", "NEW_ACCOUNTS_AVAILABLE"),
+    ("ITEM", "PENDING_DISCONNECT"),
+    ("ITEM", "LOGIN_REPAIRED"),
+    ("ITEM", "USER_PERMISSION_REVOKED"),
+    ("ITEM", "ERROR"),
+    ("HOLDINGS", "DEFAULT_UPDATE"),
+    ("INVESTMENTS_TRANSACTIONS", "DEFAULT_UPDATE"),
+];
+
+/// Development helpers that skip Plaid Link. They are only mounted when the API talks to
+/// Plaid's sandbox, so production never exposes them.
+pub fn routes(plaid_env: &str) -> Option<Router<Arc<AppState>>> {
+    if plaid_env != SANDBOX_ENV {
+        return None;
+    }
+    Some(
+        Router::new()
+            .route("/plaid/sandbox/link", post(link_sandbox_item))
+            .route(
+                "/plaid/sandbox/items/:item_id/fire-webhook",
+                post(fire_webhook),
+            )
+            .route(
+                "/plaid/sandbox/items/:item_id/reset-login",
+                post(reset_login),
+            ),
+    )
+}
+
+async fn sandbox_item_token(
+    state: &AppState,
+    user_id: &str,
+    item_id: &str,
+) -> Result<Secret, Response> {
+    let output = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-plaid-items")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("item_id", AttributeValue::S(item_id.to_string()))
+        .send()
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response()
+        })?;
+    let Some(item) = output.item else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError::new("Plaid item not found")),
+        )
+            .into_response());
+    };
+    item_access_token(state, user_id, &item).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(e.to_string())),
+        )
+            .into_response()
+    })
+}
+
+// --- Link Without Plaid Link ---
+
+#[derive(Deserialize, Default)]
+pub struct SandboxLinkRequest {
+    pub institution_id: Option<String>,
+    pub institution_name: Option<String>,
+    #[serde(default)]
+    pub investments: bool,
+    #[serde(default)]
+    pub liabilities: bool,
+}
+
+/// Create a sandbox public token and store the item exactly as `exchange_token` would.
+pub async fn link_sandbox_item(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    request: Option<Json<SandboxLinkRequest>>,
+) -> impl IntoResponse {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let institution_id = request
+        .institution_id
+        .unwrap_or_else(|| DEFAULT_INSTITUTION_ID.to_string());
+    let institution_name = request
+        .institution_name
+        .unwrap_or_else(|| DEFAULT_INSTITUTION_NAME.to_string());
+
+    let mut initial_products = state.plaid_link.products.clone();
+    for (requested, product) in [
+        (request.investments, "investments"),
+        (request.liabilities, "liabilities"),
+    ] {
+        if requested && !initial_products.iter().any(|p| p == product) {
+            initial_products.push(product.to_string());
+        }
+    }
+
+    let created = state
+        .plaid
+        .sandbox_public_token_create(&SandboxPublicTokenCreateRequest {
+            institution_id: institution_id.clone(),
+            initial_products,
+        })
+        .await;
+    let public_token = match created {
+        Ok(created) => created.public_token,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response();
+        }
+    };
+    let exchanged = match state.plaid.item_public_token_exchange(&public_token).await {
+        Ok(exchanged) => exchanged,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response();
+        }
+    };
+    // Link would report the accounts the user picked; in the sandbox every account is linked
+    let accounts = match state.plaid.accounts_get(&exchanged.access_token).await {
+        Ok(resp) => resp.accounts,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response();
+        }
+    };
+
+    let body = ExchangeTokenRequest {
+        public_token,
+        institution_id,
+        institution_name,
+        accounts: accounts
+            .into_iter()
+            .map(|account| PlaidAccountInfo {
+                id: account.account_id,
+                name: account.name,
+                subtype: account.subtype.unwrap_or(account.account_type),
+                mask: account.mask,
+            })
+            .collect(),
+    };
+    store_linked_item(
+        &state,
+        &claims.sub,
+        &exchanged.access_token,
+        &exchanged.item_id,
+        &body,
+    )
+    .await
+}
+
+// --- Fire Webhook ---
+
+#[derive(Deserialize)]
+pub struct FireWebhookRequest {
+    pub webhook_code: String,
+    /// Needed only where the code is ambiguous; defaults to the first type with that code
+    pub webhook_type: Option<String>,
+}
+
+fn sandbox_webhook(code: &str, webhook_type: Option<&str>) -> Option<(&'static str, &'static str)> {
+    SANDBOX_WEBHOOKS
+        .iter()
+        .find(|(t, c)| *c == code && (webhook_type.is_none() || webhook_type == Some(*t)))
+        .copied()
+}
+
+pub async fn fire_webhook(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(item_id): Path<String>,
+    Json(body): Json<FireWebhookRequest>,
+) -> impl IntoResponse {
+    let code = body.webhook_code.trim().to_uppercase();
+    let webhook_type = body.webhook_type.map(|t| t.trim().to_uppercase());
+    let Some((webhook_type, webhook_code)) = sandbox_webhook(&code, webhook_type.as_deref()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(format!(
+                "Unsupported sandbox webhook: {}",
+                code
+            ))),
+        )
+            .into_response();
+    };
+
+    let access_token = match sandbox_item_token(&state, &claims.sub, &item_id).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
+    let request = SandboxItemFireWebhookRequest {
+        access_token,
+        webhook_type: webhook_type.to_string(),
+        webhook_code: webhook_code.to_string(),
+    };
+    match state.plaid.sandbox_item_fire_webhook(&request).await {
+        Ok(resp) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "item_id": item_id,
+                "webhook_type": webhook_type,
+                "webhook_code": webhook_code,
+                "webhook_fired": resp.webhook_fired,
+            })),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response(),
+    }
+}
+
+// --- Reset Login ---
+
+/// Put the item into `ITEM_LOGIN_REQUIRED`; the next sync records it and update mode repairs it.
+pub async fn reset_login(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(item_id): Path<String>,
+) -> impl IntoResponse {
+    let access_token = match sandbox_item_token(&state, &claims.sub, &item_id).await {
+        Ok(token) => token,
+        Err(response) => return response,
+    };
+    match state.plaid.sandbox_item_reset_login(&access_token).await {
+        Ok(resp) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "item_id": item_id,
+                "reset_login": resp.reset_login,
+            })),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(ApiError::new(e.to_string()))).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sandbox_routes_are_never_mounted_outside_the_sandbox() {
+        assert!(routes("sandbox").is_some());
+        assert!(routes("development").is_none());
+        assert!(routes("production").is_none());
+        assert!(routes("").is_none());
+    }
+
+    #[test]
+    fn webhook_codes_resolve_to_their_type() {
+        assert_eq!(
+            sandbox_webhook("SYNC_UPDATES_AVAILABLE", None),
+            Some(("TRANSACTIONS", "SYNC_UPDATES_AVAILABLE"))
+        );
+        assert_eq!(
+            sandbox_webhook("DEFAULT_UPDATE", Some("HOLDINGS")),
+            Some(("HOLDINGS", "DEFAULT_UPDATE"))
+        );
+        assert_eq!(
+            sandbox_webhook("LOGIN_REPAIRED", Some("TRANSACTIONS")),
+            None
+        );
+        assert_eq!(sandbox_webhook("NOT_A_WEBHOOK", None), None);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use aws_sdk_dynamodb::types::AttributeValue;
@@ -9,8 +10,11 @@ use axum::{
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
 use uuid::Uuid;
 
+use crate::db::dynamo::is_conditional_check_failure;
+use crate::market_data::{is_asset_class, ASSET_CLASSES};
 use crate::middleware::auth::AuthUser;
 use crate::models::ApiError;
 use crate::AppState;
@@ -22,6 +26,13 @@ pub struct Holding {
     pub symbol: String,
     pub shares: f64,
     pub avg_cost: f64,
+    /// "equity", "etf", "mutual_fund", "crypto", "cash" or "manual"
+    pub asset_class: String,
+    /// User-entered price per unit, used to value manual assets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manual_price: Option<f64>,
+    /// Where the holding came from: "manual", "import:{format}" or "plaid"
+    pub source: String,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -31,6 +42,8 @@ pub struct AddHoldingRequest {
     pub symbol: String,
     pub shares: f64,
     pub avg_cost: f64,
+    pub asset_class: Option<String>,
+    pub manual_price: Option<f64>,
 }
 
 #[derive(Deserialize)]
@@ -39,9 +52,11 @@ pub struct UpdateHoldingRequest {
     pub shares: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avg_cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manual_price: Option<f64>,
 }
 
-fn item_to_holding(item: &std::collections::HashMap<String, AttributeValue>) -> Holding {
+pub(crate) fn item_to_holding(item: &HashMap<String, AttributeValue>) -> Holding {
     Holding {
         holding_id: item
             .get("holding_id")
@@ -68,6 +83,21 @@ fn item_to_holding(item: &std::collections::HashMap<String, AttributeValue>) ->
             .and_then(|v| v.as_n().ok())
             .and_then(|n| n.parse::<f64>().ok())
             .unwrap_or(0.0),
+        // Holdings created before asset classes existed are all stocks
+        asset_class: item
+            .get("asset_class")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_else(|| "equity".to_string()),
+        manual_price: item
+            .get("manual_price")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok()),
+        source: item
+            .get("source")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_else(|| "manual".to_string()),
         created_at: item
             .get("created_at")
             .and_then(|v| v.as_s().ok())
@@ -81,6 +111,45 @@ fn item_to_holding(item: &std::collections::HashMap<String, AttributeValue>) ->
     }
 }
 
+pub(crate) fn holding_to_item(h: &Holding) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::from([
+        ("user_id".to_string(), AttributeValue::S(h.user_id.clone())),
+        (
+            "holding_id".to_string(),
+            AttributeValue::S(h.holding_id.clone()),
+        ),
+        ("symbol".to_string(), AttributeValue::S(h.symbol.clone())),
+        (
+            "shares".to_string(),
+            AttributeValue::N(h.shares.to_string()),
+        ),
+        (
+            "avg_cost".to_string(),
+            AttributeValue::N(h.avg_cost.to_string()),
+        ),
+        (
+            "asset_class".to_string(),
+            AttributeValue::S(h.asset_class.clone()),
+        ),
+        ("source".to_string(), AttributeValue::S(h.source.clone())),
+        (
+            "created_at".to_string(),
+            AttributeValue::S(h.created_at.clone()),
+        ),
+        (
+            "updated_at".to_string(),
+            AttributeValue::S(h.updated_at.clone()),
+        ),
+    ]);
+    if let Some(price) = h.manual_price {
+        item.insert(
+            "manual_price".to_string(),
+            AttributeValue::N(price.to_string()),
+        );
+    }
+    item
+}
+
 pub async fn get_portfolio(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
@@ -116,15 +185,186 @@ pub async fn get_portfolio(
     }
 }
 
+// --- Summary ---
+
+#[derive(Serialize)]
+pub struct ValuedHolding {
+    #[serde(flatten)]
+    pub holding: Holding,
+    /// Latest price per unit; `None` when no price was available and cost basis was used
+    pub price: Option<f64>,
+    pub market_value: f64,
+    pub cost_basis: f64,
+}
+
+#[derive(Serialize)]
+pub struct AssetClassTotal {
+    pub asset_class: String,
+    pub market_value: f64,
+    pub cost_basis: f64,
+    pub weight: f64,
+    pub holdings: usize,
+}
+
+#[derive(Serialize)]
+pub struct PortfolioSummary {
+    pub total_value: f64,
+    pub total_cost: f64,
+    pub classes: Vec<AssetClassTotal>,
+    pub holdings: Vec<ValuedHolding>,
+}
+
+fn totals_by_class(holdings: &[ValuedHolding]) -> Vec<AssetClassTotal> {
+    let total_value: f64 = holdings.iter().map(|h| h.market_value).sum();
+    let mut classes: Vec<AssetClassTotal> = Vec::new();
+    for h in holdings {
+        let index = match classes
+            .iter()
+            .position(|c| c.asset_class == h.holding.asset_class)
+        {
+            Some(i) => i,
+            None => {
+                classes.push(AssetClassTotal {
+                    asset_class: h.holding.asset_class.clone(),
+                    market_value: 0.0,
+                    cost_basis: 0.0,
+                    weight: 0.0,
+                    holdings: 0,
+                });
+                classes.len() - 1
+            }
+        };
+        let class = &mut classes[index];
+        class.market_value += h.market_value;
+        class.cost_basis += h.cost_basis;
+        class.holdings += 1;
+    }
+    for class in classes.iter_mut() {
+        if total_value > 0.0 {
+            class.weight = class.market_value / total_value;
+        }
+    }
+    classes.sort_by(|a, b| {
+        b.market_value
+            .partial_cmp(&a.market_value)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    classes
+}
+
+/// Price each holding through the provider for its asset class, falling back to cost basis.
+pub(crate) async fn value_holdings(state: &AppState, holdings: Vec<Holding>) -> Vec<ValuedHolding> {
+    let mut tasks = JoinSet::new();
+    for (index, h) in holdings.iter().enumerate() {
+        let market_data = state.market_data.clone();
+        let (asset_class, symbol, manual_price) =
+            (h.asset_class.clone(), h.symbol.clone(), h.manual_price);
+        tasks.spawn(async move {
+            let price = market_data.price(&asset_class, &symbol, manual_price).await;
+            (index, price)
+        });
+    }
+
+    let mut prices: Vec<Option<f64>> = vec![None; holdings.len()];
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((index, Ok(price))) => prices[index] = price,
+            Ok((index, Err(e))) => {
+                tracing::warn!("Pricing {} failed: {e}", holdings[index].symbol);
+            }
+            Err(e) => tracing::warn!("Pricing task failed: {e}"),
+        }
+    }
+
+    holdings
+        .into_iter()
+        .zip(prices)
+        .map(|(holding, price)| {
+            let cost_basis = holding.shares * holding.avg_cost;
+            let market_value = price.map(|p| holding.shares * p).unwrap_or(cost_basis);
+            ValuedHolding {
+                holding,
+                price,
+                market_value,
+                cost_basis,
+            }
+        })
+        .collect()
+}
+
+pub async fn get_portfolio_summary(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let holdings: Vec<Holding> = match result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(item_to_holding)
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let valued = value_holdings(&state, holdings).await;
+
+    let summary = PortfolioSummary {
+        total_value: valued.iter().map(|h| h.market_value).sum(),
+        total_cost: valued.iter().map(|h| h.cost_basis).sum(),
+        classes: totals_by_class(&valued),
+        holdings: valued,
+    };
+
+    (StatusCode::OK, Json(serde_json::to_value(summary).unwrap())).into_response()
+}
+
 pub async fn add_holding(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
     Json(body): Json<AddHoldingRequest>,
 ) -> impl IntoResponse {
+    let asset_class = body
+        .asset_class
+        .clone()
+        .unwrap_or_else(|| "equity".to_string())
+        .to_lowercase();
+    if !is_asset_class(&asset_class) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(format!(
+                "asset_class must be one of: {}",
+                ASSET_CLASSES.join(", ")
+            ))),
+        )
+            .into_response();
+    }
+    if asset_class == "manual" && body.manual_price.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("manual_price is required for manual assets")),
+        )
+            .into_response();
+    }
+
     let holding_id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
 
-    let result = state
+    let mut put = state
         .dynamo
         .put_item()
         .table_name("ovaflus-portfolio")
@@ -133,12 +373,16 @@ pub async fn add_holding(
         .item("symbol", AttributeValue::S(body.symbol.clone()))
         .item("shares", AttributeValue::N(body.shares.to_string()))
         .item("avg_cost", AttributeValue::N(body.avg_cost.to_string()))
+        .item("asset_class", AttributeValue::S(asset_class.clone()))
+        .item("source", AttributeValue::S("manual".to_string()))
         .item("created_at", AttributeValue::S(now.clone()))
-        .item("updated_at", AttributeValue::S(now.clone()))
-        .send()
-        .await;
+        .item("updated_at", AttributeValue::S(now.clone()));
 
-    match result {
+    if let Some(manual_price) = body.manual_price {
+        put = put.item("manual_price", AttributeValue::N(manual_price.to_string()));
+    }
+
+    match put.send().await {
         Ok(_) => {
             let holding = Holding {
                 holding_id,
@@ -146,6 +390,9 @@ pub async fn add_holding(
                 symbol: body.symbol,
                 shares: body.shares,
                 avg_cost: body.avg_cost,
+                asset_class,
+                manual_price: body.manual_price,
+                source: "manual".to_string(),
                 created_at: now.clone(),
                 updated_at: now,
             };
@@ -163,6 +410,22 @@ pub async fn add_holding(
     }
 }
 
+/// Source tag of holdings and trades written by the Plaid investments sync.
+pub(crate) const PLAID_SOURCE: &str = "plaid";
+
+/// Plaid-synced holdings belong to the sync; editing them by hand would be overwritten.
+const NOT_PLAID_SYNCED: &str = "attribute_not_exists(#source) OR #source <> :plaid";
+
+fn plaid_synced_conflict() -> axum::response::Response {
+    (
+        StatusCode::CONFLICT,
+        Json(ApiError::new(
+            "Holding is synced from a linked brokerage account and can't be changed",
+        )),
+    )
+        .into_response()
+}
+
 pub async fn update_holding(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
@@ -183,6 +446,13 @@ pub async fn update_holding(
             AttributeValue::N(avg_cost.to_string()),
         ));
     }
+    if let Some(manual_price) = body.manual_price {
+        update_parts.push("manual_price = :manual_price".to_string());
+        expr_values.push((
+            ":manual_price".to_string(),
+            AttributeValue::N(manual_price.to_string()),
+        ));
+    }
 
     if update_parts.is_empty() {
         return (
@@ -207,6 +477,9 @@ pub async fn update_holding(
         .key("user_id", AttributeValue::S(claims.sub.clone()))
         .key("holding_id", AttributeValue::S(holding_id))
         .update_expression(&update_expression)
+        .condition_expression(NOT_PLAID_SYNCED)
+        .expression_attribute_names("#source", "source")
+        .expression_attribute_values(":plaid", AttributeValue::S(PLAID_SOURCE.to_string()))
         .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew);
 
     for (k, v) in expr_values {
@@ -219,6 +492,7 @@ pub async fn update_holding(
             let holding = item_to_holding(&item);
             (StatusCode::OK, Json(serde_json::to_value(holding).unwrap())).into_response()
         }
+        Err(e) if is_conditional_check_failure(&e) => plaid_synced_conflict(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiError::new(format!("Update failed: {}", e))),
@@ -238,6 +512,251 @@ pub async fn delete_holding(
         .table_name("ovaflus-portfolio")
         .key("user_id", AttributeValue::S(claims.sub.clone()))
         .key("holding_id", AttributeValue::S(holding_id))
+        .condition_expression(NOT_PLAID_SYNCED)
+        .expression_attribute_names("#source", "source")
+        .expression_attribute_values(":plaid", AttributeValue::S(PLAID_SOURCE.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) if is_conditional_check_failure(&e) => plaid_synced_conflict(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Delete failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+// --- Trades ---
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Trade {
+    pub trade_id: String,
+    pub user_id: String,
+    pub symbol: String,
+    /// "buy", "sell" or "dividend"
+    pub trade_type: String,
+    pub quantity: f64,
+    pub price: f64,
+    /// Cash amount of the trade: quantity * price for buys and sells, the payout for dividends.
+    pub amount: f64,
+    pub date: String,
+    /// Where the trade came from: "manual", "import:{format}" or "plaid"
+    pub source: String,
+    pub created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTradeRequest {
+    pub symbol: String,
+    pub trade_type: String,
+    #[serde(default)]
+    pub quantity: f64,
+    #[serde(default)]
+    pub price: f64,
+    pub amount: Option<f64>,
+    pub date: String,
+}
+
+pub(crate) fn item_to_trade(item: &HashMap<String, AttributeValue>) -> Trade {
+    Trade {
+        trade_id: item
+            .get("trade_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        user_id: item
+            .get("user_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        symbol: item
+            .get("symbol")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        trade_type: item
+            .get("trade_type")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        quantity: item
+            .get("quantity")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(0.0),
+        price: item
+            .get("price")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(0.0),
+        amount: item
+            .get("amount")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(0.0),
+        date: item
+            .get("date")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        source: item
+            .get("source")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_else(|| "manual".to_string()),
+        created_at: item
+            .get("created_at")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+    }
+}
+
+pub(crate) fn trade_to_item(t: &Trade) -> HashMap<String, AttributeValue> {
+    HashMap::from([
+        ("user_id".to_string(), AttributeValue::S(t.user_id.clone())),
+        (
+            "trade_id".to_string(),
+            AttributeValue::S(t.trade_id.clone()),
+        ),
+        ("symbol".to_string(), AttributeValue::S(t.symbol.clone())),
+        (
+            "trade_type".to_string(),
+            AttributeValue::S(t.trade_type.clone()),
+        ),
+        (
+            "quantity".to_string(),
+            AttributeValue::N(t.quantity.to_string()),
+        ),
+        ("price".to_string(), AttributeValue::N(t.price.to_string())),
+        (
+            "amount".to_string(),
+            AttributeValue::N(t.amount.to_string()),
+        ),
+        ("date".to_string(), AttributeValue::S(t.date.clone())),
+        ("source".to_string(), AttributeValue::S(t.source.clone())),
+        (
+            "created_at".to_string(),
+            AttributeValue::S(t.created_at.clone()),
+        ),
+    ])
+}
+
+pub async fn list_trades(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio-trades")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    match result {
+        Ok(output) => {
+            let mut trades: Vec<Trade> = output
+                .items
+                .unwrap_or_default()
+                .iter()
+                .map(item_to_trade)
+                .collect();
+            trades.sort_by(|a, b| a.date.cmp(&b.date));
+            (StatusCode::OK, Json(serde_json::to_value(trades).unwrap())).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn create_trade(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(body): Json<CreateTradeRequest>,
+) -> impl IntoResponse {
+    if !matches!(body.trade_type.as_str(), "buy" | "sell" | "dividend") {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("trade_type must be buy, sell or dividend")),
+        )
+            .into_response();
+    }
+    if chrono::NaiveDate::parse_from_str(&body.date, "%Y-%m-%d").is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("date must be YYYY-MM-DD")),
+        )
+            .into_response();
+    }
+
+    let trade_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let amount = body.amount.unwrap_or(body.quantity * body.price);
+
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-portfolio-trades")
+        .item("user_id", AttributeValue::S(claims.sub.clone()))
+        .item("trade_id", AttributeValue::S(trade_id.clone()))
+        .item("symbol", AttributeValue::S(body.symbol.to_uppercase()))
+        .item("trade_type", AttributeValue::S(body.trade_type.clone()))
+        .item("quantity", AttributeValue::N(body.quantity.to_string()))
+        .item("price", AttributeValue::N(body.price.to_string()))
+        .item("amount", AttributeValue::N(amount.to_string()))
+        .item("date", AttributeValue::S(body.date.clone()))
+        .item("source", AttributeValue::S("manual".to_string()))
+        .item("created_at", AttributeValue::S(now.clone()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => {
+            let trade = Trade {
+                trade_id,
+                user_id: claims.sub,
+                symbol: body.symbol.to_uppercase(),
+                trade_type: body.trade_type,
+                quantity: body.quantity,
+                price: body.price,
+                amount,
+                date: body.date,
+                source: "manual".to_string(),
+                created_at: now,
+            };
+            (
+                StatusCode::CREATED,
+                Json(serde_json::to_value(trade).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to add trade: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn delete_trade(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(trade_id): Path<String>,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-portfolio-trades")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("trade_id", AttributeValue::S(trade_id))
         .send()
         .await;
 
@@ -250,3 +769,54 @@ pub async fn delete_holding(
             .into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valued(symbol: &str, asset_class: &str, market_value: f64) -> ValuedHolding {
+        ValuedHolding {
+            holding: Holding {
+                holding_id: symbol.to_lowercase(),
+                user_id: "user-1".to_string(),
+                symbol: symbol.to_string(),
+                shares: 1.0,
+                avg_cost: market_value,
+                asset_class: asset_class.to_string(),
+                manual_price: None,
+                source: "manual".to_string(),
+                created_at: String::new(),
+                updated_at: String::new(),
+            },
+            price: Some(market_value),
+            market_value,
+            cost_basis: market_value,
+        }
+    }
+
+    #[test]
+    fn totals_group_holdings_by_asset_class() {
+        let holdings = vec![
+            valued("AAPL", "equity", 300.0),
+            valued("MSFT", "equity", 300.0),
+            valued("BTC", "crypto", 200.0),
+            valued("HOUSE", "manual", 200.0),
+        ];
+
+        let classes = totals_by_class(&holdings);
+        assert_eq!(classes.len(), 3);
+        assert_eq!(classes[0].asset_class, "equity");
+        assert_eq!(classes[0].holdings, 2);
+        assert!((classes[0].weight - 0.6).abs() < 1e-9);
+        assert!((classes.iter().map(|c| c.weight).sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn holdings_without_asset_class_default_to_equity() {
+        let mut item = std::collections::HashMap::new();
+        item.insert("symbol".to_string(), AttributeValue::S("AAPL".to_string()));
+        let holding = item_to_holding(&item);
+        assert_eq!(holding.asset_class, "equity");
+        assert!(holding.manual_price.is_none());
+    }
+}
@@ -0,0 +1,819 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, TransactWriteItem};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::db::dynamo::{query_by_pk, TABLE_PORTFOLIO, TABLE_PORTFOLIO_TRADES};
+use crate::handlers::portfolio::{
+    holding_to_item, item_to_holding, item_to_trade, trade_to_item, Holding, Trade,
+};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+/// DynamoDB's limit on actions in a single TransactWriteItems call.
+const MAX_TRANSACTION_ITEMS: usize = 100;
+/// Broker exports often start with a few lines of account details before the header.
+const MAX_PREAMBLE_ROWS: usize = 20;
+
+#[derive(Deserialize, Default, Clone)]
+pub struct ColumnMap {
+    pub symbol: Option<String>,
+    pub quantity: Option<String>,
+    pub price: Option<String>,
+    /// Total cost basis of a position
+    pub cost_basis: Option<String>,
+    /// Cost basis per share of a position
+    pub average_cost: Option<String>,
+    pub date: Option<String>,
+    pub action: Option<String>,
+    pub amount: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    /// "fidelity", "schwab", "vanguard" or "generic"
+    pub format: String,
+    /// "positions" or "trades"; detected from the columns when omitted
+    pub kind: Option<String>,
+    pub csv: String,
+    /// Header names for each field, required for the generic format
+    #[serde(default)]
+    pub column_map: ColumnMap,
+    /// Preview only (the default); pass `false` to write the import
+    #[serde(default = "default_dry_run")]
+    pub dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ParsedPosition {
+    line: usize,
+    symbol: String,
+    quantity: f64,
+    average_cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ParsedTrade {
+    line: usize,
+    date: String,
+    symbol: String,
+    trade_type: String,
+    quantity: f64,
+    price: f64,
+    amount: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkippedRow {
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+struct ParsedImport {
+    kind: String,
+    positions: Vec<ParsedPosition>,
+    trades: Vec<ParsedTrade>,
+    skipped: Vec<SkippedRow>,
+}
+
+#[derive(Serialize)]
+pub struct HoldingPreview {
+    /// "create", "update", "unchanged" or "delete"
+    pub status: String,
+    /// Line in the file; 0 for positions deleted because the file no longer lists them
+    pub line: usize,
+    pub holding: Holding,
+}
+
+#[derive(Serialize)]
+pub struct TradePreview {
+    /// "create" or "duplicate"
+    pub status: String,
+    pub line: usize,
+    pub trade: Trade,
+}
+
+#[derive(Serialize)]
+pub struct ImportResult {
+    pub format: String,
+    pub kind: String,
+    pub dry_run: bool,
+    pub committed: bool,
+    pub holdings: Vec<HoldingPreview>,
+    pub trades: Vec<TradePreview>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Symbol,
+    Quantity,
+    Price,
+    CostBasis,
+    AverageCost,
+    Date,
+    Action,
+    Amount,
+}
+
+/// Header names each broker uses for a field, lowercased.
+fn preset_headers(format: &str, field: Field) -> &'static [&'static str] {
+    match (format, field) {
+        (_, Field::Symbol) => &["symbol"],
+        ("fidelity", Field::Quantity) => &["quantity"],
+        ("fidelity", Field::Price) => &["last price", "price ($)"],
+        ("fidelity", Field::CostBasis) => &["cost basis total"],
+        ("fidelity", Field::AverageCost) => &["average cost basis"],
+        ("fidelity", Field::Date) => &["run date"],
+        ("fidelity", Field::Action) => &["action"],
+        ("fidelity", Field::Amount) => &["amount ($)"],
+        ("schwab", Field::Quantity) => &["quantity", "qty (quantity)"],
+        ("schwab", Field::Price) => &["price"],
+        ("schwab", Field::CostBasis) => &["cost basis"],
+        ("schwab", Field::AverageCost) => &[],
+        ("schwab", Field::Date) => &["date"],
+        ("schwab", Field::Action) => &["action"],
+        ("schwab", Field::Amount) => &["amount"],
+        ("vanguard", Field::Quantity) => &["shares"],
+        ("vanguard", Field::Price) => &["share price"],
+        ("vanguard", Field::CostBasis) => &[],
+        ("vanguard", Field::AverageCost) => &[],
+        ("vanguard", Field::Date) => &["trade date"],
+        ("vanguard", Field::Action) => &["transaction type"],
+        ("vanguard", Field::Amount) => &["net amount", "principal amount"],
+        _ => &[],
+    }
+}
+
+struct Columns {
+    symbol: usize,
+    quantity: Option<usize>,
+    price: Option<usize>,
+    cost_basis: Option<usize>,
+    average_cost: Option<usize>,
+    date: Option<usize>,
+    action: Option<usize>,
+    amount: Option<usize>,
+}
+
+fn normalize_header(h: &str) -> String {
+    h.trim().trim_matches('"').to_lowercase()
+}
+
+fn find_column(
+    header: &[String],
+    format: &str,
+    field: Field,
+    mapped: &Option<String>,
+) -> Option<usize> {
+    if let Some(name) = mapped {
+        let name = normalize_header(name);
+        return header.iter().position(|h| *h == name);
+    }
+    preset_headers(format, field)
+        .iter()
+        .find_map(|candidate| header.iter().position(|h| h == candidate))
+}
+
+fn resolve_columns(header: &[String], format: &str, map: &ColumnMap) -> Option<Columns> {
+    let col = |field, mapped| find_column(header, format, field, mapped);
+    Some(Columns {
+        symbol: col(Field::Symbol, &map.symbol)?,
+        quantity: col(Field::Quantity, &map.quantity),
+        price: col(Field::Price, &map.price),
+        cost_basis: col(Field::CostBasis, &map.cost_basis),
+        average_cost: col(Field::AverageCost, &map.average_cost),
+        date: col(Field::Date, &map.date),
+        action: col(Field::Action, &map.action),
+        amount: col(Field::Amount, &map.amount),
+    })
+}
+
+/// Parse a broker-formatted number: `$1,234.50`, `(12.00)` for negatives, `--` for none.
+fn parse_number(raw: &str) -> Option<f64> {
+    let s = raw.trim().trim_matches('"');
+    let (s, negative) = match s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (inner, true),
+        None => (s, false),
+    };
+    let cleaned: String = s
+        .chars()
+        .filter(|c| !matches!(c, '$' | ',' | ' ' | '+'))
+        .collect();
+    if cleaned.is_empty() || cleaned == "--" || cleaned.eq_ignore_ascii_case("n/a") {
+        return None;
+    }
+    let value = cleaned.parse::<f64>().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Normalize broker dates to YYYY-MM-DD. Schwab writes "04/15/2024 as of 04/12/2024".
+fn parse_date(raw: &str) -> Option<String> {
+    let first = raw.split_whitespace().next()?;
+    ["%m/%d/%Y", "%Y-%m-%d", "%m/%d/%y", "%m-%d-%Y"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(first, fmt).ok())
+        .map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+/// Map a broker action description onto buy, sell or dividend.
+fn parse_action(raw: &str) -> Option<&'static str> {
+    let action = raw.to_lowercase();
+    if action.contains("dividend") && !action.contains("reinvest") {
+        Some("dividend")
+    } else if action.contains("bought") || action.contains("buy") || action.contains("reinvest") {
+        Some("buy")
+    } else if action.contains("sold") || action.contains("sell") {
+        Some("sell")
+    } else {
+        None
+    }
+}
+
+/// Money market sweep funds are marked with asterisks in Fidelity exports ("SPAXX**").
+fn clean_symbol(raw: &str) -> String {
+    raw.trim()
+        .trim_matches('"')
+        .trim_end_matches('*')
+        .to_uppercase()
+}
+
+fn parse_csv(
+    csv_text: &str,
+    format: &str,
+    kind: Option<&str>,
+    map: &ColumnMap,
+) -> Result<ParsedImport, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(csv_text.as_bytes());
+
+    let records: Vec<csv::StringRecord> = reader
+        .records()
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid CSV: {}", e))?;
+
+    let (header_index, columns) = records
+        .iter()
+        .take(MAX_PREAMBLE_ROWS)
+        .enumerate()
+        .find_map(|(i, record)| {
+            let header: Vec<String> = record.iter().map(normalize_header).collect();
+            resolve_columns(&header, format, map).map(|c| (i, c))
+        })
+        .ok_or_else(|| "Could not find a header row with a symbol column".to_string())?;
+
+    let kind = match kind {
+        Some(k @ ("positions" | "trades")) => k.to_string(),
+        Some(other) => return Err(format!("Unknown import kind: {}", other)),
+        None if columns.action.is_some() && columns.date.is_some() => "trades".to_string(),
+        None => "positions".to_string(),
+    };
+    if kind == "trades" && (columns.action.is_none() || columns.date.is_none()) {
+        return Err("Trade imports need date and action columns".to_string());
+    }
+    if columns.quantity.is_none() {
+        return Err("Could not find a quantity column".to_string());
+    }
+
+    let mut parsed = ParsedImport {
+        kind: kind.clone(),
+        ..Default::default()
+    };
+
+    for (i, record) in records.iter().enumerate().skip(header_index + 1) {
+        let line = record
+            .position()
+            .map(|p| p.line() as usize)
+            .unwrap_or(i + 1);
+        let cell = |index: Option<usize>| index.and_then(|i| record.get(i)).unwrap_or("");
+        let symbol = clean_symbol(cell(Some(columns.symbol)));
+        if symbol.is_empty() || record.iter().all(|c| c.trim().is_empty()) {
+            continue;
+        }
+        let skip = |reason: &str| SkippedRow {
+            line,
+            reason: reason.to_string(),
+        };
+
+        let quantity = parse_number(cell(columns.quantity)).map(f64::abs);
+        let price = parse_number(cell(columns.price)).map(f64::abs);
+
+        if kind == "positions" {
+            let Some(quantity) = quantity.filter(|q| *q > 0.0) else {
+                parsed.skipped.push(skip("missing quantity"));
+                continue;
+            };
+            let average_cost = parse_number(cell(columns.average_cost))
+                .or_else(|| parse_number(cell(columns.cost_basis)).map(|total| total / quantity))
+                .or(price)
+                .unwrap_or(0.0)
+                .abs();
+            parsed.positions.push(ParsedPosition {
+                line,
+                symbol,
+                quantity,
+                average_cost,
+            });
+        } else {
+            let Some(date) = parse_date(cell(columns.date)) else {
+                parsed.skipped.push(skip("invalid date"));
+                continue;
+            };
+            let Some(trade_type) = parse_action(cell(columns.action)) else {
+                parsed.skipped.push(skip("unsupported action"));
+                continue;
+            };
+            // Cash dividends carry only an amount
+            let quantity = match (quantity, trade_type) {
+                (Some(q), _) => q,
+                (None, "dividend") => 0.0,
+                (None, _) => {
+                    parsed.skipped.push(skip("missing quantity"));
+                    continue;
+                }
+            };
+            let amount = parse_number(cell(columns.amount))
+                .map(f64::abs)
+                .unwrap_or(quantity * price.unwrap_or(0.0));
+            parsed.trades.push(ParsedTrade {
+                line,
+                date,
+                symbol,
+                trade_type: trade_type.to_string(),
+                quantity,
+                price: price.unwrap_or(0.0),
+                amount,
+            });
+        }
+    }
+
+    parsed.positions = merge_positions(std::mem::take(&mut parsed.positions));
+    Ok(parsed)
+}
+
+/// Brokers list a symbol once per account, but holdings are kept per symbol: rows for the
+/// same symbol are combined, with the average cost weighted by quantity.
+fn merge_positions(positions: Vec<ParsedPosition>) -> Vec<ParsedPosition> {
+    let mut merged: Vec<ParsedPosition> = Vec::new();
+    for p in positions {
+        match merged.iter_mut().find(|m| m.symbol == p.symbol) {
+            Some(m) => {
+                let quantity = m.quantity + p.quantity;
+                m.average_cost =
+                    (m.average_cost * m.quantity + p.average_cost * p.quantity) / quantity;
+                m.quantity = quantity;
+            }
+            None => merged.push(p),
+        }
+    }
+    merged
+}
+
+pub(crate) fn short_hash(input: &str) -> String {
+    hex::encode(Sha256::digest(input.as_bytes()))[..32].to_string()
+}
+
+/// Imported positions are keyed by broker and symbol, so re-importing updates them in place.
+fn position_id(format: &str, symbol: &str) -> String {
+    format!("import-{}", short_hash(&format!("{format}|{symbol}")))
+}
+
+/// Imported trades are keyed by their contents; identical rows in one file get an occurrence
+/// number so they stay distinct while re-imports of the same file collapse onto them.
+fn trade_ids(format: &str, trades: &[ParsedTrade]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    trades
+        .iter()
+        .map(|t| {
+            let key = format!(
+                "{format}|{}|{}|{}|{}|{}|{}",
+                t.date, t.symbol, t.trade_type, t.quantity, t.price, t.amount
+            );
+            let occurrence = seen.entry(key.clone()).or_insert(0);
+            *occurrence += 1;
+            format!("import-{}", short_hash(&format!("{key}|{occurrence}")))
+        })
+        .collect()
+}
+
+/// Positions from an earlier import of the same broker that the new file no longer lists,
+/// because they were sold off since.
+fn sold_positions(
+    existing: &HashMap<String, Holding>,
+    source: &str,
+    kept: &HashSet<String>,
+) -> Vec<String> {
+    let mut sold: Vec<&Holding> = existing
+        .values()
+        .filter(|h| h.source == source && !kept.contains(&h.holding_id))
+        .collect();
+    sold.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    sold.into_iter().map(|h| h.holding_id.clone()).collect()
+}
+
+fn delete_action(
+    table: &str,
+    user_id: &str,
+    holding_id: &str,
+) -> Result<TransactWriteItem, String> {
+    let delete = Delete::builder()
+        .table_name(table)
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("holding_id", AttributeValue::S(holding_id.to_string()))
+        .build()
+        .map_err(|e| format!("Failed to build import delete: {}", e))?;
+    Ok(TransactWriteItem::builder().delete(delete).build())
+}
+
+fn put_action(
+    table: &str,
+    item: HashMap<String, AttributeValue>,
+) -> Result<TransactWriteItem, String> {
+    let put = Put::builder()
+        .table_name(table)
+        .set_item(Some(item))
+        .build()
+        .map_err(|e| format!("Failed to build import write: {}", e))?;
+    Ok(TransactWriteItem::builder().put(put).build())
+}
+
+pub async fn import_portfolio(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(body): Json<ImportRequest>,
+) -> impl IntoResponse {
+    let format = body.format.to_lowercase();
+    if !matches!(
+        format.as_str(),
+        "fidelity" | "schwab" | "vanguard" | "generic"
+    ) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(
+                "format must be one of fidelity, schwab, vanguard, generic",
+            )),
+        )
+            .into_response();
+    }
+    if format == "generic" && body.column_map.symbol.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(
+                "column_map.symbol is required for generic imports",
+            )),
+        )
+            .into_response();
+    }
+
+    let parsed = match parse_csv(&body.csv, &format, body.kind.as_deref(), &body.column_map) {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+
+    let source = format!("import:{}", format);
+    let now = Utc::now().to_rfc3339();
+    let mut holdings = Vec::new();
+    let mut trades = Vec::new();
+
+    if parsed.kind == "positions" {
+        let result = query_by_pk(&state.dynamo, TABLE_PORTFOLIO, "user_id", &claims.sub).await;
+        let mut existing: HashMap<String, Holding> = match result {
+            Ok(items) => items
+                .iter()
+                .map(item_to_holding)
+                .map(|h| (h.holding_id.clone(), h))
+                .collect(),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiError::new(format!("Database error: {}", e))),
+                )
+                    .into_response();
+            }
+        };
+
+        for p in &parsed.positions {
+            let holding_id = position_id(&format, &p.symbol);
+            let previous = existing.get(&holding_id);
+            let status = match previous {
+                None => "create",
+                Some(h) if h.shares == p.quantity && h.avg_cost == p.average_cost => "unchanged",
+                Some(_) => "update",
+            };
+            holdings.push(HoldingPreview {
+                status: status.to_string(),
+                line: p.line,
+                holding: Holding {
+                    holding_id,
+                    user_id: claims.sub.clone(),
+                    symbol: p.symbol.clone(),
+                    shares: p.quantity,
+                    avg_cost: p.average_cost,
+                    asset_class: previous
+                        .map(|h| h.asset_class.clone())
+                        .unwrap_or_else(|| "equity".to_string()),
+                    manual_price: None,
+                    source: source.clone(),
+                    created_at: previous
+                        .map(|h| h.created_at.clone())
+                        .unwrap_or_else(|| now.clone()),
+                    updated_at: now.clone(),
+                },
+            });
+        }
+
+        let kept: HashSet<String> = holdings
+            .iter()
+            .map(|p| p.holding.holding_id.clone())
+            .collect();
+        for holding_id in sold_positions(&existing, &source, &kept) {
+            if let Some(holding) = existing.remove(&holding_id) {
+                holdings.push(HoldingPreview {
+                    status: "delete".to_string(),
+                    line: 0,
+                    holding,
+                });
+            }
+        }
+    } else {
+        let result = query_by_pk(
+            &state.dynamo,
+            TABLE_PORTFOLIO_TRADES,
+            "user_id",
+            &claims.sub,
+        )
+        .await;
+        let existing: HashSet<String> = match result {
+            Ok(items) => items
+                .iter()
+                .map(|item| item_to_trade(item).trade_id)
+                .collect(),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiError::new(format!("Database error: {}", e))),
+                )
+                    .into_response();
+            }
+        };
+
+        for (t, trade_id) in parsed.trades.iter().zip(trade_ids(&format, &parsed.trades)) {
+            let status = if existing.contains(&trade_id) {
+                "duplicate"
+            } else {
+                "create"
+            };
+            trades.push(TradePreview {
+                status: status.to_string(),
+                line: t.line,
+                trade: Trade {
+                    trade_id,
+                    user_id: claims.sub.clone(),
+                    symbol: t.symbol.clone(),
+                    trade_type: t.trade_type.clone(),
+                    quantity: t.quantity,
+                    price: t.price,
+                    amount: t.amount,
+                    date: t.date.clone(),
+                    source: source.clone(),
+                    created_at: now.clone(),
+                },
+            });
+        }
+    }
+
+    let mut committed = false;
+    if !body.dry_run {
+        let mut writes = Vec::new();
+        for preview in holdings.iter() {
+            match preview.status.as_str() {
+                "unchanged" => {}
+                "delete" => writes.push(delete_action(
+                    "ovaflus-portfolio",
+                    &claims.sub,
+                    &preview.holding.holding_id,
+                )),
+                _ => writes.push(put_action(
+                    "ovaflus-portfolio",
+                    holding_to_item(&preview.holding),
+                )),
+            }
+        }
+        for preview in trades.iter().filter(|p| p.status == "create") {
+            writes.push(put_action(
+                "ovaflus-portfolio-trades",
+                trade_to_item(&preview.trade),
+            ));
+        }
+        let writes = match writes
+            .into_iter()
+            .collect::<Result<Vec<TransactWriteItem>, _>>()
+        {
+            Ok(w) => w,
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError::new(e))).into_response();
+            }
+        };
+
+        // Each transaction holds at most 100 rows, so larger files land in several
+        for chunk in writes.chunks(MAX_TRANSACTION_ITEMS) {
+            let result = state
+                .dynamo
+                .transact_write_items()
+                .set_transact_items(Some(chunk.to_vec()))
+                .send()
+                .await;
+
+            if let Err(e) = result {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiError::new(format!("Import failed: {}", e))),
+                )
+                    .into_response();
+            }
+        }
+        committed = true;
+    }
+
+    let status = if committed {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    (
+        status,
+        Json(
+            serde_json::to_value(ImportResult {
+                format,
+                kind: parsed.kind,
+                dry_run: body.dry_run,
+                committed,
+                holdings,
+                trades,
+                skipped: parsed.skipped,
+            })
+            .unwrap(),
+        ),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIDELITY_POSITIONS: &str = "\
+Account Number,Account Name,Symbol,Description,Quantity,Last Price,Current Value,Cost Basis Total,Average Cost Basis,Type
+X123,Individual,AAPL,APPLE INC,10,$190.00,\"$1,900.00\",\"$1,500.00\",$150.00,Cash
+X123,Individual,SPAXX**,HELD IN MONEY MARKET,,,$250.00,,,Cash
+X123,Individual,MSFT,MICROSOFT CORP,5,$400.00,\"$2,000.00\",\"$1,000.00\",$200.00,Cash
+";
+
+    const SCHWAB_TRANSACTIONS: &str = "\
+\"Transactions for account XXXX-1234 as of 05/01/2024\"
+\"Date\",\"Action\",\"Symbol\",\"Description\",\"Quantity\",\"Price\",\"Fees & Comm\",\"Amount\"
+\"04/15/2024 as of 04/12/2024\",\"Buy\",\"VTI\",\"VANGUARD TOTAL STOCK\",\"10\",\"$250.00\",\"\",\"-$2,500.00\"
+\"04/20/2024\",\"Qualified Dividend\",\"VTI\",\"VANGUARD TOTAL STOCK\",\"\",\"\",\"\",\"$12.34\"
+\"04/22/2024\",\"Journal\",\"VTI\",\"TRANSFER\",\"1\",\"\",\"\",\"\"
+\"04/25/2024\",\"Sell\",\"VTI\",\"VANGUARD TOTAL STOCK\",\"2\",\"$260.00\",\"\",\"$520.00\"
+";
+
+    #[test]
+    fn fidelity_positions_use_average_cost_and_skip_cash_rows() {
+        let parsed =
+            parse_csv(FIDELITY_POSITIONS, "fidelity", None, &ColumnMap::default()).unwrap();
+        assert_eq!(parsed.kind, "positions");
+        assert_eq!(parsed.positions.len(), 2);
+        assert_eq!(parsed.positions[0].symbol, "AAPL");
+        assert_eq!(parsed.positions[0].average_cost, 150.0);
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].reason, "missing quantity");
+    }
+
+    #[test]
+    fn positions_missing_from_a_reimport_are_sold() {
+        let holding = |id: &str, symbol: &str, source: &str| Holding {
+            holding_id: id.to_string(),
+            user_id: "user-1".to_string(),
+            symbol: symbol.to_string(),
+            shares: 1.0,
+            avg_cost: 1.0,
+            asset_class: "equity".to_string(),
+            manual_price: None,
+            source: source.to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        };
+        let existing = HashMap::from([
+            ("a".to_string(), holding("a", "AAPL", "import:fidelity")),
+            ("m".to_string(), holding("m", "MSFT", "import:fidelity")),
+            ("s".to_string(), holding("s", "VTI", "import:schwab")),
+            ("h".to_string(), holding("h", "HOUSE", "manual")),
+        ]);
+        let kept = HashSet::from(["a".to_string()]);
+
+        let sold = sold_positions(&existing, "import:fidelity", &kept);
+        assert_eq!(sold, vec!["m"]);
+    }
+
+    #[test]
+    fn a_symbol_held_in_two_accounts_becomes_one_position() {
+        let csv_text = "\
+Account Number,Account Name,Symbol,Description,Quantity,Last Price,Average Cost Basis
+X123,Individual,AAPL,APPLE INC,10,$190.00,$150.00
+Y456,Roth IRA,AAPL,APPLE INC,30,$190.00,$170.00
+Y456,Roth IRA,MSFT,MICROSOFT CORP,5,$400.00,$200.00
+";
+        let parsed = parse_csv(csv_text, "fidelity", None, &ColumnMap::default()).unwrap();
+        assert_eq!(parsed.positions.len(), 2);
+        assert_eq!(parsed.positions[0].symbol, "AAPL");
+        assert_eq!(parsed.positions[0].quantity, 40.0);
+        assert_eq!(parsed.positions[0].average_cost, 165.0);
+        assert_eq!(parsed.positions[1].symbol, "MSFT");
+    }
+
+    #[test]
+    fn schwab_transactions_skip_preamble_and_map_actions() {
+        let parsed = parse_csv(SCHWAB_TRANSACTIONS, "schwab", None, &ColumnMap::default()).unwrap();
+        assert_eq!(parsed.kind, "trades");
+        assert_eq!(parsed.trades.len(), 3);
+        assert_eq!(parsed.trades[0].date, "2024-04-15");
+        assert_eq!(parsed.trades[0].trade_type, "buy");
+        assert_eq!(parsed.trades[0].amount, 2500.0);
+        assert_eq!(parsed.trades[1].trade_type, "dividend");
+        assert_eq!(parsed.trades[1].amount, 12.34);
+        assert_eq!(parsed.trades[2].trade_type, "sell");
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].reason, "unsupported action");
+    }
+
+    #[test]
+    fn generic_format_uses_column_map() {
+        let csv_text = "Ticker,Units,Paid,When,Kind\nNVDA,3,100,2024-01-02,BUY\n";
+        let map = ColumnMap {
+            symbol: Some("Ticker".to_string()),
+            quantity: Some("Units".to_string()),
+            price: Some("Paid".to_string()),
+            date: Some("When".to_string()),
+            action: Some("Kind".to_string()),
+            ..Default::default()
+        };
+        let parsed = parse_csv(csv_text, "generic", None, &map).unwrap();
+        assert_eq!(parsed.trades.len(), 1);
+        assert_eq!(parsed.trades[0].amount, 300.0);
+    }
+
+    #[test]
+    fn parse_number_handles_broker_formatting() {
+        assert_eq!(parse_number("$1,234.50"), Some(1234.5));
+        assert_eq!(parse_number("(12.00)"), Some(-12.0));
+        assert_eq!(parse_number("-$2,500.00"), Some(-2500.0));
+        assert_eq!(parse_number("--"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn reimporting_the_same_rows_yields_the_same_ids() {
+        let parsed = parse_csv(SCHWAB_TRANSACTIONS, "schwab", None, &ColumnMap::default()).unwrap();
+        assert_eq!(
+            trade_ids("schwab", &parsed.trades),
+            trade_ids("schwab", &parsed.trades)
+        );
+        assert_eq!(
+            position_id("fidelity", "AAPL"),
+            position_id("fidelity", "AAPL")
+        );
+        assert_ne!(
+            position_id("fidelity", "AAPL"),
+            position_id("schwab", "AAPL")
+        );
+    }
+
+    #[test]
+    fn identical_rows_in_one_file_stay_distinct() {
+        let trade = ParsedTrade {
+            line: 2,
+            date: "2024-01-02".to_string(),
+            symbol: "VTI".to_string(),
+            trade_type: "buy".to_string(),
+            quantity: 1.0,
+            price: 250.0,
+            amount: 250.0,
+        };
+        let ids = trade_ids("schwab", &[trade.clone(), trade]);
+        assert_ne!(ids[0], ids[1]);
+    }
+}
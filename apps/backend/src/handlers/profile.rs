@@ -16,6 +16,9 @@ pub struct UserProfile {
     pub created_at: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub currency: Option<String>,
+    /// ISO 3166-1 alpha-2 code, used to pick the banks offered when linking
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub notifications_enabled: Option<bool>,
 }
@@ -27,9 +30,30 @@ pub struct UpdateProfileRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub currency: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub notifications_enabled: Option<bool>,
 }
 
+/// The country on the user's profile, if they've set one.
+pub async fn load_profile_country(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Option<String>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-users")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .projection_expression("country")
+        .send()
+        .await?;
+
+    Ok(output
+        .item
+        .and_then(|item| item.get("country").and_then(|v| v.as_s().ok()).cloned()))
+}
+
 pub async fn get_profile(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
@@ -67,6 +91,7 @@ pub async fn get_profile(
                         .cloned()
                         .unwrap_or_default(),
                     currency: item.get("currency").and_then(|v| v.as_s().ok()).cloned(),
+                    country: item.get("country").and_then(|v| v.as_s().ok()).cloned(),
                     notifications_enabled: item
                         .get("notifications_enabled")
                         .and_then(|v| v.as_bool().ok())
@@ -104,6 +129,21 @@ pub async fn update_profile(
         expr_attr_values.push((":currency".to_string(), AttributeValue::S(currency.clone())));
     }
 
+    if let Some(ref country) = body.country {
+        let country = country.trim().to_uppercase();
+        if country.len() != 2 || !country.chars().all(|c| c.is_ascii_uppercase()) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiError::new(
+                    "country must be a two-letter ISO country code",
+                )),
+            )
+                .into_response();
+        }
+        update_expr_parts.push("country = :country".to_string());
+        expr_attr_values.push((":country".to_string(), AttributeValue::S(country)));
+    }
+
     if let Some(notifications_enabled) = body.notifications_enabled {
         update_expr_parts.push("notifications_enabled = :notif".to_string());
         expr_attr_values.push((
@@ -172,6 +212,7 @@ pub async fn update_profile(
                     .cloned()
                     .unwrap_or_default(),
                 currency: item.get("currency").and_then(|v| v.as_s().ok()).cloned(),
+                country: item.get("country").and_then(|v| v.as_s().ok()).cloned(),
                 notifications_enabled: item
                     .get("notifications_enabled")
                     .and_then(|v| v.as_bool().ok())
@@ -0,0 +1,783 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::handlers::portfolio::{item_to_holding, Holding};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const DEFAULT_DRIFT_THRESHOLD: f64 = 0.05;
+/// Weights may sum to slightly more than 1.0 because of rounding on the client.
+const WEIGHT_TOLERANCE: f64 = 1e-6;
+
+// --- Target Allocation ---
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AllocationTarget {
+    /// "symbol" or "asset_class"
+    pub kind: String,
+    /// Ticker symbol or asset class name, depending on `kind`
+    pub key: String,
+    /// Target share of the portfolio, 0.0..=1.0
+    pub weight: f64,
+}
+
+#[derive(Deserialize)]
+pub struct SetTargetsRequest {
+    pub targets: Vec<AllocationTarget>,
+}
+
+fn target_id(target: &AllocationTarget) -> String {
+    format!("{}#{}", target.kind, target.key)
+}
+
+fn item_to_target(item: &HashMap<String, AttributeValue>) -> AllocationTarget {
+    AllocationTarget {
+        kind: item
+            .get("kind")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        key: item
+            .get("key")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default(),
+        weight: item
+            .get("weight")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(0.0),
+    }
+}
+
+fn normalize_target(target: AllocationTarget) -> Result<AllocationTarget, String> {
+    let key = match target.kind.as_str() {
+        "symbol" => target.key.trim().to_uppercase(),
+        "asset_class" => target.key.trim().to_lowercase(),
+        other => return Err(format!("Unknown target kind: {}", other)),
+    };
+    if key.is_empty() {
+        return Err("Target key must not be empty".to_string());
+    }
+    if !(0.0..=1.0).contains(&target.weight) {
+        return Err(format!("Weight for {} must be between 0 and 1", key));
+    }
+    Ok(AllocationTarget { key, ..target })
+}
+
+/// Normalize a full allocation; each `(kind, key)` may only appear once, since targets
+/// are stored by that pair and a repeat would silently overwrite the earlier weight.
+fn normalize_targets(targets: Vec<AllocationTarget>) -> Result<Vec<AllocationTarget>, String> {
+    let mut seen = HashSet::new();
+    targets
+        .into_iter()
+        .map(|target| {
+            let target = normalize_target(target)?;
+            if !seen.insert(target_id(&target)) {
+                return Err(format!("Duplicate {} target: {}", target.kind, target.key));
+            }
+            Ok(target)
+        })
+        .collect()
+}
+
+async fn load_targets(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<(String, AllocationTarget)>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-allocation-targets")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+
+    Ok(output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .map(|item| {
+            let id = item
+                .get("target_id")
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .unwrap_or_default();
+            (id, item_to_target(item))
+        })
+        .collect())
+}
+
+pub async fn get_targets(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match load_targets(&state, &claims.sub).await {
+        Ok(targets) => {
+            let targets: Vec<AllocationTarget> = targets.into_iter().map(|(_, t)| t).collect();
+            (StatusCode::OK, Json(serde_json::to_value(targets).unwrap())).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Replace the user's whole target allocation.
+pub async fn set_targets(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(body): Json<SetTargetsRequest>,
+) -> impl IntoResponse {
+    let targets = match normalize_targets(body.targets) {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+
+    let total_weight: f64 = targets.iter().map(|t| t.weight).sum();
+    if total_weight > 1.0 + WEIGHT_TOLERANCE {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("Target weights must not sum to more than 1")),
+        )
+            .into_response();
+    }
+
+    let existing = match load_targets(&state, &claims.sub).await {
+        Ok(t) => t,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let now = Utc::now().to_rfc3339();
+    for target in &targets {
+        let put_result = state
+            .dynamo
+            .put_item()
+            .table_name("ovaflus-allocation-targets")
+            .item("user_id", AttributeValue::S(claims.sub.clone()))
+            .item("target_id", AttributeValue::S(target_id(target)))
+            .item("kind", AttributeValue::S(target.kind.clone()))
+            .item("key", AttributeValue::S(target.key.clone()))
+            .item("weight", AttributeValue::N(target.weight.to_string()))
+            .item("updated_at", AttributeValue::S(now.clone()))
+            .send()
+            .await;
+
+        if let Err(e) = put_result {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Failed to store target: {}", e))),
+            )
+                .into_response();
+        }
+    }
+
+    // Drop targets that are no longer part of the allocation
+    for (id, _) in existing {
+        if targets.iter().any(|t| target_id(t) == id) {
+            continue;
+        }
+        let delete_result = state
+            .dynamo
+            .delete_item()
+            .table_name("ovaflus-allocation-targets")
+            .key("user_id", AttributeValue::S(claims.sub.clone()))
+            .key("target_id", AttributeValue::S(id))
+            .send()
+            .await;
+
+        if let Err(e) = delete_result {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Failed to remove target: {}", e))),
+            )
+                .into_response();
+        }
+    }
+
+    (StatusCode::OK, Json(serde_json::to_value(targets).unwrap())).into_response()
+}
+
+// --- Rebalance ---
+
+#[derive(Deserialize)]
+pub struct RebalanceQuery {
+    /// Only rebalance buckets whose weight is off target by at least this much (default 0.05).
+    pub drift_threshold: Option<f64>,
+    /// Skip suggested trades smaller than this cash amount.
+    pub min_trade_size: Option<f64>,
+    /// New cash to invest as part of the rebalance.
+    pub contribution: Option<f64>,
+    /// Only buy with the contribution; never suggest sells.
+    #[serde(default)]
+    pub contributions_only: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Position {
+    symbol: String,
+    asset_class: String,
+    quantity: f64,
+    price: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RebalanceOptions {
+    drift_threshold: f64,
+    min_trade_size: f64,
+    contribution: f64,
+    contributions_only: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AllocationDrift {
+    pub kind: String,
+    pub key: String,
+    pub target_weight: f64,
+    pub current_weight: f64,
+    /// Current minus target weight, measured against the value after the contribution.
+    pub drift: f64,
+    pub current_value: f64,
+    pub target_value: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SuggestedTrade {
+    /// `None` when an asset class target has no holding to buy into.
+    pub symbol: Option<String>,
+    pub target_kind: String,
+    pub target_key: String,
+    /// "buy" or "sell"
+    pub action: String,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RebalancePlan {
+    pub total_value: f64,
+    pub contribution: f64,
+    pub allocations: Vec<AllocationDrift>,
+    pub trades: Vec<SuggestedTrade>,
+    /// Contribution plus sale proceeds left over after the suggested buys.
+    pub unallocated_cash: f64,
+}
+
+/// Index of the target a position counts towards. Symbol targets win over asset class targets.
+fn target_for(position: &Position, targets: &[AllocationTarget]) -> Option<usize> {
+    targets
+        .iter()
+        .position(|t| t.kind == "symbol" && t.key == position.symbol)
+        .or_else(|| {
+            targets
+                .iter()
+                .position(|t| t.kind == "asset_class" && t.key == position.asset_class)
+        })
+}
+
+fn round_quantity(quantity: f64) -> f64 {
+    (quantity * 10_000.0).round() / 10_000.0
+}
+
+/// Spread a bucket's cash delta over its positions in proportion to their current value.
+fn split_delta(
+    target: &AllocationTarget,
+    delta: f64,
+    members: &[&Position],
+    options: &RebalanceOptions,
+) -> Vec<SuggestedTrade> {
+    let action = if delta > 0.0 { "buy" } else { "sell" };
+    if members.is_empty() {
+        if delta <= 0.0 || delta < options.min_trade_size {
+            return Vec::new();
+        }
+        return vec![SuggestedTrade {
+            symbol: None,
+            target_kind: target.kind.clone(),
+            target_key: target.key.clone(),
+            action: action.to_string(),
+            quantity: None,
+            price: None,
+            amount: delta,
+        }];
+    }
+
+    let bucket_value: f64 = members.iter().map(|p| p.quantity * p.price).sum();
+    members
+        .iter()
+        .filter(|p| p.price > 0.0)
+        .filter_map(|p| {
+            let share = if bucket_value > 0.0 {
+                p.quantity * p.price / bucket_value
+            } else {
+                1.0 / members.len() as f64
+            };
+            let mut quantity = delta.abs() * share / p.price;
+            if delta < 0.0 {
+                quantity = quantity.min(p.quantity);
+            }
+            let quantity = round_quantity(quantity);
+            let amount = quantity * p.price;
+            if quantity <= 0.0 || amount < options.min_trade_size {
+                return None;
+            }
+            Some(SuggestedTrade {
+                symbol: Some(p.symbol.clone()),
+                target_kind: target.kind.clone(),
+                target_key: target.key.clone(),
+                action: action.to_string(),
+                quantity: Some(quantity),
+                price: Some(p.price),
+                amount,
+            })
+        })
+        .collect()
+}
+
+/// Merge lots of the same symbol into one position priced at their combined cost basis,
+/// along with each position's manual price. Lots that disagree on asset class or manual
+/// price are rejected, since either would change which target and price the position gets.
+fn merge_lots(holdings: &[Holding]) -> Result<(Vec<Position>, Vec<Option<f64>>), String> {
+    let mut positions: Vec<Position> = Vec::new();
+    let mut manual_prices: Vec<Option<f64>> = Vec::new();
+    for h in holdings {
+        let symbol = h.symbol.to_uppercase();
+        match positions.iter().position(|p| p.symbol == symbol) {
+            Some(i) => {
+                let p = &mut positions[i];
+                if p.asset_class != h.asset_class {
+                    return Err(format!(
+                        "Holdings of {} have different asset classes ({} and {})",
+                        symbol, p.asset_class, h.asset_class
+                    ));
+                }
+                if manual_prices[i] != h.manual_price {
+                    return Err(format!(
+                        "Holdings of {} have different manual prices",
+                        symbol
+                    ));
+                }
+                let quantity = p.quantity + h.shares;
+                if quantity > 0.0 {
+                    p.price = (p.price * p.quantity + h.avg_cost * h.shares) / quantity;
+                }
+                p.quantity = quantity;
+            }
+            None => {
+                positions.push(Position {
+                    symbol,
+                    asset_class: h.asset_class.clone(),
+                    quantity: h.shares,
+                    price: h.avg_cost,
+                });
+                manual_prices.push(h.manual_price);
+            }
+        }
+    }
+    Ok((positions, manual_prices))
+}
+
+/// Compare current weights to targets and propose trades that move back towards them.
+///
+/// Positions not covered by any target are treated as having a target weight of zero.
+fn plan_rebalance(
+    positions: &[Position],
+    targets: &[AllocationTarget],
+    options: RebalanceOptions,
+) -> RebalancePlan {
+    let mut buckets: Vec<AllocationTarget> = targets.to_vec();
+    let mut members: Vec<Vec<&Position>> = vec![Vec::new(); buckets.len()];
+    for position in positions {
+        let index = match target_for(position, &buckets) {
+            Some(i) => i,
+            None => {
+                buckets.push(AllocationTarget {
+                    kind: "symbol".to_string(),
+                    key: position.symbol.clone(),
+                    weight: 0.0,
+                });
+                members.push(Vec::new());
+                buckets.len() - 1
+            }
+        };
+        members[index].push(position);
+    }
+
+    let current_total: f64 = positions.iter().map(|p| p.quantity * p.price).sum();
+    let new_total = current_total + options.contribution;
+
+    let mut allocations = Vec::with_capacity(buckets.len());
+    let mut deltas = Vec::with_capacity(buckets.len());
+    for (target, bucket) in buckets.iter().zip(&members) {
+        let current_value: f64 = bucket.iter().map(|p| p.quantity * p.price).sum();
+        let target_value = target.weight * new_total;
+        let drift = if new_total > 0.0 {
+            (current_value - target_value) / new_total
+        } else {
+            0.0
+        };
+        let out_of_band = drift.abs() >= options.drift_threshold;
+        let delta = if !out_of_band || (options.contributions_only && drift > 0.0) {
+            0.0
+        } else {
+            target_value - current_value
+        };
+        deltas.push(delta);
+        allocations.push(AllocationDrift {
+            kind: target.kind.clone(),
+            key: target.key.clone(),
+            target_weight: target.weight,
+            current_weight: if current_total > 0.0 {
+                current_value / current_total
+            } else {
+                0.0
+            },
+            drift,
+            current_value,
+            target_value,
+        });
+    }
+
+    // Without sells, buys can only spend the contribution: scale shortfalls down to fit
+    if options.contributions_only {
+        let shortfall: f64 = deltas.iter().filter(|d| **d > 0.0).sum();
+        if shortfall > options.contribution && shortfall > 0.0 {
+            let scale = options.contribution / shortfall;
+            for d in deltas.iter_mut() {
+                *d *= scale;
+            }
+        }
+    }
+
+    let trades: Vec<SuggestedTrade> = buckets
+        .iter()
+        .zip(&members)
+        .zip(&deltas)
+        .filter(|(_, delta)| **delta != 0.0)
+        .flat_map(|((target, bucket), delta)| split_delta(target, *delta, bucket, &options))
+        .collect();
+
+    let bought: f64 = trades
+        .iter()
+        .filter(|t| t.action == "buy")
+        .map(|t| t.amount)
+        .sum();
+    let sold: f64 = trades
+        .iter()
+        .filter(|t| t.action == "sell")
+        .map(|t| t.amount)
+        .sum();
+
+    RebalancePlan {
+        total_value: current_total,
+        contribution: options.contribution,
+        allocations,
+        trades,
+        unallocated_cash: options.contribution + sold - bought,
+    }
+}
+
+pub async fn get_rebalance(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<RebalanceQuery>,
+) -> impl IntoResponse {
+    let options = RebalanceOptions {
+        drift_threshold: params
+            .drift_threshold
+            .unwrap_or(DEFAULT_DRIFT_THRESHOLD)
+            .max(0.0),
+        min_trade_size: params.min_trade_size.unwrap_or(0.0).max(0.0),
+        contribution: params.contribution.unwrap_or(0.0).max(0.0),
+        contributions_only: params.contributions_only,
+    };
+
+    let targets: Vec<AllocationTarget> = match load_targets(&state, &claims.sub).await {
+        Ok(t) => t.into_iter().map(|(_, t)| t).collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+    if targets.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("No target allocation set")),
+        )
+            .into_response();
+    }
+
+    let result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    let holdings: Vec<Holding> = match result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(item_to_holding)
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let (mut positions, manual_prices) = match merge_lots(&holdings) {
+        Ok(merged) => merged,
+        Err(e) => return (StatusCode::CONFLICT, Json(ApiError::new(e))).into_response(),
+    };
+
+    // Price every position through the provider for its asset class
+    let mut tasks = JoinSet::new();
+    for (index, (p, manual_price)) in positions.iter().zip(manual_prices).enumerate() {
+        let market_data = state.market_data.clone();
+        let (asset_class, symbol) = (p.asset_class.clone(), p.symbol.clone());
+        tasks.spawn(async move {
+            let result = market_data.price(&asset_class, &symbol, manual_price).await;
+            (index, result)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((index, Ok(Some(price)))) => positions[index].price = price,
+            Ok((_, Ok(None))) => {}
+            // Fall back to cost basis, as the portfolio summary does
+            Ok((index, Err(e))) => {
+                tracing::warn!("Pricing {} failed: {e}", positions[index].symbol);
+            }
+            Err(e) => tracing::warn!("Quote task failed: {e}"),
+        }
+    }
+
+    let plan = plan_rebalance(&positions, &targets, options);
+    (StatusCode::OK, Json(serde_json::to_value(plan).unwrap())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(symbol: &str, asset_class: &str, quantity: f64, price: f64) -> Position {
+        Position {
+            symbol: symbol.to_string(),
+            asset_class: asset_class.to_string(),
+            quantity,
+            price,
+        }
+    }
+
+    fn target(kind: &str, key: &str, weight: f64) -> AllocationTarget {
+        AllocationTarget {
+            kind: kind.to_string(),
+            key: key.to_string(),
+            weight,
+        }
+    }
+
+    fn options() -> RebalanceOptions {
+        RebalanceOptions {
+            drift_threshold: 0.05,
+            min_trade_size: 0.0,
+            contribution: 0.0,
+            contributions_only: false,
+        }
+    }
+
+    #[test]
+    fn overweight_symbol_is_sold_into_underweight_one() {
+        let positions = vec![
+            position("AAPL", "equity", 70.0, 10.0),
+            position("BND", "etf", 30.0, 10.0),
+        ];
+        let targets = vec![target("symbol", "AAPL", 0.5), target("symbol", "BND", 0.5)];
+
+        let plan = plan_rebalance(&positions, &targets, options());
+        assert_eq!(plan.trades.len(), 2);
+        let sell = plan.trades.iter().find(|t| t.action == "sell").unwrap();
+        assert_eq!(sell.symbol.as_deref(), Some("AAPL"));
+        assert_eq!(sell.quantity, Some(20.0));
+        let buy = plan.trades.iter().find(|t| t.action == "buy").unwrap();
+        assert_eq!(buy.symbol.as_deref(), Some("BND"));
+        assert!(plan.unallocated_cash.abs() < 1e-9);
+    }
+
+    #[test]
+    fn drift_below_threshold_produces_no_trades() {
+        let positions = vec![
+            position("AAPL", "equity", 52.0, 10.0),
+            position("BND", "etf", 48.0, 10.0),
+        ];
+        let targets = vec![target("symbol", "AAPL", 0.5), target("symbol", "BND", 0.5)];
+
+        let plan = plan_rebalance(&positions, &targets, options());
+        assert!(plan.trades.is_empty());
+    }
+
+    #[test]
+    fn contributions_only_never_sells_and_spends_at_most_the_contribution() {
+        let positions = vec![
+            position("AAPL", "equity", 80.0, 10.0),
+            position("BND", "etf", 20.0, 10.0),
+        ];
+        let targets = vec![target("symbol", "AAPL", 0.5), target("symbol", "BND", 0.5)];
+        let opts = RebalanceOptions {
+            contribution: 100.0,
+            contributions_only: true,
+            ..options()
+        };
+
+        let plan = plan_rebalance(&positions, &targets, opts);
+        assert!(plan.trades.iter().all(|t| t.action == "buy"));
+        let spent: f64 = plan.trades.iter().map(|t| t.amount).sum();
+        assert!((spent - 100.0).abs() < 1e-6);
+        assert!(plan.unallocated_cash.abs() < 1e-6);
+    }
+
+    #[test]
+    fn min_trade_size_filters_small_trades() {
+        let positions = vec![
+            position("AAPL", "equity", 60.0, 10.0),
+            position("BND", "etf", 40.0, 10.0),
+        ];
+        let targets = vec![target("symbol", "AAPL", 0.5), target("symbol", "BND", 0.5)];
+        let opts = RebalanceOptions {
+            min_trade_size: 500.0,
+            ..options()
+        };
+
+        let plan = plan_rebalance(&positions, &targets, opts);
+        assert!(plan.trades.is_empty());
+    }
+
+    #[test]
+    fn asset_class_target_splits_across_its_holdings() {
+        let positions = vec![
+            position("AAPL", "equity", 30.0, 10.0),
+            position("MSFT", "equity", 10.0, 30.0),
+            position("BND", "etf", 40.0, 10.0),
+        ];
+        let targets = vec![
+            target("asset_class", "equity", 0.8),
+            target("symbol", "BND", 0.2),
+        ];
+
+        let plan = plan_rebalance(&positions, &targets, options());
+        let buys: Vec<&SuggestedTrade> = plan.trades.iter().filter(|t| t.action == "buy").collect();
+        assert_eq!(buys.len(), 2);
+        assert!(buys.iter().all(|t| t.target_key == "equity"));
+        assert!((buys.iter().map(|t| t.amount).sum::<f64>() - 200.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn untargeted_positions_are_sold() {
+        let positions = vec![
+            position("AAPL", "equity", 50.0, 10.0),
+            position("GME", "equity", 50.0, 10.0),
+        ];
+        let targets = vec![target("symbol", "AAPL", 1.0)];
+
+        let plan = plan_rebalance(&positions, &targets, options());
+        let sell = plan.trades.iter().find(|t| t.action == "sell").unwrap();
+        assert_eq!(sell.symbol.as_deref(), Some("GME"));
+        assert_eq!(sell.quantity, Some(50.0));
+    }
+
+    fn holding(symbol: &str, asset_class: &str, shares: f64, avg_cost: f64) -> Holding {
+        Holding {
+            holding_id: format!("{symbol}-{shares}"),
+            user_id: "user-1".to_string(),
+            symbol: symbol.to_string(),
+            shares,
+            avg_cost,
+            asset_class: asset_class.to_string(),
+            manual_price: None,
+            source: "manual".to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn lots_merge_at_combined_cost_and_conflicts_are_rejected() {
+        let (positions, manual_prices) = merge_lots(&[
+            holding("vti", "etf", 10.0, 100.0),
+            holding("VTI", "etf", 30.0, 200.0),
+        ])
+        .unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].quantity, 40.0);
+        assert!((positions[0].price - 175.0).abs() < 1e-9);
+        assert_eq!(manual_prices, vec![None]);
+
+        assert!(merge_lots(&[
+            holding("VTI", "etf", 10.0, 100.0),
+            holding("VTI", "equity", 1.0, 100.0),
+        ])
+        .is_err());
+
+        let mut priced = holding("HOUSE", "manual", 1.0, 100.0);
+        priced.manual_price = Some(150.0);
+        assert!(merge_lots(&[holding("HOUSE", "manual", 1.0, 100.0), priced]).is_err());
+    }
+
+    #[test]
+    fn normalize_target_rejects_bad_input() {
+        assert!(normalize_target(target("sector", "tech", 0.1)).is_err());
+        assert!(normalize_target(target("symbol", "AAPL", 1.5)).is_err());
+        let t = normalize_target(target("symbol", " aapl ", 0.5)).unwrap();
+        assert_eq!(t.key, "AAPL");
+    }
+
+    #[test]
+    fn normalize_targets_rejects_duplicates_after_normalizing() {
+        let err = normalize_targets(vec![
+            target("symbol", "AAPL", 0.3),
+            target("symbol", " aapl", 0.2),
+        ])
+        .unwrap_err();
+        assert!(err.contains("AAPL"));
+
+        let targets = normalize_targets(vec![
+            target("symbol", "AAPL", 0.3),
+            target("asset_class", "AAPL", 0.2),
+        ])
+        .unwrap();
+        assert_eq!(targets.len(), 2);
+    }
+}
@@ -0,0 +1,948 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Days, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::dynamo::is_conditional_check_failure;
+use crate::db::transaction_keys::{budget_key, date_key, ATTR_BUDGET_KEY, ATTR_DATE_KEY};
+use crate::handlers::merchants::normalize_merchant;
+use crate::handlers::transactions::{apply_spent_changes, budget_allocations};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+/// Occurrences materialized per schedule in one job run; a schedule further behind catches
+/// up over the following runs.
+pub(crate) const MAX_CATCH_UP: usize = 31;
+
+const DEFAULT_UPCOMING_DAYS: u64 = 30;
+const MAX_UPCOMING_DAYS: u64 = 366;
+
+// --- Recurrence Rules ---
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The subset of an iCalendar RRULE schedules support: `FREQ` and `INTERVAL`. The day of the
+/// week or month comes from the schedule's start date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Recurrence {
+    frequency: Frequency,
+    interval: u32,
+}
+
+impl Recurrence {
+    /// Parse an RRULE such as `FREQ=MONTHLY;INTERVAL=2`, or one of the app's frequency names
+    /// (`daily`, `weekly`, `biweekly`, `monthly`, `yearly`).
+    fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let shorthand = value.to_lowercase().replace(['-', ' '], "");
+        let named = |frequency, interval| {
+            Ok(Recurrence {
+                frequency,
+                interval,
+            })
+        };
+        match shorthand.as_str() {
+            "daily" => return named(Frequency::Daily, 1),
+            "weekly" => return named(Frequency::Weekly, 1),
+            "biweekly" => return named(Frequency::Weekly, 2),
+            "monthly" => return named(Frequency::Monthly, 1),
+            "yearly" | "annually" => return named(Frequency::Yearly, 1),
+            _ => {}
+        }
+
+        let rule = value.to_uppercase();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(&rule);
+        let mut frequency = None;
+        let mut interval = 1;
+        for part in rule.split(';').filter(|p| !p.is_empty()) {
+            let (name, part_value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid rule part: {}", part))?;
+            match name {
+                "FREQ" => {
+                    frequency = Some(match part_value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => return Err(format!("Unsupported frequency: {}", other)),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = part_value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|i| (1..=366).contains(i))
+                        .ok_or_else(|| format!("Invalid interval: {}", part_value))?;
+                }
+                other => return Err(format!("Unsupported rule part: {}", other)),
+            }
+        }
+        let frequency = frequency.ok_or("The rule needs a FREQ")?;
+        Ok(Recurrence {
+            frequency,
+            interval,
+        })
+    }
+
+    fn to_rrule(self) -> String {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        format!("FREQ={};INTERVAL={}", frequency, self.interval)
+    }
+
+    /// The `n`th occurrence counting from `start`. Each one is computed from the start, so a
+    /// schedule on the 31st lands on the last day of shorter months without drifting.
+    fn nth(self, start: NaiveDate, n: u32) -> Option<NaiveDate> {
+        let steps = n.checked_mul(self.interval)?;
+        match self.frequency {
+            Frequency::Daily => start.checked_add_days(Days::new(steps.into())),
+            Frequency::Weekly => start.checked_add_days(Days::new(u64::from(steps) * 7)),
+            Frequency::Monthly => start.checked_add_months(Months::new(steps)),
+            Frequency::Yearly => start.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }
+    }
+
+    /// Occurrences from `from` through `until`, inclusive, none after `end`.
+    fn between(
+        self,
+        start: NaiveDate,
+        end: Option<NaiveDate>,
+        from: NaiveDate,
+        until: NaiveDate,
+    ) -> impl Iterator<Item = NaiveDate> {
+        let last = end.map_or(until, |end| end.min(until));
+        (0..)
+            .map_while(move |n| self.nth(start, n))
+            .skip_while(move |date| *date < from)
+            .take_while(move |date| *date <= last)
+    }
+
+    fn first_on_or_after(
+        self,
+        start: NaiveDate,
+        end: Option<NaiveDate>,
+        from: NaiveDate,
+    ) -> Option<NaiveDate> {
+        self.between(start, end, from, NaiveDate::MAX).next()
+    }
+}
+
+// --- Schedules ---
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecurringSchedule {
+    pub schedule_id: String,
+    pub user_id: String,
+    pub amount: f64,
+    pub description: String,
+    pub category: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_id: Option<String>,
+    /// RRULE, e.g. `FREQ=WEEKLY;INTERVAL=2`
+    pub frequency: String,
+    pub start_date: String,
+    /// Last day an occurrence may fall on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date: Option<String>,
+    pub active: bool,
+    /// The next occurrence still to be created; unset once paused or finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_date: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateScheduleRequest {
+    pub amount: f64,
+    pub description: String,
+    pub category: String,
+    pub budget_id: Option<String>,
+    /// An RRULE or one of `daily`, `weekly`, `biweekly`, `monthly`, `yearly`
+    pub frequency: String,
+    pub start_date: String,
+    pub end_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateScheduleRequest {
+    pub amount: Option<f64>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    /// An empty string removes the budget
+    pub budget_id: Option<String>,
+    pub frequency: Option<String>,
+    /// An empty string removes the end date
+    pub end_date: Option<String>,
+    pub active: Option<bool>,
+}
+
+fn parse_date(name: &str, value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("{} must be a date like 2024-05-01", name))
+}
+
+impl RecurringSchedule {
+    fn recurrence(&self) -> Option<Recurrence> {
+        Recurrence::parse(&self.frequency).ok()
+    }
+
+    fn start(&self) -> Option<NaiveDate> {
+        parse_date("start_date", &self.start_date).ok()
+    }
+
+    fn end(&self) -> Option<NaiveDate> {
+        self.end_date
+            .as_deref()
+            .and_then(|d| parse_date("end_date", d).ok())
+    }
+
+    /// The first occurrence on or after `from`, or none when paused or finished.
+    fn next_on_or_after(&self, from: NaiveDate) -> Option<NaiveDate> {
+        if !self.active {
+            return None;
+        }
+        self.recurrence()?
+            .first_on_or_after(self.start()?, self.end(), from)
+    }
+
+    /// The stored next date when it has fallen behind `today`, so a recomputed next date keeps
+    /// the occurrences the job hasn't created yet; otherwise `today`.
+    fn backlog_start(&self, today: NaiveDate) -> NaiveDate {
+        self.next_date
+            .as_deref()
+            .and_then(|d| parse_date("next_date", d).ok())
+            .filter(|next| *next < today)
+            .unwrap_or(today)
+    }
+
+    /// Occurrences not yet created, through `until`, including any backlog before today.
+    pub(crate) fn pending_through(&self, until: NaiveDate) -> Vec<NaiveDate> {
+        let (Some(recurrence), Some(start), Some(next)) = (
+            self.recurrence(),
+            self.start(),
+            self.next_date
+                .as_deref()
+                .and_then(|d| parse_date("next_date", d).ok()),
+        ) else {
+            return Vec::new();
+        };
+        recurrence.between(start, self.end(), next, until).collect()
+    }
+
+    /// The pending occurrences one job run creates: those through `today`, oldest first and
+    /// at most `MAX_CATCH_UP` of them.
+    fn due_through(&self, today: NaiveDate) -> Vec<NaiveDate> {
+        let mut due = self.pending_through(today);
+        due.truncate(MAX_CATCH_UP);
+        due
+    }
+
+    /// Check the fields a client can set and put them into canonical form.
+    fn validate(&mut self) -> Result<(), String> {
+        if !self.amount.is_finite() || self.amount == 0.0 {
+            return Err("amount must be a non-zero number".to_string());
+        }
+        self.description = self.description.trim().to_string();
+        self.category = self.category.trim().to_string();
+        if self.category.is_empty() {
+            return Err("category is required".to_string());
+        }
+        self.budget_id = self
+            .budget_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string);
+        self.frequency = Recurrence::parse(&self.frequency)?.to_rrule();
+        let start = parse_date("start_date", &self.start_date)?;
+        self.start_date = start.to_string();
+        if let Some(ref end_date) = self.end_date {
+            let end = parse_date("end_date", end_date)?;
+            if end < start {
+                return Err("end_date must not be before start_date".to_string());
+            }
+            self.end_date = Some(end.to_string());
+        }
+        Ok(())
+    }
+}
+
+fn item_to_schedule(item: &HashMap<String, AttributeValue>) -> RecurringSchedule {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    RecurringSchedule {
+        schedule_id: s("schedule_id").unwrap_or_default(),
+        user_id: s("user_id").unwrap_or_default(),
+        amount: item
+            .get("amount")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(0.0),
+        description: s("description").unwrap_or_default(),
+        category: s("category").unwrap_or_default(),
+        budget_id: s("budget_id"),
+        frequency: s("frequency").unwrap_or_default(),
+        start_date: s("start_date").unwrap_or_default(),
+        end_date: s("end_date"),
+        active: item
+            .get("active")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(true),
+        next_date: s("next_date"),
+        created_at: s("created_at").unwrap_or_default(),
+        updated_at: s("updated_at").unwrap_or_default(),
+    }
+}
+
+fn schedule_to_item(schedule: &RecurringSchedule) -> HashMap<String, AttributeValue> {
+    let s = |value: &str| AttributeValue::S(value.to_string());
+    let mut item = HashMap::from([
+        ("user_id".to_string(), s(&schedule.user_id)),
+        ("schedule_id".to_string(), s(&schedule.schedule_id)),
+        (
+            "amount".to_string(),
+            AttributeValue::N(schedule.amount.to_string()),
+        ),
+        ("description".to_string(), s(&schedule.description)),
+        ("category".to_string(), s(&schedule.category)),
+        ("frequency".to_string(), s(&schedule.frequency)),
+        ("start_date".to_string(), s(&schedule.start_date)),
+        ("active".to_string(), AttributeValue::Bool(schedule.active)),
+        ("created_at".to_string(), s(&schedule.created_at)),
+        ("updated_at".to_string(), s(&schedule.updated_at)),
+    ]);
+    for (name, value) in [
+        ("budget_id", &schedule.budget_id),
+        ("end_date", &schedule.end_date),
+        ("next_date", &schedule.next_date),
+    ] {
+        if let Some(value) = value {
+            item.insert(name.to_string(), s(value));
+        }
+    }
+    item
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+pub async fn list_schedules(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-recurring")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    match result {
+        Ok(output) => {
+            let schedules: Vec<RecurringSchedule> = output
+                .items
+                .unwrap_or_default()
+                .iter()
+                .map(item_to_schedule)
+                .collect();
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(schedules).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// New schedules start from today: occurrences between a past start date and today are
+/// assumed to be recorded already.
+pub async fn create_schedule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(body): Json<CreateScheduleRequest>,
+) -> impl IntoResponse {
+    let now = Utc::now().to_rfc3339();
+    let mut schedule = RecurringSchedule {
+        schedule_id: Uuid::new_v4().to_string(),
+        user_id: claims.sub,
+        amount: body.amount,
+        description: body.description,
+        category: body.category,
+        budget_id: body.budget_id,
+        frequency: body.frequency,
+        start_date: body.start_date,
+        end_date: body.end_date,
+        active: true,
+        next_date: None,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    if let Err(e) = schedule.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response();
+    }
+    schedule.next_date = schedule.next_on_or_after(today()).map(|d| d.to_string());
+
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-recurring")
+        .set_item(Some(schedule_to_item(&schedule)))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::CREATED,
+            Json(serde_json::to_value(schedule).unwrap()),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to create schedule: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn get_schedule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(schedule_id): Path<String>,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-recurring")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("schedule_id", AttributeValue::S(schedule_id))
+        .send()
+        .await;
+
+    match result {
+        Ok(output) => match output.item {
+            Some(item) => (
+                StatusCode::OK,
+                Json(serde_json::to_value(item_to_schedule(&item)).unwrap()),
+            )
+                .into_response(),
+            None => (
+                StatusCode::NOT_FOUND,
+                Json(ApiError::new("Schedule not found")),
+            )
+                .into_response(),
+        },
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Update a schedule and work out its next occurrence again, from its stored next date when
+/// the job is behind so the backlog is still created, otherwise from today. Occurrences
+/// already created keep their ids, so one that falls on today isn't created twice.
+pub async fn update_schedule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(schedule_id): Path<String>,
+    Json(body): Json<UpdateScheduleRequest>,
+) -> impl IntoResponse {
+    let stored = match state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-recurring")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("schedule_id", AttributeValue::S(schedule_id))
+        .send()
+        .await
+    {
+        Ok(output) => match output.item {
+            Some(item) => item_to_schedule(&item),
+            None => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(ApiError::new("Schedule not found")),
+                )
+                    .into_response()
+            }
+        },
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response()
+        }
+    };
+
+    let mut schedule = stored.clone();
+    if let Some(amount) = body.amount {
+        schedule.amount = amount;
+    }
+    if let Some(description) = body.description {
+        schedule.description = description;
+    }
+    if let Some(category) = body.category {
+        schedule.category = category;
+    }
+    if let Some(budget_id) = body.budget_id {
+        schedule.budget_id = Some(budget_id);
+    }
+    if let Some(frequency) = body.frequency {
+        schedule.frequency = frequency;
+    }
+    if let Some(end_date) = body.end_date {
+        schedule.end_date = Some(end_date).filter(|d| !d.trim().is_empty());
+    }
+    if let Some(active) = body.active {
+        schedule.active = active;
+    }
+    if let Err(e) = schedule.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response();
+    }
+    let from = stored.backlog_start(today());
+    schedule.next_date = schedule.next_on_or_after(from).map(|d| d.to_string());
+    schedule.updated_at = Utc::now().to_rfc3339();
+
+    // Fail if the job advanced the schedule, or another request changed it, since the read
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-recurring")
+        .set_item(Some(schedule_to_item(&schedule)))
+        .condition_expression("updated_at = :seen")
+        .expression_attribute_values(":seen", AttributeValue::S(stored.updated_at))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(schedule).unwrap()),
+        )
+            .into_response(),
+        Err(e) if is_conditional_check_failure(&e) => (
+            StatusCode::CONFLICT,
+            Json(ApiError::new(
+                "The schedule was changed by another request; reload it and try again",
+            )),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Update failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Delete a schedule. Transactions it already created are kept.
+pub async fn delete_schedule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(schedule_id): Path<String>,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-recurring")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("schedule_id", AttributeValue::S(schedule_id))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Delete failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+// --- Upcoming Occurrences ---
+
+#[derive(Deserialize)]
+pub struct UpcomingQuery {
+    /// How far ahead to look, 30 days by default
+    pub days: Option<u64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Occurrence {
+    pub schedule_id: String,
+    pub date: String,
+    pub amount: f64,
+    pub description: String,
+    pub category: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_id: Option<String>,
+}
+
+/// Occurrences of active schedules from their next date through `until`, soonest first.
+/// A schedule the job is behind on lists its whole backlog, which has dates before today.
+fn upcoming_occurrences(schedules: &[RecurringSchedule], until: NaiveDate) -> Vec<Occurrence> {
+    let mut occurrences: Vec<Occurrence> = schedules
+        .iter()
+        .flat_map(|schedule| {
+            schedule
+                .pending_through(until)
+                .into_iter()
+                .map(|date| Occurrence {
+                    schedule_id: schedule.schedule_id.clone(),
+                    date: date.to_string(),
+                    amount: schedule.amount,
+                    description: schedule.description.clone(),
+                    category: schedule.category.clone(),
+                    budget_id: schedule.budget_id.clone(),
+                })
+        })
+        .collect();
+    occurrences.sort_by(|a, b| {
+        a.date
+            .cmp(&b.date)
+            .then_with(|| a.schedule_id.cmp(&b.schedule_id))
+    });
+    occurrences
+}
+
+pub async fn list_upcoming(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<UpcomingQuery>,
+) -> impl IntoResponse {
+    let days = params.days.unwrap_or(DEFAULT_UPCOMING_DAYS);
+    if !(1..=MAX_UPCOMING_DAYS).contains(&days) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(format!(
+                "days must be between 1 and {}",
+                MAX_UPCOMING_DAYS
+            ))),
+        )
+            .into_response();
+    }
+    let until = today() + Days::new(days);
+
+    let result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-recurring")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    match result {
+        Ok(output) => {
+            let schedules: Vec<RecurringSchedule> = output
+                .items
+                .unwrap_or_default()
+                .iter()
+                .map(item_to_schedule)
+                .collect();
+            let occurrences = upcoming_occurrences(&schedules, until);
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(occurrences).unwrap()),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+// --- Materializing ---
+
+/// Occurrences get a fixed id, so creating one twice is caught by the put's condition.
+fn occurrence_transaction_id(schedule_id: &str, date: NaiveDate) -> String {
+    format!("recurring-{}-{}", schedule_id, date)
+}
+
+/// Create the schedule's transactions due through `today` and move its next date past them.
+/// Returns how many transactions were created; ones that already existed are skipped.
+pub(crate) async fn materialize_schedule(
+    state: &AppState,
+    schedule: &RecurringSchedule,
+    today: NaiveDate,
+) -> Result<usize, aws_sdk_dynamodb::Error> {
+    let due = schedule.due_through(today);
+    let mut created = 0;
+    for &date in &due {
+        let transaction_id = occurrence_transaction_id(&schedule.schedule_id, date);
+        let now = Utc::now().to_rfc3339();
+        let mut put = state
+            .dynamo
+            .put_item()
+            .table_name("ovaflus-transactions")
+            .item("user_id", AttributeValue::S(schedule.user_id.clone()))
+            .item("transaction_id", AttributeValue::S(transaction_id.clone()))
+            .item("amount", AttributeValue::N(schedule.amount.to_string()))
+            .item(
+                "description",
+                AttributeValue::S(schedule.description.clone()),
+            )
+            .item("category", AttributeValue::S(schedule.category.clone()))
+            .item("date", AttributeValue::S(date.to_string()))
+            .item(
+                ATTR_DATE_KEY,
+                AttributeValue::S(date_key(&date.to_string(), &transaction_id)),
+            )
+            .item(
+                "recurring_schedule_id",
+                AttributeValue::S(schedule.schedule_id.clone()),
+            )
+            .item("created_at", AttributeValue::S(now.clone()))
+            .item("updated_at", AttributeValue::S(now))
+            .condition_expression("attribute_not_exists(transaction_id)");
+        if let Some(ref budget_id) = schedule.budget_id {
+            put = put
+                .item("budget_id", AttributeValue::S(budget_id.clone()))
+                .item(
+                    ATTR_BUDGET_KEY,
+                    AttributeValue::S(budget_key(&schedule.user_id, budget_id)),
+                );
+        }
+        if let Some(merchant) = normalize_merchant(&schedule.description) {
+            put = put
+                .item("merchant_id", AttributeValue::S(merchant.merchant_id))
+                .item("merchant", AttributeValue::S(merchant.display_name));
+        }
+
+        match put.send().await {
+            Ok(_) => {
+                created += 1;
+                let allocations =
+                    budget_allocations(schedule.budget_id.as_deref(), schedule.amount, &[], false);
+                apply_spent_changes(state, &schedule.user_id, &BTreeMap::new(), &allocations)
+                    .await?;
+            }
+            // Created by an earlier run that stopped before advancing the schedule
+            Err(e) if is_conditional_check_failure(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let Some(&last) = due.last() else {
+        return Ok(created);
+    };
+    let next = last
+        .succ_opt()
+        .and_then(|day| schedule.next_on_or_after(day));
+    let update = state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-recurring")
+        .key("user_id", AttributeValue::S(schedule.user_id.clone()))
+        .key(
+            "schedule_id",
+            AttributeValue::S(schedule.schedule_id.clone()),
+        )
+        .condition_expression("next_date = :seen")
+        .expression_attribute_values(
+            ":seen",
+            AttributeValue::S(schedule.next_date.clone().unwrap_or_default()),
+        )
+        .expression_attribute_values(":now", AttributeValue::S(Utc::now().to_rfc3339()));
+    let update = match next {
+        Some(next) => update
+            .update_expression("SET next_date = :next, updated_at = :now")
+            .expression_attribute_values(":next", AttributeValue::S(next.to_string())),
+        None => update.update_expression("SET updated_at = :now REMOVE next_date"),
+    };
+    match update.send().await {
+        // The user edited the schedule meanwhile and its next date was worked out again
+        Err(e) if is_conditional_check_failure(&e) => Ok(created),
+        other => other.map(|_| created).map_err(Into::into),
+    }
+}
+
+/// Every schedule with an occurrence due by `today`.
+pub(crate) async fn due_schedules(
+    state: &AppState,
+    today: NaiveDate,
+) -> Result<Vec<RecurringSchedule>, aws_sdk_dynamodb::Error> {
+    let mut schedules = Vec::new();
+    let mut start_key = None;
+    loop {
+        let output = state
+            .dynamo
+            .scan()
+            .table_name("ovaflus-recurring")
+            .filter_expression("next_date <= :today")
+            .expression_attribute_values(":today", AttributeValue::S(today.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        schedules.extend(
+            output
+                .items
+                .unwrap_or_default()
+                .iter()
+                .map(item_to_schedule),
+        );
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(schedules);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn schedule(frequency: &str, start: &str, end: Option<&str>) -> RecurringSchedule {
+        RecurringSchedule {
+            schedule_id: "s1".to_string(),
+            user_id: "u1".to_string(),
+            amount: 15.99,
+            description: "Netflix".to_string(),
+            category: "subscriptions".to_string(),
+            budget_id: None,
+            frequency: frequency.to_string(),
+            start_date: start.to_string(),
+            end_date: end.map(str::to_string),
+            active: true,
+            next_date: Some(start.to_string()),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn rules_and_app_frequencies_parse_to_one_form() {
+        let rule = |value: &str| Recurrence::parse(value).map(Recurrence::to_rrule);
+        assert_eq!(rule("Bi-weekly").unwrap(), "FREQ=WEEKLY;INTERVAL=2");
+        assert_eq!(rule("monthly").unwrap(), "FREQ=MONTHLY;INTERVAL=1");
+        assert_eq!(
+            rule("rrule:FREQ=monthly;INTERVAL=3").unwrap(),
+            "FREQ=MONTHLY;INTERVAL=3"
+        );
+        assert!(rule("FREQ=HOURLY").is_err());
+        assert!(rule("FREQ=WEEKLY;BYDAY=MO").is_err());
+        assert!(rule("INTERVAL=2").is_err());
+        assert!(rule("FREQ=DAILY;INTERVAL=0").is_err());
+    }
+
+    #[test]
+    fn occurrences_keep_the_start_day_and_stop_at_the_end_date() {
+        let monthly = Recurrence::parse("monthly").unwrap();
+        let dates: Vec<String> = monthly
+            .between(
+                date("2024-01-31"),
+                None,
+                date("2024-01-01"),
+                date("2024-04-30"),
+            )
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            dates,
+            ["2024-01-31", "2024-02-29", "2024-03-31", "2024-04-30"]
+        );
+
+        let biweekly = schedule("biweekly", "2024-05-03", Some("2024-05-31"));
+        assert_eq!(
+            biweekly.next_on_or_after(date("2024-05-04")),
+            Some(date("2024-05-17"))
+        );
+        assert_eq!(
+            biweekly.pending_through(date("2024-12-31")),
+            [date("2024-05-03"), date("2024-05-17"), date("2024-05-31")]
+        );
+        assert_eq!(biweekly.next_on_or_after(date("2024-06-01")), None);
+
+        let paused = RecurringSchedule {
+            active: false,
+            ..biweekly
+        };
+        assert_eq!(paused.next_on_or_after(date("2024-05-04")), None);
+
+        let daily = schedule("daily", "2020-01-01", None);
+        assert_eq!(daily.due_through(date("2024-01-01")).len(), MAX_CATCH_UP);
+        assert_eq!(daily.pending_through(date("2020-03-01")).len(), 61);
+    }
+
+    #[test]
+    fn backlog_start_keeps_occurrences_the_job_has_not_created() {
+        let mut rent = schedule("monthly", "2024-01-01", None);
+        rent.next_date = Some("2024-03-01".to_string());
+        assert_eq!(rent.backlog_start(date("2024-05-15")), date("2024-03-01"));
+        assert_eq!(
+            rent.next_on_or_after(rent.backlog_start(date("2024-05-15"))),
+            Some(date("2024-03-01"))
+        );
+        assert_eq!(rent.backlog_start(date("2024-02-15")), date("2024-02-15"));
+
+        rent.next_date = None;
+        assert_eq!(rent.backlog_start(date("2024-05-15")), date("2024-05-15"));
+    }
+
+    #[test]
+    fn upcoming_occurrences_merge_schedules_by_date() {
+        let mut rent = schedule("monthly", "2024-05-01", None);
+        rent.schedule_id = "rent".to_string();
+        rent.next_date = Some("2024-06-01".to_string());
+        let gym = schedule("weekly", "2024-05-20", None);
+
+        let upcoming = upcoming_occurrences(&[rent, gym], date("2024-06-03"));
+        let dates: Vec<(&str, &str)> = upcoming
+            .iter()
+            .map(|o| (o.schedule_id.as_str(), o.date.as_str()))
+            .collect();
+        assert_eq!(
+            dates,
+            [
+                ("s1", "2024-05-20"),
+                ("s1", "2024-05-27"),
+                ("rent", "2024-06-01"),
+                ("s1", "2024-06-03"),
+            ]
+        );
+        assert_eq!(
+            occurrence_transaction_id("rent", date("2024-06-01")),
+            "recurring-rent-2024-06-01"
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::db::dynamo::{query_by_pk, TABLE_PORTFOLIO, TABLE_PORTFOLIO_TRADES};
+use crate::handlers::portfolio::{item_to_holding, item_to_trade, Holding, Trade};
+use crate::handlers::stocks::fetch_daily_closes;
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const DEFAULT_BENCHMARK: &str = "SPY";
+const DEFAULT_RANGE: &str = "1y";
+/// Days of candles requested per Finnhub call; longer ranges are fetched in windows.
+const CANDLE_WINDOW_DAYS: i64 = 365;
+
+#[derive(Deserialize)]
+pub struct ReturnsQuery {
+    /// One of 1m, 3m, 6m, ytd, 1y, 3y, 5y, all
+    pub range: Option<String>,
+    pub benchmark: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BenchmarkReturn {
+    pub symbol: String,
+    pub total_return: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct PortfolioReturns {
+    pub range: String,
+    pub start_date: String,
+    pub end_date: String,
+    pub start_value: f64,
+    pub end_value: f64,
+    /// Net cash put into the portfolio during the window (buys minus sells and dividends).
+    pub net_contributions: f64,
+    /// Cumulative time-weighted return over the window.
+    pub time_weighted_return: Option<f64>,
+    /// Annualized money-weighted return (XIRR) over the window.
+    pub money_weighted_return: Option<f64>,
+    pub benchmark: BenchmarkReturn,
+    /// Symbols whose prices could not be fetched; they are valued at their last traded price.
+    pub missing_prices: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct DayValue {
+    date: NaiveDate,
+    value: f64,
+    /// Net external cash flow into the portfolio on this day.
+    inflow: f64,
+}
+
+pub(crate) fn range_start(range: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let months = |m: u32| today.checked_sub_months(chrono::Months::new(m));
+    match range {
+        "1m" => months(1),
+        "3m" => months(3),
+        "6m" => months(6),
+        "ytd" => NaiveDate::from_ymd_opt(today.year(), 1, 1),
+        "1y" => months(12),
+        "3y" => months(36),
+        "5y" => months(60),
+        _ => None,
+    }
+}
+
+/// Cash flow into the portfolio from a trade: buys add money, sells and dividends take it out.
+fn trade_inflow(trade: &Trade) -> f64 {
+    match trade.trade_type.as_str() {
+        "buy" => trade.amount,
+        "sell" | "dividend" => -trade.amount,
+        _ => 0.0,
+    }
+}
+
+fn trade_date(trade: &Trade) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(trade.date.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// Stand-in history for holdings without recorded trades, such as manually entered ones:
+/// one buy per holding at its cost basis.
+fn trades_from_holdings(holdings: &[Holding], trades: &[Trade]) -> Vec<Trade> {
+    let traded: BTreeSet<String> = trades.iter().map(|t| t.symbol.to_uppercase()).collect();
+    holdings
+        .iter()
+        .filter(|h| !traded.contains(&h.symbol.to_uppercase()))
+        .map(|h| Trade {
+            trade_id: h.holding_id.clone(),
+            user_id: h.user_id.clone(),
+            symbol: h.symbol.to_uppercase(),
+            trade_type: "buy".to_string(),
+            quantity: h.shares,
+            price: h.avg_cost,
+            amount: h.shares * h.avg_cost,
+            date: h.created_at.get(..10).unwrap_or_default().to_string(),
+            source: h.source.clone(),
+            created_at: h.created_at.clone(),
+        })
+        .collect()
+}
+
+/// Value the portfolio on each day of `days`, replaying `trades` (sorted by date).
+///
+/// Prices carry forward from the last close on or before each day, falling back to the
+/// last traded price for symbols without candles.
+fn value_series(
+    days: &[NaiveDate],
+    trades: &[(NaiveDate, &Trade)],
+    closes: &HashMap<String, BTreeMap<NaiveDate, f64>>,
+) -> Vec<DayValue> {
+    let mut quantities: HashMap<&str, f64> = HashMap::new();
+    let mut trade_prices: HashMap<&str, f64> = HashMap::new();
+    let mut next = 0;
+    let mut series = Vec::with_capacity(days.len());
+
+    for day in days {
+        let mut inflow = 0.0;
+        while next < trades.len() && trades[next].0 <= *day {
+            let trade = trades[next].1;
+            let qty = quantities.entry(trade.symbol.as_str()).or_insert(0.0);
+            match trade.trade_type.as_str() {
+                "buy" => *qty += trade.quantity,
+                "sell" => *qty -= trade.quantity,
+                _ => {}
+            }
+            if trade.price > 0.0 {
+                trade_prices.insert(trade.symbol.as_str(), trade.price);
+            }
+            inflow += trade_inflow(trade);
+            next += 1;
+        }
+
+        let value = quantities
+            .iter()
+            .map(|(symbol, qty)| {
+                let price = closes
+                    .get(*symbol)
+                    .and_then(|c| c.range(..=*day).next_back())
+                    .map(|(_, close)| *close)
+                    .or_else(|| trade_prices.get(symbol).copied())
+                    .unwrap_or(0.0);
+                qty * price
+            })
+            .sum();
+
+        series.push(DayValue {
+            date: *day,
+            value,
+            inflow,
+        });
+    }
+    series
+}
+
+/// Chain daily returns with each day's cash flow removed, so deposits don't count as gains.
+fn time_weighted_return(series: &[DayValue]) -> Option<f64> {
+    let mut growth = 1.0;
+    let mut periods = 0;
+    for pair in series.windows(2) {
+        let (prev, cur) = (&pair[0], &pair[1]);
+        if prev.value <= 0.0 {
+            continue;
+        }
+        growth *= (cur.value - cur.inflow) / prev.value;
+        periods += 1;
+    }
+    (periods > 0).then_some(growth - 1.0)
+}
+
+/// Annualized internal rate of return for dated cash flows (investor's perspective).
+fn xirr(flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let first = flows.first()?.0;
+    let has_in = flows.iter().any(|(_, cf)| *cf < 0.0);
+    let has_out = flows.iter().any(|(_, cf)| *cf > 0.0);
+    if !has_in || !has_out {
+        return None;
+    }
+
+    let npv = |rate: f64| -> f64 {
+        flows
+            .iter()
+            .map(|(date, cf)| {
+                let years = (*date - first).num_days() as f64 / 365.0;
+                cf / (1.0 + rate).powf(years)
+            })
+            .sum()
+    };
+
+    // NPV falls as the rate rises for a deposit-then-withdraw profile; bisect for the root.
+    let (mut lo, mut hi) = (-0.9999, 100.0);
+    let (f_lo, f_hi) = (npv(lo), npv(hi));
+    if f_lo.is_nan() || f_hi.is_nan() || f_lo.signum() == f_hi.signum() {
+        return None;
+    }
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        if npv(mid).signum() == f_lo.signum() {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Some((lo + hi) / 2.0)
+}
+
+/// XIRR cash flows: the opening value goes in, trades follow, the closing value comes out.
+fn money_weighted_flows(series: &[DayValue]) -> Vec<(NaiveDate, f64)> {
+    let (Some(first), Some(last)) = (series.first(), series.last()) else {
+        return Vec::new();
+    };
+    let mut flows = vec![(first.date, -first.value)];
+    flows.extend(
+        series
+            .iter()
+            .skip(1)
+            .filter(|d| d.inflow != 0.0)
+            .map(|d| (d.date, -d.inflow)),
+    );
+    flows.push((last.date, last.value));
+    flows
+}
+
+/// Daily closes between two unix timestamps, requested in `CANDLE_WINDOW_DAYS` windows.
+async fn fetch_closes_windowed(
+    client: &reqwest::Client,
+    api_key: &str,
+    symbol: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<(i64, f64)>, String> {
+    let window = CANDLE_WINDOW_DAYS * 86_400;
+    let mut candles = Vec::new();
+    let mut start = from;
+    while start < to {
+        let end = (start + window).min(to);
+        candles.extend(fetch_daily_closes(client, api_key, symbol, start, end).await?);
+        start = end + 1;
+    }
+    Ok(candles)
+}
+
+fn candles_by_date(candles: Vec<(i64, f64)>) -> BTreeMap<NaiveDate, f64> {
+    candles
+        .into_iter()
+        .filter_map(|(t, close)| {
+            let date = DateTime::<Utc>::from_timestamp(t, 0)?.date_naive();
+            Some((date, close))
+        })
+        .collect()
+}
+
+pub async fn get_portfolio_returns(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<ReturnsQuery>,
+) -> impl IntoResponse {
+    let range = params.range.unwrap_or_else(|| DEFAULT_RANGE.to_string());
+    if !matches!(
+        range.as_str(),
+        "1m" | "3m" | "6m" | "ytd" | "1y" | "3y" | "5y" | "all"
+    ) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(
+                "range must be one of 1m, 3m, 6m, ytd, 1y, 3y, 5y, all",
+            )),
+        )
+            .into_response();
+    }
+    let benchmark = params
+        .benchmark
+        .unwrap_or_else(|| DEFAULT_BENCHMARK.to_string())
+        .to_uppercase();
+
+    let trade_items = match query_by_pk(
+        &state.dynamo,
+        TABLE_PORTFOLIO_TRADES,
+        "user_id",
+        &claims.sub,
+    )
+    .await
+    {
+        Ok(items) => items,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+    let holdings: Vec<Holding> =
+        match query_by_pk(&state.dynamo, TABLE_PORTFOLIO, "user_id", &claims.sub).await {
+            Ok(items) => items.iter().map(item_to_holding).collect(),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiError::new(format!("Database error: {}", e))),
+                )
+                    .into_response();
+            }
+        };
+
+    let mut trades: Vec<Trade> = trade_items.iter().map(item_to_trade).collect();
+    trades.extend(trades_from_holdings(&holdings, &trades));
+
+    let mut dated: Vec<(NaiveDate, &Trade)> = trades
+        .iter()
+        .filter_map(|t| trade_date(t).map(|d| (d, t)))
+        .collect();
+    dated.sort_by_key(|(d, _)| *d);
+
+    let today = Utc::now().date_naive();
+    let start = match range_start(&range, today) {
+        Some(d) => d,
+        None => dated.first().map(|(d, _)| *d).unwrap_or(today),
+    };
+
+    // Fetch a little before the window so the first day has a price to carry forward
+    let client = reqwest::Client::new();
+    let from = (start - chrono::Duration::days(7))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp();
+    let to = Utc::now().timestamp();
+    let symbols: BTreeSet<String> = dated
+        .iter()
+        .map(|(_, t)| t.symbol.to_uppercase())
+        .chain(std::iter::once(benchmark.clone()))
+        .collect();
+
+    let mut tasks = JoinSet::new();
+    for symbol in symbols {
+        let client = client.clone();
+        let api_key = state.finnhub_api_key.clone();
+        tasks.spawn(async move {
+            let result = fetch_closes_windowed(&client, &api_key, &symbol, from, to).await;
+            (symbol, result)
+        });
+    }
+
+    let mut closes: HashMap<String, BTreeMap<NaiveDate, f64>> = HashMap::new();
+    let mut missing_prices = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((symbol, Ok(candles))) => {
+                closes.insert(symbol, candles_by_date(candles));
+            }
+            Ok((symbol, Err(e))) => {
+                tracing::warn!(symbol, "Candle fetch failed: {e}");
+                missing_prices.push(symbol);
+            }
+            Err(e) => tracing::warn!("Candle fetch task failed: {e}"),
+        }
+    }
+    missing_prices.sort();
+
+    let mut days: BTreeSet<NaiveDate> = closes
+        .values()
+        .flat_map(|c| c.keys().copied())
+        .filter(|d| *d >= start && *d <= today)
+        .collect();
+    days.insert(start);
+    let days: Vec<NaiveDate> = days.into_iter().collect();
+
+    let series = value_series(&days, &dated, &closes);
+    let flows = money_weighted_flows(&series);
+
+    let bench_closes = closes.get(&benchmark);
+    let bench_start = bench_closes.and_then(|c| c.range(..=start).next_back().or(c.iter().next()));
+    let bench_end = bench_closes.and_then(|c| c.iter().next_back());
+    let bench_return = match (bench_start, bench_end) {
+        (Some((_, s)), Some((_, e))) if *s > 0.0 => Some(e / s - 1.0),
+        _ => None,
+    };
+
+    let returns = PortfolioReturns {
+        range,
+        start_date: start.format("%Y-%m-%d").to_string(),
+        end_date: days.last().unwrap_or(&today).format("%Y-%m-%d").to_string(),
+        start_value: series.first().map(|d| d.value).unwrap_or(0.0),
+        end_value: series.last().map(|d| d.value).unwrap_or(0.0),
+        net_contributions: series.iter().skip(1).map(|d| d.inflow).sum(),
+        time_weighted_return: time_weighted_return(&series),
+        money_weighted_return: xirr(&flows),
+        benchmark: BenchmarkReturn {
+            symbol: benchmark,
+            total_return: bench_return,
+        },
+        missing_prices,
+    };
+
+    (StatusCode::OK, Json(serde_json::to_value(returns).unwrap())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn trade(symbol: &str, trade_type: &str, quantity: f64, price: f64, day: &str) -> Trade {
+        Trade {
+            trade_id: format!("{symbol}-{day}"),
+            user_id: "user-1".to_string(),
+            symbol: symbol.to_string(),
+            trade_type: trade_type.to_string(),
+            quantity,
+            price,
+            amount: quantity * price,
+            date: day.to_string(),
+            source: "manual".to_string(),
+            created_at: String::new(),
+        }
+    }
+
+    fn holding(symbol: &str, shares: f64, avg_cost: f64) -> Holding {
+        Holding {
+            holding_id: format!("h-{symbol}"),
+            user_id: "user-1".to_string(),
+            symbol: symbol.to_string(),
+            shares,
+            avg_cost,
+            asset_class: "equity".to_string(),
+            manual_price: None,
+            source: "manual".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn holdings_without_trades_become_opening_buys() {
+        let trades = vec![trade("AAPL", "buy", 10.0, 100.0, "2026-01-01")];
+        let holdings = vec![holding("aapl", 10.0, 100.0), holding("VTI", 4.0, 250.0)];
+
+        let synthesized = trades_from_holdings(&holdings, &trades);
+        assert_eq!(synthesized.len(), 1);
+        assert_eq!(synthesized[0].symbol, "VTI");
+        assert_eq!(synthesized[0].amount, 1000.0);
+        assert_eq!(synthesized[0].date, "2026-01-01");
+    }
+
+    #[test]
+    fn deposits_do_not_inflate_time_weighted_return() {
+        // Flat price, but the user doubles their position halfway through
+        let buy1 = trade("AAPL", "buy", 10.0, 100.0, "2026-01-01");
+        let buy2 = trade("AAPL", "buy", 10.0, 100.0, "2026-01-02");
+        let trades = vec![(date("2026-01-01"), &buy1), (date("2026-01-02"), &buy2)];
+        let closes = HashMap::from([(
+            "AAPL".to_string(),
+            BTreeMap::from([(date("2026-01-01"), 100.0), (date("2026-01-02"), 100.0)]),
+        )]);
+
+        let series = value_series(&[date("2026-01-01"), date("2026-01-02")], &trades, &closes);
+        assert_eq!(series[1].value, 2000.0);
+        assert_eq!(series[1].inflow, 1000.0);
+        assert!(time_weighted_return(&series).unwrap().abs() < 1e-9);
+    }
+
+    #[test]
+    fn time_weighted_return_chains_daily_growth() {
+        let series = vec![
+            DayValue {
+                date: date("2026-01-01"),
+                value: 100.0,
+                inflow: 100.0,
+            },
+            DayValue {
+                date: date("2026-01-02"),
+                value: 110.0,
+                inflow: 0.0,
+            },
+            DayValue {
+                date: date("2026-01-03"),
+                value: 121.0,
+                inflow: 0.0,
+            },
+        ];
+        assert!((time_weighted_return(&series).unwrap() - 0.21).abs() < 1e-9);
+    }
+
+    #[test]
+    fn xirr_of_one_year_ten_percent_gain() {
+        let flows = vec![(date("2025-01-01"), -1000.0), (date("2026-01-01"), 1100.0)];
+        assert!((xirr(&flows).unwrap() - 0.10).abs() < 1e-6);
+    }
+
+    #[test]
+    fn xirr_needs_flows_in_both_directions() {
+        assert!(xirr(&[(date("2025-01-01"), -1000.0)]).is_none());
+        assert!(xirr(&[]).is_none());
+    }
+
+    #[test]
+    fn sells_and_dividends_are_outflows() {
+        let sell = trade("AAPL", "sell", 1.0, 50.0, "2026-01-01");
+        let dividend = Trade {
+            amount: 5.0,
+            ..trade("AAPL", "dividend", 0.0, 0.0, "2026-01-01")
+        };
+        assert_eq!(trade_inflow(&sell), -50.0);
+        assert_eq!(trade_inflow(&dividend), -5.0);
+    }
+
+    #[test]
+    fn range_start_handles_ytd_and_all() {
+        let today = date("2026-10-19");
+        assert_eq!(range_start("ytd", today), Some(date("2026-01-01")));
+        assert_eq!(range_start("3m", today), Some(date("2026-07-19")));
+        assert_eq!(range_start("all", today), None);
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::handlers::portfolio::{item_to_holding, Holding};
+use crate::handlers::stocks::{fetch_daily_closes, fetch_profile};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+const DEFAULT_BENCHMARK: &str = "SPY";
+const DEFAULT_LOOKBACK_DAYS: i64 = 365;
+const MAX_LOOKBACK_DAYS: i64 = 5 * 365;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Deserialize)]
+pub struct RiskQuery {
+    pub benchmark: Option<String>,
+    pub days: Option<i64>,
+    /// Annual risk-free rate used for the Sharpe ratio, e.g. `0.04` for 4%.
+    pub risk_free_rate: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct SectorWeight {
+    pub sector: String,
+    pub weight: f64,
+    pub symbols: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Concentration {
+    pub largest_position_symbol: Option<String>,
+    pub largest_position_weight: f64,
+    /// Herfindahl-Hirschman index of position weights (1.0 = a single position).
+    pub position_hhi: f64,
+    /// Herfindahl-Hirschman index of sector weights.
+    pub sector_hhi: f64,
+    pub sectors: Vec<SectorWeight>,
+}
+
+#[derive(Serialize)]
+pub struct PortfolioRisk {
+    pub benchmark: String,
+    pub days: i64,
+    pub observations: usize,
+    pub market_value: f64,
+    pub annualized_return: Option<f64>,
+    pub annualized_volatility: Option<f64>,
+    pub beta: Option<f64>,
+    pub sharpe_ratio: Option<f64>,
+    pub max_drawdown: Option<f64>,
+    pub concentration: Concentration,
+    /// Held symbols with no usable price history, left out of the return series.
+    pub excluded: Vec<String>,
+}
+
+/// Daily simple returns keyed by day number (unix timestamp / 86400).
+fn daily_returns(closes: &[(i64, f64)]) -> BTreeMap<i64, f64> {
+    closes
+        .windows(2)
+        .filter(|w| w[0].1 > 0.0)
+        .map(|w| (w[1].0 / SECONDS_PER_DAY, w[1].1 / w[0].1 - 1.0))
+        .collect()
+}
+
+/// Weighted daily portfolio returns. On days where only some positions traded, the
+/// return is averaged over those positions' weights, so a recent listing or a gap in
+/// one symbol's history doesn't drop the day for the whole portfolio.
+fn portfolio_returns(positions: &[(f64, BTreeMap<i64, f64>)]) -> BTreeMap<i64, f64> {
+    let mut days: BTreeMap<i64, (f64, f64)> = BTreeMap::new();
+    for (weight, returns) in positions {
+        for (day, r) in returns {
+            let entry = days.entry(*day).or_default();
+            entry.0 += weight * r;
+            entry.1 += weight;
+        }
+    }
+
+    days.into_iter()
+        .filter(|(_, (_, covered))| *covered > 0.0)
+        .map(|(day, (weighted, covered))| (day, weighted / covered))
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation; `None` with fewer than two observations.
+fn std_dev(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let m = mean(values);
+    let var = values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    Some(var.sqrt())
+}
+
+/// Beta of `returns` against `benchmark`, over the days both series have.
+fn beta(returns: &BTreeMap<i64, f64>, benchmark: &BTreeMap<i64, f64>) -> Option<f64> {
+    let (p, b): (Vec<f64>, Vec<f64>) = returns
+        .iter()
+        .filter_map(|(day, r)| benchmark.get(day).map(|br| (*r, *br)))
+        .unzip();
+    if p.len() < 2 {
+        return None;
+    }
+    let (mp, mb) = (mean(&p), mean(&b));
+    let cov = p
+        .iter()
+        .zip(&b)
+        .map(|(x, y)| (x - mp) * (y - mb))
+        .sum::<f64>();
+    let var = b.iter().map(|y| (y - mb).powi(2)).sum::<f64>();
+    if var == 0.0 {
+        None
+    } else {
+        Some(cov / var)
+    }
+}
+
+/// Largest peak-to-trough decline of the compounded return series, as a positive fraction.
+fn max_drawdown(returns: &[f64]) -> f64 {
+    let mut value = 1.0;
+    let mut peak = 1.0;
+    let mut worst: f64 = 0.0;
+    for r in returns {
+        value *= 1.0 + r;
+        peak = f64::max(peak, value);
+        worst = worst.max((peak - value) / peak);
+    }
+    worst
+}
+
+fn hhi<'a>(weights: impl Iterator<Item = &'a f64>) -> f64 {
+    weights.map(|w| w * w).sum()
+}
+
+/// Position and sector concentration from market values per symbol.
+fn concentration(
+    values: &HashMap<String, f64>,
+    sectors: &HashMap<String, String>,
+) -> Concentration {
+    let total: f64 = values.values().sum();
+    let weight_of = |v: f64| if total > 0.0 { v / total } else { 0.0 };
+
+    let weights: HashMap<&String, f64> = values.iter().map(|(s, v)| (s, weight_of(*v))).collect();
+    let largest = weights
+        .iter()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut by_sector: BTreeMap<String, (f64, Vec<String>)> = BTreeMap::new();
+    for (symbol, weight) in &weights {
+        let sector = sectors
+            .get(*symbol)
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .unwrap_or_else(|| "Unknown".to_string());
+        let entry = by_sector.entry(sector).or_default();
+        entry.0 += weight;
+        entry.1.push((*symbol).clone());
+    }
+
+    let mut sector_weights: Vec<SectorWeight> = by_sector
+        .into_iter()
+        .map(|(sector, (weight, mut symbols))| {
+            symbols.sort();
+            SectorWeight {
+                sector,
+                weight,
+                symbols,
+            }
+        })
+        .collect();
+    sector_weights.sort_by(|a, b| {
+        b.weight
+            .partial_cmp(&a.weight)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Concentration {
+        largest_position_symbol: largest.map(|(s, _)| (*s).clone()),
+        largest_position_weight: largest.map(|(_, w)| *w).unwrap_or(0.0),
+        position_hhi: hhi(weights.values()),
+        sector_hhi: hhi(sector_weights.iter().map(|s| &s.weight)),
+        sectors: sector_weights,
+    }
+}
+
+pub async fn get_portfolio_risk(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<RiskQuery>,
+) -> impl IntoResponse {
+    let benchmark = params
+        .benchmark
+        .unwrap_or_else(|| DEFAULT_BENCHMARK.to_string())
+        .to_uppercase();
+    let days = params
+        .days
+        .unwrap_or(DEFAULT_LOOKBACK_DAYS)
+        .clamp(30, MAX_LOOKBACK_DAYS);
+    let risk_free_rate = params.risk_free_rate.unwrap_or(0.0);
+
+    let result = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-portfolio")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+        .send()
+        .await;
+
+    // Candle-based metrics only cover exchange-traded holdings
+    let holdings: Vec<Holding> = match result {
+        Ok(output) => output
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(item_to_holding)
+            .filter(|h| matches!(h.asset_class.as_str(), "equity" | "etf"))
+            .collect(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response();
+        }
+    };
+
+    let mut shares: HashMap<String, f64> = HashMap::new();
+    for h in &holdings {
+        *shares.entry(h.symbol.to_uppercase()).or_insert(0.0) += h.shares;
+    }
+
+    // Fetch candles and profiles for every held symbol, plus benchmark candles
+    let client = reqwest::Client::new();
+    let to = Utc::now().timestamp();
+    let from = to - days * SECONDS_PER_DAY;
+    let mut tasks = JoinSet::new();
+    for symbol in shares
+        .keys()
+        .cloned()
+        .chain(std::iter::once(benchmark.clone()))
+    {
+        let client = client.clone();
+        let api_key = state.finnhub_api_key.clone();
+        tasks.spawn(async move {
+            let (closes, profile) = tokio::join!(
+                fetch_daily_closes(&client, &api_key, &symbol, from, to),
+                fetch_profile(&client, &api_key, &symbol)
+            );
+            (symbol, closes, profile)
+        });
+    }
+
+    let mut closes: HashMap<String, Vec<(i64, f64)>> = HashMap::new();
+    let mut excluded: Vec<String> = Vec::new();
+    let mut sectors: HashMap<String, String> = HashMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (symbol, candles, profile) = match joined {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("Risk data task failed: {e}");
+                continue;
+            }
+        };
+        match candles {
+            Ok(c) => {
+                closes.insert(symbol.clone(), c);
+            }
+            // Without the benchmark there is nothing to measure against
+            Err(e) if symbol == benchmark => {
+                return (StatusCode::BAD_GATEWAY, Json(ApiError::new(e))).into_response();
+            }
+            Err(e) => {
+                tracing::warn!(symbol, "Excluding position from risk metrics: {e}");
+            }
+        }
+        if let Some(industry) = profile.ok().and_then(|p| {
+            p.get("finnhubIndustry")
+                .and_then(|v| v.as_str())
+                .map(String::from)
+        }) {
+            sectors.insert(symbol, industry);
+        }
+    }
+
+    // Market value per symbol at the latest close, falling back to cost basis
+    let mut values: HashMap<String, f64> = HashMap::new();
+    for h in &holdings {
+        let symbol = h.symbol.to_uppercase();
+        let price = closes
+            .get(&symbol)
+            .and_then(|c| c.last())
+            .map(|(_, close)| *close)
+            .unwrap_or(h.avg_cost);
+        *values.entry(symbol).or_insert(0.0) += h.shares * price;
+    }
+    let market_value: f64 = values.values().sum();
+
+    let mut positions: Vec<(f64, BTreeMap<i64, f64>)> = Vec::new();
+    for (symbol, v) in values
+        .iter()
+        .filter(|(_, v)| market_value > 0.0 && **v > 0.0)
+    {
+        let returns = closes
+            .get(symbol)
+            .map(|c| daily_returns(c))
+            .unwrap_or_default();
+        if returns.is_empty() {
+            excluded.push(symbol.clone());
+        } else {
+            positions.push((v / market_value, returns));
+        }
+    }
+    excluded.sort();
+
+    let bench_returns = closes
+        .get(&benchmark)
+        .map(|c| daily_returns(c))
+        .unwrap_or_default();
+    if bench_returns.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(format!(
+                "No price history for benchmark {}",
+                benchmark
+            ))),
+        )
+            .into_response();
+    }
+    let port_returns = portfolio_returns(&positions);
+    let series: Vec<f64> = port_returns.values().copied().collect();
+
+    let annualized_return = (!series.is_empty()).then(|| mean(&series) * TRADING_DAYS_PER_YEAR);
+    let annualized_volatility = std_dev(&series).map(|sd| sd * TRADING_DAYS_PER_YEAR.sqrt());
+    let sharpe_ratio = match (annualized_return, annualized_volatility) {
+        (Some(ret), Some(vol)) if vol > 0.0 => Some((ret - risk_free_rate) / vol),
+        _ => None,
+    };
+
+    let risk = PortfolioRisk {
+        benchmark,
+        days,
+        observations: series.len(),
+        market_value,
+        annualized_return,
+        annualized_volatility,
+        beta: beta(&port_returns, &bench_returns),
+        sharpe_ratio,
+        max_drawdown: (!series.is_empty()).then(|| max_drawdown(&series)),
+        concentration: concentration(&values, &sectors),
+        excluded,
+    };
+
+    (StatusCode::OK, Json(serde_json::to_value(risk).unwrap())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = SECONDS_PER_DAY;
+
+    #[test]
+    fn daily_returns_are_keyed_by_day() {
+        let closes = vec![(0, 100.0), (DAY, 110.0), (2 * DAY, 99.0)];
+        let returns = daily_returns(&closes);
+        assert_eq!(returns.len(), 2);
+        assert!((returns[&1] - 0.10).abs() < 1e-9);
+        assert!((returns[&2] + 0.10).abs() < 1e-9);
+    }
+
+    #[test]
+    fn portfolio_returns_weight_the_positions_trading_each_day() {
+        let a = BTreeMap::from([(1, 0.10), (2, 0.20)]);
+        let b = BTreeMap::from([(2, 0.00)]);
+        let combined = portfolio_returns(&[(0.75, a), (0.25, b)]);
+        assert_eq!(combined.len(), 2);
+        // Only `a` traded on day 1
+        assert!((combined[&1] - 0.10).abs() < 1e-9);
+        assert!((combined[&2] - 0.15).abs() < 1e-9);
+    }
+
+    #[test]
+    fn portfolio_returns_ignore_positions_without_history() {
+        let a = BTreeMap::from([(1, 0.10)]);
+        let combined = portfolio_returns(&[(0.5, a), (0.5, BTreeMap::new())]);
+        assert!((combined[&1] - 0.10).abs() < 1e-9);
+        assert!(portfolio_returns(&[]).is_empty());
+    }
+
+    #[test]
+    fn beta_of_levered_benchmark_is_leverage() {
+        let bench = BTreeMap::from([(1, 0.01), (2, -0.02), (3, 0.03)]);
+        let levered: BTreeMap<i64, f64> = bench.iter().map(|(d, r)| (*d, r * 2.0)).collect();
+        assert!((beta(&levered, &bench).unwrap() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn max_drawdown_measures_peak_to_trough() {
+        // 100 -> 120 -> 60 -> 90
+        let dd = max_drawdown(&[0.20, -0.50, 0.50]);
+        assert!((dd - 0.50).abs() < 1e-9);
+        assert_eq!(max_drawdown(&[0.01, 0.02]), 0.0);
+    }
+
+    #[test]
+    fn std_dev_needs_two_observations() {
+        assert!(std_dev(&[0.1]).is_none());
+        assert!((std_dev(&[1.0, 3.0]).unwrap() - 2.0_f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn concentration_groups_by_sector() {
+        let values = HashMap::from([
+            ("AAPL".to_string(), 600.0),
+            ("MSFT".to_string(), 200.0),
+            ("XOM".to_string(), 200.0),
+        ]);
+        let sectors = HashMap::from([
+            ("AAPL".to_string(), "Technology".to_string()),
+            ("MSFT".to_string(), "Technology".to_string()),
+        ]);
+
+        let c = concentration(&values, &sectors);
+        assert_eq!(c.largest_position_symbol.as_deref(), Some("AAPL"));
+        assert!((c.largest_position_weight - 0.6).abs() < 1e-9);
+        assert_eq!(c.sectors[0].sector, "Technology");
+        assert_eq!(c.sectors[0].symbols, vec!["AAPL", "MSFT"]);
+        assert_eq!(c.sectors[1].sector, "Unknown");
+        assert!((c.sector_hhi - (0.8 * 0.8 + 0.2 * 0.2)).abs() < 1e-9);
+    }
+}
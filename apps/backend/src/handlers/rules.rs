@@ -0,0 +1,992 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::dynamo::is_conditional_check_failure;
+use crate::db::transaction_keys::{budget_key, ATTR_BUDGET_KEY};
+use crate::handlers::budgets::budget_exists;
+use crate::handlers::category_model::learn_categories;
+use crate::handlers::transactions::{
+    apply_spent_changes, counted_allocations, transactions_since, Transaction,
+};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+const MAX_RULES: usize = 100;
+const MAX_PATTERN_LEN: usize = 200;
+/// Compiled size limit for rule regexes, so one rule can't slow down every sync.
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+const MAX_TAGS: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RuleConditions {
+    /// Case-insensitive text in the merchant name, or the description when there is none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant_contains: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_contains: Option<String>,
+    /// Case-insensitive regular expression matched against the description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_amount: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_amount: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RuleActions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename_merchant: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rule {
+    pub rule_id: String,
+    pub user_id: String,
+    pub name: String,
+    /// Rules run in ascending priority
+    pub priority: i64,
+    pub enabled: bool,
+    pub conditions: RuleConditions,
+    pub actions: RuleActions,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRuleRequest {
+    pub name: String,
+    /// Defaults to after the user's existing rules
+    pub priority: Option<i64>,
+    #[serde(default)]
+    pub conditions: RuleConditions,
+    #[serde(default)]
+    pub actions: RuleActions,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRuleRequest {
+    pub name: Option<String>,
+    pub priority: Option<i64>,
+    pub enabled: Option<bool>,
+    /// Replaces all conditions
+    pub conditions: Option<RuleConditions>,
+    /// Replaces all actions
+    pub actions: Option<RuleActions>,
+}
+
+fn trimmed(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, String> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!(
+            "description_regex must be at most {} characters",
+            MAX_PATTERN_LEN
+        ));
+    }
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid description_regex: {}", e))
+}
+
+impl Rule {
+    /// Check the rule and put its text fields into canonical form. A rule needs at least one
+    /// condition, so it can't match every transaction, and at least one action.
+    fn validate(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err("name is required".to_string());
+        }
+
+        let conditions = &mut self.conditions;
+        conditions.merchant_contains = trimmed(&conditions.merchant_contains);
+        conditions.description_contains = trimmed(&conditions.description_contains);
+        conditions.description_regex = trimmed(&conditions.description_regex);
+        conditions.account_id = trimmed(&conditions.account_id);
+        if let Some(ref pattern) = conditions.description_regex {
+            compile_pattern(pattern)?;
+        }
+        if let (Some(min), Some(max)) = (conditions.min_amount, conditions.max_amount) {
+            if min > max {
+                return Err("min_amount must not be more than max_amount".to_string());
+            }
+        }
+        if *conditions == RuleConditions::default() {
+            return Err("A rule needs at least one condition".to_string());
+        }
+
+        let actions = &mut self.actions;
+        actions.category = trimmed(&actions.category).map(|c| c.to_lowercase());
+        actions.budget_id = trimmed(&actions.budget_id);
+        actions.rename_merchant = trimmed(&actions.rename_merchant);
+        let mut tags: Vec<String> = Vec::new();
+        for tag in &actions.add_tags {
+            let tag = tag.trim().to_lowercase();
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.len() > MAX_TAGS {
+            return Err(format!("A rule can add at most {} tags", MAX_TAGS));
+        }
+        actions.add_tags = tags;
+        if *actions == RuleActions::default() {
+            return Err("A rule needs at least one action".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn item_to_rule(item: &HashMap<String, AttributeValue>) -> Rule {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    let n = |name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+    };
+    Rule {
+        rule_id: s("rule_id").unwrap_or_default(),
+        user_id: s("user_id").unwrap_or_default(),
+        name: s("name").unwrap_or_default(),
+        priority: n("priority").unwrap_or(0.0) as i64,
+        enabled: item
+            .get("enabled")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(true),
+        conditions: RuleConditions {
+            merchant_contains: s("merchant_contains"),
+            description_contains: s("description_contains"),
+            description_regex: s("description_regex"),
+            min_amount: n("min_amount"),
+            max_amount: n("max_amount"),
+            account_id: s("account_id"),
+        },
+        actions: RuleActions {
+            category: s("set_category"),
+            budget_id: s("set_budget_id"),
+            add_tags: item
+                .get("add_tags")
+                .and_then(|v| v.as_ss().ok())
+                .cloned()
+                .unwrap_or_default(),
+            rename_merchant: s("rename_merchant"),
+        },
+        created_at: s("created_at").unwrap_or_default(),
+        updated_at: s("updated_at").unwrap_or_default(),
+    }
+}
+
+fn rule_to_item(rule: &Rule) -> HashMap<String, AttributeValue> {
+    let s = |value: &str| AttributeValue::S(value.to_string());
+    let mut item = HashMap::from([
+        ("user_id".to_string(), s(&rule.user_id)),
+        ("rule_id".to_string(), s(&rule.rule_id)),
+        ("name".to_string(), s(&rule.name)),
+        (
+            "priority".to_string(),
+            AttributeValue::N(rule.priority.to_string()),
+        ),
+        ("enabled".to_string(), AttributeValue::Bool(rule.enabled)),
+        ("created_at".to_string(), s(&rule.created_at)),
+        ("updated_at".to_string(), s(&rule.updated_at)),
+    ]);
+    let conditions = &rule.conditions;
+    let actions = &rule.actions;
+    for (name, value) in [
+        ("merchant_contains", &conditions.merchant_contains),
+        ("description_contains", &conditions.description_contains),
+        ("description_regex", &conditions.description_regex),
+        ("account_id", &conditions.account_id),
+        ("set_category", &actions.category),
+        ("set_budget_id", &actions.budget_id),
+        ("rename_merchant", &actions.rename_merchant),
+    ] {
+        if let Some(value) = value {
+            item.insert(name.to_string(), s(value));
+        }
+    }
+    for (name, value) in [
+        ("min_amount", conditions.min_amount),
+        ("max_amount", conditions.max_amount),
+    ] {
+        if let Some(value) = value {
+            item.insert(name.to_string(), AttributeValue::N(value.to_string()));
+        }
+    }
+    if !actions.add_tags.is_empty() {
+        item.insert(
+            "add_tags".to_string(),
+            AttributeValue::Ss(actions.add_tags.clone()),
+        );
+    }
+    item
+}
+
+// --- Engine ---
+
+/// What a rule looks at in a transaction.
+pub struct RuleInput<'a> {
+    pub description: &'a str,
+    pub merchant: Option<&'a str>,
+    pub amount: f64,
+    pub account_id: Option<&'a str>,
+}
+
+impl<'a> RuleInput<'a> {
+    pub fn from_transaction(transaction: &'a Transaction) -> Self {
+        RuleInput {
+            description: &transaction.description,
+            merchant: transaction.merchant.as_deref(),
+            amount: transaction.amount,
+            account_id: transaction.account_id.as_deref(),
+        }
+    }
+}
+
+/// The combined actions of every rule that matched.
+#[derive(Debug, Default, PartialEq)]
+pub struct RuleOutcome {
+    pub category: Option<String>,
+    pub budget_id: Option<String>,
+    pub merchant: Option<String>,
+    pub tags: Vec<String>,
+}
+
+struct CompiledRule {
+    rule: Rule,
+    regex: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn matches(&self, input: &RuleInput) -> bool {
+        let conditions = &self.rule.conditions;
+        let contains =
+            |text: &str, needle: &str| text.to_lowercase().contains(&needle.to_lowercase());
+        if let Some(ref needle) = conditions.merchant_contains {
+            if !contains(input.merchant.unwrap_or(input.description), needle) {
+                return false;
+            }
+        }
+        if let Some(ref needle) = conditions.description_contains {
+            if !contains(input.description, needle) {
+                return false;
+            }
+        }
+        if let Some(ref regex) = self.regex {
+            if !regex.is_match(input.description) {
+                return false;
+            }
+        }
+        if conditions.min_amount.is_some_and(|min| input.amount < min)
+            || conditions.max_amount.is_some_and(|max| input.amount > max)
+        {
+            return false;
+        }
+        match conditions.account_id {
+            Some(ref account_id) => input.account_id == Some(account_id.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// A user's enabled rules in the order they run.
+#[derive(Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    pub fn new(mut rules: Vec<Rule>) -> Self {
+        rules.retain(|r| r.enabled);
+        rules.sort_by(|a, b| {
+            a.priority
+                .cmp(&b.priority)
+                .then_with(|| a.created_at.cmp(&b.created_at))
+        });
+        let rules = rules
+            .into_iter()
+            .filter_map(|rule| {
+                let regex = match rule.conditions.description_regex {
+                    Some(ref pattern) => Some(compile_pattern(pattern).ok()?),
+                    None => None,
+                };
+                Some(CompiledRule { rule, regex })
+            })
+            .collect();
+        RuleSet { rules }
+    }
+
+    /// Run every matching rule in order. The first rule to set the category, budget or
+    /// merchant wins; tags from all of them are added.
+    pub fn apply(&self, input: &RuleInput) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+        for compiled in self.rules.iter().filter(|r| r.matches(input)) {
+            let actions = &compiled.rule.actions;
+            if outcome.category.is_none() {
+                outcome.category = actions.category.clone();
+            }
+            if outcome.budget_id.is_none() {
+                outcome.budget_id = actions.budget_id.clone();
+            }
+            if outcome.merchant.is_none() {
+                outcome.merchant = actions.rename_merchant.clone();
+            }
+            for tag in &actions.add_tags {
+                if !outcome.tags.contains(tag) {
+                    outcome.tags.push(tag.clone());
+                }
+            }
+        }
+        outcome
+    }
+}
+
+async fn query_rules(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<Rule>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-rules")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+    Ok(output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .map(item_to_rule)
+        .collect())
+}
+
+pub async fn load_rules(
+    state: &AppState,
+    user_id: &str,
+) -> Result<RuleSet, aws_sdk_dynamodb::Error> {
+    Ok(RuleSet::new(query_rules(state, user_id).await?))
+}
+
+// --- Rules CRUD ---
+
+pub async fn list_rules(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    match query_rules(&state, &claims.sub).await {
+        Ok(mut rules) => {
+            rules.sort_by(|a, b| {
+                a.priority
+                    .cmp(&b.priority)
+                    .then_with(|| a.created_at.cmp(&b.created_at))
+            });
+            (StatusCode::OK, Json(serde_json::to_value(rules).unwrap())).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn create_rule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(body): Json<CreateRuleRequest>,
+) -> impl IntoResponse {
+    let existing = match query_rules(&state, &claims.sub).await {
+        Ok(rules) => rules,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response()
+        }
+    };
+    if existing.len() >= MAX_RULES {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(format!(
+                "You can have at most {} rules",
+                MAX_RULES
+            ))),
+        )
+            .into_response();
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut rule = Rule {
+        rule_id: Uuid::new_v4().to_string(),
+        user_id: claims.sub,
+        name: body.name,
+        priority: body
+            .priority
+            .unwrap_or_else(|| existing.iter().map(|r| r.priority + 1).max().unwrap_or(0)),
+        enabled: true,
+        conditions: body.conditions,
+        actions: body.actions,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    if let Err(e) = rule.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response();
+    }
+    if let Err(response) = check_budget(&state, &rule).await {
+        return response;
+    }
+
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-rules")
+        .set_item(Some(rule_to_item(&rule)))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::CREATED,
+            Json(serde_json::to_value(rule).unwrap()),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Failed to create rule: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+/// Reject a rule that files transactions into a budget the user doesn't have.
+async fn check_budget(state: &AppState, rule: &Rule) -> Result<(), axum::response::Response> {
+    let Some(ref budget_id) = rule.actions.budget_id else {
+        return Ok(());
+    };
+    match budget_exists(state, &rule.user_id, budget_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(format!("Budget not found: {}", budget_id))),
+        )
+            .into_response()),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response()),
+    }
+}
+
+async fn get_rule_item(
+    state: &AppState,
+    user_id: &str,
+    rule_id: &str,
+) -> Result<Rule, axum::response::Response> {
+    let result = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-rules")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("rule_id", AttributeValue::S(rule_id.to_string()))
+        .send()
+        .await;
+    match result {
+        Ok(output) => match output.item {
+            Some(item) => Ok(item_to_rule(&item)),
+            None => {
+                Err((StatusCode::NOT_FOUND, Json(ApiError::new("Rule not found"))).into_response())
+            }
+        },
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response()),
+    }
+}
+
+pub async fn get_rule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(rule_id): Path<String>,
+) -> impl IntoResponse {
+    match get_rule_item(&state, &claims.sub, &rule_id).await {
+        Ok(rule) => (StatusCode::OK, Json(serde_json::to_value(rule).unwrap())).into_response(),
+        Err(response) => response,
+    }
+}
+
+pub async fn update_rule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(rule_id): Path<String>,
+    Json(body): Json<UpdateRuleRequest>,
+) -> impl IntoResponse {
+    let stored = match get_rule_item(&state, &claims.sub, &rule_id).await {
+        Ok(rule) => rule,
+        Err(response) => return response,
+    };
+
+    let mut rule = stored.clone();
+    if let Some(name) = body.name {
+        rule.name = name;
+    }
+    if let Some(priority) = body.priority {
+        rule.priority = priority;
+    }
+    if let Some(enabled) = body.enabled {
+        rule.enabled = enabled;
+    }
+    if let Some(conditions) = body.conditions {
+        rule.conditions = conditions;
+    }
+    if let Some(actions) = body.actions {
+        rule.actions = actions;
+    }
+    if let Err(e) = rule.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response();
+    }
+    if let Err(response) = check_budget(&state, &rule).await {
+        return response;
+    }
+    rule.updated_at = Utc::now().to_rfc3339();
+
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-rules")
+        .set_item(Some(rule_to_item(&rule)))
+        .condition_expression("updated_at = :seen")
+        .expression_attribute_values(":seen", AttributeValue::S(stored.updated_at))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(serde_json::to_value(rule).unwrap())).into_response(),
+        Err(e) if is_conditional_check_failure(&e) => (
+            StatusCode::CONFLICT,
+            Json(ApiError::new(
+                "The rule was changed by another request; reload it and try again",
+            )),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Update failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn delete_rule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(rule_id): Path<String>,
+) -> impl IntoResponse {
+    let result = state
+        .dynamo
+        .delete_item()
+        .table_name("ovaflus-rules")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("rule_id", AttributeValue::S(rule_id))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Delete failed: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+// --- Retroactive Apply ---
+
+#[derive(Deserialize, Default)]
+pub struct ApplyRuleRequest {
+    /// Only transactions dated on or after this day, `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Write the changes; without it the response is only a preview
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RuleChange {
+    pub transaction_id: String,
+    pub date: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merchant: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub add_tags: Vec<String>,
+}
+
+/// What applying the rule would change on a transaction, or `None` when it doesn't match or
+/// the transaction already has everything the rule sets.
+fn rule_change(rule_set: &RuleSet, transaction: &Transaction) -> Option<RuleChange> {
+    let outcome = rule_set.apply(&RuleInput::from_transaction(transaction));
+    let change = RuleChange {
+        transaction_id: transaction.transaction_id.clone(),
+        date: transaction.date.clone(),
+        description: transaction.description.clone(),
+        category: outcome.category.filter(|c| *c != transaction.category),
+        budget_id: outcome.budget_id.filter(|b| *b != transaction.budget_id),
+        merchant: outcome
+            .merchant
+            .filter(|m| transaction.merchant.as_ref() != Some(m)),
+        add_tags: outcome
+            .tags
+            .into_iter()
+            .filter(|t| !transaction.tags.contains(t))
+            .collect(),
+    };
+    let unchanged = change.category.is_none()
+        && change.budget_id.is_none()
+        && change.merchant.is_none()
+        && change.add_tags.is_empty();
+    (!unchanged).then_some(change)
+}
+
+/// Write one change, moving the amount between budgets if the budget changed. Skipped with
+/// `Ok(false)` when the transaction was edited since it was read.
+async fn write_change(
+    state: &AppState,
+    user_id: &str,
+    transaction: &Transaction,
+    change: &RuleChange,
+) -> Result<bool, aws_sdk_dynamodb::Error> {
+    let mut sets = vec!["updated_at = :now"];
+    let mut update = state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-transactions")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key(
+            "transaction_id",
+            AttributeValue::S(transaction.transaction_id.clone()),
+        )
+        .condition_expression("updated_at = :seen")
+        .expression_attribute_values(":seen", AttributeValue::S(transaction.updated_at.clone()))
+        .expression_attribute_values(":now", AttributeValue::S(Utc::now().to_rfc3339()))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld);
+    if let Some(ref category) = change.category {
+        sets.push("category = :category");
+        update =
+            update.expression_attribute_values(":category", AttributeValue::S(category.clone()));
+    }
+    if let Some(ref budget_id) = change.budget_id {
+        sets.push("budget_id = :bid, budget_key = :budget_key");
+        update = update
+            .expression_attribute_values(":bid", AttributeValue::S(budget_id.clone()))
+            .expression_attribute_values(
+                ":budget_key",
+                AttributeValue::S(budget_key(user_id, budget_id)),
+            );
+    }
+    if let Some(ref merchant) = change.merchant {
+        sets.push("merchant = :merchant");
+        update =
+            update.expression_attribute_values(":merchant", AttributeValue::S(merchant.clone()));
+    }
+    let mut expression = format!("SET {}", sets.join(", "));
+    if !change.add_tags.is_empty() {
+        expression.push_str(" ADD tags :tags");
+        update = update
+            .expression_attribute_values(":tags", AttributeValue::Ss(change.add_tags.clone()));
+    }
+
+    let old = match update.update_expression(expression).send().await {
+        Ok(output) => output.attributes.unwrap_or_default(),
+        Err(e) if is_conditional_check_failure(&e) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(ref budget_id) = change.budget_id {
+        let mut new = old.clone();
+        new.insert(
+            "budget_id".to_string(),
+            AttributeValue::S(budget_id.clone()),
+        );
+        new.insert(
+            ATTR_BUDGET_KEY.to_string(),
+            AttributeValue::S(budget_key(user_id, budget_id)),
+        );
+        apply_spent_changes(
+            state,
+            user_id,
+            &counted_allocations(&old),
+            &counted_allocations(&new),
+        )
+        .await?;
+    }
+    Ok(true)
+}
+
+/// Run one rule over existing transactions. Without `confirm` this only previews the
+/// changes; with it they are written and the response lists what was changed.
+pub async fn apply_rule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(rule_id): Path<String>,
+    body: Option<Json<ApplyRuleRequest>>,
+) -> impl IntoResponse {
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+    let from = match body
+        .from
+        .as_deref()
+        .map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d"))
+    {
+        Some(Err(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiError::new("from must be a date like 2024-05-01")),
+            )
+                .into_response()
+        }
+        Some(Ok(from)) => Some(from),
+        None => None,
+    };
+    let rule = match get_rule_item(&state, &claims.sub, &rule_id).await {
+        Ok(rule) => rule,
+        Err(response) => return response,
+    };
+    let rule_set = RuleSet::new(vec![Rule {
+        enabled: true,
+        ..rule
+    }]);
+
+    let transactions = match transactions_since(&state, &claims.sub, from).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response()
+        }
+    };
+    let mut changes: Vec<(&Transaction, RuleChange)> = transactions
+        .iter()
+        .filter_map(|t| rule_change(&rule_set, t).map(|change| (t, change)))
+        .collect();
+    changes.sort_by(|a, b| b.0.date.cmp(&a.0.date));
+
+    let mut skipped = 0;
+    if body.confirm {
+        let mut applied = Vec::with_capacity(changes.len());
+        for (transaction, change) in changes {
+            match write_change(&state, &claims.sub, transaction, &change).await {
+                Ok(true) => applied.push((transaction, change)),
+                Ok(false) => skipped += 1,
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ApiError::new(format!(
+                            "Applied {} changes, then failed: {}",
+                            applied.len(),
+                            e
+                        ))),
+                    )
+                        .into_response()
+                }
+            }
+        }
+        // Categories the user applied a rule for are their choice, like a manual edit
+        let edited: Vec<(&Transaction, Transaction)> = applied
+            .iter()
+            .filter_map(|(transaction, change)| {
+                let category = change.category.clone()?;
+                Some((
+                    *transaction,
+                    Transaction {
+                        category,
+                        ..(*transaction).clone()
+                    },
+                ))
+            })
+            .collect();
+        let edits: Vec<(Option<&Transaction>, &Transaction)> = edited
+            .iter()
+            .map(|(before, after)| (Some(*before), after))
+            .collect();
+        if let Err(e) = learn_categories(&state, &claims.sub, &edits).await {
+            tracing::error!("Updating the category model failed: {e}");
+        }
+        changes = applied;
+    }
+
+    let changes: Vec<RuleChange> = changes.into_iter().map(|(_, change)| change).collect();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "rule_id": rule_id,
+            "applied": body.confirm,
+            "matched": changes.len(),
+            "skipped": skipped,
+            "changes": changes,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, priority: i64, conditions: RuleConditions, actions: RuleActions) -> Rule {
+        Rule {
+            rule_id: id.to_string(),
+            user_id: "u1".to_string(),
+            name: id.to_string(),
+            priority,
+            enabled: true,
+            conditions,
+            actions,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    fn input(description: &str, amount: f64) -> RuleInput<'_> {
+        RuleInput {
+            description,
+            merchant: None,
+            amount,
+            account_id: None,
+        }
+    }
+
+    #[test]
+    fn rules_need_a_condition_an_action_and_a_valid_regex() {
+        let coffee = RuleActions {
+            category: Some(" Dining ".to_string()),
+            add_tags: vec!["Coffee".to_string(), "coffee ".to_string()],
+            ..Default::default()
+        };
+        let mut valid = rule(
+            "r1",
+            0,
+            RuleConditions {
+                merchant_contains: Some(" starbucks ".to_string()),
+                ..Default::default()
+            },
+            coffee.clone(),
+        );
+        valid.validate().unwrap();
+        assert_eq!(
+            valid.conditions.merchant_contains.as_deref(),
+            Some("starbucks")
+        );
+        assert_eq!(valid.actions.category.as_deref(), Some("dining"));
+        assert_eq!(valid.actions.add_tags, ["coffee"]);
+
+        let mut match_all = rule("r2", 0, RuleConditions::default(), coffee.clone());
+        assert!(match_all.validate().is_err());
+        let mut no_action = rule("r3", 0, valid.conditions.clone(), RuleActions::default());
+        assert!(no_action.validate().is_err());
+        let bad_regex = RuleConditions {
+            description_regex: Some("(starbucks".to_string()),
+            ..Default::default()
+        };
+        assert!(rule("r4", 0, bad_regex, coffee).validate().is_err());
+
+        let item = rule_to_item(&valid);
+        let stored = item_to_rule(&item);
+        assert_eq!(stored.conditions, valid.conditions);
+        assert_eq!(stored.actions, valid.actions);
+    }
+
+    #[test]
+    fn matching_rules_run_in_priority_order_and_the_first_setter_wins() {
+        let rules = RuleSet::new(vec![
+            rule(
+                "any-coffee",
+                2,
+                RuleConditions {
+                    description_regex: Some(r"starbucks|blue bottle".to_string()),
+                    ..Default::default()
+                },
+                RuleActions {
+                    category: Some("dining".to_string()),
+                    add_tags: vec!["coffee".to_string()],
+                    ..Default::default()
+                },
+            ),
+            rule(
+                "big-starbucks",
+                1,
+                RuleConditions {
+                    merchant_contains: Some("STARBUCKS".to_string()),
+                    min_amount: Some(50.0),
+                    ..Default::default()
+                },
+                RuleActions {
+                    category: Some("shopping".to_string()),
+                    budget_id: Some("gifts".to_string()),
+                    add_tags: vec!["gift-card".to_string()],
+                    rename_merchant: Some("Starbucks".to_string()),
+                },
+            ),
+        ]);
+
+        let small = rules.apply(&input("STARBUCKS STORE 00123", 5.75));
+        assert_eq!(small.category.as_deref(), Some("dining"));
+        assert_eq!(small.budget_id, None);
+        assert_eq!(small.tags, ["coffee"]);
+
+        let big = rules.apply(&input("Starbucks Card Reload", 75.0));
+        assert_eq!(big.category.as_deref(), Some("shopping"));
+        assert_eq!(big.budget_id.as_deref(), Some("gifts"));
+        assert_eq!(big.merchant.as_deref(), Some("Starbucks"));
+        assert_eq!(big.tags, ["gift-card", "coffee"]);
+
+        assert_eq!(
+            rules.apply(&input("Peet's Coffee", 5.0)),
+            RuleOutcome::default()
+        );
+        let on_card = RuleInput {
+            account_id: Some("acc-1"),
+            ..input("Blue Bottle", 6.0)
+        };
+        assert_eq!(rules.apply(&on_card).tags, ["coffee"]);
+    }
+}
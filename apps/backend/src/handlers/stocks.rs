@@ -116,42 +116,172 @@ pub async fn search_stocks(
     }
 }
 
-pub async fn get_stock_news(
-    State(state): State<Arc<AppState>>,
-    Path(symbol): Path<String>,
-) -> impl IntoResponse {
-    let client = reqwest::Client::new();
-
-    let now = chrono::Utc::now();
-    let from = (now - chrono::Duration::days(7))
-        .format("%Y-%m-%d")
-        .to_string();
-    let to = now.format("%Y-%m-%d").to_string();
+#[derive(Deserialize)]
+pub struct NewsQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
 
-    let result = client
+/// Fetch Finnhub company news for one symbol between `from` and `to` (YYYY-MM-DD).
+pub(crate) async fn fetch_company_news(
+    client: &reqwest::Client,
+    api_key: &str,
+    symbol: &str,
+    from: &str,
+    to: &str,
+) -> Result<Vec<serde_json::Value>, String> {
+    let resp = client
         .get("https://finnhub.io/api/v1/company-news")
         .query(&[
-            ("symbol", symbol.as_str()),
+            ("symbol", symbol),
+            ("from", from),
+            ("to", to),
+            ("token", api_key),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Finnhub request failed: {}", e))?;
+
+    let status = resp.status();
+    let data = resp
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+    // Bad keys and rate limits come back as an object with an `error` message
+    match data {
+        serde_json::Value::Array(stories) if status.is_success() => Ok(stories),
+        other => Err(format!(
+            "Finnhub returned {}: {}",
+            status,
+            other
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("unexpected response")
+        )),
+    }
+}
+
+/// Fetch the latest price (`c`) from a Finnhub quote; `None` when Finnhub has no quote.
+pub(crate) async fn fetch_quote(
+    client: &reqwest::Client,
+    api_key: &str,
+    symbol: &str,
+) -> Result<Option<f64>, String> {
+    let quote = client
+        .get("https://finnhub.io/api/v1/quote")
+        .query(&[("symbol", symbol), ("token", api_key)])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch quote: {}", e))?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| format!("Failed to parse quote: {}", e))?;
+
+    // Finnhub returns zeros rather than an error for unknown symbols
+    Ok(quote
+        .get("c")
+        .and_then(|v| v.as_f64())
+        .filter(|price| *price > 0.0))
+}
+
+/// Fetch a Finnhub company profile (`profile2`), which includes `finnhubIndustry`.
+pub(crate) async fn fetch_profile(
+    client: &reqwest::Client,
+    api_key: &str,
+    symbol: &str,
+) -> Result<serde_json::Value, String> {
+    client
+        .get("https://finnhub.io/api/v1/stock/profile2")
+        .query(&[("symbol", symbol), ("token", api_key)])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch profile: {}", e))?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| format!("Failed to parse profile: {}", e))
+}
+
+/// Fetch daily closing prices for `symbol` between two unix timestamps as `(timestamp, close)`.
+pub(crate) async fn fetch_daily_closes(
+    client: &reqwest::Client,
+    api_key: &str,
+    symbol: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<(i64, f64)>, String> {
+    let (from, to) = (from.to_string(), to.to_string());
+    let resp = client
+        .get("https://finnhub.io/api/v1/stock/candle")
+        .query(&[
+            ("symbol", symbol),
+            ("resolution", "D"),
             ("from", from.as_str()),
             ("to", to.as_str()),
-            ("token", state.finnhub_api_key.as_str()),
+            ("token", api_key),
         ])
         .send()
-        .await;
+        .await
+        .map_err(|e| format!("Failed to fetch candles: {}", e))?;
 
-    match result {
-        Ok(resp) => match resp.json::<serde_json::Value>().await {
-            Ok(data) => (StatusCode::OK, Json(data)).into_response(),
-            Err(e) => (
-                StatusCode::BAD_GATEWAY,
-                Json(ApiError::new(format!("Failed to parse response: {}", e))),
-            )
-                .into_response(),
-        },
-        Err(e) => (
-            StatusCode::BAD_GATEWAY,
-            Json(ApiError::new(format!("Finnhub request failed: {}", e))),
-        )
-            .into_response(),
+    let status = resp.status();
+    let data = resp
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| format!("Failed to parse candles: {}", e))?;
+
+    // Only `no_data` means there are no candles; anything else is an error object
+    match data.get("s").and_then(|v| v.as_str()) {
+        Some("ok") if status.is_success() => {}
+        Some("no_data") if status.is_success() => return Ok(Vec::new()),
+        _ => {
+            return Err(format!(
+                "Finnhub returned {}: {}",
+                status,
+                data.get("error")
+                    .and_then(|e| e.as_str())
+                    .unwrap_or("unexpected response")
+            ));
+        }
+    }
+
+    let closes = data
+        .get("c")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let times = data
+        .get("t")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    Ok(times
+        .iter()
+        .zip(closes.iter())
+        .filter_map(|(t, c)| Some((t.as_i64()?, c.as_f64()?)))
+        .collect())
+}
+
+pub async fn get_stock_news(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<NewsQuery>,
+) -> impl IntoResponse {
+    let client = reqwest::Client::new();
+
+    let now = chrono::Utc::now();
+    let from = params.from.unwrap_or_else(|| {
+        (now - chrono::Duration::days(7))
+            .format("%Y-%m-%d")
+            .to_string()
+    });
+    let to = params
+        .to
+        .unwrap_or_else(|| now.format("%Y-%m-%d").to_string());
+
+    match fetch_company_news(&client, &state.finnhub_api_key, &symbol, &from, &to).await {
+        Ok(stories) => (StatusCode::OK, Json(serde_json::Value::Array(stories))).into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(ApiError::new(e))).into_response(),
     }
 }
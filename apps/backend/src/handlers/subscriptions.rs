@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Days, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::handlers::merchants::{is_merchant_id, normalize_merchant};
+use crate::handlers::transactions::{transactions_since, Transaction};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+/// How far back charges are looked at; long enough to see a yearly charge twice.
+const LOOKBACK_DAYS: u64 = 400;
+/// Share of the gaps between charges that must match the cadence.
+const REGULAR_SHARE: f64 = 0.8;
+/// How far a charge may be from the one before it and still count as the same price.
+const AMOUNT_TOLERANCE: f64 = 0.3;
+/// Days a charge may post after its expected date before the subscription counts as lapsed.
+const LATE_DAYS: u64 = 3;
+
+const STATUS_CANDIDATE: &str = "candidate";
+const STATUS_CONFIRMED: &str = "confirmed";
+const STATUS_DISMISSED: &str = "dismissed";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cadence {
+    Weekly,
+    Biweekly,
+    Monthly,
+    Yearly,
+}
+
+impl Cadence {
+    /// Cadences with the gaps in days they accept and the charges needed to trust them.
+    const ALL: [(Cadence, std::ops::RangeInclusive<i64>, usize); 4] = [
+        (Cadence::Weekly, 6..=8, 4),
+        (Cadence::Biweekly, 13..=16, 3),
+        (Cadence::Monthly, 26..=35, 3),
+        (Cadence::Yearly, 350..=380, 2),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Cadence::Weekly => "weekly",
+            Cadence::Biweekly => "biweekly",
+            Cadence::Monthly => "monthly",
+            Cadence::Yearly => "yearly",
+        }
+    }
+
+    fn next_after(self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Cadence::Weekly => date.checked_add_days(Days::new(7)),
+            Cadence::Biweekly => date.checked_add_days(Days::new(14)),
+            Cadence::Monthly => date.checked_add_months(Months::new(1)),
+            Cadence::Yearly => date.checked_add_months(Months::new(12)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Subscription {
+    /// The normalized merchant, which identifies the subscription
+    pub subscription_id: String,
+    /// The merchant's name on the latest charge
+    pub merchant: String,
+    pub category: String,
+    /// `weekly`, `biweekly`, `monthly` or `yearly`
+    pub frequency: &'static str,
+    pub average_amount: f64,
+    pub latest_amount: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_amount: Option<f64>,
+    /// The latest charge differs from the one before it
+    pub price_changed: bool,
+    pub charges: usize,
+    pub last_date: String,
+    pub next_expected_date: String,
+    /// False once the next expected charge is overdue, e.g. after a cancellation
+    pub active: bool,
+    /// `candidate`, `confirmed` or `dismissed`
+    pub status: String,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        (values[mid - 1] + values[mid]) / 2.0
+    }
+}
+
+/// Whether the amounts hold one price, allowing a single change of price: each charge is
+/// compared with the one before it, so a raise of any size splits the charges in two.
+fn stable_amounts(amounts: &[f64]) -> bool {
+    let changes = amounts
+        .windows(2)
+        .filter(|pair| (pair[1] - pair[0]).abs() > AMOUNT_TOLERANCE * pair[0].abs())
+        .count();
+    changes <= 1
+}
+
+/// The charges of one merchant as a subscription, if they recur on a known cadence with a
+/// stable amount. A `confirmed` subscription only needs the cadence. `charges` must be
+/// sorted by date.
+fn detect(
+    key: &str,
+    charges: &[(NaiveDate, &Transaction)],
+    confirmed: bool,
+    today: NaiveDate,
+) -> Option<Subscription> {
+    if charges.len() < 2 {
+        return None;
+    }
+    let gaps: Vec<i64> = charges
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0).num_days())
+        .collect();
+    let mut sorted_gaps: Vec<f64> = gaps.iter().map(|&g| g as f64).collect();
+    let typical_gap = median(&mut sorted_gaps).round() as i64;
+
+    let (cadence, range, min_charges) = Cadence::ALL
+        .into_iter()
+        .find(|(_, range, _)| range.contains(&typical_gap))?;
+    if charges.len() < min_charges {
+        return None;
+    }
+    let regular = gaps.iter().filter(|g| range.contains(g)).count();
+    if (regular as f64) < REGULAR_SHARE * gaps.len() as f64 {
+        return None;
+    }
+
+    let amounts: Vec<f64> = charges.iter().map(|(_, t)| t.amount).collect();
+    if !confirmed && !stable_amounts(&amounts) {
+        return None;
+    }
+
+    let (last_date, latest) = charges[charges.len() - 1];
+    let previous_amount = charges[charges.len() - 2].1.amount;
+    let price_changed = (latest.amount - previous_amount).abs() >= 0.01;
+    let average_amount = amounts.iter().sum::<f64>() / amounts.len() as f64;
+    let next_expected = cadence.next_after(last_date)?;
+    Some(Subscription {
+        subscription_id: key.to_string(),
+        merchant: latest
+            .merchant
+            .clone()
+            .unwrap_or_else(|| latest.description.clone()),
+        category: latest.category.clone(),
+        frequency: cadence.name(),
+        average_amount: (average_amount * 100.0).round() / 100.0,
+        latest_amount: latest.amount,
+        previous_amount: price_changed.then_some(previous_amount),
+        price_changed,
+        charges: charges.len(),
+        last_date: last_date.to_string(),
+        next_expected_date: next_expected.to_string(),
+        active: next_expected + Days::new(LATE_DAYS) >= today,
+        status: if confirmed {
+            STATUS_CONFIRMED
+        } else {
+            STATUS_CANDIDATE
+        }
+        .to_string(),
+    })
+}
+
+/// Group charges by merchant and keep the groups that look like subscriptions, most
+/// expensive first, with the status the user gave each. Income, transfers and refunds are
+/// left out.
+fn detect_subscriptions(
+    transactions: &[Transaction],
+    statuses: &HashMap<String, String>,
+    today: NaiveDate,
+) -> Vec<Subscription> {
+    let mut by_merchant: BTreeMap<String, Vec<(NaiveDate, &Transaction)>> = BTreeMap::new();
+    for transaction in transactions {
+        if transaction.transaction_type != "expense" {
+            continue;
+        }
+        let Some(date) = transaction
+            .date
+            .get(..10)
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        else {
+            continue;
+        };
+        // Older transactions may predate merchant ids
+        let Some(key) = transaction
+            .merchant_id
+            .clone()
+            .or_else(|| normalize_merchant(&transaction.description).map(|m| m.merchant_id))
+        else {
+            continue;
+        };
+        by_merchant
+            .entry(key)
+            .or_default()
+            .push((date, transaction));
+    }
+
+    let mut subscriptions: Vec<Subscription> = by_merchant
+        .iter_mut()
+        .filter_map(|(key, charges)| {
+            charges.sort_by_key(|(date, _)| *date);
+            let status = statuses.get(key.as_str()).map(String::as_str);
+            let mut subscription = detect(key, charges, status == Some(STATUS_CONFIRMED), today)?;
+            if let Some(status) = status {
+                subscription.status = status.to_string();
+            }
+            Some(subscription)
+        })
+        .collect();
+    subscriptions.sort_by(|a, b| b.average_amount.total_cmp(&a.average_amount));
+    subscriptions
+}
+
+async fn load_statuses(
+    state: &AppState,
+    user_id: &str,
+) -> Result<HashMap<String, String>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .query()
+        .table_name("ovaflus-subscriptions")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+    Ok(output
+        .items
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+            Some((s("subscription_id")?, s("status")?))
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+pub struct ListSubscriptionsQuery {
+    /// Also list candidates the user dismissed
+    #[serde(default)]
+    pub include_dismissed: bool,
+}
+
+pub async fn list_subscriptions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<ListSubscriptionsQuery>,
+) -> impl IntoResponse {
+    let today = Utc::now().date_naive();
+    let from = today - Days::new(LOOKBACK_DAYS);
+    let loaded = tokio::try_join!(
+        transactions_since(&state, &claims.sub, Some(from)),
+        load_statuses(&state, &claims.sub),
+    );
+    let (transactions, statuses) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response()
+        }
+    };
+
+    let subscriptions: Vec<Subscription> = detect_subscriptions(&transactions, &statuses, today)
+        .into_iter()
+        .filter(|s| params.include_dismissed || s.status != STATUS_DISMISSED)
+        .collect();
+    (
+        StatusCode::OK,
+        Json(serde_json::to_value(subscriptions).unwrap()),
+    )
+        .into_response()
+}
+
+async fn set_status(
+    state: &AppState,
+    user_id: &str,
+    subscription_id: &str,
+    status: &str,
+) -> axum::response::Response {
+    if !is_merchant_id(subscription_id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("Invalid subscription id")),
+        )
+            .into_response();
+    }
+    let result = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-subscriptions")
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item(
+            "subscription_id",
+            AttributeValue::S(subscription_id.to_string()),
+        )
+        .item("status", AttributeValue::S(status.to_string()))
+        .item("updated_at", AttributeValue::S(Utc::now().to_rfc3339()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "subscription_id": subscription_id,
+                "status": status,
+            })),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn confirm_subscription(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(subscription_id): Path<String>,
+) -> impl IntoResponse {
+    set_status(&state, &claims.sub, &subscription_id, STATUS_CONFIRMED).await
+}
+
+/// Hide a candidate from the list; it stays dismissed however many more charges follow.
+pub async fn dismiss_subscription(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Path(subscription_id): Path<String>,
+) -> impl IntoResponse {
+    set_status(&state, &claims.sub, &subscription_id, STATUS_DISMISSED).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn charge(date: &str, amount: f64, description: &str) -> Transaction {
+        Transaction {
+            transaction_id: format!("{}-{}", description, date),
+            user_id: "u1".to_string(),
+            amount,
+            description: description.to_string(),
+            category: "entertainment".to_string(),
+            transaction_type: "expense".to_string(),
+            date: date.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn regular_charges_with_stable_amounts_become_subscriptions() {
+        let transactions = vec![
+            charge("2024-02-15", 15.0, "NETFLIX.COM 866-579"),
+            charge("2024-03-15", 15.0, "NETFLIX.COM 866-579"),
+            charge("2024-04-16", 15.0, "Netflix"),
+            charge("2024-05-15", 17.0, "NETFLIX.COM 866-579"),
+            // Weekly, but only three charges so far
+            charge("2024-05-01", 12.0, "Blue Bottle"),
+            charge("2024-05-08", 12.0, "Blue Bottle"),
+            charge("2024-05-15", 12.0, "Blue Bottle"),
+            // Regular dates, wildly different amounts
+            charge("2024-03-01", 20.0, "Shell"),
+            charge("2024-04-01", 80.0, "Shell"),
+            charge("2024-05-01", 45.0, "Shell"),
+        ];
+        let subscriptions = detect_subscriptions(&transactions, &HashMap::new(), day("2024-05-20"));
+        let ids: Vec<&str> = subscriptions
+            .iter()
+            .map(|s| s.subscription_id.as_str())
+            .collect();
+        assert_eq!(ids, ["netflix"]);
+
+        let netflix = &subscriptions[0];
+        assert_eq!(netflix.frequency, "monthly");
+        assert_eq!(netflix.charges, 4);
+        assert_eq!(netflix.average_amount, 15.5);
+        assert!(netflix.price_changed);
+        assert_eq!(netflix.previous_amount, Some(15.0));
+        assert_eq!(netflix.next_expected_date, "2024-06-15");
+        assert!(netflix.active);
+
+        let prime = [
+            charge("2024-01-03", 119.0, "Amazon Prime"),
+            charge("2025-01-02", 139.0, "Amazon Prime"),
+        ];
+        let prime = detect_subscriptions(&prime, &HashMap::new(), day("2025-01-10"));
+        assert_eq!(prime[0].frequency, "yearly");
+        assert_eq!(prime[0].next_expected_date, "2026-01-02");
+        assert!(prime[0].price_changed);
+    }
+
+    #[test]
+    fn price_rises_confirmed_entries_and_lapsed_subscriptions() {
+        let transactions = vec![
+            // A raise well past the tolerance is still one price change
+            charge("2024-02-01", 10.0, "Spotify"),
+            charge("2024-03-01", 10.0, "Spotify"),
+            charge("2024-04-01", 15.0, "Spotify"),
+            charge("2024-05-01", 15.0, "Spotify"),
+            charge("2024-03-01", 20.0, "Shell"),
+            charge("2024-04-01", 80.0, "Shell"),
+            charge("2024-05-01", 45.0, "Shell"),
+            // Cancelled after March
+            charge("2024-01-10", 9.0, "Hulu"),
+            charge("2024-02-10", 9.0, "Hulu"),
+            charge("2024-03-10", 9.0, "Hulu"),
+        ];
+        let statuses = HashMap::from([("shell".to_string(), STATUS_CONFIRMED.to_string())]);
+        let subscriptions = detect_subscriptions(&transactions, &statuses, day("2024-05-12"));
+        let found: Vec<(&str, &str, bool)> = subscriptions
+            .iter()
+            .map(|s| (s.subscription_id.as_str(), s.status.as_str(), s.active))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("shell", STATUS_CONFIRMED, true),
+                ("spotify", STATUS_CANDIDATE, true),
+                ("hulu", STATUS_CANDIDATE, false),
+            ]
+        );
+        assert_eq!(subscriptions[1].previous_amount, None);
+        assert!(!stable_amounts(&[10.0, 15.0, 10.0]));
+    }
+}
@@ -0,0 +1,1339 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::dynamo::{batch_write_unprocessed, put_request};
+use crate::db::transaction_keys::{budget_key, date_key, ATTR_BUDGET_KEY, ATTR_DATE_KEY};
+use crate::handlers::budgets::{load_budgets, Budget};
+use crate::handlers::categories::{match_budget, APP_CATEGORIES, FALLBACK_CATEGORY};
+use crate::handlers::category_model::{user_model, CategoryModel, AUTO_CATEGORY_CONFIDENCE};
+use crate::handlers::merchants::{global_merchants, normalize_merchant, user_merchants, Merchant};
+use crate::handlers::portfolio_import::{short_hash, SkippedRow};
+use crate::handlers::rules::{load_rules, RuleInput, RuleSet};
+use crate::handlers::transactions::{
+    apply_spent_changes, budget_allocations, transaction_type, transactions_since, Transaction,
+};
+use crate::middleware::auth::AuthUser;
+use crate::models::ApiError;
+use crate::AppState;
+
+/// Bank exports often start with a few lines of account details before the header.
+const MAX_PREAMBLE_ROWS: usize = 20;
+/// Rows are written one by one, so a single request is kept to a bounded size.
+const MAX_IMPORT_ROWS: usize = 2000;
+/// Rows per BatchWriteItem call, DynamoDB's limit.
+const WRITE_BATCH_ROWS: usize = 25;
+const DEFAULT_MAPPING: &str = "default";
+/// Languages that write `1.234,56`.
+const DECIMAL_COMMA_LANGUAGES: &[&str] = &[
+    "de", "fr", "es", "it", "nl", "pt", "pl", "sv", "da", "fi", "nb", "no", "ru", "tr", "cs", "id",
+];
+/// English-speaking regions that write the month before the day.
+const MONTH_FIRST_REGIONS: &[&str] = &["US", "PH"];
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct CsvColumnMap {
+    pub date: Option<String>,
+    pub description: Option<String>,
+    /// One signed amount column
+    pub amount: Option<String>,
+    /// Or separate columns for money out and money in
+    pub debit: Option<String>,
+    pub credit: Option<String>,
+    pub category: Option<String>,
+    /// The bank's own transaction id, when the export has one
+    pub reference: Option<String>,
+    /// The file shows money leaving the account as positive; most show it as negative
+    #[serde(default)]
+    pub outflow_positive: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ImportTransactionsRequest {
+    /// "csv", "ofx", "qfx" or "qif"; detected from the content when omitted
+    pub format: Option<String>,
+    pub content: String,
+    /// How dates and numbers are written, e.g. `en-US` (the default), `en-GB` or `de-DE`
+    pub locale: Option<String>,
+    /// CSV columns; without it the saved mapping is used, or else common header names
+    pub column_map: Option<CsvColumnMap>,
+    /// Name of the saved CSV mapping to use or save
+    pub mapping: Option<String>,
+    /// Save `column_map` and `locale` under `mapping` when the import is committed
+    #[serde(default)]
+    pub save_mapping: bool,
+    pub account_id: Option<String>,
+    /// Preview only (the default); pass `false` to write the import
+    #[serde(default = "default_dry_run")]
+    pub dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+/// How a locale writes numeric dates and amounts; the default is `en-US`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Locale {
+    day_first: bool,
+    decimal_comma: bool,
+}
+
+fn parse_locale(tag: &str) -> Result<Locale, String> {
+    let mut parts = tag.trim().split(['-', '_']);
+    let language = parts.next().unwrap_or_default().to_lowercase();
+    let region = parts.next().map(str::to_uppercase);
+    if language.len() != 2 || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("Unsupported locale: {}", tag));
+    }
+    let month_first = language == "en"
+        && region
+            .as_deref()
+            .is_none_or(|r| MONTH_FIRST_REGIONS.contains(&r));
+    Ok(Locale {
+        day_first: !month_first,
+        decimal_comma: DECIMAL_COMMA_LANGUAGES.contains(&language.as_str()),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ParsedRow {
+    line: usize,
+    date: String,
+    /// Money leaving the account is positive, as everywhere else in the app
+    amount: f64,
+    description: String,
+    category: Option<String>,
+    /// The bank's transaction id (OFX `FITID`)
+    reference: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct ParsedFile {
+    rows: Vec<ParsedRow>,
+    skipped: Vec<SkippedRow>,
+}
+
+/// Parse an amount written in the locale: `$1,234.50` or `1.234,50 €`, with `(12.00)` or a
+/// trailing minus for negatives.
+fn parse_amount(raw: &str, locale: Locale) -> Option<f64> {
+    let s = raw.trim().trim_matches('"').trim();
+    let (s, mut negative) = match s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (inner, true),
+        None => (s, false),
+    };
+    let (decimal, grouping) = if locale.decimal_comma {
+        (',', '.')
+    } else {
+        ('.', ',')
+    };
+    let mut cleaned = String::new();
+    for c in s.chars() {
+        match c {
+            '0'..='9' => cleaned.push(c),
+            '-' | '\u{2212}' => negative = !negative,
+            c if c == decimal => cleaned.push('.'),
+            c if c == grouping || c == '\'' || c == '+' || c.is_whitespace() => {}
+            // Currency symbols and codes
+            c if c.is_alphabetic() || matches!(c, '$' | '€' | '£' | '¥' | '₹') => {}
+            _ => return None,
+        }
+    }
+    if cleaned.is_empty() {
+        return None;
+    }
+    let value = cleaned.parse::<f64>().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Normalize a date to YYYY-MM-DD. Numeric dates are read day-first or month-first as the
+/// locale writes them; ISO and spelled-out months work everywhere.
+fn parse_date(raw: &str, locale: Locale) -> Option<String> {
+    const ISO: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%Y%m%d"];
+    const DAY_FIRST: &[&str] = &["%d/%m/%Y", "%d.%m.%Y", "%d-%m-%Y", "%d/%m/%y", "%d.%m.%y"];
+    const MONTH_FIRST: &[&str] = &["%m/%d/%Y", "%m-%d-%Y", "%m/%d/%y", "%m-%d-%y"];
+    const SPELLED: &[&str] = &["%d %b %Y", "%d-%b-%Y", "%b %d, %Y", "%d %B %Y", "%B %d, %Y"];
+
+    let raw = raw.trim().trim_matches('"').trim();
+    let numeric = if locale.day_first {
+        DAY_FIRST
+    } else {
+        MONTH_FIRST
+    };
+    // Also try the date without a trailing time
+    let candidates = [Some(raw), raw.split_whitespace().next(), raw.get(..10)];
+    candidates.into_iter().flatten().find_map(|candidate| {
+        ISO.iter()
+            .chain(numeric)
+            .chain(SPELLED)
+            .filter_map(|fmt| NaiveDate::parse_from_str(candidate, fmt).ok())
+            .find(|d| (1900..=2200).contains(&d.year()))
+            .map(|d| d.format("%Y-%m-%d").to_string())
+    })
+}
+
+/// An app category named in the file, if it is one the app knows. Anything else is left to
+/// the rules and the user's history.
+fn file_category(raw: &str) -> Option<String> {
+    let category = raw.trim().to_lowercase();
+    APP_CATEGORIES
+        .contains(&category.as_str())
+        .then_some(category)
+}
+
+fn detect_format(content: &str) -> &'static str {
+    let start = content.trim_start().to_ascii_uppercase();
+    if start.starts_with("OFXHEADER") || start.starts_with("<?XML") || start.contains("<OFX>") {
+        "ofx"
+    } else if start.starts_with("!TYPE")
+        || start.starts_with("!OPTION")
+        || start.starts_with("!ACCOUNT")
+    {
+        "qif"
+    } else {
+        "csv"
+    }
+}
+
+// --- CSV ---
+
+#[derive(Clone, Copy)]
+enum Field {
+    Date,
+    Description,
+    Amount,
+    Debit,
+    Credit,
+    Category,
+    Reference,
+}
+
+/// Header names banks commonly use for a field, lowercased.
+fn common_headers(field: Field) -> &'static [&'static str] {
+    match field {
+        Field::Date => &[
+            "date",
+            "transaction date",
+            "posted date",
+            "posting date",
+            "booking date",
+            "buchungstag",
+            "datum",
+        ],
+        Field::Description => &[
+            "description",
+            "payee",
+            "name",
+            "merchant",
+            "details",
+            "memo",
+            "verwendungszweck",
+        ],
+        Field::Amount => &["amount", "transaction amount", "betrag", "montant"],
+        Field::Debit => &[
+            "debit",
+            "withdrawal",
+            "withdrawals",
+            "money out",
+            "paid out",
+        ],
+        Field::Credit => &["credit", "deposit", "deposits", "money in", "paid in"],
+        Field::Category => &["category"],
+        Field::Reference => &["reference", "transaction id", "fitid"],
+    }
+}
+
+struct Columns {
+    date: usize,
+    description: usize,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    category: Option<usize>,
+    reference: Option<usize>,
+}
+
+fn normalize_header(h: &str) -> String {
+    h.trim()
+        .trim_matches('"')
+        .trim_start_matches('\u{feff}')
+        .to_lowercase()
+}
+
+fn find_column(header: &[String], field: Field, mapped: &Option<String>) -> Option<usize> {
+    if let Some(name) = mapped {
+        let name = normalize_header(name);
+        return header.iter().position(|h| *h == name);
+    }
+    common_headers(field)
+        .iter()
+        .find_map(|candidate| header.iter().position(|h| h == candidate))
+}
+
+fn resolve_columns(header: &[String], map: &CsvColumnMap) -> Option<Columns> {
+    let col = |field, mapped| find_column(header, field, mapped);
+    let columns = Columns {
+        date: col(Field::Date, &map.date)?,
+        description: col(Field::Description, &map.description)?,
+        amount: col(Field::Amount, &map.amount),
+        debit: col(Field::Debit, &map.debit),
+        credit: col(Field::Credit, &map.credit),
+        category: col(Field::Category, &map.category),
+        reference: col(Field::Reference, &map.reference),
+    };
+    let has_amount =
+        columns.amount.is_some() || columns.debit.is_some() || columns.credit.is_some();
+    has_amount.then_some(columns)
+}
+
+/// European exports are usually separated by semicolons, some by tabs.
+fn detect_delimiter(text: &str) -> u8 {
+    let head: String = text
+        .lines()
+        .take(MAX_PREAMBLE_ROWS)
+        .collect::<Vec<_>>()
+        .join("\n");
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|d| head.bytes().filter(|b| b == d).count())
+        .unwrap_or(b',')
+}
+
+fn parse_csv(text: &str, map: &CsvColumnMap, locale: Locale) -> Result<ParsedFile, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(detect_delimiter(text))
+        .from_reader(text.as_bytes());
+
+    let records: Vec<csv::StringRecord> = reader
+        .records()
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid CSV: {}", e))?;
+
+    let (header_index, columns) = records
+        .iter()
+        .take(MAX_PREAMBLE_ROWS)
+        .enumerate()
+        .find_map(|(i, record)| {
+            let header: Vec<String> = record.iter().map(normalize_header).collect();
+            resolve_columns(&header, map).map(|c| (i, c))
+        })
+        .ok_or_else(|| {
+            "Could not find a header row with date, description and amount columns".to_string()
+        })?;
+
+    let mut parsed = ParsedFile::default();
+    for (i, record) in records.iter().enumerate().skip(header_index + 1) {
+        let line = record
+            .position()
+            .map(|p| p.line() as usize)
+            .unwrap_or(i + 1);
+        if record.iter().all(|c| c.trim().is_empty()) {
+            continue;
+        }
+        let cell = |index: Option<usize>| index.and_then(|i| record.get(i)).unwrap_or("");
+        let skip = |reason: &str| SkippedRow {
+            line,
+            reason: reason.to_string(),
+        };
+
+        let Some(date) = parse_date(cell(Some(columns.date)), locale) else {
+            parsed.skipped.push(skip("invalid date"));
+            continue;
+        };
+        let amount = match columns.amount {
+            Some(index) => parse_amount(cell(Some(index)), locale).map(|a| {
+                if map.outflow_positive {
+                    a
+                } else {
+                    -a
+                }
+            }),
+            None => {
+                let out = parse_amount(cell(columns.debit), locale).map(f64::abs);
+                let into = parse_amount(cell(columns.credit), locale).map(f64::abs);
+                (out.is_some() || into.is_some()).then(|| out.unwrap_or(0.0) - into.unwrap_or(0.0))
+            }
+        };
+        let Some(amount) = amount else {
+            parsed.skipped.push(skip("missing amount"));
+            continue;
+        };
+        let description = cell(Some(columns.description)).trim().to_string();
+        if description.is_empty() {
+            parsed.skipped.push(skip("missing description"));
+            continue;
+        }
+        let reference = cell(columns.reference).trim();
+        parsed.rows.push(ParsedRow {
+            line,
+            date,
+            amount,
+            description,
+            category: file_category(cell(columns.category)),
+            reference: (!reference.is_empty()).then(|| reference.to_string()),
+        });
+    }
+    Ok(parsed)
+}
+
+// --- OFX / QFX ---
+
+fn unescape_sgml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// The value of the first `<TAG>` in a block. OFX 1.x is SGML and leaves elements unclosed,
+/// so the value runs to the next tag either way.
+fn ofx_field(block: &str, upper: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = upper.find(&open)? + open.len();
+    let end = block[start..].find('<').map_or(block.len(), |i| start + i);
+    let value = unescape_sgml(block[start..end].trim());
+    (!value.is_empty()).then_some(value)
+}
+
+fn parse_ofx(text: &str, locale: Locale) -> Result<ParsedFile, String> {
+    // ASCII uppercasing keeps byte offsets, so positions found in it index the original
+    let upper = text.to_ascii_uppercase();
+    if !upper.contains("<OFX>") {
+        return Err("Not an OFX file".to_string());
+    }
+
+    let mut parsed = ParsedFile::default();
+    let starts: Vec<usize> = upper.match_indices("<STMTTRN>").map(|(i, _)| i).collect();
+    for (n, &start) in starts.iter().enumerate() {
+        let next = starts.get(n + 1).copied().unwrap_or(text.len());
+        let end = upper[start..next]
+            .find("</STMTTRN>")
+            .map_or(next, |i| start + i);
+        let (block, block_upper) = (&text[start..end], &upper[start..end]);
+        let field = |tag: &str| ofx_field(block, block_upper, tag);
+        let line = text[..start].lines().count() + 1;
+        let skip = |reason: &str| SkippedRow {
+            line,
+            reason: reason.to_string(),
+        };
+
+        let Some(date) = field("DTPOSTED")
+            .and_then(|d| d.get(..8).map(str::to_string))
+            .and_then(|d| parse_date(&d, locale))
+        else {
+            parsed.skipped.push(skip("invalid date"));
+            continue;
+        };
+        // OFX amounts use a decimal point, though some banks write a comma
+        let Some(amount) = field("TRNAMT").and_then(|a| {
+            let decimal_comma = a.contains(',') && !a.contains('.');
+            parse_amount(
+                &a,
+                Locale {
+                    decimal_comma,
+                    ..locale
+                },
+            )
+        }) else {
+            parsed.skipped.push(skip("missing amount"));
+            continue;
+        };
+        let Some(description) = field("NAME").or_else(|| field("MEMO")) else {
+            parsed.skipped.push(skip("missing description"));
+            continue;
+        };
+        parsed.rows.push(ParsedRow {
+            line,
+            date,
+            // Debits are negative in OFX
+            amount: -amount,
+            description,
+            category: None,
+            reference: field("FITID"),
+        });
+    }
+    Ok(parsed)
+}
+
+// --- QIF ---
+
+/// Account types a QIF file can hold that are plain cash accounts.
+const QIF_CASH_TYPES: &[&str] = &["bank", "ccard", "cash", "oth a", "oth l"];
+
+fn parse_qif(text: &str, locale: Locale) -> Result<ParsedFile, String> {
+    let mut parsed = ParsedFile::default();
+    let mut fields: HashMap<char, String> = HashMap::new();
+    let mut record_line = 1;
+
+    for (i, raw_line) in text.lines().enumerate() {
+        let line = raw_line.trim_end();
+        if let Some(header) = line.strip_prefix('!') {
+            if let Some(kind) = header.strip_prefix("Type:") {
+                if !QIF_CASH_TYPES.contains(&kind.trim().to_lowercase().as_str()) {
+                    return Err(
+                        "Only bank, cash and credit card QIF files can be imported".to_string()
+                    );
+                }
+            }
+            continue;
+        }
+        if line.starts_with('^') {
+            if let Some(row) = qif_row(&fields, record_line, locale, &mut parsed.skipped) {
+                parsed.rows.push(row);
+            }
+            fields.clear();
+            continue;
+        }
+        let mut chars = line.chars();
+        let Some(code) = chars.next() else {
+            continue;
+        };
+        if fields.is_empty() {
+            record_line = i + 1;
+        }
+        // Split lines (`S`, `E`, `$`) repeat per part; only the first of a code is kept
+        fields
+            .entry(code)
+            .or_insert_with(|| chars.as_str().to_string());
+    }
+    // The last record may be missing its terminator
+    if !fields.is_empty() {
+        if let Some(row) = qif_row(&fields, record_line, locale, &mut parsed.skipped) {
+            parsed.rows.push(row);
+        }
+    }
+    Ok(parsed)
+}
+
+fn qif_row(
+    fields: &HashMap<char, String>,
+    line: usize,
+    locale: Locale,
+    skipped: &mut Vec<SkippedRow>,
+) -> Option<ParsedRow> {
+    let mut skip = |reason: &str| {
+        skipped.push(SkippedRow {
+            line,
+            reason: reason.to_string(),
+        });
+        None
+    };
+    // Quicken pads with spaces and marks 2000s years with an apostrophe: `1/ 5'24`
+    let date = fields
+        .get(&'D')
+        .map(|d| d.replace(' ', "").replace('\'', "/"));
+    let Some(date) = date.and_then(|d| parse_date(&d, locale)) else {
+        return skip("invalid date");
+    };
+    let amount = fields
+        .get(&'T')
+        .or_else(|| fields.get(&'U'))
+        .and_then(|a| parse_amount(a, locale));
+    let Some(amount) = amount else {
+        return skip("missing amount");
+    };
+    let description = fields
+        .get(&'P')
+        .or_else(|| fields.get(&'M'))
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    let Some(description) = description else {
+        return skip("missing description");
+    };
+    // `[Savings]` is a transfer to another account; `Food:Groceries` is a subcategory
+    let category = fields.get(&'L').and_then(|l| {
+        if l.starts_with('[') {
+            Some("transfer".to_string())
+        } else {
+            file_category(l.split(':').next().unwrap_or_default())
+        }
+    });
+    Some(ParsedRow {
+        line,
+        date,
+        // Payments are negative in QIF
+        amount: -amount,
+        description,
+        category,
+        reference: None,
+    })
+}
+
+// --- Duplicates ---
+
+/// Matches the same charge however it reached the app: the day, the amount to the cent and
+/// the merchant.
+fn fingerprint(date: &str, amount: f64, description: &str, merchant_id: Option<&str>) -> String {
+    let merchant = merchant_id
+        .map(str::to_string)
+        .or_else(|| normalize_merchant(description).map(|m| m.merchant_id))
+        .unwrap_or_else(|| description.trim().to_lowercase());
+    format!(
+        "{}|{:.2}|{}",
+        date.get(..10).unwrap_or(date),
+        amount,
+        merchant
+    )
+}
+
+/// Imported rows are keyed by the bank's id when the file has one, and otherwise by their
+/// contents; identical rows in one file get an occurrence number so they stay distinct while
+/// re-imports of the same file collapse onto them.
+fn import_ids(account: &str, rows: &[ParsedRow]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    rows.iter()
+        .map(|row| {
+            let key = match row.reference {
+                Some(ref reference) => format!("ref|{account}|{reference}"),
+                None => {
+                    let key = format!(
+                        "row|{account}|{}",
+                        fingerprint(&row.date, row.amount, &row.description, None)
+                    );
+                    let occurrence = seen.entry(key.clone()).or_insert(0);
+                    *occurrence += 1;
+                    format!("{key}|{occurrence}")
+                }
+            };
+            format!("import-{}", short_hash(&key))
+        })
+        .collect()
+}
+
+/// The fingerprints an existing transaction matches under: its description normalized the
+/// way imported rows are, and the merchant it was stored with, which for synced transactions
+/// comes from Plaid's merchant name and can differ from the bank's description.
+fn existing_fingerprints(transaction: &Transaction) -> Vec<String> {
+    let (date, amount, description) = (
+        &transaction.date,
+        transaction.amount,
+        &transaction.description,
+    );
+    let mut keys = vec![fingerprint(date, amount, description, None)];
+    if let Some(merchant_id) = transaction.merchant_id.as_deref() {
+        keys.push(fingerprint(date, amount, description, Some(merchant_id)));
+    }
+    keys.dedup();
+    keys
+}
+
+/// Which rows are already in the app: rows imported before, by id, and then rows whose
+/// fingerprint matches a transaction that came another way on the same account. A side
+/// without an account matches any. Each existing transaction accounts for at most one row.
+fn find_duplicates(
+    rows: &[ParsedRow],
+    ids: &[String],
+    account_id: Option<&str>,
+    existing: &[Transaction],
+) -> Vec<bool> {
+    let by_id: HashMap<&str, usize> = existing
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.transaction_id.as_str(), i))
+        .collect();
+    let mut by_fingerprint: HashMap<String, Vec<usize>> = HashMap::new();
+    let same_account = |transaction: &Transaction| match (&transaction.account_id, account_id) {
+        (Some(theirs), Some(ours)) => theirs == ours,
+        _ => true,
+    };
+    for (i, transaction) in existing.iter().enumerate().filter(|(_, t)| same_account(t)) {
+        for key in existing_fingerprints(transaction) {
+            by_fingerprint.entry(key).or_default().push(i);
+        }
+    }
+
+    let mut matched = vec![false; existing.len()];
+    let mut duplicate = vec![false; rows.len()];
+    for (dup, id) in duplicate.iter_mut().zip(ids) {
+        if let Some(&i) = by_id.get(id.as_str()) {
+            matched[i] = true;
+            *dup = true;
+        }
+    }
+    for (row, dup) in rows.iter().zip(duplicate.iter_mut()) {
+        if *dup {
+            continue;
+        }
+        let key = fingerprint(&row.date, row.amount, &row.description, None);
+        let candidates = by_fingerprint
+            .get(&key)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if let Some(&i) = candidates.iter().find(|&&i| !matched[i]) {
+            matched[i] = true;
+            *dup = true;
+        }
+    }
+    duplicate
+}
+
+// --- Saved Mappings ---
+
+async fn load_mapping(
+    state: &AppState,
+    user_id: &str,
+    name: &str,
+) -> Result<Option<(CsvColumnMap, Option<String>)>, aws_sdk_dynamodb::Error> {
+    let output = state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-import-mappings")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("name", AttributeValue::S(name.to_string()))
+        .send()
+        .await?;
+    Ok(output.item.map(|item| {
+        let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        let map = CsvColumnMap {
+            date: s("date"),
+            description: s("description"),
+            amount: s("amount"),
+            debit: s("debit"),
+            credit: s("credit"),
+            category: s("category"),
+            reference: s("reference"),
+            outflow_positive: item
+                .get("outflow_positive")
+                .and_then(|v| v.as_bool().ok())
+                .copied()
+                .unwrap_or(false),
+        };
+        (map, s("locale"))
+    }))
+}
+
+async fn save_mapping(
+    state: &AppState,
+    user_id: &str,
+    name: &str,
+    map: &CsvColumnMap,
+    locale: Option<&str>,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let mut put = state
+        .dynamo
+        .put_item()
+        .table_name("ovaflus-import-mappings")
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("name", AttributeValue::S(name.to_string()))
+        .item(
+            "outflow_positive",
+            AttributeValue::Bool(map.outflow_positive),
+        )
+        .item("updated_at", AttributeValue::S(Utc::now().to_rfc3339()));
+    for (field, value) in [
+        ("date", &map.date),
+        ("description", &map.description),
+        ("amount", &map.amount),
+        ("debit", &map.debit),
+        ("credit", &map.credit),
+        ("category", &map.category),
+        ("reference", &map.reference),
+    ] {
+        if let Some(value) = value {
+            put = put.item(field, AttributeValue::S(value.clone()));
+        }
+    }
+    if let Some(locale) = locale {
+        put = put.item("locale", AttributeValue::S(locale.to_string()));
+    }
+    put.send().await?;
+    Ok(())
+}
+
+// --- Import ---
+
+/// What categorizes imported rows the way synced ones are: the user's rules, the merchant
+/// directory, the learned model and their budgets.
+struct ImportContext {
+    rules: RuleSet,
+    model: CategoryModel,
+    merchants: HashMap<String, Merchant>,
+    budgets: Vec<Budget>,
+}
+
+impl ImportContext {
+    async fn load(
+        state: &AppState,
+        user_id: &str,
+        rows: &[ParsedRow],
+    ) -> Result<Self, aws_sdk_dynamodb::Error> {
+        let mut merchants = user_merchants(state, user_id).await?;
+        let unknown: BTreeSet<String> = rows
+            .iter()
+            .filter_map(|row| normalize_merchant(&row.description))
+            .map(|m| m.merchant_id)
+            .filter(|id| !merchants.contains_key(id))
+            .collect();
+        for (id, merchant) in global_merchants(state, &unknown).await? {
+            merchants.entry(id).or_insert(merchant);
+        }
+        Ok(ImportContext {
+            rules: load_rules(state, user_id).await?,
+            model: user_model(state, user_id).await?,
+            merchants,
+            budgets: load_budgets(state, user_id).await?,
+        })
+    }
+
+    /// The transaction a row becomes. A rule's category wins, then one named in the file, the
+    /// merchant's default and a confident suggestion.
+    fn transaction(
+        &self,
+        row: &ParsedRow,
+        transaction_id: String,
+        user_id: &str,
+        account_id: Option<&str>,
+        now: &str,
+    ) -> Transaction {
+        let normalized = normalize_merchant(&row.description);
+        let entry = normalized
+            .as_ref()
+            .and_then(|m| self.merchants.get(&m.merchant_id));
+        let name = entry
+            .map(|m| m.display_name.clone())
+            .or_else(|| normalized.as_ref().map(|m| m.display_name.clone()));
+
+        let outcome = self.rules.apply(&RuleInput {
+            description: &row.description,
+            merchant: name.as_deref(),
+            amount: row.amount,
+            account_id,
+        });
+        let merchant = outcome.merchant.or(name);
+        let category = outcome
+            .category
+            .or_else(|| row.category.clone())
+            .or_else(|| entry.and_then(|m| m.default_category.clone()))
+            .or_else(|| {
+                self.model
+                    .suggest_for(&row.description, merchant.as_deref(), row.amount)
+                    .into_iter()
+                    .next()
+                    .filter(|s| s.confidence >= AUTO_CATEGORY_CONFIDENCE)
+                    .map(|s| s.category)
+            })
+            .unwrap_or_else(|| FALLBACK_CATEGORY.to_string());
+        let budget_id = outcome
+            .budget_id
+            .or_else(|| match_budget(&category, &self.budgets).map(|b| b.budget_id.clone()))
+            .unwrap_or_default();
+
+        Transaction {
+            transaction_id,
+            user_id: user_id.to_string(),
+            budget_id,
+            amount: row.amount,
+            description: row.description.clone(),
+            transaction_type: transaction_type(&category, row.amount).to_string(),
+            category,
+            date: row.date.clone(),
+            account_id: account_id.map(str::to_string),
+            plaid_transaction_id: None,
+            recurring_schedule_id: None,
+            merchant_id: normalized.map(|m| m.merchant_id),
+            merchant,
+            tags: outcome.tags,
+            splits: Vec::new(),
+            created_at: now.to_string(),
+            updated_at: now.to_string(),
+        }
+    }
+}
+
+fn imported_item(transaction: &Transaction) -> HashMap<String, AttributeValue> {
+    let s = |value: &str| AttributeValue::S(value.to_string());
+    let mut item = HashMap::from([
+        ("user_id".to_string(), s(&transaction.user_id)),
+        ("transaction_id".to_string(), s(&transaction.transaction_id)),
+        (
+            "amount".to_string(),
+            AttributeValue::N(transaction.amount.to_string()),
+        ),
+        ("description".to_string(), s(&transaction.description)),
+        ("category".to_string(), s(&transaction.category)),
+        ("date".to_string(), s(&transaction.date)),
+        (
+            ATTR_DATE_KEY.to_string(),
+            s(&date_key(&transaction.date, &transaction.transaction_id)),
+        ),
+        ("created_at".to_string(), s(&transaction.created_at)),
+        ("updated_at".to_string(), s(&transaction.updated_at)),
+    ]);
+    if !transaction.budget_id.is_empty() {
+        item.insert("budget_id".to_string(), s(&transaction.budget_id));
+        item.insert(
+            ATTR_BUDGET_KEY.to_string(),
+            s(&budget_key(&transaction.user_id, &transaction.budget_id)),
+        );
+    }
+    for (name, value) in [
+        ("account_id", &transaction.account_id),
+        ("merchant_id", &transaction.merchant_id),
+        ("merchant", &transaction.merchant),
+    ] {
+        if let Some(value) = value {
+            item.insert(name.to_string(), s(value));
+        }
+    }
+    if !transaction.tags.is_empty() {
+        item.insert(
+            "tags".to_string(),
+            AttributeValue::Ss(transaction.tags.clone()),
+        );
+    }
+    item
+}
+
+/// Write the new transactions a batch at a time, then move each budget's spent once by the
+/// total written to it. Returns the ids written and what stopped the writes early, if
+/// anything. Batch writes can't be conditional, so the same file imported twice at the same
+/// moment is counted twice; imported again afterwards, its rows are found as duplicates.
+async fn write_imported(
+    state: &AppState,
+    user_id: &str,
+    transactions: &[&Transaction],
+) -> (HashSet<String>, Option<String>) {
+    let mut written = HashSet::new();
+    let mut spent: BTreeMap<String, f64> = BTreeMap::new();
+    let mut failure = None;
+    for chunk in transactions.chunks(WRITE_BATCH_ROWS) {
+        let requests = chunk
+            .iter()
+            .map(|t| put_request(imported_item(t)))
+            .collect();
+        let unprocessed =
+            match batch_write_unprocessed(&state.dynamo, "ovaflus-transactions", requests).await {
+                Ok(unprocessed) => unprocessed,
+                Err(e) => {
+                    failure = Some(e.to_string());
+                    break;
+                }
+            };
+        let left: HashSet<&str> = unprocessed
+            .iter()
+            .filter_map(|request| request.put_request()?.item().get("transaction_id"))
+            .filter_map(|id| id.as_s().ok().map(String::as_str))
+            .collect();
+        for transaction in chunk {
+            if left.contains(transaction.transaction_id.as_str()) {
+                continue;
+            }
+            written.insert(transaction.transaction_id.clone());
+            let allocations = budget_allocations(
+                Some(transaction.budget_id.as_str()),
+                transaction.amount,
+                &[],
+                false,
+            );
+            for (budget_id, amount) in allocations {
+                *spent.entry(budget_id).or_insert(0.0) += amount;
+            }
+        }
+        if !left.is_empty() {
+            failure = Some(format!("{} rows were throttled", left.len()));
+            break;
+        }
+    }
+    if let Err(e) = apply_spent_changes(state, user_id, &BTreeMap::new(), &spent).await {
+        failure.get_or_insert(format!("updating budgets failed: {}", e));
+    }
+    (written, failure)
+}
+
+#[derive(Serialize)]
+pub struct TransactionPreview {
+    /// "create" or "duplicate"
+    pub status: String,
+    pub line: usize,
+    pub transaction: Transaction,
+}
+
+#[derive(Serialize)]
+pub struct TransactionImportResult {
+    pub format: String,
+    pub dry_run: bool,
+    pub committed: bool,
+    pub created: usize,
+    pub duplicates: usize,
+    pub transactions: Vec<TransactionPreview>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+fn bad_request(message: impl Into<String>) -> axum::response::Response {
+    (StatusCode::BAD_REQUEST, Json(ApiError::new(message))).into_response()
+}
+
+fn database_error(e: aws_sdk_dynamodb::Error) -> axum::response::Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ApiError::new(format!("Database error: {}", e))),
+    )
+        .into_response()
+}
+
+pub async fn import_transactions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(body): Json<ImportTransactionsRequest>,
+) -> impl IntoResponse {
+    let format = match body.format.as_deref().map(str::to_lowercase) {
+        Some(f) if matches!(f.as_str(), "csv" | "ofx" | "qif") => f,
+        Some(f) if f == "qfx" => "ofx".to_string(),
+        Some(_) => return bad_request("format must be one of csv, ofx, qfx, qif"),
+        None => detect_format(&body.content).to_string(),
+    };
+    let mapping_name = body
+        .mapping
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .unwrap_or(DEFAULT_MAPPING)
+        .to_string();
+
+    // A CSV without a column map uses the saved one, with the locale saved alongside it
+    let mut locale_tag = body.locale.clone();
+    let column_map = match (format.as_str(), body.column_map.clone()) {
+        ("csv", None) => match load_mapping(&state, &claims.sub, &mapping_name).await {
+            Ok(Some((map, saved_locale))) => {
+                locale_tag = locale_tag.or(saved_locale);
+                map
+            }
+            Ok(None) => CsvColumnMap::default(),
+            Err(e) => return database_error(e),
+        },
+        (_, map) => map.unwrap_or_default(),
+    };
+    let locale = match locale_tag.as_deref().map(parse_locale).transpose() {
+        Ok(locale) => locale.unwrap_or_default(),
+        Err(e) => return bad_request(e),
+    };
+
+    let parsed = match format.as_str() {
+        "ofx" => parse_ofx(&body.content, locale),
+        "qif" => parse_qif(&body.content, locale),
+        _ => parse_csv(&body.content, &column_map, locale),
+    };
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => return bad_request(e),
+    };
+    if parsed.rows.len() > MAX_IMPORT_ROWS {
+        return bad_request(format!(
+            "Imports are limited to {} rows; split the file",
+            MAX_IMPORT_ROWS
+        ));
+    }
+
+    let account_id = body
+        .account_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|a| !a.is_empty());
+    let ids = import_ids(account_id.unwrap_or("manual"), &parsed.rows);
+    let earliest = parsed
+        .rows
+        .iter()
+        .filter_map(|row| NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").ok())
+        .min();
+    let existing = match earliest {
+        Some(from) => match transactions_since(&state, &claims.sub, Some(from)).await {
+            Ok(existing) => existing,
+            Err(e) => return database_error(e),
+        },
+        None => Vec::new(),
+    };
+    let duplicates = find_duplicates(&parsed.rows, &ids, account_id, &existing);
+    let ctx = match ImportContext::load(&state, &claims.sub, &parsed.rows).await {
+        Ok(ctx) => ctx,
+        Err(e) => return database_error(e),
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let previews: Vec<TransactionPreview> = parsed
+        .rows
+        .iter()
+        .zip(ids)
+        .zip(&duplicates)
+        .map(|((row, id), duplicate)| TransactionPreview {
+            status: if *duplicate { "duplicate" } else { "create" }.to_string(),
+            line: row.line,
+            transaction: ctx.transaction(row, id, &claims.sub, account_id, &now),
+        })
+        .collect();
+
+    let mut committed = false;
+    if !body.dry_run {
+        let creates: Vec<&Transaction> = previews
+            .iter()
+            .filter(|p| p.status == "create")
+            .map(|p| &p.transaction)
+            .collect();
+        let (written, failure) = write_imported(&state, &claims.sub, &creates).await;
+        if let Some(e) = failure {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!(
+                    "Imported {} transactions, then failed: {}; importing the file again \
+                     skips the ones already written",
+                    written.len(),
+                    e
+                ))),
+            )
+                .into_response();
+        }
+        if body.save_mapping && format == "csv" {
+            let saved = save_mapping(
+                &state,
+                &claims.sub,
+                &mapping_name,
+                &column_map,
+                locale_tag.as_deref(),
+            )
+            .await;
+            if let Err(e) = saved {
+                tracing::error!("Saving the import mapping failed: {e}");
+            }
+        }
+        committed = true;
+    }
+
+    let created = previews.iter().filter(|p| p.status == "create").count();
+    let status = if committed {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    (
+        status,
+        Json(TransactionImportResult {
+            format,
+            dry_run: body.dry_run,
+            committed,
+            created,
+            duplicates: previews.len() - created,
+            transactions: previews,
+            skipped: parsed.skipped,
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GERMAN_CSV: &str = "\
+Kontonummer;DE12 3456 7890
+Zeitraum;01.05.2024 - 31.05.2024
+
+Buchungstag;Verwendungszweck;Betrag;Kategorie
+02.05.2024;SQ *BLUE BOTTLE 0423;-6,50 €;
+03.05.2024;Gehalt Mai;\"3.250,00 €\";income
+04.05.2024;REWE Markt;abc;
+";
+
+    const OFX: &str = "\
+OFXHEADER:100
+DATA:OFXSGML
+
+<OFX>
+<BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20240105120000[-5:EST]
+<TRNAMT>-42.10
+<FITID>202401050001
+<NAME>WHOLE FOODS MKT #10233
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>CREDIT
+<DTPOSTED>20240106
+<TRNAMT>1500.00
+<FITID>202401060002
+<NAME>ACME CORP PAYROLL &amp; CO
+<MEMO>Salary
+</STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+    const QIF: &str = "\
+!Type:Bank
+D1/ 5'24
+T-42.10
+PWhole Foods
+LGroceries
+^
+D01/07/2024
+T-500.00
+PTransfer to savings
+L[Savings]
+^
+D1/8/24
+U12.00
+MInterest
+";
+
+    fn row(date: &str, amount: f64, description: &str) -> ParsedRow {
+        ParsedRow {
+            line: 1,
+            date: date.to_string(),
+            amount,
+            description: description.to_string(),
+            category: None,
+            reference: None,
+        }
+    }
+
+    #[test]
+    fn amounts_and_dates_follow_the_locale() {
+        let us = parse_locale("en-US").unwrap();
+        let de = parse_locale("de-DE").unwrap();
+        assert_eq!(parse_locale("en").unwrap(), us);
+        assert!(parse_locale("en-GB").unwrap().day_first);
+        assert!(parse_locale("english").is_err());
+
+        assert_eq!(parse_amount("$1,234.50", us), Some(1234.5));
+        assert_eq!(parse_amount("(12.00)", us), Some(-12.0));
+        assert_eq!(parse_amount("12.00-", us), Some(-12.0));
+        assert_eq!(parse_amount("-1.234,56 €", de), Some(-1234.56));
+        assert_eq!(parse_amount("1\u{a0}234,56", de), Some(1234.56));
+        assert_eq!(parse_amount("EUR 7,5", de), Some(7.5));
+        assert_eq!(parse_amount("n/a", us), None);
+
+        assert_eq!(parse_date("05/03/2024", us).as_deref(), Some("2024-05-03"));
+        assert_eq!(parse_date("05/03/2024", de).as_deref(), Some("2024-03-05"));
+        assert_eq!(parse_date("05.03.24", de).as_deref(), Some("2024-03-05"));
+        assert_eq!(parse_date("1/5/24", us).as_deref(), Some("2024-01-05"));
+        assert_eq!(
+            parse_date("2024-03-05 10:15", de).as_deref(),
+            Some("2024-03-05")
+        );
+        assert_eq!(parse_date("5 Mar 2024", us).as_deref(), Some("2024-03-05"));
+        assert_eq!(parse_date("13/13/2024", us), None);
+    }
+
+    #[test]
+    fn csv_exports_are_read_with_presets_or_a_column_map() {
+        let map = CsvColumnMap {
+            category: Some("Kategorie".to_string()),
+            ..Default::default()
+        };
+        let parsed = parse_csv(GERMAN_CSV, &map, parse_locale("de-DE").unwrap()).unwrap();
+        assert_eq!(parsed.rows.len(), 2);
+        assert_eq!(parsed.rows[0].date, "2024-05-02");
+        assert_eq!(parsed.rows[0].amount, 6.5);
+        assert_eq!(parsed.rows[1].amount, -3250.0);
+        assert_eq!(parsed.rows[1].category.as_deref(), Some("income"));
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].reason, "missing amount");
+
+        let split = "Date,Payee,Money Out,Money In,Reference\n\
+                     01/05/2024,Shell,48.00,,T-1\n\
+                     01/06/2024,Refund,,12.50,T-2\n";
+        let parsed = parse_csv(split, &CsvColumnMap::default(), Locale::default()).unwrap();
+        let amounts: Vec<f64> = parsed.rows.iter().map(|r| r.amount).collect();
+        assert_eq!(amounts, [48.0, -12.5]);
+        assert_eq!(parsed.rows[1].reference.as_deref(), Some("T-2"));
+    }
+
+    #[test]
+    fn ofx_and_qif_files_become_rows() {
+        assert_eq!(detect_format(OFX), "ofx");
+        let ofx = parse_ofx(OFX, Locale::default()).unwrap();
+        assert_eq!(ofx.rows.len(), 2);
+        assert_eq!(ofx.rows[0].date, "2024-01-05");
+        assert_eq!(ofx.rows[0].amount, 42.1);
+        assert_eq!(ofx.rows[0].reference.as_deref(), Some("202401050001"));
+        assert_eq!(ofx.rows[1].amount, -1500.0);
+        assert_eq!(ofx.rows[1].description, "ACME CORP PAYROLL & CO");
+
+        assert_eq!(detect_format(QIF), "qif");
+        let qif = parse_qif(QIF, Locale::default()).unwrap();
+        assert_eq!(qif.rows.len(), 3);
+        assert_eq!(qif.rows[0].date, "2024-01-05");
+        assert_eq!(qif.rows[0].amount, 42.1);
+        assert_eq!(qif.rows[0].category, None, "not an app category");
+        assert_eq!(qif.rows[1].category.as_deref(), Some("transfer"));
+        assert_eq!(qif.rows[2].description, "Interest");
+        assert_eq!(qif.rows[2].amount, -12.0);
+        assert!(parse_qif("!Type:Invst\nD1/5/24\n^\n", Locale::default()).is_err());
+    }
+
+    #[test]
+    fn rows_already_in_the_app_are_duplicates() {
+        let rows = vec![
+            row("2024-05-02", 6.5, "SQ *BLUE BOTTLE 0423"),
+            row("2024-05-02", 6.5, "SQ *BLUE BOTTLE 0423"),
+            row("2024-05-03", 48.0, "Shell"),
+            row("2024-05-04", 12.0, "Netflix"),
+        ];
+        let ids = import_ids("acc-1", &rows);
+        assert_ne!(ids[0], ids[1], "identical rows in one file stay distinct");
+        assert_eq!(ids, import_ids("acc-1", &rows));
+
+        let existing_row = |id: &str, date: &str, amount: f64, description: &str| Transaction {
+            transaction_id: id.to_string(),
+            user_id: "u1".to_string(),
+            amount,
+            description: description.to_string(),
+            category: "dining".to_string(),
+            transaction_type: "expense".to_string(),
+            date: date.to_string(),
+            ..Default::default()
+        };
+        let existing = [
+            // One of the two coffees came in through Plaid under a cleaner name
+            existing_row("plaid-1", "2024-05-02", 6.5, "Blue Bottle"),
+            // The Shell row was imported before
+            existing_row(&ids[2], "2024-05-03", 48.0, "Shell"),
+            existing_row("manual-1", "2024-05-04", 12.0, "Netflix"),
+        ];
+        assert_eq!(
+            find_duplicates(&rows, &ids, Some("acc-1"), &existing),
+            [true, false, true, true]
+        );
+
+        // Synced from Plaid, keyed by Plaid's merchant name rather than the bank's description
+        let synced = Transaction {
+            merchant_id: Some("blue-bottle-coffee".to_string()),
+            merchant: Some("Blue Bottle Coffee".to_string()),
+            ..existing_row("plaid-2", "2024-05-02", 6.5, "SQ *BLUE BOTTLE 0423 SF")
+        };
+        assert_eq!(
+            find_duplicates(&rows, &ids, Some("acc-1"), std::slice::from_ref(&synced)),
+            [true, false, false, false]
+        );
+        // A file that names the merchant the way Plaid does matches on the stored merchant
+        let named = [row("2024-05-02", 6.5, "BLUE BOTTLE COFFEE #12")];
+        let named_ids = import_ids("acc-1", &named);
+        assert_eq!(
+            find_duplicates(
+                &named,
+                &named_ids,
+                Some("acc-1"),
+                std::slice::from_ref(&synced)
+            ),
+            [true]
+        );
+
+        // The same charge on another account is a different transaction
+        let other_account = Transaction {
+            account_id: Some("acc-2".to_string()),
+            ..synced
+        };
+        assert_eq!(
+            find_duplicates(&named, &named_ids, Some("acc-1"), &[other_account]),
+            [false]
+        );
+    }
+}
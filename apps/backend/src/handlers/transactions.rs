@@ -1,5 +1,7 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 
+use aws_sdk_dynamodb::operation::query::builders::QueryFluentBuilder;
 use aws_sdk_dynamodb::types::AttributeValue;
 use axum::{
     extract::{Path, Query, State},
@@ -7,15 +9,30 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use chrono::Utc;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::db::dynamo::{
+    batch_get, batch_write, delete_request, is_conditional_check_failure, put_request,
+};
+use crate::db::transaction_keys::{
+    budget_key, date_key, date_key_bounds, date_key_transaction_id, is_split_index_key,
+    split_index_id, split_index_pk, split_index_row, ATTR_BUDGET_KEY, ATTR_DATE_KEY, ATTR_SPLIT_OF,
+    BUDGET_DATE_INDEX, USER_DATE_INDEX,
+};
+use crate::handlers::categories::FALLBACK_CATEGORY;
+use crate::handlers::category_model::{
+    learn_category, user_model, CategoryModel, AUTO_CATEGORY_CONFIDENCE,
+};
+use crate::handlers::merchants::{normalize_merchant, resolve_merchant};
+use crate::handlers::rules::{load_rules, RuleInput, RuleOutcome};
 use crate::middleware::auth::AuthUser;
 use crate::models::ApiError;
 use crate::AppState;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Transaction {
     pub transaction_id: String,
     pub user_id: String,
@@ -23,22 +40,59 @@ pub struct Transaction {
     pub amount: f64,
     pub description: String,
     pub category: String,
+    /// `expense`, `income` or `transfer`, derived from the category and the amount's sign
+    #[serde(rename = "type")]
+    pub transaction_type: String,
     pub date: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub plaid_transaction_id: Option<String>,
+    /// The recurring schedule that created the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recurring_schedule_id: Option<String>,
+    /// Normalized merchant, the key of the merchant directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant_id: Option<String>,
+    /// Display name of the merchant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Parts of the amount with their own category and budget; empty when not split
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<TransactionSplit>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TransactionSplit {
+    pub amount: f64,
+    pub category: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_id: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct CreateTransactionRequest {
+    /// May be left out when every split names its own budget
+    #[serde(default)]
     pub budget_id: String,
     pub amount: f64,
     pub description: String,
+    /// Left empty, it comes from the user's rules
+    #[serde(default)]
     pub category: String,
     pub date: String,
+    #[serde(default)]
+    pub splits: Vec<TransactionSplit>,
 }
 
+/// String set naming the fields the user edited by hand, which a Plaid sync leaves alone:
+/// "description" (with the merchant taken from it) and "category" (with its budget).
+pub(crate) const ATTR_USER_EDITED: &str = "user_edited";
+
 #[derive(Deserialize)]
 pub struct UpdateTransactionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -49,14 +103,57 @@ pub struct UpdateTransactionRequest {
     pub category: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub date: Option<String>,
+    /// Replaces the splits; an empty list turns the transaction back into a single one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub splits: Option<Vec<TransactionSplit>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct ListTransactionsQuery {
     pub budget_id: Option<String>,
+    /// First day to include, `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Last day to include, `YYYY-MM-DD`
+    pub to: Option<String>,
+    pub category: Option<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    /// Case-insensitive text to find in the merchant or description
+    pub q: Option<String>,
+    #[serde(rename = "type")]
+    pub transaction_type: Option<String>,
+    pub account_id: Option<String>,
+    pub merchant_id: Option<String>,
+    /// `date_desc` (default), `date_asc`, `amount_desc` or `amount_asc`
+    pub sort: Option<String>,
+    /// Page size; without it every match is returned
+    pub limit: Option<usize>,
+    /// The `x-next-cursor` header of the previous page
+    pub cursor: Option<String>,
+}
+
+/// `expense`, `income` or `transfer`. Amounts follow Plaid's sign convention, where money
+/// leaving the account is positive.
+pub(crate) fn transaction_type(category: &str, amount: f64) -> &'static str {
+    match category {
+        "transfer" => "transfer",
+        "income" => "income",
+        _ if amount < 0.0 => "income",
+        _ => "expense",
+    }
 }
 
-fn item_to_transaction(item: &std::collections::HashMap<String, AttributeValue>) -> Transaction {
+fn item_to_transaction(item: &HashMap<String, AttributeValue>) -> Transaction {
+    let amount = item
+        .get("amount")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let category = item
+        .get("category")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
     Transaction {
         transaction_id: item
             .get("transaction_id")
@@ -73,30 +170,36 @@ fn item_to_transaction(item: &std::collections::HashMap<String, AttributeValue>)
             .and_then(|v| v.as_s().ok())
             .cloned()
             .unwrap_or_default(),
-        amount: item
-            .get("amount")
-            .and_then(|v| v.as_n().ok())
-            .and_then(|n| n.parse::<f64>().ok())
-            .unwrap_or(0.0),
+        amount,
         description: item
             .get("description")
             .and_then(|v| v.as_s().ok())
             .cloned()
             .unwrap_or_default(),
-        category: item
-            .get("category")
-            .and_then(|v| v.as_s().ok())
-            .cloned()
-            .unwrap_or_default(),
+        transaction_type: transaction_type(&category, amount).to_string(),
+        category,
         date: item
             .get("date")
             .and_then(|v| v.as_s().ok())
             .cloned()
             .unwrap_or_default(),
+        account_id: item.get("account_id").and_then(|v| v.as_s().ok()).cloned(),
         plaid_transaction_id: item
             .get("plaid_transaction_id")
             .and_then(|v| v.as_s().ok())
             .cloned(),
+        recurring_schedule_id: item
+            .get("recurring_schedule_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned(),
+        merchant_id: item.get("merchant_id").and_then(|v| v.as_s().ok()).cloned(),
+        merchant: item.get("merchant").and_then(|v| v.as_s().ok()).cloned(),
+        tags: item
+            .get("tags")
+            .and_then(|v| v.as_ss().ok())
+            .cloned()
+            .unwrap_or_default(),
+        splits: item_splits(item),
         created_at: item
             .get("created_at")
             .and_then(|v| v.as_s().ok())
@@ -110,81 +213,1152 @@ fn item_to_transaction(item: &std::collections::HashMap<String, AttributeValue>)
     }
 }
 
-pub async fn list_transactions(
-    State(state): State<Arc<AppState>>,
-    AuthUser(claims): AuthUser,
-    Query(params): Query<ListTransactionsQuery>,
-) -> impl IntoResponse {
-    let result = if let Some(ref budget_id) = params.budget_id {
-        state
-            .dynamo
-            .query()
-            .table_name("ovaflus-transactions")
-            .index_name("budget-index")
-            .key_condition_expression("user_id = :uid AND budget_id = :bid")
-            .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
-            .expression_attribute_values(":bid", AttributeValue::S(budget_id.to_string()))
-            .send()
-            .await
-    } else {
-        state
+// --- Splits ---
+
+/// Most parts a transaction can be split into.
+const MAX_SPLITS: usize = 20;
+/// Splits have to add up to the transaction's amount to the cent.
+const SPLIT_TOLERANCE: f64 = 0.005;
+
+pub(crate) fn splits_add_up(amount: f64, splits: &[TransactionSplit]) -> bool {
+    let total: f64 = splits.iter().map(|s| s.amount).sum();
+    (total - amount).abs() < SPLIT_TOLERANCE
+}
+
+/// Trim requested splits and check they add up to `amount`. No splits means the transaction
+/// isn't split.
+fn validate_splits(
+    amount: f64,
+    splits: Vec<TransactionSplit>,
+) -> Result<Vec<TransactionSplit>, String> {
+    if splits.is_empty() {
+        return Ok(splits);
+    }
+    if splits.len() == 1 {
+        return Err("A split transaction needs at least two splits".to_string());
+    }
+    if splits.len() > MAX_SPLITS {
+        return Err(format!(
+            "A transaction can be split into at most {} parts",
+            MAX_SPLITS
+        ));
+    }
+
+    let mut validated = Vec::with_capacity(splits.len());
+    for split in splits {
+        let category = split.category.trim().to_string();
+        if category.is_empty() {
+            return Err("Every split needs a category".to_string());
+        }
+        if !split.amount.is_finite() || split.amount == 0.0 {
+            return Err("Split amounts must be non-zero".to_string());
+        }
+        validated.push(TransactionSplit {
+            amount: split.amount,
+            category,
+            budget_id: non_empty(&split.budget_id),
+        });
+    }
+    if !splits_add_up(amount, &validated) {
+        let total: f64 = validated.iter().map(|s| s.amount).sum();
+        return Err(format!(
+            "Splits add up to {:.2} but the transaction is {:.2}",
+            total, amount
+        ));
+    }
+    Ok(validated)
+}
+
+fn splits_to_attribute(splits: &[TransactionSplit]) -> AttributeValue {
+    AttributeValue::L(
+        splits
+            .iter()
+            .map(|split| {
+                let mut map = HashMap::from([
+                    (
+                        "amount".to_string(),
+                        AttributeValue::N(split.amount.to_string()),
+                    ),
+                    (
+                        "category".to_string(),
+                        AttributeValue::S(split.category.clone()),
+                    ),
+                ]);
+                if let Some(ref budget_id) = split.budget_id {
+                    map.insert(
+                        "budget_id".to_string(),
+                        AttributeValue::S(budget_id.clone()),
+                    );
+                }
+                AttributeValue::M(map)
+            })
+            .collect(),
+    )
+}
+
+/// The split categories as a string set, so category filters can match any split.
+fn split_categories_attribute(splits: &[TransactionSplit]) -> AttributeValue {
+    let mut categories: Vec<String> = splits.iter().map(|s| s.category.clone()).collect();
+    categories.sort();
+    categories.dedup();
+    AttributeValue::Ss(categories)
+}
+
+pub(crate) fn item_splits(item: &HashMap<String, AttributeValue>) -> Vec<TransactionSplit> {
+    let Some(list) = item.get("splits").and_then(|v| v.as_l().ok()) else {
+        return Vec::new();
+    };
+    list.iter()
+        .filter_map(|v| v.as_m().ok())
+        .map(|map| TransactionSplit {
+            amount: map
+                .get("amount")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse::<f64>().ok())
+                .unwrap_or(0.0),
+            category: map
+                .get("category")
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .unwrap_or_default(),
+            budget_id: map.get("budget_id").and_then(|v| v.as_s().ok()).cloned(),
+        })
+        .collect()
+}
+
+/// Drop splits that no longer add up after the bank changed a synced transaction's amount.
+pub(crate) async fn clear_splits(
+    state: &AppState,
+    user_id: &str,
+    transaction_id: &str,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-transactions")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key(
+            "transaction_id",
+            AttributeValue::S(transaction_id.to_string()),
+        )
+        .update_expression("REMOVE splits, split_categories, split_budgets")
+        .send()
+        .await?;
+    Ok(())
+}
+
+// --- Split Index ---
+
+/// The budgets a transaction's splits count towards, each with the date its split-index row
+/// sorts under.
+pub(crate) fn split_index_entries(
+    date: &str,
+    splits: &[TransactionSplit],
+) -> BTreeSet<(String, String)> {
+    splits
+        .iter()
+        .filter_map(|s| s.budget_id.clone())
+        .map(|budget_id| (budget_id, date.to_string()))
+        .collect()
+}
+
+/// The split-index entries a stored transaction has.
+pub(crate) fn item_split_index(
+    item: &HashMap<String, AttributeValue>,
+) -> BTreeSet<(String, String)> {
+    let date = item
+        .get("date")
+        .and_then(|v| v.as_s().ok())
+        .map(String::as_str)
+        .unwrap_or_default();
+    split_index_entries(date, &item_splits(item))
+}
+
+/// Move a transaction's split-index rows from the `old` entries to the `new` ones. Rows of
+/// budgets that are no longer split into are deleted; the rest are written over.
+pub(crate) async fn update_split_index(
+    state: &AppState,
+    user_id: &str,
+    transaction_id: &str,
+    old: &BTreeSet<(String, String)>,
+    new: &BTreeSet<(String, String)>,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let kept: HashSet<&str> = new
+        .iter()
+        .map(|(budget_id, _)| budget_id.as_str())
+        .collect();
+    let deletes = old
+        .iter()
+        .filter(|(budget_id, _)| !kept.contains(budget_id.as_str()))
+        .map(|(budget_id, _)| {
+            delete_request(HashMap::from([
+                (
+                    "user_id".to_string(),
+                    AttributeValue::S(split_index_pk(user_id)),
+                ),
+                (
+                    "transaction_id".to_string(),
+                    AttributeValue::S(split_index_id(transaction_id, budget_id)),
+                ),
+            ]))
+        });
+    let puts = new.difference(old).map(|(budget_id, date)| {
+        put_request(split_index_row(user_id, transaction_id, budget_id, date))
+    });
+    let requests: Vec<_> = deletes.chain(puts).collect();
+    if requests.is_empty() {
+        return Ok(());
+    }
+    let unprocessed = batch_write(&state.dynamo, "ovaflus-transactions", requests).await?;
+    if unprocessed > 0 {
+        tracing::warn!("{unprocessed} split-index rows of {transaction_id} were not written");
+    }
+    Ok(())
+}
+
+// --- Budget Spent ---
+
+/// How much of a transaction counts towards each budget's `spent`: every split towards its
+/// own budget, or the whole amount towards the transaction's budget. Pending transactions
+/// count once they post.
+pub(crate) fn budget_allocations(
+    budget_id: Option<&str>,
+    amount: f64,
+    splits: &[TransactionSplit],
+    pending: bool,
+) -> BTreeMap<String, f64> {
+    let mut allocations = BTreeMap::new();
+    if pending {
+        return allocations;
+    }
+    if splits.is_empty() {
+        if let Some(budget_id) = budget_id.filter(|id| !id.is_empty()) {
+            allocations.insert(budget_id.to_string(), amount);
+        }
+    }
+    for split in splits {
+        if let Some(ref budget_id) = split.budget_id {
+            *allocations.entry(budget_id.clone()).or_insert(0.0) += split.amount;
+        }
+    }
+    allocations
+}
+
+/// The budget allocations of a stored transaction row.
+pub(crate) fn counted_allocations(item: &HashMap<String, AttributeValue>) -> BTreeMap<String, f64> {
+    let pending = item
+        .get("pending")
+        .and_then(|v| v.as_bool().ok())
+        .copied()
+        .unwrap_or(false);
+    let Some(amount) = item
+        .get("amount")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<f64>().ok())
+    else {
+        return BTreeMap::new();
+    };
+    let budget_id = item.get("budget_id").and_then(|v| v.as_s().ok());
+    budget_allocations(
+        budget_id.map(String::as_str),
+        amount,
+        &item_splits(item),
+        pending,
+    )
+}
+
+/// The change to each budget's `spent` when a transaction's allocations go from `previous`
+/// to `current`; budgets left as they were are omitted.
+fn spent_changes(
+    previous: &BTreeMap<String, f64>,
+    current: &BTreeMap<String, f64>,
+) -> Vec<(String, f64)> {
+    let mut changes = current.clone();
+    for (budget_id, amount) in previous {
+        *changes.entry(budget_id.clone()).or_insert(0.0) -= amount;
+    }
+    changes
+        .into_iter()
+        .filter(|(_, delta)| *delta != 0.0)
+        .collect()
+}
+
+async fn adjust_budget_spent(
+    state: &AppState,
+    user_id: &str,
+    budget_id: &str,
+    delta: f64,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let result = state
+        .dynamo
+        .update_item()
+        .table_name("ovaflus-budgets")
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .key("budget_id", AttributeValue::S(budget_id.to_string()))
+        .update_expression("SET spent = spent + :amount")
+        .condition_expression("attribute_exists(budget_id)")
+        .expression_attribute_values(":amount", AttributeValue::N(delta.to_string()))
+        .send()
+        .await;
+
+    match result {
+        // The budget was deleted after the transaction was assigned to it
+        Err(e) if is_conditional_check_failure(&e) => Ok(()),
+        other => other.map(|_| ()).map_err(Into::into),
+    }
+}
+
+/// Move a transaction's amounts between budgets after its allocations changed.
+pub(crate) async fn apply_spent_changes(
+    state: &AppState,
+    user_id: &str,
+    previous: &BTreeMap<String, f64>,
+    current: &BTreeMap<String, f64>,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    for (budget_id, delta) in spent_changes(previous, current) {
+        adjust_budget_spent(state, user_id, &budget_id, delta).await?;
+    }
+    Ok(())
+}
+
+// --- Querying ---
+
+const MAX_PAGE_SIZE: usize = 500;
+
+/// A filter expression with its attribute names and values.
+type FilterExpression = (
+    String,
+    HashMap<String, String>,
+    HashMap<String, AttributeValue>,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransactionSort {
+    DateDesc,
+    DateAsc,
+    AmountDesc,
+    AmountAsc,
+}
+
+/// Where the next page of a listing starts.
+#[derive(Debug, PartialEq)]
+enum Cursor {
+    /// Position in a listing sorted in memory
+    Offset(usize),
+    /// The last `date_key` returned by a date-ordered index read
+    After(String),
+}
+
+/// Conditions on a transaction listing. Everything except `q` and `transaction_type` is
+/// applied by DynamoDB, the date range as a key condition where the date indexes are in use.
+#[derive(Debug, Default)]
+struct TransactionFilter {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    category: Option<String>,
+    min_amount: Option<f64>,
+    max_amount: Option<f64>,
+    account_id: Option<String>,
+    merchant_id: Option<String>,
+    /// Lowercased search text
+    q: Option<String>,
+    transaction_type: Option<&'static str>,
+}
+
+#[derive(Debug)]
+struct TransactionListing {
+    filter: TransactionFilter,
+    sort: TransactionSort,
+    limit: Option<usize>,
+    cursor: Option<Cursor>,
+}
+
+fn parse_date(name: &str, value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("{} must be a date like 2024-05-01", name))
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+fn encode_cursor(cursor: &Cursor) -> String {
+    let raw = match cursor {
+        Cursor::Offset(offset) => format!("o:{}", offset),
+        Cursor::After(date_key) => format!("k:{}", date_key),
+    };
+    URL_SAFE_NO_PAD.encode(raw)
+}
+
+fn decode_cursor(cursor: &str) -> Option<Cursor> {
+    let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    match raw.split_once(':')? {
+        ("o", offset) => offset.parse().ok().map(Cursor::Offset),
+        ("k", date_key) if date_key_transaction_id(date_key).is_some() => {
+            Some(Cursor::After(date_key.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn parse_listing(params: &ListTransactionsQuery) -> Result<TransactionListing, String> {
+    let from = params
+        .from
+        .as_deref()
+        .map(|d| parse_date("from", d))
+        .transpose()?;
+    let to = params
+        .to
+        .as_deref()
+        .map(|d| parse_date("to", d))
+        .transpose()?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err("from must not be after to".to_string());
+        }
+    }
+    if let (Some(min), Some(max)) = (params.min_amount, params.max_amount) {
+        if min > max {
+            return Err("min_amount must not be more than max_amount".to_string());
+        }
+    }
+
+    let transaction_type = match non_empty(&params.transaction_type).as_deref() {
+        None => None,
+        Some("expense") => Some("expense"),
+        Some("income") => Some("income"),
+        Some("transfer") => Some("transfer"),
+        Some(other) => {
+            return Err(format!(
+                "type must be expense, income or transfer, not '{}'",
+                other
+            ))
+        }
+    };
+    let sort = match non_empty(&params.sort).as_deref() {
+        None | Some("date_desc") => TransactionSort::DateDesc,
+        Some("date_asc") => TransactionSort::DateAsc,
+        Some("amount_desc") => TransactionSort::AmountDesc,
+        Some("amount_asc") => TransactionSort::AmountAsc,
+        Some(other) => return Err(format!("Unknown sort: {}", other)),
+    };
+    let limit = match params.limit {
+        Some(limit) if !(1..=MAX_PAGE_SIZE).contains(&limit) => {
+            return Err(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+        }
+        limit => limit,
+    };
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|c| decode_cursor(c).ok_or("Invalid cursor"))
+        .transpose()?;
+
+    Ok(TransactionListing {
+        filter: TransactionFilter {
+            from,
+            to,
+            category: non_empty(&params.category),
+            min_amount: params.min_amount,
+            max_amount: params.max_amount,
+            account_id: non_empty(&params.account_id),
+            merchant_id: non_empty(&params.merchant_id),
+            q: non_empty(&params.q).map(|q| q.to_lowercase()),
+            transaction_type,
+        },
+        sort,
+        limit,
+        cursor,
+    })
+}
+
+impl TransactionFilter {
+    /// The DynamoDB filter expression with its placeholder names and values, or `None` when
+    /// nothing is left for DynamoDB to filter. The date range is only included when it isn't
+    /// already part of the key condition.
+    fn expression(&self, include_dates: bool) -> Option<FilterExpression> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut names = HashMap::new();
+        let mut values = HashMap::new();
+
+        let (from, to) = if include_dates {
+            (self.from, self.to)
+        } else {
+            (None, None)
+        };
+        if from.is_some() || to.is_some() {
+            names.insert("#d".to_string(), "date".to_string());
+        }
+        if let Some(from) = from {
+            conditions.push("#d >= :from");
+            values.insert(
+                ":from".to_string(),
+                AttributeValue::S(from.format("%Y-%m-%d").to_string()),
+            );
+        }
+        // Dates may carry a time, so the range ends before the following day
+        if let Some(to) = to {
+            conditions.push("#d < :before");
+            let before = to.succ_opt().unwrap_or(to);
+            values.insert(
+                ":before".to_string(),
+                AttributeValue::S(before.format("%Y-%m-%d").to_string()),
+            );
+        }
+        if let Some(ref category) = self.category {
+            conditions.push("(category = :category OR contains(split_categories, :category))");
+            values.insert(":category".to_string(), AttributeValue::S(category.clone()));
+        }
+        if let Some(min) = self.min_amount {
+            conditions.push("amount >= :min_amount");
+            values.insert(
+                ":min_amount".to_string(),
+                AttributeValue::N(min.to_string()),
+            );
+        }
+        if let Some(max) = self.max_amount {
+            conditions.push("amount <= :max_amount");
+            values.insert(
+                ":max_amount".to_string(),
+                AttributeValue::N(max.to_string()),
+            );
+        }
+        if let Some(ref account_id) = self.account_id {
+            conditions.push("account_id = :account_id");
+            values.insert(
+                ":account_id".to_string(),
+                AttributeValue::S(account_id.clone()),
+            );
+        }
+        if let Some(ref merchant_id) = self.merchant_id {
+            conditions.push("merchant_id = :merchant_id");
+            values.insert(
+                ":merchant_id".to_string(),
+                AttributeValue::S(merchant_id.clone()),
+            );
+        }
+
+        if conditions.is_empty() {
+            None
+        } else {
+            Some((conditions.join(" AND "), names, values))
+        }
+    }
+
+    /// Every condition, for transactions read by key where DynamoDB applied none of them.
+    fn matches_all(&self, transaction: &Transaction) -> bool {
+        let day = transaction.date.get(..10).unwrap_or(&transaction.date);
+        let formatted = |d: NaiveDate| d.format("%Y-%m-%d").to_string();
+        if self.from.is_some_and(|from| day < formatted(from).as_str())
+            || self.to.is_some_and(|to| day > formatted(to).as_str())
+            || self.min_amount.is_some_and(|min| transaction.amount < min)
+            || self.max_amount.is_some_and(|max| transaction.amount > max)
+        {
+            return false;
+        }
+        if let Some(ref category) = self.category {
+            if transaction.category != *category
+                && !transaction.splits.iter().any(|s| s.category == *category)
+            {
+                return false;
+            }
+        }
+        if self.account_id.is_some() && transaction.account_id != self.account_id {
+            return false;
+        }
+        if self.merchant_id.is_some() && transaction.merchant_id != self.merchant_id {
+            return false;
+        }
+        self.matches(transaction)
+    }
+
+    /// The conditions DynamoDB can't evaluate: case-insensitive text search and the derived
+    /// transaction type.
+    fn matches(&self, transaction: &Transaction) -> bool {
+        if let Some(ref q) = self.q {
+            let found = |text: &str| text.to_lowercase().contains(q.as_str());
+            if !found(&transaction.description)
+                && !transaction.merchant.as_deref().is_some_and(found)
+            {
+                return false;
+            }
+        }
+        match self.transaction_type {
+            Some(wanted) => transaction.transaction_type == wanted,
+            None => true,
+        }
+    }
+}
+
+fn sort_transactions(transactions: &mut [Transaction], sort: TransactionSort) {
+    let by_date = |a: &Transaction, b: &Transaction| {
+        a.date
+            .cmp(&b.date)
+            .then_with(|| a.transaction_id.cmp(&b.transaction_id))
+    };
+    match sort {
+        TransactionSort::DateDesc => transactions.sort_by(|a, b| by_date(b, a)),
+        TransactionSort::DateAsc => transactions.sort_by(by_date),
+        TransactionSort::AmountDesc => {
+            transactions.sort_by(|a, b| b.amount.total_cmp(&a.amount).then_with(|| by_date(b, a)))
+        }
+        TransactionSort::AmountAsc => {
+            transactions.sort_by(|a, b| a.amount.total_cmp(&b.amount).then_with(|| by_date(b, a)))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct KeyCondition {
+    index: Option<&'static str>,
+    expression: String,
+    values: HashMap<String, AttributeValue>,
+}
+
+/// Where a listing reads from: all of a user's transactions or one budget's, through the
+/// date-ordered indexes once their keys are backfilled.
+///
+/// A budget's index partitions also hold a split-index row for each split transaction with
+/// a part in the budget. `budget-date-index` returns them in date order with the budget's
+/// own rows; without the date indexes they're read from `budget-index` separately.
+struct TransactionSource<'a> {
+    user_id: &'a str,
+    budget_id: Option<&'a str>,
+    date_index: bool,
+    /// Read the budget's split-index rows rather than its transactions
+    split_index: bool,
+}
+
+impl<'a> TransactionSource<'a> {
+    /// The split-index rows to read alongside a budget's transactions when they don't come
+    /// through the same index.
+    fn split_rows(&self) -> Option<TransactionSource<'a>> {
+        if self.date_index || self.split_index {
+            return None;
+        }
+        self.budget_id.map(|budget_id| TransactionSource {
+            user_id: self.user_id,
+            budget_id: Some(budget_id),
+            date_index: false,
+            split_index: true,
+        })
+    }
+
+    /// The listing's filter expression. Split-index rows carry none of the filtered
+    /// attributes; they pass, and their transactions are filtered once read.
+    fn filter_expression(&self, filter: &TransactionFilter) -> Option<FilterExpression> {
+        if self.split_index {
+            return None;
+        }
+        let expression = filter.expression(!self.date_index);
+        if self.budget_id.is_none() || !self.date_index {
+            return expression;
+        }
+        expression.map(|(condition, names, values)| {
+            let condition = format!("attribute_exists({}) OR ({})", ATTR_SPLIT_OF, condition);
+            (condition, names, values)
+        })
+    }
+
+    fn key_condition(&self, filter: &TransactionFilter) -> KeyCondition {
+        let mut values = HashMap::new();
+        let (index, mut expression) = match (self.date_index, self.budget_id) {
+            (true, Some(budget_id)) => {
+                values.insert(
+                    ":pk".to_string(),
+                    AttributeValue::S(budget_key(self.user_id, budget_id)),
+                );
+                (
+                    Some(BUDGET_DATE_INDEX),
+                    format!("{} = :pk", ATTR_BUDGET_KEY),
+                )
+            }
+            (true, None) => {
+                values.insert(
+                    ":pk".to_string(),
+                    AttributeValue::S(self.user_id.to_string()),
+                );
+                (Some(USER_DATE_INDEX), "user_id = :pk".to_string())
+            }
+            (false, Some(budget_id)) => {
+                let pk = if self.split_index {
+                    split_index_pk(self.user_id)
+                } else {
+                    self.user_id.to_string()
+                };
+                values.insert(":pk".to_string(), AttributeValue::S(pk));
+                values.insert(":bid".to_string(), AttributeValue::S(budget_id.to_string()));
+                (
+                    Some("budget-index"),
+                    "user_id = :pk AND budget_id = :bid".to_string(),
+                )
+            }
+            (false, None) => {
+                values.insert(
+                    ":pk".to_string(),
+                    AttributeValue::S(self.user_id.to_string()),
+                );
+                (None, "user_id = :pk".to_string())
+            }
+        };
+
+        if self.date_index {
+            let range = match date_key_bounds(filter.from, filter.to) {
+                (Some(from), Some(to)) => {
+                    values.insert(":from_key".to_string(), AttributeValue::S(from));
+                    values.insert(":to_key".to_string(), AttributeValue::S(to));
+                    Some("BETWEEN :from_key AND :to_key")
+                }
+                (Some(from), None) => {
+                    values.insert(":from_key".to_string(), AttributeValue::S(from));
+                    Some(">= :from_key")
+                }
+                (None, Some(to)) => {
+                    values.insert(":to_key".to_string(), AttributeValue::S(to));
+                    Some("<= :to_key")
+                }
+                (None, None) => None,
+            };
+            if let Some(range) = range {
+                expression = format!("{} AND {} {}", expression, ATTR_DATE_KEY, range);
+            }
+        }
+
+        KeyCondition {
+            index,
+            expression,
+            values,
+        }
+    }
+
+    fn query(&self, state: &AppState, filter: &TransactionFilter) -> QueryFluentBuilder {
+        let key = self.key_condition(filter);
+        let mut query = state
             .dynamo
             .query()
             .table_name("ovaflus-transactions")
-            .key_condition_expression("user_id = :uid")
-            .expression_attribute_values(":uid", AttributeValue::S(claims.sub.clone()))
+            .set_index_name(key.index.map(str::to_string))
+            .key_condition_expression(key.expression)
+            .set_expression_attribute_values(Some(key.values));
+        if let Some((condition, names, values)) = self.filter_expression(filter) {
+            query = query.filter_expression(condition);
+            for (k, v) in names {
+                query = query.expression_attribute_names(k, v);
+            }
+            for (k, v) in values {
+                query = query.expression_attribute_values(k, v);
+            }
+        }
+        query
+    }
+
+    /// The index key to resume a date-ordered read after `date_key`, which may have been
+    /// taken at a split-index row.
+    fn start_key(&self, date_key: &str) -> HashMap<String, AttributeValue> {
+        let transaction_id = date_key_transaction_id(date_key).unwrap_or_default();
+        let user_id = if is_split_index_key(date_key) {
+            split_index_pk(self.user_id)
+        } else {
+            self.user_id.to_string()
+        };
+        let mut key = HashMap::from([
+            ("user_id".to_string(), AttributeValue::S(user_id)),
+            (
+                "transaction_id".to_string(),
+                AttributeValue::S(transaction_id.to_string()),
+            ),
+            (
+                ATTR_DATE_KEY.to_string(),
+                AttributeValue::S(date_key.to_string()),
+            ),
+        ]);
+        if let Some(budget_id) = self.budget_id {
+            key.insert(
+                ATTR_BUDGET_KEY.to_string(),
+                AttributeValue::S(budget_key(self.user_id, budget_id)),
+            );
+        }
+        key
+    }
+}
+
+/// Every transaction from `source` that passes the filter, in no particular order.
+async fn query_transactions(
+    state: &AppState,
+    source: &TransactionSource<'_>,
+    filter: &TransactionFilter,
+) -> Result<Vec<Transaction>, aws_sdk_dynamodb::Error> {
+    let mut seen = HashSet::new();
+    let mut transactions = query_source(state, source, filter, &mut seen).await?;
+    if let Some(split_rows) = source.split_rows() {
+        transactions.extend(query_source(state, &split_rows, filter, &mut seen).await?);
+    }
+    Ok(transactions)
+}
+
+async fn query_source(
+    state: &AppState,
+    source: &TransactionSource<'_>,
+    filter: &TransactionFilter,
+    seen: &mut HashSet<String>,
+) -> Result<Vec<Transaction>, aws_sdk_dynamodb::Error> {
+    let mut transactions = Vec::new();
+    let mut start_key = None;
+    loop {
+        let output = source
+            .query(state, filter)
+            .set_exclusive_start_key(start_key)
             .send()
-            .await
+            .await?;
+        let rows = resolve_rows(state, source, output.items.unwrap_or_default(), filter).await?;
+        transactions.extend(
+            rows.into_iter()
+                .filter(|t| seen.insert(t.transaction_id.clone())),
+        );
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(transactions);
+        }
+    }
+}
+
+/// The transactions of rows read from `source`, in order and filtered. A split-index row
+/// gives way to its transaction, read by key, as long as that is still split into the
+/// row's budget.
+async fn resolve_rows(
+    state: &AppState,
+    source: &TransactionSource<'_>,
+    items: Vec<HashMap<String, AttributeValue>>,
+    filter: &TransactionFilter,
+) -> Result<Vec<Transaction>, aws_sdk_dynamodb::Error> {
+    let s = |item: &HashMap<String, AttributeValue>, name: &str| {
+        item.get(name).and_then(|v| v.as_s().ok()).cloned()
     };
+    let split_of: BTreeSet<String> = items.iter().filter_map(|i| s(i, ATTR_SPLIT_OF)).collect();
+    let mut split: HashMap<String, Transaction> = HashMap::new();
+    if !split_of.is_empty() {
+        let keys = split_of
+            .into_iter()
+            .map(|transaction_id| {
+                HashMap::from([
+                    (
+                        "user_id".to_string(),
+                        AttributeValue::S(source.user_id.to_string()),
+                    ),
+                    (
+                        "transaction_id".to_string(),
+                        AttributeValue::S(transaction_id),
+                    ),
+                ])
+            })
+            .collect();
+        for item in batch_get(&state.dynamo, "ovaflus-transactions", keys).await? {
+            let transaction = item_to_transaction(&item);
+            split.insert(transaction.transaction_id.clone(), transaction);
+        }
+    }
 
-    match result {
-        Ok(output) => {
-            let transactions: Vec<Transaction> = output
-                .items
-                .unwrap_or_default()
-                .iter()
-                .map(item_to_transaction)
-                .collect();
-            (
-                StatusCode::OK,
-                Json(serde_json::to_value(transactions).unwrap()),
-            )
-                .into_response()
+    let mut transactions = Vec::new();
+    for item in &items {
+        let Some(transaction_id) = s(item, ATTR_SPLIT_OF) else {
+            let transaction = item_to_transaction(item);
+            if filter.matches(&transaction) {
+                transactions.push(transaction);
+            }
+            continue;
+        };
+        let budget_id = s(item, "budget_id");
+        let Some(transaction) = split.remove(&transaction_id) else {
+            continue;
+        };
+        let still_split = transaction
+            .splits
+            .iter()
+            .any(|part| part.budget_id.is_some() && part.budget_id == budget_id);
+        if still_split && filter.matches_all(&transaction) {
+            transactions.push(transaction);
         }
-        Err(e) => (
+    }
+    Ok(transactions)
+}
+
+/// All of a user's transactions dated `from` or later, or all of them without `from`.
+pub(crate) async fn transactions_since(
+    state: &AppState,
+    user_id: &str,
+    from: Option<NaiveDate>,
+) -> Result<Vec<Transaction>, aws_sdk_dynamodb::Error> {
+    let source = TransactionSource {
+        user_id,
+        budget_id: None,
+        date_index: state.transactions_date_index,
+        split_index: false,
+    };
+    let filter = TransactionFilter {
+        from,
+        ..Default::default()
+    };
+    query_transactions(state, &source, &filter).await
+}
+
+/// One page of a date-ordered listing, with the date key to continue after when more
+/// transactions may follow. Only as many rows as the page still needs are read per request.
+async fn query_transaction_page(
+    state: &AppState,
+    source: &TransactionSource<'_>,
+    filter: &TransactionFilter,
+    ascending: bool,
+    limit: usize,
+    after: Option<&str>,
+) -> Result<(Vec<Transaction>, Option<String>), aws_sdk_dynamodb::Error> {
+    let mut page: Vec<Transaction> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut start_key = after.map(|date_key| source.start_key(date_key));
+    loop {
+        let output = source
+            .query(state, filter)
+            .scan_index_forward(ascending)
+            .limit((limit - page.len()) as i32)
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        let items = output.items.unwrap_or_default();
+        // The cursor is the last row read, which a split-index row stands in for
+        let last = items
+            .last()
+            .and_then(|item| item.get(ATTR_DATE_KEY))
+            .and_then(|v| v.as_s().ok())
+            .cloned();
+        let rows = resolve_rows(state, source, items, filter).await?;
+        page.extend(
+            rows.into_iter()
+                .filter(|t| seen.insert(t.transaction_id.clone())),
+        );
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok((page, None));
+        }
+        if page.len() >= limit {
+            return Ok((page, last));
+        }
+    }
+}
+
+/// The body stays a plain list; the cursor for the next page travels in a header.
+fn listing_response(page: Vec<Transaction>, next: Option<Cursor>) -> axum::response::Response {
+    let body = Json(serde_json::to_value(page).unwrap());
+    match next {
+        Some(cursor) => (
+            StatusCode::OK,
+            [("x-next-cursor", encode_cursor(&cursor))],
+            body,
+        )
+            .into_response(),
+        None => (StatusCode::OK, body).into_response(),
+    }
+}
+
+pub async fn list_transactions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<ListTransactionsQuery>,
+) -> impl IntoResponse {
+    let listing = match parse_listing(&params) {
+        Ok(listing) => listing,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+    let source = TransactionSource {
+        user_id: &claims.sub,
+        budget_id: params.budget_id.as_deref(),
+        date_index: state.transactions_date_index,
+        split_index: false,
+    };
+    let database_error = |e: aws_sdk_dynamodb::Error| {
+        (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiError::new(format!("Database error: {}", e))),
         )
-            .into_response(),
+            .into_response()
+    };
+    let invalid_cursor = || {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("Invalid cursor")),
+        )
+            .into_response()
+    };
+
+    // Date-sorted pages come straight off the date index, a key-range read per page
+    let by_date = matches!(
+        listing.sort,
+        TransactionSort::DateDesc | TransactionSort::DateAsc
+    );
+    if let (true, true, Some(limit)) = (source.date_index, by_date, listing.limit) {
+        let after = match listing.cursor {
+            None => None,
+            Some(Cursor::After(date_key)) => Some(date_key),
+            Some(Cursor::Offset(_)) => return invalid_cursor(),
+        };
+        let ascending = listing.sort == TransactionSort::DateAsc;
+        return match query_transaction_page(
+            &state,
+            &source,
+            &listing.filter,
+            ascending,
+            limit,
+            after.as_deref(),
+        )
+        .await
+        {
+            Ok((page, next)) => listing_response(page, next.map(Cursor::After)),
+            Err(e) => database_error(e),
+        };
     }
+
+    let offset = match listing.cursor {
+        None => 0,
+        Some(Cursor::Offset(offset)) => offset,
+        Some(Cursor::After(_)) => return invalid_cursor(),
+    };
+    let mut transactions = match query_transactions(&state, &source, &listing.filter).await {
+        Ok(transactions) => transactions,
+        Err(e) => return database_error(e),
+    };
+    sort_transactions(&mut transactions, listing.sort);
+
+    let total = transactions.len();
+    let page: Vec<Transaction> = transactions
+        .into_iter()
+        .skip(offset)
+        .take(listing.limit.unwrap_or(usize::MAX))
+        .collect();
+    let end = offset + page.len();
+    let next = (listing.limit.is_some() && end < total).then_some(Cursor::Offset(end));
+    listing_response(page, next)
 }
 
 pub async fn create_transaction(
     State(state): State<Arc<AppState>>,
     AuthUser(claims): AuthUser,
-    Json(body): Json<CreateTransactionRequest>,
+    Json(mut body): Json<CreateTransactionRequest>,
 ) -> impl IntoResponse {
+    // The merchant directory names the merchant and may give it a default category
+    let normalized = normalize_merchant(&body.description);
+    let directory_entry = match normalized {
+        Some(ref m) => resolve_merchant(&state, &claims.sub, &m.merchant_id)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Looking up the merchant failed: {e}");
+                None
+            }),
+        None => None,
+    };
+    let merchant_name = directory_entry
+        .as_ref()
+        .map(|m| m.display_name.clone())
+        .or_else(|| normalized.as_ref().map(|m| m.display_name.clone()));
+
+    // Rules fill in what the request left out; their tags and merchant always apply
+    let mut outcome = match load_rules(&state, &claims.sub).await {
+        Ok(rules) => rules.apply(&RuleInput {
+            description: &body.description,
+            merchant: merchant_name.as_deref(),
+            amount: body.amount,
+            account_id: None,
+        }),
+        Err(e) => {
+            tracing::error!("Loading rules failed: {e}");
+            RuleOutcome::default()
+        }
+    };
+    let model = user_model(&state, &claims.sub).await.unwrap_or_else(|e| {
+        tracing::error!("Loading the category model failed: {e}");
+        CategoryModel::default()
+    });
+    let merchant = outcome.merchant.take().or(merchant_name);
+    let suggestions = model.suggest_for(&body.description, merchant.as_deref(), body.amount);
+    let chosen_by_user = !body.category.trim().is_empty();
+    if !chosen_by_user {
+        body.category = outcome
+            .category
+            .or_else(|| directory_entry.and_then(|m| m.default_category))
+            .or_else(|| {
+                suggestions
+                    .first()
+                    .filter(|s| s.confidence >= AUTO_CATEGORY_CONFIDENCE)
+                    .map(|s| s.category.clone())
+            })
+            .unwrap_or_else(|| FALLBACK_CATEGORY.to_string());
+    }
+    if body.budget_id.is_empty() && body.splits.is_empty() {
+        body.budget_id = outcome.budget_id.unwrap_or_default();
+    }
+
+    let splits = match validate_splits(body.amount, body.splits) {
+        Ok(splits) => splits,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+    if body.budget_id.is_empty() && splits.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new(
+                "budget_id is required unless the transaction is split",
+            )),
+        )
+            .into_response();
+    }
+
     let transaction_id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
 
     // Create transaction
-    let put_result = state
+    let mut put = state
         .dynamo
         .put_item()
         .table_name("ovaflus-transactions")
         .item("user_id", AttributeValue::S(claims.sub.clone()))
         .item("transaction_id", AttributeValue::S(transaction_id.clone()))
-        .item("budget_id", AttributeValue::S(body.budget_id.clone()))
         .item("amount", AttributeValue::N(body.amount.to_string()))
         .item("description", AttributeValue::S(body.description.clone()))
         .item("category", AttributeValue::S(body.category.clone()))
         .item("date", AttributeValue::S(body.date.clone()))
+        .item(
+            ATTR_DATE_KEY,
+            AttributeValue::S(date_key(&body.date, &transaction_id)),
+        )
         .item("created_at", AttributeValue::S(now.clone()))
-        .item("updated_at", AttributeValue::S(now.clone()))
-        .send()
-        .await;
+        .item("updated_at", AttributeValue::S(now.clone()));
+    if !body.budget_id.is_empty() {
+        put = put
+            .item("budget_id", AttributeValue::S(body.budget_id.clone()))
+            .item(
+                ATTR_BUDGET_KEY,
+                AttributeValue::S(budget_key(&claims.sub, &body.budget_id)),
+            );
+    }
+    if !splits.is_empty() {
+        put = put
+            .item("splits", splits_to_attribute(&splits))
+            .item("split_categories", split_categories_attribute(&splits));
+    }
+    if let Some(ref normalized) = normalized {
+        put = put.item(
+            "merchant_id",
+            AttributeValue::S(normalized.merchant_id.clone()),
+        );
+    }
+    if let Some(ref merchant) = merchant {
+        put = put.item("merchant", AttributeValue::S(merchant.clone()));
+    }
+    if !outcome.tags.is_empty() {
+        put = put.item("tags", AttributeValue::Ss(outcome.tags.clone()));
+    }
 
-    if let Err(e) = put_result {
+    if let Err(e) = put.send().await {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiError::new(format!(
@@ -195,36 +1369,49 @@ pub async fn create_transaction(
             .into_response();
     }
 
-    // Update budget spent amount
-    let _ = state
-        .dynamo
-        .update_item()
-        .table_name("ovaflus-budgets")
-        .key("user_id", AttributeValue::S(claims.sub.clone()))
-        .key("budget_id", AttributeValue::S(body.budget_id.clone()))
-        .update_expression("SET spent = spent + :amount")
-        .expression_attribute_values(":amount", AttributeValue::N(body.amount.to_string()))
-        .send()
-        .await;
+    let split_index = split_index_entries(&body.date, &splits);
+    let no_rows = BTreeSet::new();
+    if let Err(e) =
+        update_split_index(&state, &claims.sub, &transaction_id, &no_rows, &split_index).await
+    {
+        tracing::error!("Writing split-index rows failed: {e}");
+    }
+
+    // Update budget spent amounts, one per split budget
+    let allocations =
+        budget_allocations(Some(body.budget_id.as_str()), body.amount, &splits, false);
+    if let Err(e) = apply_spent_changes(&state, &claims.sub, &BTreeMap::new(), &allocations).await {
+        tracing::error!("Updating budget spent failed: {e}");
+    }
 
     let transaction = Transaction {
         transaction_id,
-        user_id: claims.sub,
+        user_id: claims.sub.clone(),
         budget_id: body.budget_id,
         amount: body.amount,
         description: body.description,
+        transaction_type: transaction_type(&body.category, body.amount).to_string(),
         category: body.category,
         date: body.date,
+        account_id: None,
         plaid_transaction_id: None,
+        recurring_schedule_id: None,
+        merchant_id: normalized.map(|m| m.merchant_id),
+        merchant,
+        tags: outcome.tags,
+        splits,
         created_at: now.clone(),
         updated_at: now,
     };
+    if chosen_by_user {
+        if let Err(e) = learn_category(&state, &claims.sub, None, &transaction).await {
+            tracing::error!("Updating the category model failed: {e}");
+        }
+    }
 
-    (
-        StatusCode::CREATED,
-        Json(serde_json::to_value(transaction).unwrap()),
-    )
-        .into_response()
+    let mut response = serde_json::to_value(transaction).unwrap();
+    response["category_suggestions"] = serde_json::json!(suggestions);
+    (StatusCode::CREATED, Json(response)).into_response()
 }
 
 pub async fn get_transaction(
@@ -271,7 +1458,57 @@ pub async fn update_transaction(
     Path(transaction_id): Path<String>,
     Json(body): Json<UpdateTransactionRequest>,
 ) -> impl IntoResponse {
+    if body.amount.is_none()
+        && body.description.is_none()
+        && body.category.is_none()
+        && body.date.is_none()
+        && body.splits.is_none()
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiError::new("No fields to update")),
+        )
+            .into_response();
+    }
+
+    // Splits are checked against the stored amount, so the row is read before it is written
+    let old = match state
+        .dynamo
+        .get_item()
+        .table_name("ovaflus-transactions")
+        .key("user_id", AttributeValue::S(claims.sub.clone()))
+        .key("transaction_id", AttributeValue::S(transaction_id.clone()))
+        .send()
+        .await
+    {
+        Ok(output) => match output.item {
+            Some(item) => item,
+            None => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(ApiError::new("Transaction not found")),
+                )
+                    .into_response()
+            }
+        },
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiError::new(format!("Database error: {}", e))),
+            )
+                .into_response()
+        }
+    };
+    let stored = item_to_transaction(&old);
+    let amount = body.amount.unwrap_or(stored.amount);
+    let splits = body.splits.clone().unwrap_or_else(|| stored.splits.clone());
+    let splits = match validate_splits(amount, splits) {
+        Ok(splits) => splits,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+
     let mut update_parts: Vec<String> = Vec::new();
+    let mut remove_parts: Vec<&str> = Vec::new();
     let mut expr_values: Vec<(String, AttributeValue)> = Vec::new();
 
     if let Some(amount) = body.amount {
@@ -281,6 +1518,21 @@ pub async fn update_transaction(
     if let Some(ref description) = body.description {
         update_parts.push("description = :desc".to_string());
         expr_values.push((":desc".to_string(), AttributeValue::S(description.clone())));
+        // A new description may name another merchant
+        match normalize_merchant(description) {
+            Some(merchant) => {
+                update_parts.push("merchant_id = :merchant_id, merchant = :merchant".to_string());
+                expr_values.push((
+                    ":merchant_id".to_string(),
+                    AttributeValue::S(merchant.merchant_id),
+                ));
+                expr_values.push((
+                    ":merchant".to_string(),
+                    AttributeValue::S(merchant.display_name),
+                ));
+            }
+            None => remove_parts.extend(["merchant_id", "merchant"]),
+        }
     }
     if let Some(ref category) = body.category {
         update_parts.push("category = :category".to_string());
@@ -289,14 +1541,26 @@ pub async fn update_transaction(
     if let Some(ref date) = body.date {
         update_parts.push("#d = :date".to_string());
         expr_values.push((":date".to_string(), AttributeValue::S(date.clone())));
+        update_parts.push(format!("{} = :date_key", ATTR_DATE_KEY));
+        expr_values.push((
+            ":date_key".to_string(),
+            AttributeValue::S(date_key(date, &transaction_id)),
+        ));
     }
-
-    if update_parts.is_empty() {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ApiError::new("No fields to update")),
-        )
-            .into_response();
+    if body.splits.is_some() {
+        if splits.is_empty() {
+            remove_parts.extend(["splits", "split_categories", "split_budgets"]);
+        } else {
+            update_parts.push("splits = :splits".to_string());
+            expr_values.push((":splits".to_string(), splits_to_attribute(&splits)));
+            update_parts.push("split_categories = :split_categories".to_string());
+            expr_values.push((
+                ":split_categories".to_string(),
+                split_categories_attribute(&splits),
+            ));
+            // Only rows split before the split index existed have this
+            remove_parts.push("split_budgets");
+        }
     }
 
     update_parts.push("updated_at = :updated_at".to_string());
@@ -305,14 +1569,29 @@ pub async fn update_transaction(
         AttributeValue::S(Utc::now().to_rfc3339()),
     ));
 
-    let update_expression = format!("SET {}", update_parts.join(", "));
+    let mut update_expression = format!("SET {}", update_parts.join(", "));
+    if !remove_parts.is_empty() {
+        update_expression = format!("{} REMOVE {}", update_expression, remove_parts.join(", "));
+    }
+    let edited: Vec<String> = [
+        ("description", body.description.is_some()),
+        ("category", body.category.is_some()),
+    ]
+    .into_iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(field, _)| field.to_string())
+    .collect();
+    if !edited.is_empty() {
+        update_expression = format!("{} ADD {} :edited", update_expression, ATTR_USER_EDITED);
+        expr_values.push((":edited".to_string(), AttributeValue::Ss(edited)));
+    }
 
     let mut update = state
         .dynamo
         .update_item()
         .table_name("ovaflus-transactions")
         .key("user_id", AttributeValue::S(claims.sub.clone()))
-        .key("transaction_id", AttributeValue::S(transaction_id))
+        .key("transaction_id", AttributeValue::S(transaction_id.clone()))
         .update_expression(&update_expression)
         .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew);
 
@@ -325,16 +1604,53 @@ pub async fn update_transaction(
         update = update.expression_attribute_names("#d", "date");
     }
 
+    // Fail if another request changed the row since it was read
+    update = match old.get("updated_at") {
+        Some(seen) => update
+            .condition_expression("updated_at = :seen")
+            .expression_attribute_values(":seen", seen.clone()),
+        None => update.condition_expression("attribute_exists(transaction_id)"),
+    };
+
     match update.send().await {
         Ok(output) => {
             let item = output.attributes.unwrap_or_default();
+            let changed = apply_spent_changes(
+                &state,
+                &claims.sub,
+                &counted_allocations(&old),
+                &counted_allocations(&item),
+            )
+            .await;
+            if let Err(e) = changed {
+                tracing::error!("Updating budget spent failed: {e}");
+            }
+            let (was, now) = (item_split_index(&old), item_split_index(&item));
+            if let Err(e) =
+                update_split_index(&state, &claims.sub, &transaction_id, &was, &now).await
+            {
+                tracing::error!("Updating split-index rows failed: {e}");
+            }
             let transaction = item_to_transaction(&item);
+            if transaction.category != stored.category {
+                let learned = learn_category(&state, &claims.sub, Some(&stored), &transaction);
+                if let Err(e) = learned.await {
+                    tracing::error!("Updating the category model failed: {e}");
+                }
+            }
             (
                 StatusCode::OK,
                 Json(serde_json::to_value(transaction).unwrap()),
             )
                 .into_response()
         }
+        Err(e) if is_conditional_check_failure(&e) => (
+            StatusCode::CONFLICT,
+            Json(ApiError::new(
+                "The transaction was changed by another request; reload it and try again",
+            )),
+        )
+            .into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiError::new(format!("Update failed: {}", e))),
@@ -353,12 +1669,32 @@ pub async fn delete_transaction(
         .delete_item()
         .table_name("ovaflus-transactions")
         .key("user_id", AttributeValue::S(claims.sub.clone()))
-        .key("transaction_id", AttributeValue::S(transaction_id))
+        .key("transaction_id", AttributeValue::S(transaction_id.clone()))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
         .send()
         .await;
 
     match result {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Ok(output) => {
+            let old = output.attributes.unwrap_or_default();
+            let reverted = apply_spent_changes(
+                &state,
+                &claims.sub,
+                &counted_allocations(&old),
+                &BTreeMap::new(),
+            )
+            .await;
+            if let Err(e) = reverted {
+                tracing::error!("Updating budget spent failed: {e}");
+            }
+            let (was, now) = (item_split_index(&old), BTreeSet::new());
+            if let Err(e) =
+                update_split_index(&state, &claims.sub, &transaction_id, &was, &now).await
+            {
+                tracing::error!("Deleting split-index rows failed: {e}");
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiError::new(format!("Delete failed: {}", e))),
@@ -366,3 +1702,439 @@ pub async fn delete_transaction(
             .into_response(),
     }
 }
+
+// --- Category Totals ---
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub total: f64,
+    /// Transactions and splits counted in the total
+    pub count: usize,
+}
+
+/// Totals per category, largest first, with each split counted under its own category.
+/// With a category filter only that category's splits count.
+fn category_totals(
+    transactions: &[Transaction],
+    category: Option<&str>,
+    budget_id: Option<&str>,
+) -> Vec<CategoryTotal> {
+    let mut totals: BTreeMap<&str, (f64, usize)> = BTreeMap::new();
+    for transaction in transactions {
+        // Within a budget, only the parts of a split transaction that count towards it
+        let parts: Vec<(&str, f64)> = if transaction.splits.is_empty() {
+            vec![(transaction.category.as_str(), transaction.amount)]
+        } else {
+            transaction
+                .splits
+                .iter()
+                .filter(|split| budget_id.is_none() || split.budget_id.as_deref() == budget_id)
+                .map(|split| (split.category.as_str(), split.amount))
+                .collect()
+        };
+        for (part_category, amount) in parts {
+            if category.is_some_and(|wanted| wanted != part_category) {
+                continue;
+            }
+            let entry = totals.entry(part_category).or_insert((0.0, 0));
+            entry.0 += amount;
+            entry.1 += 1;
+        }
+    }
+
+    let mut totals: Vec<CategoryTotal> = totals
+        .into_iter()
+        .map(|(category, (total, count))| CategoryTotal {
+            category: category.to_string(),
+            total,
+            count,
+        })
+        .collect();
+    totals.sort_by(|a, b| b.total.total_cmp(&a.total));
+    totals
+}
+
+/// Spending per category over the same filters as the listing; sort and paging are ignored.
+pub async fn category_spending(
+    State(state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Query(params): Query<ListTransactionsQuery>,
+) -> impl IntoResponse {
+    let listing = match parse_listing(&params) {
+        Ok(listing) => listing,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(ApiError::new(e))).into_response(),
+    };
+    let source = TransactionSource {
+        user_id: &claims.sub,
+        budget_id: params.budget_id.as_deref(),
+        date_index: state.transactions_date_index,
+        split_index: false,
+    };
+    match query_transactions(&state, &source, &listing.filter).await {
+        Ok(transactions) => {
+            let totals = category_totals(
+                &transactions,
+                listing.filter.category.as_deref(),
+                params.budget_id.as_deref(),
+            );
+            (StatusCode::OK, Json(serde_json::to_value(totals).unwrap())).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiError::new(format!("Database error: {}", e))),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(id: &str, date: &str, amount: f64, category: &str, desc: &str) -> Transaction {
+        Transaction {
+            transaction_id: id.to_string(),
+            user_id: "user-1".to_string(),
+            amount,
+            description: desc.to_string(),
+            transaction_type: transaction_type(category, amount).to_string(),
+            category: category.to_string(),
+            date: date.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn listing_parameters_are_validated_and_become_a_filter_expression() {
+        let params = ListTransactionsQuery {
+            from: Some("2024-05-01".to_string()),
+            to: Some("2024-05-31".to_string()),
+            category: Some("dining".to_string()),
+            min_amount: Some(10.0),
+            q: Some("  Blue Bottle ".to_string()),
+            transaction_type: Some("expense".to_string()),
+            sort: Some("amount_desc".to_string()),
+            limit: Some(20),
+            cursor: Some(encode_cursor(&Cursor::Offset(40))),
+            ..Default::default()
+        };
+        let listing = parse_listing(&params).unwrap();
+        assert_eq!(listing.sort, TransactionSort::AmountDesc);
+        assert_eq!(listing.limit, Some(20));
+        assert_eq!(listing.cursor, Some(Cursor::Offset(40)));
+        assert_eq!(listing.filter.q.as_deref(), Some("blue bottle"));
+
+        let (condition, names, values) = listing.filter.expression(true).unwrap();
+        assert_eq!(
+            condition,
+            "#d >= :from AND #d < :before \
+             AND (category = :category OR contains(split_categories, :category)) \
+             AND amount >= :min_amount"
+        );
+        assert_eq!(names["#d"], "date");
+        // `to` is inclusive, even for dates stored with a time
+        assert_eq!(
+            values[":before"],
+            AttributeValue::S("2024-06-01".to_string())
+        );
+
+        assert!(TransactionFilter::default().expression(true).is_none());
+
+        let invalid = [
+            ListTransactionsQuery {
+                from: Some("05/01/2024".to_string()),
+                ..Default::default()
+            },
+            ListTransactionsQuery {
+                from: Some("2024-06-01".to_string()),
+                to: Some("2024-05-01".to_string()),
+                ..Default::default()
+            },
+            ListTransactionsQuery {
+                transaction_type: Some("refund".to_string()),
+                ..Default::default()
+            },
+            ListTransactionsQuery {
+                sort: Some("name".to_string()),
+                ..Default::default()
+            },
+            ListTransactionsQuery {
+                limit: Some(0),
+                ..Default::default()
+            },
+            ListTransactionsQuery {
+                cursor: Some("not a cursor".to_string()),
+                ..Default::default()
+            },
+        ];
+        for params in &invalid {
+            assert!(parse_listing(params).is_err());
+        }
+    }
+
+    #[test]
+    fn text_and_type_are_matched_and_results_sorted() {
+        let filter = TransactionFilter {
+            q: Some("coffee".to_string()),
+            transaction_type: Some("expense"),
+            ..Default::default()
+        };
+        assert!(filter.matches(&transaction(
+            "a",
+            "2024-05-01",
+            4.5,
+            "dining",
+            "Corner Coffee"
+        )));
+        assert!(!filter.matches(&transaction(
+            "b",
+            "2024-05-01",
+            -4.5,
+            "dining",
+            "Coffee refund"
+        )));
+        assert!(!filter.matches(&transaction("c", "2024-05-01", 4.5, "dining", "Bakery")));
+
+        let mut transactions = vec![
+            transaction("a", "2024-05-01", 20.0, "food", "Grocer"),
+            transaction("b", "2024-05-03", 5.0, "food", "Grocer"),
+            transaction("c", "2024-05-02", 50.0, "food", "Grocer"),
+        ];
+        let ids = |t: &[Transaction]| {
+            t.iter()
+                .map(|t| t.transaction_id.as_str())
+                .collect::<Vec<_>>()
+                .join("")
+        };
+        sort_transactions(&mut transactions, TransactionSort::DateDesc);
+        assert_eq!(ids(&transactions), "bca");
+        sort_transactions(&mut transactions, TransactionSort::DateAsc);
+        assert_eq!(ids(&transactions), "acb");
+        sort_transactions(&mut transactions, TransactionSort::AmountDesc);
+        assert_eq!(ids(&transactions), "cab");
+        assert_eq!(transaction_type("transfer", 100.0), "transfer");
+        assert_eq!(transaction_type("income", 100.0), "income");
+    }
+
+    #[test]
+    fn date_ranges_become_key_ranges_on_the_date_indexes() {
+        let filter = TransactionFilter {
+            from: NaiveDate::from_ymd_opt(2024, 5, 1),
+            to: NaiveDate::from_ymd_opt(2024, 5, 31),
+            category: Some("dining".to_string()),
+            ..Default::default()
+        };
+
+        let budget = TransactionSource {
+            user_id: "u1",
+            budget_id: Some("b1"),
+            date_index: true,
+            split_index: false,
+        };
+        let key = budget.key_condition(&filter);
+        assert_eq!(key.index, Some(BUDGET_DATE_INDEX));
+        assert_eq!(
+            key.expression,
+            "budget_key = :pk AND date_key BETWEEN :from_key AND :to_key"
+        );
+        assert_eq!(key.values[":pk"], AttributeValue::S("u1#b1".to_string()));
+        assert_eq!(
+            key.values[":to_key"],
+            AttributeValue::S("2024-05-31$".to_string())
+        );
+        // Only what the key can't express is left to the filter
+        let (condition, names, _) = filter.expression(false).unwrap();
+        assert_eq!(
+            condition,
+            "(category = :category OR contains(split_categories, :category))"
+        );
+        assert!(names.is_empty());
+
+        let start = budget.start_key("2024-05-20#t9");
+        assert_eq!(start["transaction_id"], AttributeValue::S("t9".to_string()));
+        assert_eq!(
+            start[ATTR_BUDGET_KEY],
+            AttributeValue::S("u1#b1".to_string())
+        );
+
+        let recent = TransactionSource {
+            user_id: "u1",
+            budget_id: None,
+            date_index: true,
+            split_index: false,
+        };
+        let key = recent.key_condition(&TransactionFilter {
+            from: NaiveDate::from_ymd_opt(2024, 5, 1),
+            ..Default::default()
+        });
+        assert_eq!(key.index, Some(USER_DATE_INDEX));
+        assert_eq!(key.expression, "user_id = :pk AND date_key >= :from_key");
+
+        let unindexed = TransactionSource {
+            date_index: false,
+            ..recent
+        };
+        let key = unindexed.key_condition(&filter);
+        assert_eq!(key.index, None);
+        assert_eq!(key.expression, "user_id = :pk");
+
+        let cursor = Cursor::After("2024-05-20#t9".to_string());
+        assert_eq!(decode_cursor(&encode_cursor(&cursor)), Some(cursor));
+        assert_eq!(decode_cursor(&URL_SAFE_NO_PAD.encode("k:no-id")), None);
+    }
+
+    fn split(amount: f64, category: &str, budget_id: Option<&str>) -> TransactionSplit {
+        TransactionSplit {
+            amount,
+            category: category.to_string(),
+            budget_id: budget_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn splits_must_add_up_and_count_towards_their_own_budgets() {
+        let costco = vec![
+            split(80.0, " groceries ", Some("food")),
+            split(25.5, "household", Some("home")),
+            split(14.5, "clothing", Some("")),
+        ];
+        let splits = validate_splits(120.0, costco.clone()).unwrap();
+        assert_eq!(splits[0].category, "groceries");
+        assert_eq!(splits[2].budget_id, None);
+        assert!(validate_splits(120.01, costco).is_err());
+        assert!(validate_splits(80.0, vec![split(80.0, "groceries", None)]).is_err());
+        assert!(validate_splits(0.0, vec![split(5.0, "a", None), split(-5.0, "", None)]).is_err());
+        assert_eq!(validate_splits(120.0, Vec::new()).unwrap(), Vec::new());
+
+        let item = HashMap::from([
+            ("amount".to_string(), AttributeValue::N("120".to_string())),
+            (
+                "budget_id".to_string(),
+                AttributeValue::S("food".to_string()),
+            ),
+            ("splits".to_string(), splits_to_attribute(&splits)),
+        ]);
+        assert_eq!(item_splits(&item), splits);
+        let split_spend = counted_allocations(&item);
+        assert_eq!(
+            split_spend,
+            BTreeMap::from([("food".to_string(), 80.0), ("home".to_string(), 25.5)])
+        );
+
+        // Un-splitting moves the split amounts back onto the transaction's budget
+        let whole = budget_allocations(Some("food"), 120.0, &[], false);
+        assert_eq!(
+            spent_changes(&split_spend, &whole),
+            vec![("food".to_string(), 40.0), ("home".to_string(), -25.5)]
+        );
+        assert!(spent_changes(&whole, &whole).is_empty());
+        assert!(budget_allocations(Some("food"), 120.0, &splits, true).is_empty());
+    }
+
+    #[test]
+    fn category_totals_count_each_split() {
+        let mut costco = transaction("a", "2024-05-01", 120.0, "groceries", "Costco");
+        costco.splits = vec![
+            split(80.0, "groceries", Some("food")),
+            split(40.0, "household", None),
+        ];
+        let transactions = vec![
+            costco,
+            transaction("b", "2024-05-02", 30.0, "groceries", "Safeway"),
+            transaction("c", "2024-05-03", 50.0, "household", "Target"),
+        ];
+
+        let total = |category: &str, total: f64, count: usize| CategoryTotal {
+            category: category.to_string(),
+            total,
+            count,
+        };
+        assert_eq!(
+            category_totals(&transactions, None, None),
+            vec![total("groceries", 110.0, 2), total("household", 90.0, 2)]
+        );
+        assert_eq!(
+            category_totals(&transactions, Some("household"), None),
+            vec![total("household", 90.0, 2)]
+        );
+        // Within the food budget, only Costco's groceries part counts
+        assert_eq!(
+            category_totals(&transactions[..1], None, Some("food")),
+            vec![total("groceries", 80.0, 1)]
+        );
+    }
+
+    #[test]
+    fn budget_listings_include_split_transactions_with_a_part_in_the_budget() {
+        let budget = TransactionSource {
+            user_id: "u1",
+            budget_id: Some("food"),
+            date_index: true,
+            split_index: false,
+        };
+        // Split-index rows come through the budget's own index and pass the filter
+        assert!(budget.split_rows().is_none());
+        let filter = TransactionFilter {
+            min_amount: Some(10.0),
+            ..Default::default()
+        };
+        let (condition, _, _) = budget.filter_expression(&filter).unwrap();
+        assert_eq!(
+            condition,
+            "attribute_exists(split_of) OR (amount >= :min_amount)"
+        );
+
+        // Without the date indexes they're read from their own partition
+        let unindexed = TransactionSource {
+            date_index: false,
+            ..budget
+        };
+        let split_rows = unindexed.split_rows().unwrap();
+        let key = split_rows.key_condition(&filter);
+        assert_eq!(key.index, Some("budget-index"));
+        assert_eq!(
+            key.values[":pk"],
+            AttributeValue::S("u1#splits".to_string())
+        );
+        assert!(split_rows.filter_expression(&filter).is_none());
+        assert!(split_rows.split_rows().is_none());
+
+        // One row per budget a split counts towards
+        let costco = [
+            split(80.0, "groceries", Some("food")),
+            split(30.0, "household", Some("home")),
+            split(10.0, "household", None),
+        ];
+        let entries = split_index_entries("2024-05-02", &costco);
+        assert_eq!(
+            entries.into_iter().map(|(b, _)| b).collect::<Vec<_>>(),
+            ["food", "home"]
+        );
+        let row = split_index_row("u1", "b", "food", "2024-05-02");
+        assert_eq!(row["budget_key"], AttributeValue::S("u1#food".to_string()));
+        let date_key = row[ATTR_DATE_KEY].as_s().unwrap();
+        assert_eq!(date_key, "2024-05-02#b#food");
+
+        // A cursor taken at a split-index row resumes right after it
+        let start = budget.start_key(date_key);
+        assert_eq!(start["user_id"], AttributeValue::S("u1#splits".to_string()));
+        assert_eq!(
+            start["transaction_id"],
+            AttributeValue::S("b#food".to_string())
+        );
+
+        // Split transactions are filtered in full once read
+        let mut transaction = transaction("b", "2024-05-02", 120.0, "shopping", "Costco");
+        transaction.splits = costco.to_vec();
+        let filter = |category: &str, max_amount: Option<f64>| TransactionFilter {
+            category: Some(category.to_string()),
+            max_amount,
+            to: NaiveDate::from_ymd_opt(2024, 5, 2),
+            ..Default::default()
+        };
+        assert!(filter("groceries", None).matches_all(&transaction));
+        assert!(!filter("dining", None).matches_all(&transaction));
+        assert!(!filter("groceries", Some(100.0)).matches_all(&transaction));
+    }
+}
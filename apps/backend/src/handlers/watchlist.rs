@@ -21,7 +21,7 @@ pub struct WatchlistItem {
     pub added_at: String,
 }
 
-fn item_to_watchlist_item(
+pub(crate) fn item_to_watchlist_item(
     item: &std::collections::HashMap<String, AttributeValue>,
 ) -> WatchlistItem {
     WatchlistItem {
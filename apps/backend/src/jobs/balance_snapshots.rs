@@ -0,0 +1,122 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use super::emit_metrics;
+use crate::handlers::plaid::{refresh_user_balances, BalancesRefreshResult};
+use crate::AppState;
+
+/// Users refreshed at once; each makes one Plaid call per linked item.
+const MAX_CONCURRENT_USERS: usize = 8;
+
+#[derive(Serialize, Default, Debug)]
+pub struct BalanceSnapshotsReport {
+    pub users: usize,
+    pub users_failed: usize,
+    pub items_refreshed: usize,
+    pub snapshots_written: usize,
+    /// Items, accounts or portfolios that could not be refreshed or stored
+    pub errors: usize,
+    pub duration_ms: u128,
+}
+
+impl BalanceSnapshotsReport {
+    fn absorb(&mut self, user_id: &str, result: BalancesRefreshResult) {
+        self.items_refreshed += result.items_refreshed;
+        self.snapshots_written += result.snapshots_written;
+        self.errors += result.errors.len();
+        for error in &result.errors {
+            tracing::warn!(user_id, "Balance snapshot failed: {error}");
+        }
+    }
+}
+
+/// Every user with a row in a table keyed by `user_id`.
+async fn scan_user_ids(
+    dynamo: &aws_sdk_dynamodb::Client,
+    table: &str,
+    user_ids: &mut BTreeSet<String>,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let mut start_key = None;
+    loop {
+        let output = dynamo
+            .scan()
+            .table_name(table)
+            .projection_expression("user_id")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        for item in output.items.unwrap_or_default() {
+            if let Some(user_id) = item.get("user_id").and_then(|v| v.as_s().ok()) {
+                user_ids.insert(user_id.clone());
+            }
+        }
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// Write today's balance snapshots for every user with linked accounts or holdings, so the
+/// net-worth series has a point per day whether or not they open the app.
+pub async fn run(state: Arc<AppState>) -> Result<BalanceSnapshotsReport, aws_sdk_dynamodb::Error> {
+    let started = Instant::now();
+    let mut report = BalanceSnapshotsReport::default();
+
+    let mut user_ids = BTreeSet::new();
+    scan_user_ids(&state.dynamo, "ovaflus-plaid-items", &mut user_ids).await?;
+    scan_user_ids(&state.dynamo, "ovaflus-portfolio", &mut user_ids).await?;
+    report.users = user_ids.len();
+
+    let mut users = user_ids.into_iter();
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < MAX_CONCURRENT_USERS {
+            let Some(user_id) = users.next() else {
+                break;
+            };
+            let state = state.clone();
+            tasks.spawn(async move {
+                let result = refresh_user_balances(&state, &user_id, false).await;
+                (user_id, result)
+            });
+        }
+        match tasks.join_next().await {
+            Some(Ok((user_id, Ok(result)))) => report.absorb(&user_id, result),
+            Some(Ok((user_id, Err(e)))) => {
+                report.users_failed += 1;
+                tracing::error!(user_id, "Refreshing balances failed: {e}");
+            }
+            Some(Err(e)) => {
+                report.users_failed += 1;
+                tracing::error!("Balance snapshot task failed: {e}");
+            }
+            None => break,
+        }
+    }
+
+    report.duration_ms = started.elapsed().as_millis();
+    tracing::info!(
+        users = report.users,
+        users_failed = report.users_failed,
+        items_refreshed = report.items_refreshed,
+        snapshots_written = report.snapshots_written,
+        errors = report.errors,
+        duration_ms = report.duration_ms as u64,
+        "Balance snapshots job finished"
+    );
+    emit_metrics(
+        "balance-snapshots",
+        &[
+            ("UsersFailed", report.users_failed as f64),
+            ("ItemsRefreshed", report.items_refreshed as f64),
+            ("SnapshotsWritten", report.snapshots_written as f64),
+            ("Errors", report.errors as f64),
+        ],
+    );
+    Ok(report)
+}
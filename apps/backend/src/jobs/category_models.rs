@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use super::emit_metrics;
+use crate::handlers::category_model::{pending_models, train_model};
+use crate::AppState;
+
+#[derive(Serialize, Default, Debug)]
+pub struct CategoryModelsReport {
+    pub models_pending: usize,
+    pub models_trained: usize,
+    /// Queued again while training, so left for the next run
+    pub models_skipped: usize,
+    pub models_failed: usize,
+    pub duration_ms: u128,
+}
+
+/// Train every category model queued by a new user or a rebuild request. Training reads the
+/// user's whole transaction history, which is why requests leave it to this job.
+pub async fn run(state: Arc<AppState>) -> Result<CategoryModelsReport, aws_sdk_dynamodb::Error> {
+    let started = Instant::now();
+    let mut report = CategoryModelsReport::default();
+
+    let pending = pending_models(&state).await?;
+    report.models_pending = pending.len();
+    for (user_id, seen) in &pending {
+        match train_model(&state, user_id, seen).await {
+            Ok(true) => report.models_trained += 1,
+            Ok(false) => report.models_skipped += 1,
+            Err(e) => {
+                report.models_failed += 1;
+                tracing::error!(user_id, "Training the category model failed: {e}");
+            }
+        }
+    }
+
+    report.duration_ms = started.elapsed().as_millis();
+    tracing::info!(
+        models_pending = report.models_pending,
+        models_trained = report.models_trained,
+        models_skipped = report.models_skipped,
+        models_failed = report.models_failed,
+        duration_ms = report.duration_ms as u64,
+        "Category models job finished"
+    );
+    emit_metrics(
+        "category-models",
+        &[
+            ("ModelsPending", report.models_pending as f64),
+            ("ModelsTrained", report.models_trained as f64),
+            ("ModelsFailed", report.models_failed as f64),
+        ],
+    );
+    Ok(report)
+}
@@ -0,0 +1,93 @@
+pub mod balance_snapshots;
+pub mod category_models;
+pub mod plaid_sync;
+pub mod recurring;
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{crypto, AppState};
+
+/// CloudWatch namespace for metrics emitted by scheduled jobs.
+const METRICS_NAMESPACE: &str = "Ovaflus/Jobs";
+
+/// Input of the EventBridge rule that invokes the jobs function, e.g. `{"job": "plaid-sync"}`.
+/// Maintenance jobs such as `reencrypt-tokens` take the same payload through `aws lambda invoke`.
+#[derive(Deserialize)]
+pub struct JobEvent {
+    pub job: String,
+}
+
+pub async fn run(state: Arc<AppState>, event: JobEvent) -> Result<serde_json::Value, String> {
+    match event.job.as_str() {
+        "plaid-sync" => {
+            let report = plaid_sync::run(state)
+                .await
+                .map_err(|e| format!("Plaid sync failed: {}", e))?;
+            Ok(serde_json::to_value(report).unwrap())
+        }
+        "balance-snapshots" => {
+            let report = balance_snapshots::run(state)
+                .await
+                .map_err(|e| format!("Balance snapshots failed: {}", e))?;
+            Ok(serde_json::to_value(report).unwrap())
+        }
+        "recurring-transactions" => {
+            let report = recurring::run(state)
+                .await
+                .map_err(|e| format!("Recurring transactions failed: {}", e))?;
+            Ok(serde_json::to_value(report).unwrap())
+        }
+        "category-models" => {
+            let report = category_models::run(state)
+                .await
+                .map_err(|e| format!("Category models failed: {}", e))?;
+            Ok(serde_json::to_value(report).unwrap())
+        }
+        // Not scheduled: invoked by hand after rotating the token key
+        "reencrypt-tokens" => {
+            let report =
+                crypto::rotation::reencrypt_plaid_items(&state.dynamo, &state.token_cipher).await;
+            emit_metrics(
+                "reencrypt-tokens",
+                &[
+                    ("TokensScanned", report.scanned as f64),
+                    ("TokensReencrypted", report.reencrypted as f64),
+                    ("TokensFailed", report.failed as f64),
+                ],
+            );
+            if report.failed > 0 {
+                return Err(format!(
+                    "Re-encrypting tokens failed for {} of {} rows",
+                    report.failed, report.scanned
+                ));
+            }
+            Ok(serde_json::to_value(report).unwrap())
+        }
+        other => Err(format!("Unknown job: {}", other)),
+    }
+}
+
+/// Print metrics in CloudWatch's embedded metric format. Lambda ships stdout to CloudWatch Logs,
+/// which extracts the metrics without a `PutMetricData` call.
+pub fn emit_metrics(job: &str, metrics: &[(&str, f64)]) {
+    let mut line = serde_json::json!({
+        "_aws": {
+            "Timestamp": chrono::Utc::now().timestamp_millis(),
+            "CloudWatchMetrics": [{
+                "Namespace": METRICS_NAMESPACE,
+                "Dimensions": [["Job"]],
+                "Metrics": metrics
+                    .iter()
+                    .map(|(name, _)| serde_json::json!({ "Name": name, "Unit": "Count" }))
+                    .collect::<Vec<_>>(),
+            }],
+        },
+        "Job": job,
+    });
+    for (name, value) in metrics {
+        line[*name] = serde_json::json!(value);
+    }
+    println!("{}", line);
+}
@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Instant;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use super::emit_metrics;
+use crate::handlers::plaid::{
+    sync_failures, sync_item_transactions, SyncContext, ITEM_LOGIN_REQUIRED, ITEM_REVOKED,
+    MAX_SYNC_FAILURES,
+};
+use crate::AppState;
+
+/// Users synced at once. A user's items are synced one after another, so only one task at a
+/// time updates their budgets.
+const MAX_CONCURRENT_USERS: usize = 8;
+
+type PlaidItemRow = HashMap<String, AttributeValue>;
+
+#[derive(Serialize, Default, Debug)]
+pub struct PlaidSyncReport {
+    pub users: usize,
+    pub items_scanned: usize,
+    pub items_synced: usize,
+    pub items_failed: usize,
+    /// Items waiting out a backoff, over their failure budget, or needing the user to re-link
+    pub items_skipped: usize,
+    pub transactions_added: usize,
+    pub transactions_modified: usize,
+    pub transactions_removed: usize,
+    pub transactions_assigned: usize,
+    pub duration_ms: u128,
+}
+
+impl PlaidSyncReport {
+    fn absorb(&mut self, other: PlaidSyncReport) {
+        self.items_synced += other.items_synced;
+        self.items_failed += other.items_failed;
+        self.transactions_added += other.transactions_added;
+        self.transactions_modified += other.transactions_modified;
+        self.transactions_removed += other.transactions_removed;
+        self.transactions_assigned += other.transactions_assigned;
+    }
+}
+
+/// Whether the scheduled sync should try this item now. Items the user has to re-link, and
+/// items that have used up their failure budget, wait for the user; a successful sync from
+/// the app brings them back.
+fn due_for_sync(item: &PlaidItemRow, now: DateTime<Utc>) -> bool {
+    let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok());
+    if matches!(
+        s("status").map(String::as_str),
+        Some(ITEM_LOGIN_REQUIRED | ITEM_REVOKED)
+    ) {
+        return false;
+    }
+    if sync_failures(item) >= MAX_SYNC_FAILURES {
+        return false;
+    }
+    match s("sync_retry_after").and_then(|t| DateTime::parse_from_rfc3339(t).ok()) {
+        Some(retry_after) => retry_after.with_timezone(&Utc) <= now,
+        None => true,
+    }
+}
+
+async fn scan_plaid_items(
+    dynamo: &aws_sdk_dynamodb::Client,
+) -> Result<Vec<PlaidItemRow>, aws_sdk_dynamodb::Error> {
+    let mut items = Vec::new();
+    let mut start_key = None;
+    loop {
+        let output = dynamo
+            .scan()
+            .table_name("ovaflus-plaid-items")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        items.extend(output.items.unwrap_or_default());
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(items);
+        }
+    }
+}
+
+async fn sync_user(state: &AppState, user_id: &str, items: &[PlaidItemRow]) -> PlaidSyncReport {
+    let mut report = PlaidSyncReport::default();
+    let ctx = match SyncContext::load(state, user_id).await {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            tracing::error!("Loading sync context for user {user_id} failed: {e}");
+            report.items_failed = items.len();
+            return report;
+        }
+    };
+
+    for item in items {
+        let synced = sync_item_transactions(state, user_id, item, &ctx).await;
+        report.transactions_added += synced.added;
+        report.transactions_modified += synced.modified;
+        report.transactions_removed += synced.removed;
+        report.transactions_assigned += synced.assigned;
+        if synced.incomplete {
+            report.items_failed += 1;
+            for error in &synced.errors {
+                tracing::warn!(user_id, "Scheduled Plaid sync failed: {error}");
+            }
+        } else {
+            report.items_synced += 1;
+        }
+    }
+    report
+}
+
+/// Sync every linked Plaid item from its stored cursor.
+pub async fn run(state: Arc<AppState>) -> Result<PlaidSyncReport, aws_sdk_dynamodb::Error> {
+    let started = Instant::now();
+    let now = Utc::now();
+    let mut report = PlaidSyncReport::default();
+
+    let mut by_user: BTreeMap<String, Vec<PlaidItemRow>> = BTreeMap::new();
+    for item in scan_plaid_items(&state.dynamo).await? {
+        report.items_scanned += 1;
+        if !due_for_sync(&item, now) {
+            report.items_skipped += 1;
+            continue;
+        }
+        let user_id = item
+            .get("user_id")
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+            .unwrap_or_default();
+        by_user.entry(user_id).or_default().push(item);
+    }
+    report.users = by_user.len();
+
+    let mut users = by_user.into_iter();
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < MAX_CONCURRENT_USERS {
+            let Some((user_id, items)) = users.next() else {
+                break;
+            };
+            let state = state.clone();
+            tasks.spawn(async move { sync_user(&state, &user_id, &items).await });
+        }
+        match tasks.join_next().await {
+            Some(Ok(user_report)) => report.absorb(user_report),
+            Some(Err(e)) => tracing::error!("Plaid sync task failed: {e}"),
+            None => break,
+        }
+    }
+
+    report.duration_ms = started.elapsed().as_millis();
+    tracing::info!(
+        users = report.users,
+        items_scanned = report.items_scanned,
+        items_synced = report.items_synced,
+        items_failed = report.items_failed,
+        items_skipped = report.items_skipped,
+        transactions_added = report.transactions_added,
+        transactions_modified = report.transactions_modified,
+        transactions_removed = report.transactions_removed,
+        duration_ms = report.duration_ms as u64,
+        "Scheduled Plaid sync finished"
+    );
+    emit_metrics(
+        "plaid-sync",
+        &[
+            ("ItemsSynced", report.items_synced as f64),
+            ("ItemsFailed", report.items_failed as f64),
+            ("ItemsSkipped", report.items_skipped as f64),
+            ("TransactionsAdded", report.transactions_added as f64),
+            ("TransactionsModified", report.transactions_modified as f64),
+            ("TransactionsRemoved", report.transactions_removed as f64),
+        ],
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(attrs: &[(&str, AttributeValue)]) -> PlaidItemRow {
+        attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn items_are_skipped_while_backing_off_or_needing_the_user() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let s = |v: &str| AttributeValue::S(v.to_string());
+        let n = |v: u32| AttributeValue::N(v.to_string());
+
+        assert!(due_for_sync(&row(&[]), now));
+        assert!(due_for_sync(
+            &row(&[("status", s("pending_expiration"))]),
+            now
+        ));
+        assert!(!due_for_sync(
+            &row(&[("status", s(ITEM_LOGIN_REQUIRED))]),
+            now
+        ));
+        assert!(!due_for_sync(&row(&[("status", s(ITEM_REVOKED))]), now));
+
+        let backing_off = row(&[
+            ("sync_failures", n(2)),
+            ("sync_retry_after", s("2024-05-01T13:00:00Z")),
+        ]);
+        assert!(!due_for_sync(&backing_off, now));
+        let retry_due = row(&[
+            ("sync_failures", n(2)),
+            ("sync_retry_after", s("2024-05-01T11:00:00Z")),
+        ]);
+        assert!(due_for_sync(&retry_due, now));
+        let over_budget = row(&[
+            ("sync_failures", n(MAX_SYNC_FAILURES)),
+            ("sync_retry_after", s("2024-05-01T11:00:00Z")),
+        ]);
+        assert!(!due_for_sync(&over_budget, now));
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use super::emit_metrics;
+use crate::handlers::recurring::{due_schedules, materialize_schedule};
+use crate::AppState;
+
+#[derive(Serialize, Default, Debug)]
+pub struct RecurringReport {
+    pub schedules_due: usize,
+    pub schedules_failed: usize,
+    pub transactions_created: usize,
+    pub duration_ms: u128,
+}
+
+/// Create every recurring transaction due by today. Occurrences have fixed ids, so a run
+/// repeated after a failure creates nothing twice.
+pub async fn run(state: Arc<AppState>) -> Result<RecurringReport, aws_sdk_dynamodb::Error> {
+    let started = Instant::now();
+    let today = Utc::now().date_naive();
+    let mut report = RecurringReport::default();
+
+    let schedules = due_schedules(&state, today).await?;
+    report.schedules_due = schedules.len();
+    for schedule in &schedules {
+        match materialize_schedule(&state, schedule, today).await {
+            Ok(created) => report.transactions_created += created,
+            Err(e) => {
+                report.schedules_failed += 1;
+                tracing::error!(
+                    user_id = %schedule.user_id,
+                    schedule_id = %schedule.schedule_id,
+                    "Creating recurring transactions failed: {e}"
+                );
+            }
+        }
+    }
+
+    report.duration_ms = started.elapsed().as_millis();
+    tracing::info!(
+        schedules_due = report.schedules_due,
+        schedules_failed = report.schedules_failed,
+        transactions_created = report.transactions_created,
+        duration_ms = report.duration_ms as u64,
+        "Recurring transactions job finished"
+    );
+    emit_metrics(
+        "recurring-transactions",
+        &[
+            ("SchedulesDue", report.schedules_due as f64),
+            ("SchedulesFailed", report.schedules_failed as f64),
+            ("TransactionsCreated", report.transactions_created as f64),
+        ],
+    );
+    Ok(report)
+}
@@ -0,0 +1,95 @@
+pub mod crypto;
+pub mod db;
+pub mod handlers;
+pub mod jobs;
+pub mod market_data;
+pub mod middleware;
+pub mod models;
+pub mod plaid;
+
+#[cfg(test)]
+mod tests;
+
+use aws_sdk_cognitoidentityprovider::Client as CognitoClient;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_ssm::Client as SsmClient;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub dynamo: DynamoClient,
+    pub cognito: CognitoClient,
+    pub cognito_user_pool_id: String,
+    pub cognito_app_client_id: String,
+    pub cognito_issuer: String, // https://cognito-idp.{region}.amazonaws.com/{pool_id}
+    pub nonce_secret: String,
+    pub plaid: plaid::PlaidClient,
+    /// `sandbox`, `development` or `production`
+    pub plaid_env: String,
+    pub plaid_link: plaid::link::LinkConfig,
+    pub finnhub_api_key: String,
+    pub market_data: market_data::MarketData,
+    pub token_cipher: crypto::TokenCipher,
+    /// Read transactions through the date-ordered indexes. Turned on once
+    /// `migrate-transactions` has backfilled the index keys on existing rows.
+    pub transactions_date_index: bool,
+}
+
+async fn load_ssm_param(ssm: &SsmClient, name: &str) -> String {
+    ssm.get_parameter()
+        .name(name)
+        .with_decryption(true)
+        .send()
+        .await
+        .unwrap_or_else(|e| panic!("Failed to load SSM param {name}: {e}"))
+        .parameter
+        .expect("SSM parameter missing")
+        .value
+        .expect("SSM parameter value missing")
+}
+
+impl AppState {
+    /// Build the state shared by the API and the scheduled jobs from the environment and SSM.
+    pub async fn load(config: &aws_config::SdkConfig) -> Self {
+        let dynamo = DynamoClient::new(config);
+        let cognito = CognitoClient::new(config);
+        let ssm = SsmClient::new(config);
+
+        let prefix = std::env::var("SSM_PREFIX").unwrap_or_else(|_| "/ovaflus".to_string());
+
+        let cognito_user_pool_id =
+            std::env::var("COGNITO_USER_POOL_ID").unwrap_or_else(|_| "UNSET".to_string());
+        let cognito_app_client_id =
+            std::env::var("COGNITO_APP_CLIENT_ID").unwrap_or_else(|_| "UNSET".to_string());
+        let cognito_region =
+            std::env::var("COGNITO_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let cognito_issuer = format!(
+            "https://cognito-idp.{}.amazonaws.com/{}",
+            cognito_region, cognito_user_pool_id
+        );
+
+        let finnhub_api_key = load_ssm_param(&ssm, &format!("{prefix}/finnhub_api_key")).await;
+        let plaid_env = load_ssm_param(&ssm, &format!("{prefix}/plaid_env")).await;
+
+        AppState {
+            dynamo,
+            cognito,
+            cognito_user_pool_id,
+            cognito_app_client_id,
+            cognito_issuer,
+            nonce_secret: load_ssm_param(&ssm, &format!("{prefix}/nonce_secret")).await,
+            plaid: plaid::PlaidClient::for_env(
+                &plaid_env,
+                &load_ssm_param(&ssm, &format!("{prefix}/plaid_client_id")).await,
+                &load_ssm_param(&ssm, &format!("{prefix}/plaid_secret")).await,
+            ),
+            plaid_env,
+            plaid_link: plaid::link::LinkConfig::from_env()
+                .unwrap_or_else(|e| panic!("Invalid Plaid Link configuration: {e}")),
+            market_data: market_data::MarketData::from_finnhub_key(&finnhub_api_key),
+            finnhub_api_key,
+            token_cipher: crypto::TokenCipher::from_env(config),
+            transactions_date_index: std::env::var("TRANSACTIONS_DATE_INDEX")
+                .is_ok_and(|v| v == "true" || v == "1"),
+        }
+    }
+}
@@ -1,50 +1,13 @@
-mod db;
-mod handlers;
-mod middleware;
-mod models;
-
-#[cfg(test)]
-mod tests;
-
 use std::sync::Arc;
 
-use aws_sdk_cognitoidentityprovider::Client as CognitoClient;
-use aws_sdk_dynamodb::Client as DynamoClient;
-use aws_sdk_ssm::Client as SsmClient;
 use axum::{
     routing::{delete, get, post, put},
     Router,
 };
 use lambda_http::run;
+use ovaflus_backend::{crypto, handlers, AppState};
 use tracing_subscriber::EnvFilter;
 
-#[derive(Clone)]
-pub struct AppState {
-    pub dynamo: DynamoClient,
-    pub cognito: CognitoClient,
-    pub cognito_user_pool_id: String,
-    pub cognito_app_client_id: String,
-    pub cognito_issuer: String, // https://cognito-idp.{region}.amazonaws.com/{pool_id}
-    pub nonce_secret: String,
-    pub plaid_client_id: String,
-    pub plaid_secret: String,
-    pub plaid_env: String,
-    pub finnhub_api_key: String,
-}
-
-async fn load_ssm_param(ssm: &SsmClient, name: &str) -> String {
-    ssm.get_parameter()
-        .name(name)
-        .with_decryption(true)
-        .send()
-        .await
-        .unwrap_or_else(|e| panic!("Failed to load SSM param {name}: {e}"))
-        .parameter
-        .expect("SSM parameter missing")
-        .value
-        .expect("SSM parameter value missing")
-}
-
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
     tracing_subscriber::fmt()
@@ -53,39 +16,24 @@ async fn main() -> Result<(), lambda_http::Error> {
         .init();
 
     let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-    let dynamo = DynamoClient::new(&config);
-    let cognito = CognitoClient::new(&config);
-    let ssm = SsmClient::new(&config);
+    let state = Arc::new(AppState::load(&config).await);
 
-    let prefix = std::env::var("SSM_PREFIX").unwrap_or_else(|_| "/ovaflus".to_string());
+    // One-off maintenance: `bootstrap reencrypt-tokens` moves every Plaid access token to the
+    // current encryption key, then exits instead of serving requests. Deployed, run the jobs
+    // function's `reencrypt-tokens` job instead.
+    if std::env::args().nth(1).as_deref() == Some("reencrypt-tokens") {
+        let report =
+            crypto::rotation::reencrypt_plaid_items(&state.dynamo, &state.token_cipher).await;
+        tracing::info!(
+            scanned = report.scanned,
+            reencrypted = report.reencrypted,
+            failed = report.failed,
+            "Re-encrypted Plaid access tokens"
+        );
+        return Ok(());
+    }
 
-    let cognito_user_pool_id =
-        std::env::var("COGNITO_USER_POOL_ID").unwrap_or_else(|_| "UNSET".to_string());
-    let cognito_app_client_id =
-        std::env::var("COGNITO_APP_CLIENT_ID").unwrap_or_else(|_| "UNSET".to_string());
-    let cognito_region =
-        std::env::var("COGNITO_REGION").unwrap_or_else(|_| "us-east-1".to_string());
-    let cognito_issuer = format!(
-        "https://cognito-idp.{}.amazonaws.com/{}",
-        cognito_region, cognito_user_pool_id
-    );
-
-    let state = AppState {
-        dynamo,
-        cognito,
-        cognito_user_pool_id,
-        cognito_app_client_id,
-        cognito_issuer,
-        nonce_secret: load_ssm_param(&ssm, &format!("{prefix}/nonce_secret")).await,
-        plaid_client_id: load_ssm_param(&ssm, &format!("{prefix}/plaid_client_id")).await,
-        plaid_secret: load_ssm_param(&ssm, &format!("{prefix}/plaid_secret")).await,
-        plaid_env: load_ssm_param(&ssm, &format!("{prefix}/plaid_env")).await,
-        finnhub_api_key: load_ssm_param(&ssm, &format!("{prefix}/finnhub_api_key")).await,
-    };
-
-    let state = Arc::new(state);
-
-    let app = Router::new()
+    let api = Router::new()
         // Auth (public)
         .route("/auth/apple", post(handlers::auth::apple_sign_in))
         .route("/auth/google", post(handlers::auth::google_sign_in))
@@ -101,6 +49,27 @@ async fn main() -> Result<(), lambda_http::Error> {
         .route("/budgets/:id", get(handlers::budgets::get_budget))
         .route("/budgets/:id", put(handlers::budgets::update_budget))
         .route("/budgets/:id", delete(handlers::budgets::delete_budget))
+        // Categories
+        .route(
+            "/categories/mappings",
+            get(handlers::categories::get_category_mappings),
+        )
+        .route(
+            "/categories/mappings/:plaid_category",
+            put(handlers::categories::set_category_mapping),
+        )
+        .route(
+            "/categories/mappings/:plaid_category",
+            delete(handlers::categories::delete_category_mapping),
+        )
+        .route(
+            "/categories/suggestions",
+            get(handlers::category_model::get_suggestions),
+        )
+        .route(
+            "/categories/model/rebuild",
+            post(handlers::category_model::rebuild_model),
+        )
         // Transactions
         .route(
             "/transactions",
@@ -110,6 +79,14 @@ async fn main() -> Result<(), lambda_http::Error> {
             "/transactions",
             post(handlers::transactions::create_transaction),
         )
+        .route(
+            "/transactions/import",
+            post(handlers::transaction_import::import_transactions),
+        )
+        .route(
+            "/transactions/categories",
+            get(handlers::transactions::category_spending),
+        )
         .route(
             "/transactions/:id",
             get(handlers::transactions::get_transaction),
@@ -128,6 +105,31 @@ async fn main() -> Result<(), lambda_http::Error> {
             "/portfolio/holdings",
             post(handlers::portfolio::add_holding),
         )
+        .route(
+            "/portfolio/summary",
+            get(handlers::portfolio::get_portfolio_summary),
+        )
+        .route(
+            "/portfolio/import",
+            post(handlers::portfolio_import::import_portfolio),
+        )
+        .route("/portfolio/risk", get(handlers::risk::get_portfolio_risk))
+        .route(
+            "/portfolio/returns",
+            get(handlers::returns::get_portfolio_returns),
+        )
+        .route("/portfolio/targets", get(handlers::rebalance::get_targets))
+        .route("/portfolio/targets", put(handlers::rebalance::set_targets))
+        .route(
+            "/portfolio/rebalance",
+            get(handlers::rebalance::get_rebalance),
+        )
+        .route("/portfolio/trades", get(handlers::portfolio::list_trades))
+        .route("/portfolio/trades", post(handlers::portfolio::create_trade))
+        .route(
+            "/portfolio/trades/:id",
+            delete(handlers::portfolio::delete_trade),
+        )
         .route(
             "/portfolio/holdings/:id",
             put(handlers::portfolio::update_holding),
@@ -143,6 +145,8 @@ async fn main() -> Result<(), lambda_http::Error> {
             "/stocks/:symbol/news",
             get(handlers::stocks::get_stock_news),
         )
+        // News
+        .route("/news/feed", get(handlers::news::get_news_feed))
         // Watchlist
         .route("/watchlist", get(handlers::watchlist::get_watchlist))
         .route("/watchlist", post(handlers::watchlist::add_to_watchlist))
@@ -156,6 +160,47 @@ async fn main() -> Result<(), lambda_http::Error> {
         .route("/goals/:id", get(handlers::goals::get_goal))
         .route("/goals/:id", put(handlers::goals::update_goal))
         .route("/goals/:id", delete(handlers::goals::delete_goal))
+        // Recurring transactions
+        .route("/recurring", get(handlers::recurring::list_schedules))
+        .route("/recurring", post(handlers::recurring::create_schedule))
+        .route(
+            "/recurring/upcoming",
+            get(handlers::recurring::list_upcoming),
+        )
+        .route("/recurring/:id", get(handlers::recurring::get_schedule))
+        .route("/recurring/:id", put(handlers::recurring::update_schedule))
+        .route(
+            "/recurring/:id",
+            delete(handlers::recurring::delete_schedule),
+        )
+        // Subscriptions
+        .route(
+            "/subscriptions",
+            get(handlers::subscriptions::list_subscriptions),
+        )
+        .route(
+            "/subscriptions/:id/confirm",
+            post(handlers::subscriptions::confirm_subscription),
+        )
+        .route(
+            "/subscriptions/:id/dismiss",
+            post(handlers::subscriptions::dismiss_subscription),
+        )
+        // Merchant directory
+        .route("/merchants", get(handlers::merchants::list_merchants))
+        .route("/merchants/:id", get(handlers::merchants::get_merchant))
+        .route("/merchants/:id", put(handlers::merchants::put_merchant))
+        .route(
+            "/merchants/:id",
+            delete(handlers::merchants::delete_merchant),
+        )
+        // Categorization rules
+        .route("/rules", get(handlers::rules::list_rules))
+        .route("/rules", post(handlers::rules::create_rule))
+        .route("/rules/:id", get(handlers::rules::get_rule))
+        .route("/rules/:id", put(handlers::rules::update_rule))
+        .route("/rules/:id", delete(handlers::rules::delete_rule))
+        .route("/rules/:id/apply", post(handlers::rules::apply_rule))
         // Plaid
         .route(
             "/plaid/link-token",
@@ -167,11 +212,43 @@ async fn main() -> Result<(), lambda_http::Error> {
         )
         .route("/plaid/accounts", get(handlers::plaid::get_accounts))
         .route("/plaid/sync", post(handlers::plaid::sync_transactions))
+        .route(
+            "/plaid/items/:item_id/link-token",
+            post(handlers::plaid::create_update_link_token),
+        )
+        .route(
+            "/plaid/investments/sync",
+            post(handlers::plaid::sync_investments),
+        )
+        .route(
+            "/plaid/liabilities/sync",
+            post(handlers::plaid::sync_liabilities),
+        )
+        .route(
+            "/plaid/balances/refresh",
+            post(handlers::plaid::refresh_balances),
+        )
+        .route("/liabilities", get(handlers::liabilities::list_liabilities))
+        .route(
+            "/liabilities/upcoming",
+            get(handlers::liabilities::get_upcoming_payments),
+        )
         .route(
             "/plaid/accounts/:item_id",
             delete(handlers::plaid::unlink_account),
         )
-        .with_state(state);
+        // Net worth
+        .route("/net-worth", get(handlers::net_worth::get_net_worth));
+
+    // Sandbox helpers skip Plaid Link during development and are never mounted in production
+    let api = match handlers::plaid_sandbox::routes(&state.plaid_env) {
+        Some(sandbox) => {
+            tracing::info!("Mounting Plaid sandbox endpoints");
+            api.merge(sandbox)
+        }
+        None => api,
+    };
+    let app = api.with_state(state);
 
     run(app).await
 }
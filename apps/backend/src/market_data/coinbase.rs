@@ -0,0 +1,73 @@
+use reqwest::StatusCode;
+
+use super::QuoteProvider;
+
+/// Crypto spot prices in USD from Coinbase's public price API (no key required).
+pub struct CoinbaseQuotes {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl CoinbaseQuotes {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            base_url: "https://api.coinbase.com".to_string(),
+        }
+    }
+}
+
+/// Coinbase pairs look like `BTC-USD`; accept `BTC`, `btc` or `BTC-EUR` from users, always
+/// quoting the base currency in USD.
+fn spot_pair(symbol: &str) -> String {
+    let symbol = symbol.trim().to_uppercase();
+    let base = symbol.split('-').next().unwrap_or_default();
+    format!("{}-USD", base)
+}
+
+#[axum::async_trait]
+impl QuoteProvider for CoinbaseQuotes {
+    async fn quote(&self, symbol: &str) -> Result<Option<f64>, String> {
+        let resp = self
+            .client
+            .get(format!(
+                "{}/v2/prices/{}/spot",
+                self.base_url,
+                spot_pair(symbol)
+            ))
+            .send()
+            .await
+            .map_err(|e| format!("Coinbase request failed: {}", e))?;
+
+        // Unknown pairs are a 404; rate limits and bad requests must not look like "no price"
+        let status = resp.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(format!("Coinbase returned {}", status));
+        }
+
+        let data = resp
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| format!("Failed to parse Coinbase price: {}", e))?;
+
+        Ok(data
+            .pointer("/data/amount")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.parse::<f64>().ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spot_pair_always_quotes_in_usd() {
+        assert_eq!(spot_pair("btc"), "BTC-USD");
+        assert_eq!(spot_pair("BTC-USD"), "BTC-USD");
+        assert_eq!(spot_pair("ETH-EUR"), "ETH-USD");
+    }
+}
@@ -0,0 +1,24 @@
+use super::QuoteProvider;
+use crate::handlers::stocks::fetch_quote;
+
+/// Stock and ETF quotes from Finnhub.
+pub struct FinnhubQuotes {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+impl FinnhubQuotes {
+    pub fn new(client: reqwest::Client, api_key: &str) -> Self {
+        Self {
+            client,
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl QuoteProvider for FinnhubQuotes {
+    async fn quote(&self, symbol: &str) -> Result<Option<f64>, String> {
+        fetch_quote(&self.client, &self.api_key, symbol).await
+    }
+}
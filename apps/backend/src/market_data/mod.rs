@@ -0,0 +1,140 @@
+pub mod coinbase;
+pub mod finnhub;
+
+use std::sync::Arc;
+
+/// Asset classes a holding can belong to.
+pub const ASSET_CLASSES: &[&str] = &["equity", "etf", "mutual_fund", "crypto", "cash", "manual"];
+
+pub fn is_asset_class(asset_class: &str) -> bool {
+    ASSET_CLASSES.contains(&asset_class)
+}
+
+/// Source of latest prices for one kind of quotable asset.
+#[axum::async_trait]
+pub trait QuoteProvider: Send + Sync {
+    /// Latest USD price per unit, or `None` when the provider doesn't know the symbol.
+    async fn quote(&self, symbol: &str) -> Result<Option<f64>, String>;
+}
+
+/// Routes pricing to the right provider for a holding's asset class.
+#[derive(Clone)]
+pub struct MarketData {
+    equities: Arc<dyn QuoteProvider>,
+    crypto: Arc<dyn QuoteProvider>,
+}
+
+impl MarketData {
+    pub fn new(equities: Arc<dyn QuoteProvider>, crypto: Arc<dyn QuoteProvider>) -> Self {
+        Self { equities, crypto }
+    }
+
+    /// Finnhub for equities, ETFs and mutual funds, Coinbase spot prices for crypto.
+    pub fn from_finnhub_key(finnhub_api_key: &str) -> Self {
+        let client = reqwest::Client::new();
+        Self::new(
+            Arc::new(finnhub::FinnhubQuotes::new(client.clone(), finnhub_api_key)),
+            Arc::new(coinbase::CoinbaseQuotes::new(client)),
+        )
+    }
+
+    /// Quote provider for an asset class; `None` for classes that aren't market-priced.
+    pub fn provider(&self, asset_class: &str) -> Option<&dyn QuoteProvider> {
+        match asset_class {
+            "equity" | "etf" | "mutual_fund" => Some(self.equities.as_ref()),
+            "crypto" => Some(self.crypto.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Price per unit of a holding.
+    ///
+    /// Cash is always worth 1.0 per unit and manual assets use the user-entered price. Mutual
+    /// funds fall back to their manual price when the provider has no quote for the fund.
+    pub async fn price(
+        &self,
+        asset_class: &str,
+        symbol: &str,
+        manual_price: Option<f64>,
+    ) -> Result<Option<f64>, String> {
+        match asset_class {
+            "cash" => Ok(Some(1.0)),
+            "manual" => Ok(manual_price),
+            "mutual_fund" => Ok(self.equities.quote(symbol).await?.or(manual_price)),
+            other => match self.provider(other) {
+                Some(provider) => provider.quote(symbol).await,
+                None => Ok(None),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedQuotes(f64);
+
+    #[axum::async_trait]
+    impl QuoteProvider for FixedQuotes {
+        async fn quote(&self, _symbol: &str) -> Result<Option<f64>, String> {
+            Ok(Some(self.0))
+        }
+    }
+
+    struct NoQuotes;
+
+    #[axum::async_trait]
+    impl QuoteProvider for NoQuotes {
+        async fn quote(&self, _symbol: &str) -> Result<Option<f64>, String> {
+            Ok(None)
+        }
+    }
+
+    fn market_data() -> MarketData {
+        MarketData::new(
+            Arc::new(FixedQuotes(100.0)),
+            Arc::new(FixedQuotes(50_000.0)),
+        )
+    }
+
+    #[tokio::test]
+    async fn price_routes_by_asset_class() {
+        let md = market_data();
+        assert_eq!(md.price("equity", "AAPL", None).await, Ok(Some(100.0)));
+        assert_eq!(md.price("etf", "SPY", None).await, Ok(Some(100.0)));
+        assert_eq!(md.price("crypto", "BTC", None).await, Ok(Some(50_000.0)));
+    }
+
+    #[tokio::test]
+    async fn cash_and_manual_assets_are_not_quoted() {
+        let md = market_data();
+        assert_eq!(md.price("cash", "USD", None).await, Ok(Some(1.0)));
+        assert_eq!(
+            md.price("manual", "HOUSE", Some(350_000.0)).await,
+            Ok(Some(350_000.0))
+        );
+        assert_eq!(md.price("manual", "ART", None).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn mutual_funds_fall_back_to_their_manual_price() {
+        let md = market_data();
+        assert_eq!(
+            md.price("mutual_fund", "VFIAX", Some(500.0)).await,
+            Ok(Some(100.0))
+        );
+
+        let unquoted = MarketData::new(Arc::new(NoQuotes), Arc::new(NoQuotes));
+        assert_eq!(
+            unquoted.price("mutual_fund", "VFIAX", Some(500.0)).await,
+            Ok(Some(500.0))
+        );
+    }
+
+    #[test]
+    fn asset_classes_are_validated() {
+        assert!(is_asset_class("crypto"));
+        assert!(!is_asset_class("bond"));
+    }
+}
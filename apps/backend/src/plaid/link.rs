@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+
+use super::types::{AccountFilters, AccountSubtypes};
+
+/// Products the backend knows how to sync; anything else is refused even if configured.
+const SUPPORTED_PRODUCTS: &[&str] = &["transactions", "investments", "liabilities"];
+
+/// Languages Link is translated into.
+const SUPPORTED_LANGUAGES: &[&str] = &[
+    "da", "de", "en", "es", "et", "fr", "hi", "it", "lt", "lv", "nl", "no", "pl", "pt", "ro", "sv",
+    "vi",
+];
+
+/// Account types and subtypes Link can be filtered to.
+const ACCOUNT_SUBTYPES: &[(&str, &[&str])] = &[
+    (
+        "depository",
+        &[
+            "all",
+            "checking",
+            "savings",
+            "hsa",
+            "cd",
+            "money market",
+            "paypal",
+            "prepaid",
+            "cash management",
+        ],
+    ),
+    ("credit", &["all", "credit card", "paypal"]),
+    (
+        "loan",
+        &[
+            "all",
+            "auto",
+            "business",
+            "commercial",
+            "mortgage",
+            "student",
+            "home equity",
+            "line of credit",
+            "other",
+        ],
+    ),
+    (
+        "investment",
+        &[
+            "all",
+            "401k",
+            "403b",
+            "ira",
+            "roth",
+            "brokerage",
+            "529",
+            "hsa",
+            "non-taxable brokerage account",
+            "other",
+        ],
+    ),
+];
+
+/// How Link tokens are created. Defaults suit the iOS app in the US; each list can be
+/// overridden with a comma-separated `PLAID_*` environment variable.
+#[derive(Clone, Debug)]
+pub struct LinkConfig {
+    pub client_name: String,
+    /// Products every new link requires
+    pub products: Vec<String>,
+    /// Products a client may add to a link as optional
+    pub allowed_products: Vec<String>,
+    /// Countries linking is offered in; the first is used when the profile has none
+    pub country_codes: Vec<String>,
+    /// Link languages; the first is used when the client's locale isn't one of them
+    pub languages: Vec<String>,
+    /// OAuth redirect URIs registered in the Plaid dashboard
+    pub redirect_uris: Vec<String>,
+    /// Android apps registered in the Plaid dashboard
+    pub android_package_names: Vec<String>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        LinkConfig {
+            client_name: "Flus".to_string(),
+            products: vec!["transactions".to_string()],
+            allowed_products: vec!["investments".to_string(), "liabilities".to_string()],
+            country_codes: vec!["US".to_string()],
+            languages: vec!["en".to_string()],
+            redirect_uris: Vec::new(),
+            android_package_names: Vec::new(),
+        }
+    }
+}
+
+/// Platform-specific Link settings; at most one of them is set.
+#[derive(Debug, Default, PartialEq)]
+pub struct LinkPlatform {
+    pub redirect_uri: Option<String>,
+    pub android_package_name: Option<String>,
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    let value = std::env::var(name).ok()?;
+    let list: Vec<String> = value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    (!list.is_empty()).then_some(list)
+}
+
+impl LinkConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let defaults = LinkConfig::default();
+        let config = LinkConfig {
+            client_name: std::env::var("PLAID_CLIENT_NAME")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or(defaults.client_name),
+            products: env_list("PLAID_PRODUCTS").unwrap_or(defaults.products),
+            allowed_products: env_list("PLAID_OPTIONAL_PRODUCTS")
+                .unwrap_or(defaults.allowed_products),
+            country_codes: env_list("PLAID_COUNTRY_CODES")
+                .map(|codes| codes.iter().map(|c| c.to_uppercase()).collect())
+                .unwrap_or(defaults.country_codes),
+            languages: env_list("PLAID_LANGUAGES").unwrap_or(defaults.languages),
+            redirect_uris: env_list("PLAID_REDIRECT_URIS").unwrap_or_default(),
+            android_package_names: env_list("PLAID_ANDROID_PACKAGE_NAMES").unwrap_or_default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for product in self.products.iter().chain(&self.allowed_products) {
+            if !SUPPORTED_PRODUCTS.contains(&product.as_str()) {
+                return Err(format!("Unsupported Plaid product: {}", product));
+            }
+        }
+        if let Some(code) = self
+            .country_codes
+            .iter()
+            .find(|c| c.len() != 2 || !c.chars().all(|ch| ch.is_ascii_uppercase()))
+        {
+            return Err(format!("Invalid country code: {}", code));
+        }
+        if let Some(language) = self
+            .languages
+            .iter()
+            .find(|l| !SUPPORTED_LANGUAGES.contains(&l.as_str()))
+        {
+            return Err(format!("Unsupported Link language: {}", language));
+        }
+        Ok(())
+    }
+
+    /// Extra products requested by the client, which must be on the allowed list.
+    pub fn extra_products(&self, requested: &[String]) -> Result<Vec<String>, String> {
+        let mut products: Vec<String> = Vec::new();
+        for product in requested {
+            let product = product.trim().to_lowercase();
+            if !self.allowed_products.contains(&product) {
+                return Err(format!("Product '{}' can't be requested", product));
+            }
+            if !products.contains(&product) {
+                products.push(product);
+            }
+        }
+        Ok(products)
+    }
+
+    /// The Link country for a profile's country, or the default when the profile has none.
+    pub fn country_codes(&self, profile_country: Option<&str>) -> Result<Vec<String>, String> {
+        match profile_country.map(|c| c.trim().to_uppercase()) {
+            Some(country) if !country.is_empty() => {
+                if self.country_codes.contains(&country) {
+                    Ok(vec![country])
+                } else {
+                    Err(format!("Bank linking isn't available in {}", country))
+                }
+            }
+            _ => Ok(vec![self.country_codes[0].clone()]),
+        }
+    }
+
+    /// The Link language for a client locale such as `fr-CA` or `pt_BR`, falling back to the
+    /// default language when the locale isn't one Link is offered in.
+    pub fn language(&self, locale: Option<&str>) -> String {
+        locale
+            .and_then(|l| l.split(['-', '_']).next())
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| self.languages.contains(l))
+            .unwrap_or_else(|| self.languages[0].clone())
+    }
+
+    /// Check the OAuth redirect URI or Android package name against the registered ones.
+    pub fn platform(
+        &self,
+        redirect_uri: Option<&str>,
+        android_package_name: Option<&str>,
+    ) -> Result<LinkPlatform, String> {
+        match (redirect_uri, android_package_name) {
+            (Some(_), Some(_)) => {
+                Err("Set either redirect_uri or android_package_name, not both".to_string())
+            }
+            (Some(uri), None) if !self.redirect_uris.iter().any(|u| u == uri) => {
+                Err(format!("Redirect URI '{}' isn't registered", uri))
+            }
+            (None, Some(package)) if !self.android_package_names.iter().any(|p| p == package) => {
+                Err(format!("Android package '{}' isn't registered", package))
+            }
+            (uri, package) => Ok(LinkPlatform {
+                redirect_uri: uri.map(str::to_string),
+                android_package_name: package.map(str::to_string),
+            }),
+        }
+    }
+}
+
+/// Plaid account filters from `{account type: [subtypes]}`, validated against the types and
+/// subtypes Link supports. Returns `None` when no filter was requested.
+pub fn account_filters(
+    requested: &BTreeMap<String, Vec<String>>,
+) -> Result<Option<AccountFilters>, String> {
+    if requested.is_empty() {
+        return Ok(None);
+    }
+    let mut filters = AccountFilters::default();
+    for (account_type, subtypes) in requested {
+        let account_type = account_type.trim().to_lowercase();
+        let allowed = ACCOUNT_SUBTYPES
+            .iter()
+            .find(|(t, _)| *t == account_type)
+            .map(|(_, subtypes)| *subtypes)
+            .ok_or_else(|| format!("Unknown account type: {}", account_type))?;
+
+        let mut account_subtypes = Vec::new();
+        for subtype in subtypes {
+            let subtype = subtype.trim().to_lowercase();
+            if !allowed.contains(&subtype.as_str()) {
+                return Err(format!(
+                    "Unknown {} account subtype: {}",
+                    account_type, subtype
+                ));
+            }
+            account_subtypes.push(subtype);
+        }
+        if account_subtypes.is_empty() {
+            return Err(format!("No subtypes given for {} accounts", account_type));
+        }
+
+        let slot = match account_type.as_str() {
+            "depository" => &mut filters.depository,
+            "credit" => &mut filters.credit,
+            "loan" => &mut filters.loan,
+            _ => &mut filters.investment,
+        };
+        *slot = Some(AccountSubtypes { account_subtypes });
+    }
+    Ok(Some(filters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LinkConfig {
+        LinkConfig {
+            country_codes: vec!["US".to_string(), "CA".to_string()],
+            languages: vec!["en".to_string(), "fr".to_string()],
+            redirect_uris: vec!["https://app.ovaflus.com/plaid/oauth".to_string()],
+            android_package_names: vec!["com.ovaflus.app".to_string()],
+            ..LinkConfig::default()
+        }
+    }
+
+    #[test]
+    fn requested_options_are_checked_against_the_config() {
+        let config = config();
+
+        assert_eq!(config.country_codes(None).unwrap(), vec!["US"]);
+        assert_eq!(config.country_codes(Some("ca")).unwrap(), vec!["CA"]);
+        assert!(config.country_codes(Some("GB")).is_err());
+
+        assert_eq!(config.language(Some("fr-CA")), "fr");
+        assert_eq!(config.language(Some("de_DE")), "en");
+        assert_eq!(config.language(None), "en");
+
+        assert_eq!(
+            config
+                .extra_products(&["Liabilities".to_string(), "liabilities".to_string()])
+                .unwrap(),
+            vec!["liabilities"]
+        );
+        assert!(config.extra_products(&["identity".to_string()]).is_err());
+
+        let ios = config
+            .platform(Some("https://app.ovaflus.com/plaid/oauth"), None)
+            .unwrap();
+        assert!(ios.redirect_uri.is_some());
+        assert!(config.platform(Some("https://evil.example"), None).is_err());
+        assert!(config.platform(None, Some("com.ovaflus.app")).is_ok());
+        assert!(config
+            .platform(
+                Some("https://app.ovaflus.com/plaid/oauth"),
+                Some("com.ovaflus.app")
+            )
+            .is_err());
+        assert_eq!(
+            config.platform(None, None).unwrap(),
+            LinkPlatform::default()
+        );
+    }
+
+    #[test]
+    fn account_filters_only_accept_known_subtypes() {
+        let mut requested = BTreeMap::new();
+        assert!(account_filters(&requested).unwrap().is_none());
+
+        requested.insert(
+            "depository".to_string(),
+            vec!["checking".to_string(), "Savings".to_string()],
+        );
+        let filters = account_filters(&requested).unwrap().unwrap();
+        assert_eq!(
+            filters.depository.unwrap().account_subtypes,
+            vec!["checking", "savings"]
+        );
+        assert!(filters.credit.is_none());
+
+        requested.insert("credit".to_string(), vec!["mortgage".to_string()]);
+        assert!(account_filters(&requested).is_err());
+
+        let mut unknown = BTreeMap::new();
+        unknown.insert("crypto".to_string(), vec!["all".to_string()]);
+        assert!(account_filters(&unknown).is_err());
+    }
+}
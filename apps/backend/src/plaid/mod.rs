@@ -0,0 +1,280 @@
+pub mod link;
+#[cfg(test)]
+pub mod mock;
+pub mod types;
+
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, de::IgnoredAny, Deserialize, Serialize};
+
+use crate::crypto::Secret;
+use types::*;
+
+/// Plaid's balance endpoint polls the institution and can take several seconds.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// A failed Plaid call. `error_type` and `code` are Plaid's `error_type` and `error_code`
+/// when the API returned an error object; transport failures have neither.
+#[derive(Debug)]
+pub struct PlaidError {
+    pub error_type: Option<String>,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl PlaidError {
+    fn transport(message: String) -> Self {
+        PlaidError {
+            error_type: None,
+            code: None,
+            message,
+        }
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.error_type.as_deref() == Some("RATE_LIMIT_EXCEEDED")
+    }
+}
+
+impl std::fmt::Display for PlaidError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "Plaid error {}: {}", code, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Plaid's error object, returned with a non-2xx status.
+#[derive(Deserialize)]
+struct ErrorBody {
+    error_type: String,
+    error_code: String,
+    #[serde(default)]
+    error_message: String,
+}
+
+/// Every request body carries the API credentials alongside the endpoint's own fields.
+#[derive(Serialize)]
+struct Authenticated<'a, B> {
+    client_id: &'a str,
+    secret: &'a str,
+    #[serde(flatten)]
+    body: &'a B,
+}
+
+/// Typed client for the Plaid API.
+#[derive(Clone)]
+pub struct PlaidClient {
+    http: reqwest::Client,
+    base_url: String,
+    client_id: String,
+    secret: String,
+    timeout: Duration,
+    max_retries: u32,
+    retry_base_delay: Duration,
+}
+
+impl PlaidClient {
+    pub fn new(base_url: &str, client_id: &str, secret: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            secret: secret.to_string(),
+            timeout: REQUEST_TIMEOUT,
+            max_retries: MAX_RETRIES,
+            retry_base_delay: RETRY_BASE_DELAY,
+        }
+    }
+
+    /// `https://{env}.plaid.com`, unless `PLAID_BASE_URL` points somewhere else.
+    pub fn for_env(env: &str, client_id: &str, secret: &str) -> Self {
+        let base_url = std::env::var("PLAID_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| format!("https://{}.plaid.com", env));
+        Self::new(&base_url, client_id, secret)
+    }
+
+    async fn post<B: Serialize, R: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<R, PlaidError> {
+        let mut attempt = 0;
+        loop {
+            match self.post_once(path, body).await {
+                Err(e) if e.is_rate_limited() && attempt < self.max_retries => {
+                    tokio::time::sleep(self.retry_base_delay * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn post_once<B: Serialize, R: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<R, PlaidError> {
+        let resp = self
+            .http
+            .post(format!("{}{}", self.base_url, path))
+            .timeout(self.timeout)
+            .json(&Authenticated {
+                client_id: &self.client_id,
+                secret: &self.secret,
+                body,
+            })
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    PlaidError::transport(format!("Plaid request to {} timed out", path))
+                } else {
+                    PlaidError::transport(format!("Plaid request failed: {}", e))
+                }
+            })?;
+
+        let status = resp.status();
+        let bytes = resp
+            .bytes()
+            .await
+            .map_err(|e| PlaidError::transport(format!("Failed to read Plaid response: {}", e)))?;
+
+        if !status.is_success() {
+            return Err(match serde_json::from_slice::<ErrorBody>(&bytes) {
+                Ok(error) => PlaidError {
+                    error_type: Some(error.error_type),
+                    code: Some(error.error_code),
+                    message: error.error_message,
+                },
+                Err(_) => PlaidError::transport(format!("Plaid returned HTTP {}", status)),
+            });
+        }
+        serde_json::from_slice(&bytes)
+            .map_err(|e| PlaidError::transport(format!("Failed to parse Plaid response: {}", e)))
+    }
+
+    pub async fn link_token_create(
+        &self,
+        request: &LinkTokenCreateRequest,
+    ) -> Result<LinkTokenCreateResponse, PlaidError> {
+        self.post("/link/token/create", request).await
+    }
+
+    pub async fn item_public_token_exchange(
+        &self,
+        public_token: &str,
+    ) -> Result<PublicTokenExchangeResponse, PlaidError> {
+        let request = PublicTokenExchangeRequest {
+            public_token: public_token.to_string(),
+        };
+        self.post("/item/public_token/exchange", &request).await
+    }
+
+    pub async fn transactions_sync(
+        &self,
+        access_token: &Secret,
+        cursor: Option<String>,
+    ) -> Result<TransactionsSyncResponse, PlaidError> {
+        let request = TransactionsSyncRequest {
+            access_token: access_token.clone(),
+            cursor,
+        };
+        self.post("/transactions/sync", &request).await
+    }
+
+    pub async fn investments_holdings_get(
+        &self,
+        access_token: &Secret,
+    ) -> Result<InvestmentsHoldingsGetResponse, PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post("/investments/holdings/get", &request).await
+    }
+
+    pub async fn investments_transactions_get(
+        &self,
+        request: &InvestmentsTransactionsGetRequest,
+    ) -> Result<InvestmentsTransactionsGetResponse, PlaidError> {
+        self.post("/investments/transactions/get", request).await
+    }
+
+    pub async fn liabilities_get(
+        &self,
+        access_token: &Secret,
+    ) -> Result<LiabilitiesGetResponse, PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post("/liabilities/get", &request).await
+    }
+
+    pub async fn accounts_balance_get(
+        &self,
+        access_token: &Secret,
+    ) -> Result<AccountsBalanceGetResponse, PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post("/accounts/balance/get", &request).await
+    }
+
+    pub async fn accounts_get(
+        &self,
+        access_token: &Secret,
+    ) -> Result<AccountsGetResponse, PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post("/accounts/get", &request).await
+    }
+
+    pub async fn item_get(&self, access_token: &Secret) -> Result<ItemGetResponse, PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post("/item/get", &request).await
+    }
+
+    pub async fn item_remove(&self, access_token: &Secret) -> Result<(), PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post::<_, IgnoredAny>("/item/remove", &request)
+            .await
+            .map(|_| ())
+    }
+
+    /// Create a public token for a sandbox institution without going through Link.
+    pub async fn sandbox_public_token_create(
+        &self,
+        request: &SandboxPublicTokenCreateRequest,
+    ) -> Result<SandboxPublicTokenCreateResponse, PlaidError> {
+        self.post("/sandbox/public_token/create", request).await
+    }
+
+    pub async fn sandbox_item_fire_webhook(
+        &self,
+        request: &SandboxItemFireWebhookRequest,
+    ) -> Result<SandboxItemFireWebhookResponse, PlaidError> {
+        self.post("/sandbox/item/fire_webhook", request).await
+    }
+
+    /// Force a sandbox item into `ITEM_LOGIN_REQUIRED`, to exercise update mode.
+    pub async fn sandbox_item_reset_login(
+        &self,
+        access_token: &Secret,
+    ) -> Result<SandboxItemResetLoginResponse, PlaidError> {
+        let request = AccessTokenRequest {
+            access_token: access_token.clone(),
+        };
+        self.post("/sandbox/item/reset_login", &request).await
+    }
+}
@@ -0,0 +1,381 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::crypto::Secret;
+
+/// Plaid sends `null` for empty lists in several responses. Other fields it may omit or null
+/// are `Option`s or fall back to their defaults, so sandbox and production payloads both parse.
+fn null_as_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// --- Shared objects ---
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Item {
+    pub consent_expiration_time: Option<String>,
+    /// Products the item was linked with, including ones still initializing
+    #[serde(deserialize_with = "null_as_empty")]
+    pub products: Vec<String>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub billed_products: Vec<String>,
+}
+
+impl Item {
+    pub fn has_product(&self, product: &str) -> bool {
+        self.products
+            .iter()
+            .chain(&self.billed_products)
+            .any(|p| p == product)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Account {
+    pub account_id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub account_type: String,
+    pub subtype: Option<String>,
+    pub mask: Option<String>,
+    pub balances: Balances,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Balances {
+    pub current: Option<f64>,
+    pub available: Option<f64>,
+    pub iso_currency_code: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LinkUser {
+    pub client_user_id: String,
+}
+
+// --- /link/token/create ---
+
+#[derive(Serialize)]
+pub struct LinkTokenCreateRequest {
+    pub user: LinkUser,
+    pub client_name: String,
+    /// Omitted in update mode, where Plaid reuses the item's products
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub products: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub optional_products: Vec<String>,
+    /// Set to open Link in update mode for an existing item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<Secret>,
+    pub country_codes: Vec<String>,
+    pub language: String,
+    /// Where OAuth institutions send the user back to; iOS and web only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_uri: Option<String>,
+    /// Android app that OAuth institutions return to, instead of a redirect URI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub android_package_name: Option<String>,
+    /// Restrict the accounts the user can pick in Link
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_filters: Option<AccountFilters>,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct AccountFilters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depository: Option<AccountSubtypes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credit: Option<AccountSubtypes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loan: Option<AccountSubtypes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub investment: Option<AccountSubtypes>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AccountSubtypes {
+    pub account_subtypes: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LinkTokenCreateResponse {
+    pub link_token: String,
+}
+
+// --- /item/public_token/exchange ---
+
+#[derive(Serialize)]
+pub struct PublicTokenExchangeRequest {
+    pub public_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PublicTokenExchangeResponse {
+    pub access_token: Secret,
+    pub item_id: String,
+}
+
+// --- /item/get ---
+
+#[derive(Deserialize, Debug)]
+pub struct ItemGetResponse {
+    pub item: Item,
+}
+
+// --- Requests that only carry the item's access token ---
+
+#[derive(Serialize)]
+pub struct AccessTokenRequest {
+    pub access_token: Secret,
+}
+
+// --- /transactions/sync ---
+
+#[derive(Serialize)]
+pub struct TransactionsSyncRequest {
+    pub access_token: Secret,
+    /// Resume after the last page seen; omitted to start from the beginning of history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct TransactionsSyncResponse {
+    #[serde(deserialize_with = "null_as_empty")]
+    pub added: Vec<Transaction>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub modified: Vec<Transaction>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub removed: Vec<RemovedTransaction>,
+    pub next_cursor: String,
+    pub has_more: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Transaction {
+    pub transaction_id: String,
+    pub account_id: String,
+    pub name: String,
+    /// Plaid's cleaned-up merchant name, when it recognised one
+    pub merchant_name: Option<String>,
+    pub logo_url: Option<String>,
+    pub amount: f64,
+    pub date: String,
+    /// Deprecated hierarchy, e.g. `["Shops", "Supermarkets and Groceries"]`
+    #[serde(deserialize_with = "null_as_empty")]
+    pub category: Vec<String>,
+    pub personal_finance_category: Option<PersonalFinanceCategory>,
+    pub pending: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RemovedTransaction {
+    pub transaction_id: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PersonalFinanceCategory {
+    /// e.g. `FOOD_AND_DRINK`
+    pub primary: String,
+    /// e.g. `FOOD_AND_DRINK_GROCERIES`; always prefixed with the primary category
+    pub detailed: String,
+}
+
+// --- /investments/holdings/get ---
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct InvestmentsHoldingsGetResponse {
+    pub item: Option<Item>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub accounts: Vec<Account>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub holdings: Vec<InvestmentHolding>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub securities: Vec<Security>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InvestmentHolding {
+    pub account_id: String,
+    pub security_id: String,
+    pub quantity: f64,
+    /// Total cost of the position, when the institution reports it
+    pub cost_basis: Option<f64>,
+    pub institution_price: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Security {
+    pub security_id: String,
+    pub name: Option<String>,
+    pub ticker_symbol: Option<String>,
+    #[serde(rename = "type")]
+    pub security_type: Option<String>,
+    pub is_cash_equivalent: Option<bool>,
+}
+
+// --- /investments/transactions/get ---
+
+#[derive(Serialize)]
+pub struct InvestmentsTransactionsGetRequest {
+    pub access_token: Secret,
+    pub start_date: String,
+    pub end_date: String,
+    pub options: InvestmentsTransactionsOptions,
+}
+
+#[derive(Serialize)]
+pub struct InvestmentsTransactionsOptions {
+    pub count: usize,
+    pub offset: usize,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct InvestmentsTransactionsGetResponse {
+    #[serde(deserialize_with = "null_as_empty")]
+    pub investment_transactions: Vec<InvestmentTransaction>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub securities: Vec<Security>,
+    pub total_investment_transactions: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InvestmentTransaction {
+    pub investment_transaction_id: String,
+    pub security_id: Option<String>,
+    pub date: String,
+    #[serde(rename = "type")]
+    pub transaction_type: String,
+    pub subtype: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub amount: f64,
+}
+
+// --- /liabilities/get ---
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LiabilitiesGetResponse {
+    pub item: Option<Item>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub accounts: Vec<Account>,
+    pub liabilities: LiabilitiesObject,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LiabilitiesObject {
+    #[serde(deserialize_with = "null_as_empty")]
+    pub credit: Vec<CreditCardLiability>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub student: Vec<StudentLoanLiability>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub mortgage: Vec<MortgageLiability>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CreditCardLiability {
+    pub account_id: Option<String>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub aprs: Vec<Apr>,
+    pub minimum_payment_amount: Option<f64>,
+    pub next_payment_due_date: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Apr {
+    pub apr_type: String,
+    pub apr_percentage: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct StudentLoanLiability {
+    pub account_id: Option<String>,
+    pub interest_rate_percentage: Option<f64>,
+    pub minimum_payment_amount: Option<f64>,
+    pub next_payment_due_date: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MortgageLiability {
+    pub account_id: Option<String>,
+    pub interest_rate: MortgageInterestRate,
+    pub next_monthly_payment: Option<f64>,
+    pub next_payment_due_date: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MortgageInterestRate {
+    pub percentage: Option<f64>,
+}
+
+// --- /accounts/get ---
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AccountsGetResponse {
+    pub item: Option<Item>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub accounts: Vec<Account>,
+}
+
+// --- /accounts/balance/get ---
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AccountsBalanceGetResponse {
+    pub item: Option<Item>,
+    #[serde(deserialize_with = "null_as_empty")]
+    pub accounts: Vec<Account>,
+}
+
+// --- /sandbox/* (sandbox environment only) ---
+
+#[derive(Serialize)]
+pub struct SandboxPublicTokenCreateRequest {
+    pub institution_id: String,
+    pub initial_products: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SandboxPublicTokenCreateResponse {
+    pub public_token: String,
+}
+
+#[derive(Serialize)]
+pub struct SandboxItemFireWebhookRequest {
+    pub access_token: Secret,
+    pub webhook_type: String,
+    pub webhook_code: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SandboxItemFireWebhookResponse {
+    pub webhook_fired: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SandboxItemResetLoginResponse {
+    pub reset_login: bool,
+}
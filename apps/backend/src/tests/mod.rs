@@ -1,2 +1,3 @@
 mod auth_tests;
 mod budget_tests;
+mod plaid_client_tests;
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::crypto::Secret;
+use crate::plaid::link::account_filters;
+use crate::plaid::mock::{MockPlaid, CLIENT_ID, SECRET};
+use crate::plaid::types::{
+    InvestmentsTransactionsGetRequest, InvestmentsTransactionsOptions, LinkTokenCreateRequest,
+    LinkUser, SandboxItemFireWebhookRequest, SandboxPublicTokenCreateRequest,
+};
+use crate::plaid::PlaidClient;
+
+fn token() -> Secret {
+    Secret::new("access-sandbox-de3ce8ef-33f8-452c-a685-8671031fc0f6")
+}
+
+#[tokio::test]
+async fn exchange_sends_credentials_and_returns_the_access_token() {
+    let mock = MockPlaid::start().await;
+    let exchanged = mock
+        .client()
+        .item_public_token_exchange("public-sandbox-123")
+        .await
+        .unwrap();
+
+    assert_eq!(exchanged.item_id, "M5eVJqLnv3tbzdngLDp9FL5OlDNxlNhlE55op");
+    assert!(exchanged
+        .access_token
+        .expose()
+        .starts_with("access-sandbox-"));
+
+    let requests = mock.requests("/item/public_token/exchange");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["client_id"], CLIENT_ID);
+    assert_eq!(requests[0]["secret"], SECRET);
+    assert_eq!(requests[0]["public_token"], "public-sandbox-123");
+}
+
+#[tokio::test]
+async fn sandbox_responses_parse_into_typed_structs() {
+    let mock = MockPlaid::start().await;
+    let client = mock.client();
+
+    let sync = client.transactions_sync(&token(), None).await.unwrap();
+    assert_eq!(sync.added.len(), 2);
+    assert_eq!(
+        sync.added[0].category,
+        ["Shops", "Supermarkets and Groceries"]
+    );
+    let pfc = sync.added[0].personal_finance_category.as_ref().unwrap();
+    assert_eq!(pfc.detailed, "FOOD_AND_DRINK_GROCERIES");
+    // A null category list parses as empty
+    assert!(sync.modified[0].category.is_empty());
+    assert_eq!(sync.removed.len(), 1);
+    assert!(!sync.has_more);
+
+    let holdings = client.investments_holdings_get(&token()).await.unwrap();
+    assert_eq!(holdings.holdings.len(), 3);
+    assert_eq!(
+        holdings.securities[1].ticker_symbol.as_deref(),
+        Some("MIPTX")
+    );
+    assert_eq!(holdings.holdings[2].cost_basis, None);
+
+    let liabilities = client.liabilities_get(&token()).await.unwrap();
+    assert_eq!(liabilities.liabilities.credit[0].aprs.len(), 3);
+    assert_eq!(
+        liabilities.liabilities.mortgage[0].interest_rate.percentage,
+        Some(3.99)
+    );
+
+    let balances = client.accounts_balance_get(&token()).await.unwrap();
+    assert_eq!(balances.accounts[0].balances.available, Some(100.0));
+    assert_eq!(balances.accounts[1].account_type, "credit");
+
+    client.item_remove(&token()).await.unwrap();
+}
+
+#[tokio::test]
+async fn rate_limited_calls_are_retried() {
+    let mock = MockPlaid::start().await;
+    mock.fail(
+        "/accounts/balance/get",
+        2,
+        "RATE_LIMIT_EXCEEDED",
+        "ACCOUNTS_BALANCE_GET_LIMIT",
+    );
+
+    let balances = mock.client().accounts_balance_get(&token()).await.unwrap();
+    assert_eq!(balances.accounts.len(), 2);
+    assert_eq!(mock.requests("/accounts/balance/get").len(), 3);
+}
+
+#[tokio::test]
+async fn rate_limiting_gives_up_after_the_last_retry() {
+    let mock = MockPlaid::start().await;
+    mock.fail(
+        "/transactions/sync",
+        10,
+        "RATE_LIMIT_EXCEEDED",
+        "TRANSACTIONS_SYNC_LIMIT",
+    );
+
+    let client = mock.client().with_retries(2, Duration::from_millis(1));
+    let error = client.transactions_sync(&token(), None).await.unwrap_err();
+    assert!(error.is_rate_limited());
+    assert_eq!(error.code.as_deref(), Some("TRANSACTIONS_SYNC_LIMIT"));
+    assert_eq!(mock.requests("/transactions/sync").len(), 3);
+}
+
+#[tokio::test]
+async fn plaid_error_objects_are_parsed_and_not_retried() {
+    let mock = MockPlaid::start().await;
+    mock.fail("/liabilities/get", 1, "ITEM_ERROR", "ITEM_LOGIN_REQUIRED");
+
+    let error = mock.client().liabilities_get(&token()).await.unwrap_err();
+    assert_eq!(error.error_type.as_deref(), Some("ITEM_ERROR"));
+    assert_eq!(error.code.as_deref(), Some("ITEM_LOGIN_REQUIRED"));
+    assert_eq!(
+        error.to_string(),
+        "Plaid error ITEM_LOGIN_REQUIRED: mock item login required"
+    );
+    assert_eq!(mock.requests("/liabilities/get").len(), 1);
+}
+
+#[tokio::test]
+async fn wrong_credentials_are_rejected() {
+    let mock = MockPlaid::start().await;
+    let client = PlaidClient::new(mock.base_url(), CLIENT_ID, "wrong-secret");
+
+    let error = client.investments_holdings_get(&token()).await.unwrap_err();
+    assert_eq!(error.code.as_deref(), Some("INVALID_API_KEYS"));
+}
+
+#[tokio::test]
+async fn link_token_options_are_sent_in_plaids_format() {
+    let mock = MockPlaid::start().await;
+    let filters = BTreeMap::from([(
+        "depository".to_string(),
+        vec!["checking".to_string(), "savings".to_string()],
+    )]);
+
+    let request = LinkTokenCreateRequest {
+        user: LinkUser {
+            client_user_id: "user-1".to_string(),
+        },
+        client_name: "Flus".to_string(),
+        products: vec!["transactions".to_string()],
+        optional_products: vec!["liabilities".to_string()],
+        access_token: None,
+        country_codes: vec!["CA".to_string()],
+        language: "fr".to_string(),
+        redirect_uri: Some("https://app.ovaflus.com/plaid/oauth".to_string()),
+        android_package_name: None,
+        account_filters: account_filters(&filters).unwrap(),
+    };
+    mock.client().link_token_create(&request).await.unwrap();
+
+    let sent = &mock.requests("/link/token/create")[0];
+    assert_eq!(sent["country_codes"], serde_json::json!(["CA"]));
+    assert_eq!(sent["language"], "fr");
+    assert_eq!(sent["redirect_uri"], "https://app.ovaflus.com/plaid/oauth");
+    assert!(sent.get("android_package_name").is_none());
+    assert!(sent.get("access_token").is_none());
+    assert_eq!(
+        sent["account_filters"],
+        serde_json::json!({ "depository": { "account_subtypes": ["checking", "savings"] } })
+    );
+}
+
+#[tokio::test]
+async fn slow_responses_time_out() {
+    let mock = MockPlaid::start().await;
+    mock.delay("/link/token/create", Duration::from_millis(500));
+    let client = mock.client().with_timeout(Duration::from_millis(50));
+
+    let request = LinkTokenCreateRequest {
+        user: LinkUser {
+            client_user_id: "user-1".to_string(),
+        },
+        client_name: "Flus".to_string(),
+        products: vec!["transactions".to_string()],
+        optional_products: Vec::new(),
+        access_token: None,
+        country_codes: vec!["US".to_string()],
+        language: "en".to_string(),
+        redirect_uri: None,
+        android_package_name: None,
+        account_filters: None,
+    };
+    let error = client.link_token_create(&request).await.unwrap_err();
+    assert_eq!(error.code, None);
+    assert!(error.message.contains("timed out"));
+}
+
+#[tokio::test]
+async fn fixtures_can_be_overridden_per_test() {
+    let mock = MockPlaid::start().await;
+    mock.respond_with(
+        "/investments/transactions/get",
+        serde_json::json!({
+            "investment_transactions": null,
+            "securities": [],
+            "total_investment_transactions": 0,
+        }),
+    );
+
+    let request = InvestmentsTransactionsGetRequest {
+        access_token: token(),
+        start_date: "2024-01-01".to_string(),
+        end_date: "2024-05-01".to_string(),
+        options: InvestmentsTransactionsOptions {
+            count: 500,
+            offset: 0,
+        },
+    };
+    let page = mock
+        .client()
+        .investments_transactions_get(&request)
+        .await
+        .unwrap();
+    assert!(page.investment_transactions.is_empty());
+    assert_eq!(page.total_investment_transactions, 0);
+}
+
+#[tokio::test]
+async fn sandbox_helpers_create_items_and_fire_webhooks() {
+    let mock = MockPlaid::start().await;
+    let client = mock.client();
+
+    let created = client
+        .sandbox_public_token_create(&SandboxPublicTokenCreateRequest {
+            institution_id: "ins_109508".to_string(),
+            initial_products: vec!["transactions".to_string()],
+        })
+        .await
+        .unwrap();
+    assert!(created.public_token.starts_with("public-sandbox-"));
+    let sent = &mock.requests("/sandbox/public_token/create")[0];
+    assert_eq!(
+        sent["initial_products"],
+        serde_json::json!(["transactions"])
+    );
+
+    let accounts = client.accounts_get(&token()).await.unwrap().accounts;
+    assert_eq!(accounts[0].subtype.as_deref(), Some("checking"));
+    assert_eq!(accounts[1].mask.as_deref(), Some("3333"));
+
+    let fired = client
+        .sandbox_item_fire_webhook(&SandboxItemFireWebhookRequest {
+            access_token: token(),
+            webhook_type: "TRANSACTIONS".to_string(),
+            webhook_code: "SYNC_UPDATES_AVAILABLE".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(fired.webhook_fired);
+    assert!(
+        client
+            .sandbox_item_reset_login(&token())
+            .await
+            .unwrap()
+            .reset_login
+    );
+}
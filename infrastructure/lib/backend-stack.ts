@@ -7,6 +7,9 @@ import * as logs from 'aws-cdk-lib/aws-logs';
 import * as ssm from 'aws-cdk-lib/aws-ssm';
 import * as iam from 'aws-cdk-lib/aws-iam';
 import * as cognito from 'aws-cdk-lib/aws-cognito';
+import * as kms from 'aws-cdk-lib/aws-kms';
+import * as events from 'aws-cdk-lib/aws-events';
+import * as targets from 'aws-cdk-lib/aws-events-targets';
 import { Construct } from 'constructs';
 import { DatabaseTables } from './database-stack';
 
@@ -199,6 +202,29 @@ exports.handler = async (event) => {
       refreshTokenValidity: cdk.Duration.days(30),
     });
 
+    // KMS key wrapping the data keys that encrypt Plaid access tokens
+    const tokenKey = new kms.Key(this, 'PlaidTokenKey', {
+      alias: 'alias/ovaflus-plaid-tokens',
+      description: 'Envelope encryption of Plaid access tokens',
+      enableKeyRotation: true,
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    const backendEnvironment = {
+      RUST_LOG: 'info',
+      COGNITO_USER_POOL_ID: userPool.userPoolId,
+      COGNITO_APP_CLIENT_ID: userPoolClient.userPoolClientId,
+      COGNITO_REGION: this.region,
+      TOKEN_KMS_KEY_ID: tokenKey.keyArn,
+      // Plaid Link options; redirect URIs and Android packages must be registered with Plaid
+      PLAID_COUNTRY_CODES: this.node.tryGetContext('plaidCountryCodes') ?? 'US',
+      PLAID_LANGUAGES: this.node.tryGetContext('plaidLanguages') ?? 'en',
+      PLAID_REDIRECT_URIS: this.node.tryGetContext('plaidRedirectUris') ?? '',
+      PLAID_ANDROID_PACKAGE_NAMES: this.node.tryGetContext('plaidAndroidPackageNames') ?? '',
+      // Set once `migrate-transactions` has backfilled the transaction date index keys
+      TRANSACTIONS_DATE_INDEX: this.node.tryGetContext('transactionsDateIndex') ?? 'false',
+    };
+
     // Lambda function
     const fn = new lambda.Function(this, 'BackendFunction', {
       functionName: 'ovaflus-backend',
@@ -208,23 +234,89 @@ exports.handler = async (event) => {
       code: lambda.Code.fromBucket(artifactBucket, 'ovaflus-backend.zip'),
       memorySize: 256,
       timeout: cdk.Duration.seconds(30),
-      environment: {
-        RUST_LOG: 'info',
-        COGNITO_USER_POOL_ID: userPool.userPoolId,
-        COGNITO_APP_CLIENT_ID: userPoolClient.userPoolClientId,
-        COGNITO_REGION: this.region,
-      },
+      environment: backendEnvironment,
       logRetention: logs.RetentionDays.ONE_WEEK,
     });
 
-    // Grant DynamoDB access
-    Object.values(tables).forEach(table => table.grantReadWriteData(fn));
+    // Scheduled jobs function — same code base, built as the `jobs` binary
+    const jobsFn = new lambda.Function(this, 'JobsFunction', {
+      functionName: 'ovaflus-jobs',
+      runtime: lambda.Runtime.PROVIDED_AL2023,
+      architecture: lambda.Architecture.ARM_64,
+      handler: 'bootstrap',
+      code: lambda.Code.fromBucket(artifactBucket, 'ovaflus-jobs.zip'),
+      memorySize: 512,
+      timeout: cdk.Duration.minutes(15),
+      // Runs never overlap, so two syncs can't double-count a budget
+      reservedConcurrentExecutions: 1,
+      environment: backendEnvironment,
+      logRetention: logs.RetentionDays.ONE_WEEK,
+    });
 
-    // Grant SSM read access
-    fn.addToRolePolicy(new iam.PolicyStatement({
-      actions: ['ssm:GetParameter', 'ssm:GetParameters'],
-      resources: [`arn:aws:ssm:${this.region}:${this.account}:parameter/ovaflus/*`],
-    }));
+    // The jobs share one concurrent execution, so their schedules are staggered: each run
+    // finishes within its 15-minute timeout, well before the next job starts.
+
+    // Sync every linked Plaid item every 6 hours
+    new events.Rule(this, 'PlaidSyncSchedule', {
+      ruleName: 'ovaflus-plaid-sync',
+      schedule: events.Schedule.cron({ minute: '0', hour: '*/6' }),
+      targets: [
+        new targets.LambdaFunction(jobsFn, {
+          event: events.RuleTargetInput.fromObject({ job: 'plaid-sync' }),
+          retryAttempts: 0,
+        }),
+      ],
+    });
+
+    // Create the day's recurring transactions each morning (UTC)
+    new events.Rule(this, 'RecurringTransactionsSchedule', {
+      ruleName: 'ovaflus-recurring-transactions',
+      schedule: events.Schedule.cron({ minute: '0', hour: '4' }),
+      targets: [
+        new targets.LambdaFunction(jobsFn, {
+          event: events.RuleTargetInput.fromObject({ job: 'recurring-transactions' }),
+          retryAttempts: 0,
+        }),
+      ],
+    });
+
+    // Snapshot every user's balances once a day for the net-worth series, between syncs
+    new events.Rule(this, 'BalanceSnapshotsSchedule', {
+      ruleName: 'ovaflus-balance-snapshots',
+      schedule: events.Schedule.cron({ minute: '0', hour: '3' }),
+      targets: [
+        new targets.LambdaFunction(jobsFn, {
+          event: events.RuleTargetInput.fromObject({ job: 'balance-snapshots' }),
+          retryAttempts: 0,
+        }),
+      ],
+    });
+
+    // Train category models queued by new users and rebuild requests, half past every hour
+    new events.Rule(this, 'CategoryModelsSchedule', {
+      ruleName: 'ovaflus-category-models',
+      schedule: events.Schedule.cron({ minute: '30', hour: '*' }),
+      targets: [
+        new targets.LambdaFunction(jobsFn, {
+          event: events.RuleTargetInput.fromObject({ job: 'category-models' }),
+          retryAttempts: 0,
+        }),
+      ],
+    });
+
+    for (const f of [fn, jobsFn]) {
+      // Grant DynamoDB access
+      Object.values(tables).forEach(table => table.grantReadWriteData(f));
+
+      // Grant KMS access for access token encryption
+      tokenKey.grantEncryptDecrypt(f);
+
+      // Grant SSM read access
+      f.addToRolePolicy(new iam.PolicyStatement({
+        actions: ['ssm:GetParameter', 'ssm:GetParameters'],
+        resources: [`arn:aws:ssm:${this.region}:${this.account}:parameter/ovaflus/*`],
+      }));
+    }
 
     // Grant Cognito admin API access
     fn.addToRolePolicy(new iam.PolicyStatement({
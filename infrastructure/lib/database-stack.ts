@@ -7,10 +7,21 @@ export interface DatabaseTables {
   budgets: dynamodb.Table;
   transactions: dynamodb.Table;
   portfolio: dynamodb.Table;
+  portfolioTrades: dynamodb.Table;
+  allocationTargets: dynamodb.Table;
   watchlist: dynamodb.Table;
   goals: dynamodb.Table;
   plaidItems: dynamodb.Table;
   plaidAccounts: dynamodb.Table;
+  liabilities: dynamodb.Table;
+  balanceSnapshots: dynamodb.Table;
+  categoryMappings: dynamodb.Table;
+  recurring: dynamodb.Table;
+  subscriptions: dynamodb.Table;
+  rules: dynamodb.Table;
+  categoryModels: dynamodb.Table;
+  merchants: dynamodb.Table;
+  importMappings: dynamodb.Table;
 }
 
 export class DatabaseStack extends cdk.Stack {
@@ -57,6 +68,25 @@ export class DatabaseStack extends cdk.Stack {
       partitionKey: { name: 'budget_id', type: dynamodb.AttributeType.STRING },
       projectionType: dynamodb.ProjectionType.ALL,
     });
+    // Date-ordered reads. date_key is `YYYY-MM-DD#transaction_id`; budget_key is
+    // `user_id#budget_id`. Split transactions also get a row per split budget under the
+    // `user_id#splits` partition, which only the budget indexes read. DynamoDB builds one new
+    // GSI per update, so budget-date-index waits for a second deployment with
+    // `-c budgetDateIndex=true`; see migrate_transactions.rs for the full order.
+    transactions.addGlobalSecondaryIndex({
+      indexName: 'user-date-index',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'date_key', type: dynamodb.AttributeType.STRING },
+      projectionType: dynamodb.ProjectionType.ALL,
+    });
+    if (String(this.node.tryGetContext('budgetDateIndex') ?? 'false') === 'true') {
+      transactions.addGlobalSecondaryIndex({
+        indexName: 'budget-date-index',
+        partitionKey: { name: 'budget_key', type: dynamodb.AttributeType.STRING },
+        sortKey: { name: 'date_key', type: dynamodb.AttributeType.STRING },
+        projectionType: dynamodb.ProjectionType.ALL,
+      });
+    }
 
     // Portfolio table — PK: user_id, SK: holding_id
     const portfolio = new dynamodb.Table(this, 'PortfolioTable', {
@@ -68,6 +98,26 @@ export class DatabaseStack extends cdk.Stack {
       removalPolicy: cdk.RemovalPolicy.RETAIN,
     });
 
+    // Portfolio trades table — PK: user_id, SK: trade_id
+    const portfolioTrades = new dynamodb.Table(this, 'PortfolioTradesTable', {
+      tableName: 'ovaflus-portfolio-trades',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'trade_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Allocation targets table — PK: user_id, SK: target_id ("{kind}#{key}")
+    const allocationTargets = new dynamodb.Table(this, 'AllocationTargetsTable', {
+      tableName: 'ovaflus-allocation-targets',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'target_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
     // Watchlist table — PK: user_id, SK: symbol
     const watchlist = new dynamodb.Table(this, 'WatchlistTable', {
       tableName: 'ovaflus-watchlist',
@@ -108,6 +158,95 @@ export class DatabaseStack extends cdk.Stack {
       removalPolicy: cdk.RemovalPolicy.DESTROY,
     });
 
-    this.tables = { users, budgets, transactions, portfolio, watchlist, goals, plaidItems, plaidAccounts };
+    // Liabilities table — PK: user_id, SK: account_id
+    const liabilities = new dynamodb.Table(this, 'LiabilitiesTable', {
+      tableName: 'ovaflus-liabilities',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'account_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.DESTROY,
+    });
+
+    // Balance snapshots table — PK: user_id, SK: snapshot_id ("{date}#{account_id}")
+    const balanceSnapshots = new dynamodb.Table(this, 'BalanceSnapshotsTable', {
+      tableName: 'ovaflus-balance-snapshots',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'snapshot_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Category mappings table — PK: user_id, SK: plaid_category
+    const categoryMappings = new dynamodb.Table(this, 'CategoryMappingsTable', {
+      tableName: 'ovaflus-category-mappings',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'plaid_category', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Recurring transaction schedules — PK: user_id, SK: schedule_id
+    const recurring = new dynamodb.Table(this, 'RecurringTable', {
+      tableName: 'ovaflus-recurring',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'schedule_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Subscription decisions — PK: user_id, SK: subscription_id (normalized merchant)
+    const subscriptions = new dynamodb.Table(this, 'SubscriptionsTable', {
+      tableName: 'ovaflus-subscriptions',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'subscription_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Categorization rules — PK: user_id, SK: rule_id
+    const rules = new dynamodb.Table(this, 'RulesTable', {
+      tableName: 'ovaflus-rules',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'rule_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Learned category models — PK: user_id
+    const categoryModels = new dynamodb.Table(this, 'CategoryModelsTable', {
+      tableName: 'ovaflus-category-models',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Merchant directory — PK: owner (user_id, or "global" for shared entries), SK: merchant_id
+    const merchants = new dynamodb.Table(this, 'MerchantsTable', {
+      tableName: 'ovaflus-merchants',
+      partitionKey: { name: 'owner', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'merchant_id', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    // Saved CSV column mappings for transaction imports — PK: user_id, SK: name
+    const importMappings = new dynamodb.Table(this, 'ImportMappingsTable', {
+      tableName: 'ovaflus-import-mappings',
+      partitionKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+      sortKey: { name: 'name', type: dynamodb.AttributeType.STRING },
+      billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+      pointInTimeRecoverySpecification: { pointInTimeRecoveryEnabled: true },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
+
+    this.tables = { users, budgets, transactions, portfolio, portfolioTrades, allocationTargets, watchlist, goals, plaidItems, plaidAccounts, liabilities, balanceSnapshots, categoryMappings, recurring, subscriptions, rules, categoryModels, merchants, importMappings };
   }
 }